@@ -2,6 +2,6 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-pub fn reset() -> ! {
-    unimplemented!();
+fn main() {
+    build_util::expose_target_board();
 }
@@ -0,0 +1,19 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+#![no_std]
+#![no_main]
+
+extern crate panic_halt;
+extern crate riscv_rt;
+
+use riscv_rt::entry;
+
+#[entry]
+fn main() -> ! {
+    // QEMU's virt machine runs the `time` CSR at 10 MHz.
+    const CYCLES_PER_MS: u32 = 10_000;
+
+    unsafe { kern::startup::start_kernel(CYCLES_PER_MS) }
+}
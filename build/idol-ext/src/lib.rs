@@ -0,0 +1,649 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Client-side additions to the stubs generated by
+//! `idol::client::build_client_stub`.
+//!
+//! The stub's methods lend the server slices of the caller's own memory and
+//! wait as long as it takes for a reply. For an interface `Foo`, this
+//! generates:
+//!
+//! - for each operation `op` that takes leases, `Foo::op_leased`, which takes
+//!   ready-made `userlib::Lease`s instead of slices. A server can then pass on
+//!   a lease borrowed from its own caller (see `userlib::hl::Borrow::forward`)
+//!   rather than copying the data through a buffer of its own.
+//! - given `Timeouts::Supported`, `Foo::with_timeout`, which turns the client
+//!   into a `TimedFoo` whose methods (including the `_leased` ones) send with
+//!   `userlib::sys_send_with_timeout` instead. The interface's error type must
+//!   then mark a variant `#[idol(timed_out)]` (see `derive-idol-err`), which
+//!   is what a call that times out returns.
+//!
+//! The API crate includes the output after the stub, which it relies on for
+//! `FooOperation` and for `Foo`'s task ID:
+//!
+//! ```ignore
+//! include!(concat!(env!("OUT_DIR"), "/client_stub.rs"));
+//! include!(concat!(env!("OUT_DIR"), "/client_ext.rs"));
+//! ```
+//!
+//! Only the parts of Idol that the interfaces using this need are supported:
+//! zerocopy-encoded arguments, `[u8]` leases, and `Result` replies with a
+//! `CLike` error. Anything else fails the build rather than being generated
+//! wrongly.
+
+use indexmap::IndexMap;
+use serde::de::{self, Deserializer, MapAccess, Visitor};
+use serde::Deserialize;
+use std::error::Error;
+use std::fmt::Write;
+
+/// Whether to generate `with_timeout` for an interface.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Timeouts {
+    Unsupported,
+    Supported,
+}
+
+/// Reads the Idol interface in `source` and writes the additions for it to
+/// `stub_name` in `OUT_DIR`.
+pub fn build_client_ext(
+    source: &str,
+    stub_name: &str,
+    timeouts: Timeouts,
+) -> Result<(), Box<dyn Error>> {
+    println!("cargo:rerun-if-changed={}", source);
+
+    let iface: Interface =
+        ron::de::from_str(&std::fs::read_to_string(source)?)?;
+    let text =
+        generate(&iface, timeouts).map_err(|e| format!("{}: {}", source, e))?;
+
+    let dest_path = build_util::out_dir().join(stub_name);
+    std::fs::write(&dest_path, text)?;
+    call_rustfmt::rustfmt(&dest_path)?;
+
+    Ok(())
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Idol schema.
+//
+// This is just enough of the schema to parse every interface in `idl/`; the
+// fields we don't use (`idempotent`, `max_len`, and so on) are ignored.
+
+#[derive(Debug, Deserialize)]
+struct Interface {
+    name: String,
+    ops: IndexMap<String, Operation>,
+}
+
+#[derive(Debug, Deserialize)]
+struct Operation {
+    #[serde(default)]
+    doc: String,
+    #[serde(default)]
+    args: IndexMap<String, AttributedTy>,
+    #[serde(default)]
+    leases: IndexMap<String, Lease>,
+    reply: Reply,
+    #[serde(default)]
+    encoding: Encoding,
+}
+
+#[derive(Debug)]
+enum AttributedTy {
+    Plain(String),
+    Attributed { ty: String, recv: RecvStrategy },
+}
+
+/// By hand, because `ron` can't parse the `recv` enum inside an untagged one.
+impl<'de> Deserialize<'de> for AttributedTy {
+    fn deserialize<D: Deserializer<'de>>(d: D) -> Result<Self, D::Error> {
+        #[derive(Deserialize)]
+        #[serde(field_identifier, rename_all = "lowercase")]
+        enum Field {
+            Type,
+            Recv,
+            #[serde(other)]
+            Other,
+        }
+
+        struct AttributedTyVisitor;
+
+        impl<'de> Visitor<'de> for AttributedTyVisitor {
+            type Value = AttributedTy;
+
+            fn expecting(
+                &self,
+                f: &mut std::fmt::Formatter,
+            ) -> std::fmt::Result {
+                f.write_str("a type, or a (type, recv) struct")
+            }
+
+            fn visit_str<E: de::Error>(
+                self,
+                v: &str,
+            ) -> Result<Self::Value, E> {
+                Ok(AttributedTy::Plain(v.to_string()))
+            }
+
+            fn visit_map<A: MapAccess<'de>>(
+                self,
+                mut map: A,
+            ) -> Result<Self::Value, A::Error> {
+                let mut ty = None;
+                let mut recv = RecvStrategy::default();
+                while let Some(field) = map.next_key()? {
+                    match field {
+                        Field::Type => ty = Some(map.next_value()?),
+                        Field::Recv => recv = map.next_value()?,
+                        Field::Other => {
+                            map.next_value::<de::IgnoredAny>()?;
+                        }
+                    }
+                }
+                let ty = ty.ok_or_else(|| de::Error::missing_field("type"))?;
+                Ok(AttributedTy::Attributed { ty, recv })
+            }
+        }
+
+        d.deserialize_any(AttributedTyVisitor)
+    }
+}
+
+#[derive(Debug, Default, Deserialize)]
+enum RecvStrategy {
+    #[default]
+    FromBytes,
+    FromPrimitive(String),
+    From(String, Option<String>),
+}
+
+#[derive(Debug, Deserialize)]
+struct Lease {
+    #[serde(rename = "type")]
+    ty: String,
+    #[serde(default)]
+    read: bool,
+    #[serde(default)]
+    write: bool,
+}
+
+#[derive(Debug, Deserialize)]
+enum Reply {
+    Simple(String),
+    Result { ok: AttributedTy, err: ErrorReply },
+}
+
+#[derive(Debug, Deserialize)]
+enum ErrorReply {
+    CLike(String),
+    ServerDeath,
+}
+
+#[derive(Debug, Default, Deserialize)]
+enum Encoding {
+    #[default]
+    Zerocopy,
+    Ssmarshal,
+}
+
+///////////////////////////////////////////////////////////////////////////////
+// Code generation.
+
+/// An argument as the client passes it, and as it goes in the message.
+struct Arg<'a> {
+    name: &'a str,
+    ty: &'a str,
+    wire_ty: &'a str,
+    wire_value: String,
+}
+
+/// A lease, and how to make one from the slice that the stub would take.
+struct LeaseArg<'a> {
+    name: &'a str,
+    slice_ty: &'static str,
+    constructor: &'static str,
+}
+
+/// An operation in the subset of Idol that we generate code for.
+struct Op<'a> {
+    name: &'a str,
+    args: Vec<Arg<'a>>,
+    leases: Vec<LeaseArg<'a>>,
+    ok: &'a str,
+    err: &'a str,
+}
+
+impl<'a> Op<'a> {
+    fn new(name: &'a str, op: &'a Operation) -> Result<Self, String> {
+        let unsupported = |what: &str| {
+            Err(format!("operation `{}`: {} is unsupported", name, what))
+        };
+
+        if let Encoding::Ssmarshal = op.encoding {
+            return unsupported("Ssmarshal encoding");
+        }
+        let (ok, err) = match &op.reply {
+            Reply::Result {
+                ok: AttributedTy::Plain(ok),
+                err: ErrorReply::CLike(err),
+            }
+            | Reply::Result {
+                ok:
+                    AttributedTy::Attributed {
+                        ty: ok,
+                        recv: RecvStrategy::FromBytes,
+                    },
+                err: ErrorReply::CLike(err),
+            } => (ok.as_str(), err.as_str()),
+            _ => return unsupported("a reply other than Result with CLike"),
+        };
+        if ok == "bool" {
+            return unsupported("a bool reply");
+        }
+
+        let mut args = vec![];
+        for (arg, ty) in &op.args {
+            args.push(match ty {
+                AttributedTy::Plain(ty)
+                | AttributedTy::Attributed {
+                    ty,
+                    recv: RecvStrategy::FromBytes,
+                } => {
+                    if ty == "bool" {
+                        return unsupported("a bool argument");
+                    }
+                    Arg {
+                        name: arg,
+                        ty,
+                        wire_ty: ty,
+                        wire_value: arg.clone(),
+                    }
+                }
+                AttributedTy::Attributed {
+                    ty,
+                    recv: RecvStrategy::FromPrimitive(wire_ty),
+                } => Arg {
+                    name: arg,
+                    ty,
+                    wire_ty,
+                    wire_value: format!("{} as {}", arg, wire_ty),
+                },
+                AttributedTy::Attributed {
+                    ty,
+                    recv: RecvStrategy::From(wire_ty, None),
+                } => Arg {
+                    name: arg,
+                    ty,
+                    wire_ty,
+                    wire_value: format!("<{}>::from({})", wire_ty, arg),
+                },
+                AttributedTy::Attributed { .. } => {
+                    return unsupported("`From` with a fallible conversion");
+                }
+            });
+        }
+
+        let mut leases = vec![];
+        for (lease, l) in &op.leases {
+            if l.ty != "[u8]" {
+                return unsupported("a lease of a type other than [u8]");
+            }
+            let (slice_ty, constructor) = match (l.read, l.write) {
+                (true, false) => ("&[u8]", "read_only"),
+                (false, true) => ("&mut [u8]", "write_only"),
+                (true, true) => ("&mut [u8]", "read_write"),
+                (false, false) => {
+                    return unsupported("a lease with neither read nor write")
+                }
+            };
+            leases.push(LeaseArg {
+                name: lease,
+                slice_ty,
+                constructor,
+            });
+        }
+
+        Ok(Self {
+            name,
+            args,
+            leases,
+            ok,
+            err,
+        })
+    }
+
+    /// Parameters for the arguments, then for the leases as `userlib::Lease`
+    /// (if `leased`) or as slices.
+    fn params(&self, leased: bool) -> String {
+        let args = self.args.iter().map(|a| format!("{}: {}, ", a.name, a.ty));
+        let leases = self.leases.iter().map(|l| {
+            if leased {
+                format!("{}: userlib::Lease<'_>, ", l.name)
+            } else {
+                format!("{}: {}, ", l.name, l.slice_ty)
+            }
+        });
+        args.chain(leases).collect()
+    }
+
+    /// Arguments to pass on to `<op>_ext` from a method taking `params`.
+    fn forward(&self, leased: bool) -> String {
+        let args = self.args.iter().map(|a| format!("{}, ", a.name));
+        let leases = self.leases.iter().map(|l| {
+            if leased {
+                format!("{}, ", l.name)
+            } else {
+                format!("userlib::Lease::{}({}), ", l.constructor, l.name)
+            }
+        });
+        args.chain(leases).collect()
+    }
+
+    fn result(&self) -> String {
+        format!("Result<{}, {}>", self.ok, self.err)
+    }
+}
+
+fn generate(iface: &Interface, timeouts: Timeouts) -> Result<String, String> {
+    let ops = iface
+        .ops
+        .iter()
+        .filter(|(_, op)| {
+            timeouts == Timeouts::Supported || !op.leases.is_empty()
+        })
+        .map(|(name, op)| Ok((Op::new(name, op)?, op.doc.as_str())))
+        .collect::<Result<Vec<_>, String>>()?;
+
+    let mut out = String::new();
+    write_ext(&mut out, &iface.name, &ops, timeouts).unwrap();
+    Ok(out)
+}
+
+fn write_ext(
+    out: &mut String,
+    iface: &str,
+    ops: &[(Op, &str)],
+    timeouts: Timeouts,
+) -> std::fmt::Result {
+    let timed = timeouts == Timeouts::Supported;
+    let timeout_param = if timed { "timeout: Option<u32>, " } else { "" };
+    let untimed = if timed { "None, " } else { "" };
+
+    writeln!(out, "// Generated by build-idol-ext.")?;
+
+    for (op, _) in ops {
+        if op.args.is_empty() {
+            continue;
+        }
+        writeln!(out, "#[allow(non_camel_case_types)]")?;
+        writeln!(out, "#[derive(zerocopy::AsBytes)]")?;
+        writeln!(out, "#[repr(C, packed)]")?;
+        writeln!(out, "struct {}_{}_EXT_ARGS {{", iface, op.name)?;
+        for a in &op.args {
+            writeln!(out, "{}: {},", a.name, a.wire_ty)?;
+        }
+        writeln!(out, "}}")?;
+    }
+
+    writeln!(out, "impl {} {{", iface)?;
+    for (op, _) in ops.iter().filter(|(op, _)| !op.leases.is_empty()) {
+        writeln!(
+            out,
+            "/// Like `{}`, but taking ready-made leases, such as ones \
+             forwarded from our own caller.",
+            op.name
+        )?;
+        writeln!(
+            out,
+            "pub fn {}_leased(&self, {}) -> {} {{",
+            op.name,
+            op.params(true),
+            op.result()
+        )?;
+        writeln!(out, "self.{}_ext({}{})", op.name, untimed, op.forward(true))?;
+        writeln!(out, "}}")?;
+    }
+
+    if timed {
+        writeln!(
+            out,
+            "/// Turns this into a client whose calls give up if the server \
+             hasn't replied within `ticks`."
+        )?;
+        writeln!(
+            out,
+            "pub fn with_timeout(self, ticks: u32) -> Timed{} {{",
+            iface
+        )?;
+        writeln!(out, "Timed{} {{ client: self, ticks }}", iface)?;
+        writeln!(out, "}}")?;
+    }
+
+    for (op, _) in ops {
+        writeln!(
+            out,
+            "fn {}_ext(&self, {}{}) -> {} {{",
+            op.name,
+            timeout_param,
+            op.params(true),
+            op.result()
+        )?;
+        if op.args.is_empty() {
+            writeln!(out, "let args: [u8; 0] = [];")?;
+        } else {
+            writeln!(out, "let args = {}_{}_EXT_ARGS {{", iface, op.name)?;
+            for a in &op.args {
+                if a.wire_value == a.name {
+                    writeln!(out, "{},", a.name)?;
+                } else {
+                    writeln!(out, "{}: {},", a.name, a.wire_value)?;
+                }
+            }
+            writeln!(out, "}};")?;
+        }
+        writeln!(
+            out,
+            "let mut reply = [0u8; core::mem::size_of::<{}>()];",
+            op.ok
+        )?;
+        if op.leases.is_empty() {
+            writeln!(out, "let leases: [userlib::Lease<'_>; 0] = [];")?;
+        } else {
+            let leases = op.leases.iter().map(|l| l.name).collect::<Vec<_>>();
+            writeln!(out, "let leases = [{}];", leases.join(", "))?;
+        }
+        writeln!(out, "let task = self.current_id.get();")?;
+        let send = |f: &str, extra: &str| {
+            format!(
+                "userlib::{}(task, {}Operation::{} as u16, \
+                 zerocopy::AsBytes::as_bytes(&args), &mut reply, \
+                 &leases{})",
+                f, iface, op.name, extra
+            )
+        };
+        if timed {
+            writeln!(out, "let (rc, _len) = match timeout {{")?;
+            writeln!(
+                out,
+                "Some(ticks) => {},",
+                send("sys_send_with_timeout", ", ticks")
+            )?;
+            writeln!(out, "None => {},", send("sys_send", ""))?;
+            writeln!(out, "}};")?;
+        } else {
+            writeln!(out, "let (rc, _len) = {};", send("sys_send", ""))?;
+        }
+        writeln!(
+            out,
+            "if let Some(g) = userlib::extract_new_generation(rc) {{"
+        )?;
+        writeln!(
+            out,
+            "self.current_id.set(\
+             userlib::TaskId::for_index_and_gen(task.index(), g));"
+        )?;
+        writeln!(out, "}}")?;
+        if timed {
+            writeln!(out, "if rc == userlib::TIMED_OUT {{")?;
+            writeln!(out, "return Err({}::TIMED_OUT);", op.err)?;
+            writeln!(out, "}}")?;
+        }
+        writeln!(out, "if rc != 0 {{")?;
+        writeln!(
+            out,
+            "return Err(userlib::UnwrapLite::unwrap_lite(\
+             <{} as core::convert::TryFrom<u32>>::try_from(rc)));",
+            op.err
+        )?;
+        writeln!(out, "}}")?;
+        if op.ok == "()" {
+            writeln!(out, "Ok(())")?;
+        } else {
+            writeln!(
+                out,
+                "Ok(userlib::UnwrapLite::unwrap_lite(\
+                 <{} as zerocopy::FromBytes>::read_from(&reply[..])))",
+                op.ok
+            )?;
+        }
+        writeln!(out, "}}")?;
+    }
+    writeln!(out, "}}")?;
+
+    if !timed {
+        return Ok(());
+    }
+
+    writeln!(
+        out,
+        "/// A `{0}` client whose calls give up if the server doesn't reply \
+         in time; see `{0}::with_timeout`.",
+        iface
+    )?;
+    writeln!(out, "pub struct Timed{} {{", iface)?;
+    writeln!(out, "client: {},", iface)?;
+    writeln!(out, "ticks: u32,")?;
+    writeln!(out, "}}")?;
+
+    writeln!(out, "impl Timed{} {{", iface)?;
+    for (op, doc) in ops {
+        let mut variants = vec![false];
+        if !op.leases.is_empty() {
+            variants.push(true);
+        }
+        for leased in variants {
+            let suffix = if leased { "_leased" } else { "" };
+            if !leased && !doc.is_empty() {
+                writeln!(out, "/// {}", doc)?;
+                writeln!(out, "///")?;
+            }
+            writeln!(
+                out,
+                "/// Returns `{}::TIMED_OUT` if the server doesn't reply in \
+                 time.",
+                op.err
+            )?;
+            writeln!(
+                out,
+                "pub fn {}{}(&self, {}) -> {} {{",
+                op.name,
+                suffix,
+                op.params(leased),
+                op.result()
+            )?;
+            writeln!(
+                out,
+                "self.client.{}_ext(Some(self.ticks), {})",
+                op.name,
+                op.forward(leased)
+            )?;
+            writeln!(out, "}}")?;
+        }
+    }
+    writeln!(out, "}}")?;
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn load(name: &str) -> Interface {
+        let path = format!("{}/../../idl/{}", env!("CARGO_MANIFEST_DIR"), name);
+        ron::de::from_str(&std::fs::read_to_string(path).unwrap()).unwrap()
+    }
+
+    #[test]
+    fn parses_every_interface() {
+        let dir = concat!(env!("CARGO_MANIFEST_DIR"), "/../../idl");
+        for entry in std::fs::read_dir(dir).unwrap() {
+            let path = entry.unwrap().path();
+            let text = std::fs::read_to_string(&path).unwrap();
+            if let Err(e) = ron::de::from_str::<Interface>(&text) {
+                panic!("{}: {}", path.display(), e);
+            }
+        }
+    }
+
+    #[test]
+    fn leased_variants_only_for_ops_with_leases() {
+        let text = generate(&load("spi.idol"), Timeouts::Unsupported).unwrap();
+        assert!(text.contains(
+            "pub fn exchange_leased(&self, device_index: u8, \
+             source: userlib::Lease<'_>, sink: userlib::Lease<'_>, )"
+        ));
+        assert!(text.contains("let leases = [source, sink];"));
+        assert!(!text.contains("fn lock_leased"));
+        assert!(!text.contains("fn lock_ext"));
+        assert!(!text.contains("with_timeout"));
+        assert!(!text.contains("TIMED_OUT"));
+    }
+
+    #[test]
+    fn packs_arguments_in_declared_order() {
+        let text =
+            generate(&load("update.idol"), Timeouts::Unsupported).unwrap();
+        assert!(text.contains(
+            "struct Update_write_blocks_EXT_ARGS {\nblock_num: usize,\n}"
+        ));
+        assert!(!text.contains("struct Update_written_blocks_EXT_ARGS"));
+        assert!(text.contains("let args: [u8; 0] = [];"));
+
+        let text = generate(&load("sensor.idol"), Timeouts::Supported).unwrap();
+        assert!(text.contains(
+            "struct Sensor_nodata_EXT_ARGS {\nid: usize,\nnodata: u8,\n}"
+        ));
+        assert!(text.contains("id: <usize>::from(id),\nnodata: nodata as u8,"));
+    }
+
+    #[test]
+    fn timed_client_mirrors_every_op() {
+        let text = generate(&load("spi.idol"), Timeouts::Supported).unwrap();
+        for op in ["read", "write", "exchange", "lock", "release"] {
+            assert!(text.contains(&format!("pub fn {}(&self, ", op)));
+            assert!(text.contains(&format!("fn {}_ext(&self, ", op)));
+        }
+        assert!(text.contains(
+            "self.client.write_ext(Some(self.ticks), device_index, \
+             userlib::Lease::read_only(source), )"
+        ));
+        assert!(text.contains(
+            "self.client.read_ext(Some(self.ticks), device_index, \
+             userlib::Lease::write_only(sink), )"
+        ));
+        assert!(text.contains("return Err(SpiError::TIMED_OUT);"));
+    }
+
+    #[test]
+    fn rejects_ssmarshal() {
+        let e = generate(&load("net.idol"), Timeouts::Unsupported).unwrap_err();
+        assert_eq!(
+            e,
+            "operation `recv_packet`: Ssmarshal encoding is unsupported"
+        );
+    }
+}
@@ -76,6 +76,13 @@ pub struct TaskConfig {
 
     /// Should this task be started automatically on boot?
     pub start_at_boot: bool,
+
+    /// May this task read the registers and memory of faulted tasks?
+    pub dump_access: bool,
+
+    /// Indices of the tasks this task may send to or post to, if the
+    /// application restricts IPC. `None` means any task is allowed.
+    pub ipc_allowlist: Option<BTreeSet<usize>>,
 }
 
 /// An address within an owned region of memory.
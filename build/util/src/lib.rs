@@ -58,6 +58,8 @@ pub fn has_feature(s: &str) -> bool {
 ///
 /// For RISC-V targets, this will set `riscv_no_atomics` if the target doesn't
 /// implement the `A` extension.
+///
+/// Hosted targets (anything with an OS) are accepted without setting anything.
 pub fn expose_cpu_info() {
     let mut target = crate::target();
 
@@ -68,7 +70,7 @@ pub fn expose_cpu_info() {
         println!("cargo:rustc-cfg=armv7m");
     } else if target.starts_with("thumbv8m") {
         println!("cargo:rustc-cfg=armv8m");
-    } else if target.starts_with("riscv32") {
+    } else if target.starts_with("riscv") {
         target.truncate(target.find('-').unwrap());
         if !target.contains('a') && !target.contains('g') {
             eprintln!(
@@ -76,6 +78,9 @@ pub fn expose_cpu_info() {
             );
             println!("cargo:rustc-cfg=riscv_no_atomics");
         }
+    } else if target_os() != "none" {
+        // Hosted builds, used to test the kernel in simulation. Nothing to
+        // expose.
     } else {
         println!("Don't know the target {}", target);
         std::process::exit(1);
@@ -0,0 +1,896 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Structural checks on an application's configuration.
+//!
+//! These catch mistakes in `app.toml` that would otherwise turn up partway
+//! through a build, or only at runtime, and report all of them at once,
+//! pointing at the offending line of `app.toml` where we can find it.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::{Path, PathBuf};
+
+use anyhow::{bail, Result};
+use colored::*;
+
+use crate::config::Config;
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq)]
+enum Severity {
+    Error,
+    Warning,
+}
+
+/// A location in one of the files making up the config: an index into
+/// [`Checker::sources`], a 1-based line, and a 1-based column and a length
+/// to underline, both in bytes.
+#[derive(Copy, Clone, Debug)]
+struct Span {
+    file: usize,
+    line: usize,
+    col: usize,
+    len: usize,
+}
+
+/// How closely a [`Span`] found by [`Source::find`] matches what was asked
+/// for.
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord)]
+enum Precision {
+    Table,
+    Key,
+    Needle,
+}
+
+struct Problem {
+    severity: Severity,
+    message: String,
+    span: Option<Span>,
+}
+
+/// The text of an `app.toml` (or a file it inherits from), used to find the
+/// lines that problems refer to.
+///
+/// This is a line-oriented scan rather than a real TOML parser, so it
+/// understands table headers and `key = value` lines (including values that
+/// continue over several lines), which covers how our `app.toml` files are
+/// written. When it can't find something it falls back to less precise
+/// locations, and finally to no location at all.
+struct Source {
+    path: PathBuf,
+    lines: Vec<String>,
+}
+
+impl Source {
+    fn load(path: &Path) -> Self {
+        let text = std::fs::read_to_string(path).unwrap_or_default();
+        Source {
+            path: path.to_owned(),
+            lines: text.lines().map(str::to_owned).collect(),
+        }
+    }
+
+    /// Finds `needle` within the value of `key` in `table` (for example,
+    /// `["tasks", "net"]`). `key` may be written inline in the table or as
+    /// a sub-table of its own. `needle` only matches a whole token, so
+    /// `ram` doesn't match `sram`. If `needle` can't be found, this points
+    /// at `key`, and if that can't be found either, at the table's header.
+    fn find(
+        &self,
+        table: &[&str],
+        key: Option<&str>,
+        needle: Option<&str>,
+    ) -> Option<(Precision, Span)> {
+        let mut header: Vec<String> = vec![];
+        let mut in_key = false;
+        let mut table_span = None;
+        let mut key_span = None;
+
+        for (i, line) in self.lines.iter().enumerate() {
+            // Where the value starts, so that `needle` isn't found in the
+            // key itself.
+            let mut value = 0;
+            let trimmed = line.trim_start();
+            let indent = line.len() - trimmed.len();
+            let whole_line = Span {
+                file: 0,
+                line: i + 1,
+                col: indent + 1,
+                len: trimmed.trim_end().len(),
+            };
+
+            if trimmed.starts_with('[') {
+                let end = trimmed.find(']').unwrap_or(trimmed.len());
+                header = trimmed[..end]
+                    .trim_start_matches('[')
+                    .split('.')
+                    .map(|part| part.trim().trim_matches('"').to_owned())
+                    .collect();
+                in_key = false;
+                if header == table {
+                    table_span.get_or_insert(whole_line);
+                } else if let Some(key) = key {
+                    if header.len() == table.len() + 1
+                        && header[..table.len()] == *table
+                        && header[table.len()] == key
+                    {
+                        in_key = true;
+                        key_span = Some(whole_line);
+                    }
+                }
+                continue;
+            }
+
+            if header == table {
+                if let Some((lhs, _)) = trimmed.split_once('=') {
+                    let name = lhs.trim().trim_matches('"');
+                    in_key = Some(name) == key;
+                    if in_key {
+                        value = indent + lhs.len() + 1;
+                        key_span = Some(Span {
+                            file: 0,
+                            line: i + 1,
+                            col: indent + 1,
+                            len: lhs.trim_end().len(),
+                        });
+                    }
+                }
+            }
+
+            if let (true, Some(needle)) = (in_key, needle) {
+                if let Some(col) = find_token(line, needle, value) {
+                    let span = Span {
+                        file: 0,
+                        line: i + 1,
+                        col: col + 1,
+                        len: needle.len(),
+                    };
+                    return Some((Precision::Needle, span));
+                }
+            }
+        }
+
+        key_span
+            .map(|span| (Precision::Key, span))
+            .or_else(|| table_span.map(|span| (Precision::Table, span)))
+    }
+
+    fn print(&self, problem: &Problem) {
+        let label = match problem.severity {
+            Severity::Error => "error".red().bold(),
+            Severity::Warning => "warning".yellow().bold(),
+        };
+        eprintln!("{}: {}", label, problem.message);
+
+        let span = match problem.span {
+            Some(span) => span,
+            None => {
+                eprintln!("  --> {}", self.path.display());
+                return;
+            }
+        };
+        // Spans are in bytes, but we show columns and underline in
+        // characters.
+        let line = &self.lines[span.line - 1];
+        let col = line[..span.col - 1].chars().count();
+        let len = line[span.col - 1..][..span.len].chars().count();
+        let number = span.line.to_string();
+        let gutter = " ".repeat(number.len());
+        eprintln!(
+            "{}--> {}:{}:{}",
+            gutter,
+            self.path.display(),
+            span.line,
+            col + 1
+        );
+        eprintln!("{} |", gutter);
+        eprintln!("{} | {}", number, line);
+        eprintln!("{} | {}{}", gutter, " ".repeat(col), "^".repeat(len.max(1)));
+    }
+}
+
+/// Finds the first occurrence of `needle` in `line` at or after byte `from`
+/// that isn't part of a longer name, returning its byte offset.
+fn find_token(line: &str, needle: &str, from: usize) -> Option<usize> {
+    let is_name = |c: char| c.is_alphanumeric() || c == '_' || c == '-';
+    let from = from.min(line.len());
+    line[from..]
+        .match_indices(needle)
+        .map(|(i, _)| from + i)
+        .find(|&i| {
+            !line[..i].chars().next_back().map_or(false, is_name)
+                && !line[i + needle.len()..]
+                    .chars()
+                    .next()
+                    .map_or(false, is_name)
+        })
+}
+
+struct Checker<'a> {
+    toml: &'a Config,
+    /// The config file, followed by the files it inherits from.
+    sources: Vec<Source>,
+    deny_inversions: bool,
+    problems: Vec<Problem>,
+}
+
+impl<'a> Checker<'a> {
+    fn new(toml: &'a Config, deny_inversions: bool) -> Self {
+        Checker {
+            toml,
+            sources: std::iter::once(&toml.app_toml_path)
+                .chain(&toml.inherited)
+                .map(|path| Source::load(path))
+                .collect(),
+            deny_inversions,
+            problems: vec![],
+        }
+    }
+
+    fn check_all(&mut self) {
+        self.check_priorities();
+        self.check_task_slots();
+        self.check_uses();
+        self.check_interrupts();
+        self.check_memories();
+    }
+
+    /// Finds the most precise location for something across all the files
+    /// making up the config, preferring the files that inherit from others
+    /// when it's found in several, as they override the others.
+    fn find(
+        &self,
+        table: &[&str],
+        key: Option<&str>,
+        needle: Option<&str>,
+    ) -> Option<Span> {
+        let mut best: Option<(Precision, Span)> = None;
+        for (file, source) in self.sources.iter().enumerate() {
+            if let Some((precision, span)) = source.find(table, key, needle) {
+                let better = match best {
+                    Some((p, _)) => precision > p,
+                    None => true,
+                };
+                if better {
+                    best = Some((precision, Span { file, ..span }));
+                }
+            }
+        }
+        best.map(|(_, span)| span)
+    }
+
+    fn report(
+        &mut self,
+        severity: Severity,
+        message: String,
+        span: Option<Span>,
+    ) {
+        self.problems.push(Problem {
+            severity,
+            message,
+            span,
+        });
+    }
+
+    /// Reports an error in `key` of task `task`, pointing at `needle` if
+    /// given.
+    fn task_error(
+        &mut self,
+        task: &str,
+        key: Option<&str>,
+        needle: Option<&str>,
+        message: String,
+    ) {
+        let span = self.find(&["tasks", task], key, needle);
+        self.report(Severity::Error, message, span);
+    }
+
+    /// The supervisor must be priority 0, and is the only task that may be;
+    /// the idle task must be lower priority than everything else.
+    fn check_priorities(&mut self) {
+        let toml = self.toml;
+        let idle_priority = match toml.tasks.get("idle") {
+            Some(idle) => idle.priority,
+            None => {
+                let span = self.find(&["tasks"], None, None);
+                self.report(
+                    Severity::Error,
+                    "there is no task named `idle`".to_string(),
+                    span,
+                );
+                return;
+            }
+        };
+
+        for (i, (name, task)) in toml.tasks.iter().enumerate() {
+            let message = if i == 0 && task.priority != 0 {
+                format!("supervisor task `{}` is not at priority 0", name)
+            } else if i != 0 && task.priority == 0 {
+                format!(
+                    "task `{}` is not the supervisor, but has priority 0",
+                    name
+                )
+            } else if task.priority >= idle_priority && name != "idle" {
+                format!(
+                    "task `{}` has priority {}, which is not higher than \
+                     the idle task's ({})",
+                    name, task.priority, idle_priority
+                )
+            } else {
+                continue;
+            };
+            self.task_error(name, Some("priority"), None, message);
+        }
+    }
+
+    /// Task slots must name tasks that exist, should only call into
+    /// higher-priority tasks, and must not form cycles (which, as well as
+    /// implying an inversion, can deadlock).
+    fn check_task_slots(&mut self) {
+        let toml = self.toml;
+        let mut calls: BTreeMap<&str, Vec<&str>> = BTreeMap::new();
+
+        for (name, task) in &toml.tasks {
+            for (slot, callee) in &task.task_slots {
+                let needle = format!("\"{}\"", callee);
+                let callee_task = match toml.tasks.get(callee) {
+                    Some(t) => t,
+                    None => {
+                        self.task_error(
+                            name,
+                            Some("task-slots"),
+                            Some(&needle),
+                            format!(
+                                "task `{}` has slot `{}` for unknown task \
+                                 `{}`.{}",
+                                name,
+                                slot,
+                                callee,
+                                toml.task_name_suggestion(callee)
+                            ),
+                        );
+                        continue;
+                    }
+                };
+                if callee == name {
+                    continue;
+                }
+                calls
+                    .entry(name.as_str())
+                    .or_default()
+                    .push(callee.as_str());
+
+                if callee_task.priority >= task.priority {
+                    let severity = if self.deny_inversions {
+                        Severity::Error
+                    } else {
+                        Severity::Warning
+                    };
+                    let span = self.find(
+                        &["tasks", name],
+                        Some("task-slots"),
+                        Some(&needle),
+                    );
+                    self.report(
+                        severity,
+                        format!(
+                            "priority inversion: task `{}` (priority {}) \
+                             calls into `{}` (priority {})",
+                            name, task.priority, callee, callee_task.priority
+                        ),
+                        span,
+                    );
+                }
+            }
+        }
+
+        for cycle in find_cycles(&calls) {
+            let path = cycle
+                .iter()
+                .chain(std::iter::once(&cycle[0]))
+                .map(|t| format!("`{}`", t))
+                .collect::<Vec<_>>()
+                .join(" -> ");
+            self.task_error(
+                cycle[0],
+                Some("task-slots"),
+                Some(&format!("\"{}\"", cycle[1])),
+                format!("task slots form a cycle: {}", path),
+            );
+        }
+    }
+
+    /// Everything a task `uses` must be a peripheral from the chip file or
+    /// an `extratext` region.
+    fn check_uses(&mut self) {
+        let toml = self.toml;
+        for (name, task) in &toml.tasks {
+            for used in &task.uses {
+                if toml.peripherals.contains_key(used)
+                    || toml.extratext.contains_key(used)
+                {
+                    continue;
+                }
+                self.task_error(
+                    name,
+                    Some("uses"),
+                    Some(&format!("\"{}\"", used)),
+                    format!(
+                        "task `{}` uses `{}`, which is neither a peripheral \
+                         of the chip nor an extratext region",
+                        name, used
+                    ),
+                );
+            }
+        }
+    }
+
+    /// Interrupts must resolve to IRQ numbers, each IRQ can only be routed
+    /// to one place, and each interrupt must post exactly one notification
+    /// bit that isn't shared with an unrelated interrupt.
+    fn check_interrupts(&mut self) {
+        let toml = self.toml;
+        // IRQ number to the task and key that claimed it.
+        let mut claimed: BTreeMap<u32, (&str, &str)> = BTreeMap::new();
+
+        for (name, task) in &toml.tasks {
+            // Notification bit to the source (a peripheral name, or the IRQ
+            // number for numeric interrupts) and key that first used it.
+            let mut bits: BTreeMap<u32, (String, &str)> = BTreeMap::new();
+
+            for (irq_str, &mask) in &task.interrupts {
+                let needle = format!("\"{}\"", irq_str);
+                let report = |checker: &mut Self, message: String| {
+                    checker.task_error(
+                        name,
+                        Some("interrupts"),
+                        Some(&needle),
+                        message,
+                    );
+                };
+
+                // Several interrupts from one peripheral may share a bit,
+                // as drivers commonly handle them together (e.g. an I2C
+                // controller's event and error interrupts).
+                let (irq, source) = match irq_str.parse::<u32>() {
+                    Ok(irq) => (irq, irq_str.clone()),
+                    Err(_) => {
+                        let (pname, iname) = match irq_str.split_once('.') {
+                            Some(parts) => parts,
+                            None => {
+                                report(
+                                    self,
+                                    format!(
+                                        "task `{}` has interrupt `{}`, which \
+                                         is neither an IRQ number nor of the \
+                                         form `peripheral.interrupt`",
+                                        name, irq_str
+                                    ),
+                                );
+                                continue;
+                            }
+                        };
+                        let irq = toml
+                            .peripherals
+                            .get(pname)
+                            .and_then(|p| p.interrupts.get(iname));
+                        match irq {
+                            Some(&irq) => (irq, pname.to_string()),
+                            None => {
+                                report(
+                                    self,
+                                    format!(
+                                        "task `{}` has interrupt `{}`, which \
+                                         isn't an interrupt of the chip",
+                                        name, irq_str
+                                    ),
+                                );
+                                continue;
+                            }
+                        }
+                    }
+                };
+
+                if mask.count_ones() != 1 {
+                    report(
+                        self,
+                        format!(
+                            "task `{}` interrupt `{}`: notification mask \
+                             (0b{:b}) has {} bits set (expected exactly one)",
+                            name,
+                            irq_str,
+                            mask,
+                            mask.count_ones()
+                        ),
+                    );
+                } else if let Some((other_source, other)) = bits.get(&mask) {
+                    if *other_source != source {
+                        report(
+                            self,
+                            format!(
+                                "task `{}` interrupts `{}` and `{}` both \
+                                 post notification 0b{:b}",
+                                name, other, irq_str, mask
+                            ),
+                        );
+                    }
+                } else {
+                    bits.insert(mask, (source, irq_str.as_str()));
+                }
+
+                if let Some((other_task, other)) = claimed.get(&irq) {
+                    report(
+                        self,
+                        format!(
+                            "IRQ {} (`{}` in task `{}`) is already assigned \
+                             by `{}` in task `{}`",
+                            irq, irq_str, name, other, other_task
+                        ),
+                    );
+                } else {
+                    claimed.insert(irq, (name.as_str(), irq_str.as_str()));
+                }
+            }
+        }
+    }
+
+    /// `max-sizes` must only name memories that the chip's memory file
+    /// defines, as must the kernel's `requires`.
+    fn check_memories(&mut self) {
+        let toml = self.toml;
+        for (name, task) in &toml.tasks {
+            for mem in task.max_sizes.keys() {
+                if !toml.outputs.contains_key(mem) {
+                    self.task_error(
+                        name,
+                        Some("max-sizes"),
+                        Some(mem),
+                        format!(
+                            "task `{}` has a max size for `{}`, which isn't \
+                             in the chip's memory map",
+                            name, mem
+                        ),
+                    );
+                }
+            }
+        }
+        for mem in toml.kernel.requires.keys() {
+            if !toml.outputs.contains_key(mem) {
+                let span = self.find(&["kernel"], Some("requires"), Some(mem));
+                self.report(
+                    Severity::Error,
+                    format!(
+                        "kernel requires `{}`, which isn't in the chip's \
+                         memory map",
+                        mem
+                    ),
+                    span,
+                );
+            }
+        }
+    }
+}
+
+/// Finds cycles in a call graph, which is enough to report at least one
+/// cycle through every task that's part of one. Each is returned starting
+/// from its alphabetically first task, so that it's only reported once.
+fn find_cycles<'a>(
+    calls: &BTreeMap<&'a str, Vec<&'a str>>,
+) -> Vec<Vec<&'a str>> {
+    fn visit<'a>(
+        task: &'a str,
+        calls: &BTreeMap<&'a str, Vec<&'a str>>,
+        stack: &mut Vec<&'a str>,
+        done: &mut BTreeSet<&'a str>,
+        cycles: &mut BTreeSet<Vec<&'a str>>,
+    ) {
+        if let Some(pos) = stack.iter().position(|&t| t == task) {
+            let mut cycle = stack[pos..].to_vec();
+            let first = (0..cycle.len()).min_by_key(|&i| cycle[i]).unwrap();
+            cycle.rotate_left(first);
+            cycles.insert(cycle);
+            return;
+        }
+        if done.contains(task) {
+            return;
+        }
+        stack.push(task);
+        for &callee in calls.get(task).into_iter().flatten() {
+            visit(callee, calls, stack, done, cycles);
+        }
+        stack.pop();
+        done.insert(task);
+    }
+
+    let mut cycles = BTreeSet::new();
+    let mut done = BTreeSet::new();
+    for &task in calls.keys() {
+        visit(task, calls, &mut vec![], &mut done, &mut cycles);
+    }
+    cycles.into_iter().collect()
+}
+
+/// Checks `toml`, printing every problem found. Returns an error if any of
+/// them are errors; priority inversions are only warnings unless
+/// `deny_inversions` is set.
+pub fn check(toml: &Config, deny_inversions: bool) -> Result<()> {
+    let mut checker = Checker::new(toml, deny_inversions);
+    checker.check_all();
+
+    for problem in &checker.problems {
+        let source = problem.span.map_or(0, |span| span.file);
+        checker.sources[source].print(problem);
+    }
+
+    let errors = checker
+        .problems
+        .iter()
+        .filter(|p| p.severity == Severity::Error)
+        .count();
+    if errors != 0 {
+        bail!(
+            "{} has {} error{}",
+            toml.app_toml_path.display(),
+            errors,
+            if errors == 1 { "" } else { "s" }
+        );
+    }
+    Ok(())
+}
+
+pub fn run(cfg: &Path, deny_inversions: bool) -> Result<()> {
+    let toml = Config::from_file(cfg)?;
+    check(&toml, deny_inversions)?;
+    println!("{} looks good", cfg.display());
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::tests::load_with;
+
+    /// Runs every check on the skeleton app from `config::tests` (tasks `a`
+    /// at priority 0 and `b` at 1) with `extra` appended, returning each
+    /// problem's severity, message, and the line it points at.
+    fn problems(
+        extra: &str,
+        deny_inversions: bool,
+    ) -> Vec<(Severity, String, Option<String>)> {
+        load_with("thumbv7em-none-eabihf", "stm32h7", extra, |toml| {
+            let toml = toml.unwrap();
+            let mut checker = Checker::new(&toml, deny_inversions);
+            checker.check_all();
+            let line = |span: Span| {
+                checker.sources[span.file].lines[span.line - 1].clone()
+            };
+            checker
+                .problems
+                .iter()
+                .map(|p| (p.severity, p.message.clone(), p.span.map(line)))
+                .collect()
+        })
+        .unwrap()
+    }
+
+    const IDLE: &str = r#"
+[tasks.idle]
+name = "task-idle"
+priority = 3
+"#;
+
+    fn source(text: &str) -> Source {
+        Source {
+            path: PathBuf::from("app.toml"),
+            lines: text.lines().map(str::to_owned).collect(),
+        }
+    }
+
+    #[test]
+    fn cycles() {
+        let calls: BTreeMap<&str, Vec<&str>> = [
+            ("c", vec!["a"]),
+            ("a", vec!["b", "d"]),
+            ("b", vec!["c"]),
+            ("d", vec!["e"]),
+            ("e", vec!["d"]),
+            ("f", vec!["a"]),
+        ]
+        .into_iter()
+        .collect();
+        assert_eq!(find_cycles(&calls), [vec!["a", "b", "c"], vec!["d", "e"]]);
+
+        let calls: BTreeMap<&str, Vec<&str>> =
+            [("a", vec!["b", "c"]), ("b", vec!["c"])]
+                .into_iter()
+                .collect();
+        assert!(find_cycles(&calls).is_empty());
+    }
+
+    #[test]
+    fn clean() {
+        assert!(problems(IDLE, true).is_empty());
+    }
+
+    #[test]
+    fn priorities() {
+        let found = problems(
+            &format!(
+                r#"{}
+[tasks.c]
+name = "task-c"
+priority = 0
+
+[tasks.d]
+name = "task-d"
+priority = 3
+"#,
+                IDLE
+            ),
+            false,
+        );
+        assert_eq!(found.len(), 2);
+        assert!(found[0].1.contains("`c` is not the supervisor"));
+        assert!(found[1].1.contains("`d` has priority 3"));
+        assert_eq!(found[0].2.as_deref(), Some("priority = 0"));
+        assert_eq!(found[1].2.as_deref(), Some("priority = 3"));
+
+        let found = problems("", false);
+        assert_eq!(found.len(), 1);
+        assert!(found[0].1.contains("no task named `idle`"));
+    }
+
+    #[test]
+    fn inversions() {
+        // `c` calls into `b`, which is at the same priority.
+        let extra = format!(
+            r#"{}
+[tasks.c]
+name = "task-c"
+priority = 1
+task-slots = ["b"]
+"#,
+            IDLE
+        );
+        let warned = problems(&extra, false);
+        assert_eq!(warned.len(), 1);
+        assert_eq!(warned[0].0, Severity::Warning);
+        assert!(warned[0].1.starts_with("priority inversion"));
+
+        let denied = problems(&extra, true);
+        assert_eq!(denied.len(), 1);
+        assert_eq!(denied[0].0, Severity::Error);
+        assert_eq!(denied[0].2.as_deref(), Some(r#"task-slots = ["b"]"#));
+    }
+
+    #[test]
+    fn slot_cycle() {
+        let found = problems(
+            &format!(
+                r#"{}
+[tasks.c]
+name = "task-c"
+priority = 1
+task-slots = ["d"]
+
+[tasks.d]
+name = "task-d"
+priority = 2
+task-slots = ["c"]
+"#,
+                IDLE
+            ),
+            true,
+        );
+        assert!(found
+            .iter()
+            .any(|p| p.1 == "task slots form a cycle: `c` -> `d` -> `c`"));
+    }
+
+    #[test]
+    fn interrupts() {
+        let found = problems(
+            &format!(
+                r#"{}
+[tasks.a.interrupts]
+"i2c1.event" = 0b1
+"i2c1.error" = 0b1
+"spi1.irq" = 0b1
+"spi2.irq" = 0b110
+"spi3" = 0b1000
+"spi3.nope" = 0b1000
+
+[tasks.b.interrupts]
+"35" = 0b1
+"#,
+                IDLE
+            ),
+            false,
+        );
+        let messages: Vec<_> = found.iter().map(|p| &p.1).collect();
+        assert_eq!(
+            messages,
+            [
+                "task `a` interrupts `i2c1.event` and `spi1.irq` both post \
+                 notification 0b1",
+                "task `a` interrupt `spi2.irq`: notification mask (0b110) \
+                 has 2 bits set (expected exactly one)",
+                "task `a` has interrupt `spi3`, which is neither an IRQ \
+                 number nor of the form `peripheral.interrupt`",
+                "task `a` has interrupt `spi3.nope`, which isn't an \
+                 interrupt of the chip",
+                "IRQ 35 (`35` in task `b`) is already assigned by \
+                 `spi1.irq` in task `a`",
+            ]
+        );
+        assert_eq!(found[0].2.as_deref(), Some(r#""spi1.irq" = 0b1"#));
+    }
+
+    #[test]
+    fn find_whole_tokens() {
+        let source = source(
+            r#"[tasks.network]
+task-slots = ["net"]
+
+[tasks.net]
+priority = 3
+task-slots = ["network", "net"]
+
+[kernel]
+requires = {sram = 1, ram = 2}
+"#,
+        );
+        let (precision, span) = source
+            .find(&["tasks", "net"], Some("task-slots"), Some("\"net\""))
+            .unwrap();
+        assert_eq!(precision, Precision::Needle);
+        assert_eq!((span.line, span.col, span.len), (6, 26, 5));
+
+        let (_, span) = source
+            .find(&["kernel"], Some("requires"), Some("ram"))
+            .unwrap();
+        assert_eq!((span.line, span.col, span.len), (9, 23, 3));
+
+        // A needle that only appears inside a longer name falls back to the
+        // key, and a missing key to the table's header.
+        let (precision, span) = source
+            .find(&["tasks", "net"], Some("task-slots"), Some("work"))
+            .unwrap();
+        assert_eq!(precision, Precision::Key);
+        assert_eq!((span.line, span.col, span.len), (6, 1, 10));
+        let (precision, span) = source
+            .find(&["tasks", "net"], Some("uses"), Some("\"net\""))
+            .unwrap();
+        assert_eq!(precision, Precision::Table);
+        assert_eq!(span.line, 4);
+        assert!(source.find(&["tasks", "b"], None, None).is_none());
+    }
+
+    #[test]
+    fn find_in_sub_table() {
+        let source = source(
+            r#"[tasks.a]
+priority = 1
+
+[tasks.a.interrupts]
+"spi1.irq" = 1
+"#,
+        );
+        let (precision, span) = source
+            .find(&["tasks", "a"], Some("interrupts"), Some("\"spi1.irq\""))
+            .unwrap();
+        assert_eq!(precision, Precision::Needle);
+        assert_eq!((span.line, span.col), (5, 1));
+    }
+
+    #[test]
+    fn columns_are_bytes() {
+        let source = source("[kernel]\nrequires = {\"\u{e9}\" = 1, ram = 2}\n");
+        let (_, span) = source
+            .find(&["kernel"], Some("requires"), Some("ram"))
+            .unwrap();
+        // `é` is two bytes, so this is one more than its column in
+        // characters.
+        assert_eq!(span.col, 23);
+    }
+}
@@ -92,14 +92,16 @@ pub fn run(
             )?;
             let kconfig = ron::ser::to_string(&kconfig)?;
 
-            toml.kernel_build_config(
-                verbose,
-                &[
-                    ("HUBRIS_KCONFIG", &kconfig),
-                    ("HUBRIS_IMAGE_ID", "1234"), // dummy image ID
-                ],
-                None,
-            )
+            let pubkey = crate::image_signing::stage0_public_key(&toml)?;
+            let mut env = vec![
+                ("HUBRIS_KCONFIG", kconfig.as_str()),
+                ("HUBRIS_IMAGE_ID", "1234"), // dummy image ID
+            ];
+            if let Some(pubkey) = &pubkey {
+                env.push(("HUBRIS_IMAGE_PUBKEY", pubkey));
+            }
+
+            toml.kernel_build_config(verbose, &env, None)
         } else {
             toml.task_build_config(name, verbose, None).unwrap()
         };
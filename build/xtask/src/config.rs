@@ -2,7 +2,7 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use std::collections::{hash_map::DefaultHasher, BTreeMap};
+use std::collections::{hash_map::DefaultHasher, BTreeMap, BTreeSet};
 use std::hash::Hasher;
 use std::ops::Range;
 use std::path::{Path, PathBuf};
@@ -34,6 +34,8 @@ struct RawConfig {
     external_images: Vec<String>,
     #[serde(default)]
     signing: Option<Signing>,
+    #[serde(default)]
+    image_signing: Option<ImageSigning>,
     secure_separation: Option<bool>,
     stacksize: Option<u32>,
     kernel: Kernel,
@@ -41,6 +43,8 @@ struct RawConfig {
     #[serde(default)]
     extratext: IndexMap<String, Peripheral>,
     #[serde(default)]
+    shared_memory: IndexMap<String, SharedMemory>,
+    #[serde(default)]
     config: Option<ordered_toml::Value>,
     #[serde(default)]
     secure_task: Option<String>,
@@ -58,6 +62,7 @@ pub struct Config {
     pub image_names: Vec<String>,
     pub external_images: Vec<String>,
     pub signing: Option<Signing>,
+    pub image_signing: Option<ImageSigning>,
     pub secure_separation: Option<bool>,
     pub stacksize: Option<u32>,
     pub kernel: Kernel,
@@ -65,23 +70,70 @@ pub struct Config {
     pub tasks: IndexMap<String, Task>,
     pub peripherals: IndexMap<String, Peripheral>,
     pub extratext: IndexMap<String, Peripheral>,
+    pub shared_memory: IndexMap<String, SharedMemory>,
     pub config: Option<ordered_toml::Value>,
     pub buildhash: u64,
     pub app_toml_path: PathBuf,
+    /// The files `app_toml_path` inherits from, nearest first.
+    pub inherited: Vec<PathBuf>,
     pub secure_task: Option<String>,
     pub auxflash: Option<AuxFlashData>,
 }
 
 impl Config {
     pub fn from_file(cfg: &Path) -> Result<Self> {
-        let cfg_contents = std::fs::read(&cfg)?;
-        let toml: RawConfig = toml::from_slice(&cfg_contents)?;
+        let resolved = ResolvedToml::load(cfg)?;
+        let toml: RawConfig = resolved
+            .value
+            .clone()
+            .try_into()
+            .with_context(|| format!("parsing {}", cfg.display()))?;
         if toml.tasks.contains_key("kernel") {
             bail!("'kernel' is reserved and cannot be used as a task name");
         }
 
+        for (name, shared) in &toml.shared_memory {
+            // Tasks find the region through symbols named after it.
+            if !name.chars().all(|c| c.is_ascii_alphanumeric() || c == '_') {
+                bail!(
+                    "shared memory name '{}' must contain only letters, \
+                     digits, and underscores",
+                    name
+                );
+            }
+            for task in shared.access.keys() {
+                if !toml.tasks.contains_key(task) {
+                    bail!(
+                        "shared memory '{}' grants access to unknown task '{}'",
+                        name,
+                        task
+                    );
+                }
+            }
+        }
+
+        for (name, task) in &toml.tasks {
+            for peer in &task.ipc_grants {
+                if !toml.tasks.contains_key(peer) {
+                    bail!(
+                        "task '{}' has an IPC grant for unknown task '{}'",
+                        name,
+                        peer
+                    );
+                }
+            }
+            for peer in &task.posts {
+                if !toml.tasks.contains_key(peer) {
+                    bail!("task '{}' posts to unknown task '{}'", name, peer);
+                }
+            }
+        }
+
+        // Every file in the inheritance chain contributes to the build.
         let mut hasher = DefaultHasher::new();
-        hasher.write(&cfg_contents);
+        for (_, contents) in &resolved.files {
+            hasher.write(contents);
+        }
 
         // The app.toml must include a `chip` key, which defines the peripheral
         // register map in a separate file.  We load it then accumulate that
@@ -124,7 +176,7 @@ impl Config {
             None => None,
         };
 
-        Ok(Config {
+        let config = Config {
             name: toml.name,
             target: toml.target,
             board: toml.board,
@@ -134,6 +186,7 @@ impl Config {
             epoch: toml.epoch,
             version: toml.version,
             signing: toml.signing,
+            image_signing: toml.image_signing,
             secure_separation: toml.secure_separation,
             stacksize: toml.stacksize,
             kernel: toml.kernel,
@@ -141,12 +194,23 @@ impl Config {
             tasks: toml.tasks,
             peripherals,
             extratext: toml.extratext,
+            shared_memory: toml.shared_memory,
             config: toml.config,
             auxflash,
             buildhash,
             app_toml_path: cfg.to_owned(),
+            inherited: resolved
+                .files
+                .into_iter()
+                .skip(1)
+                .map(|(path, _)| path)
+                .collect(),
             secure_task: toml.secure_task,
-        })
+        };
+        if config.kernel.ipc_allowlist {
+            config.check_configured_posts()?;
+        }
+        Ok(config)
     }
 
     pub fn task_name_suggestion(&self, name: &str) -> String {
@@ -519,13 +583,24 @@ impl Config {
             .map(|(name, _out)| name.as_str())
     }
 
+    /// Checks whether the kernel runs in RISC-V Supervisor mode, isolating
+    /// tasks with page tables rather than the PMP.
+    pub fn riscv_s_mode(&self) -> bool {
+        self.target.starts_with("riscv")
+            && self.kernel.features.iter().any(|f| f == "s-mode")
+    }
+
     fn mpu_alignment(&self) -> MpuAlignment {
         // ARMv6-M and ARMv7-M require that memory regions be a power of two.
-        // ARMv8-M and RISC-V does not.
+        // ARMv8-M and RISC-V does not, though RISC-V in Supervisor mode uses
+        // page tables and so needs whole pages.
+        if self.riscv_s_mode() {
+            return MpuAlignment::Chunk(4096);
+        }
         match self.target.as_str() {
-            "riscv32imc-unknown-none-elf" | "riscv32imac-unknown-none-elf" => {
-                MpuAlignment::Chunk(16)
-            }
+            "riscv32imc-unknown-none-elf"
+            | "riscv32imac-unknown-none-elf"
+            | "riscv64imac-unknown-none-elf" => MpuAlignment::Chunk(16),
             "thumbv8m.main-none-eabihf" => MpuAlignment::Chunk(32),
             "thumbv7em-none-eabihf" | "thumbv6m-none-eabi" => {
                 MpuAlignment::PowerOfTwo
@@ -566,6 +641,215 @@ impl Config {
         self.tasks[name].uses_secure_entry
             || self.secure_task.as_ref().map_or(false, |n| n == name)
     }
+
+    /// Returns the shared memory regions that the given task may access, and
+    /// how it may access them.
+    pub fn task_shared_memory<'a>(
+        &'a self,
+        task: &'a str,
+    ) -> impl Iterator<Item = (&'a str, SharedAccess)> + 'a {
+        self.shared_memory.iter().filter_map(move |(name, shared)| {
+            shared
+                .access
+                .get(task)
+                .map(|&access| (name.as_str(), access))
+        })
+    }
+
+    /// Returns the indices of the tasks that the given task may send to or
+    /// post to under an IPC allowlist: those named by its task slots or its
+    /// IPC grants.
+    pub fn task_ipc_allowlist(&self, task: &str) -> Result<BTreeSet<usize>> {
+        let task = &self.tasks[task];
+        task.task_slots
+            .values()
+            .chain(&task.ipc_grants)
+            .map(|peer| {
+                self.tasks
+                    .get_index_of(peer)
+                    .ok_or_else(|| anyhow!("Invalid task-slot: {}", peer))
+            })
+            .collect()
+    }
+
+    /// Checks that the IPC allowlist permits every notification that tasks
+    /// declare in their `posts`, since the kernel would fault the poster.
+    fn check_configured_posts(&self) -> Result<()> {
+        let mut denied = vec![];
+        for (poster, task) in &self.tasks {
+            let allowed = self.task_ipc_allowlist(poster)?;
+            for target in &task.posts {
+                match self.tasks.get_index_of(target) {
+                    Some(i) if allowed.contains(&i) => (),
+                    _ => {
+                        denied.push(format!("  {} posts to {}", poster, target))
+                    }
+                }
+            }
+        }
+        if !denied.is_empty() {
+            bail!(
+                "the IPC allowlist doesn't permit these configured \
+                 notifications; add the targets to the posting tasks' \
+                 `ipc-grants`:\n{}",
+                denied.join("\n")
+            );
+        }
+        Ok(())
+    }
+
+    /// Suggests a size for a shared memory region, which is subject to the
+    /// same MPU constraints as a task's memory.
+    pub fn suggest_shared_memory_size(&self, size: u32) -> u32 {
+        self.mpu_alignment()
+            .suggest_memory_region_size(size.into())
+            .try_into()
+            .unwrap()
+    }
+}
+
+/// An `app.toml` with its `inherit` chain merged into a single document.
+///
+/// A file may name another to build on with `inherit = "path"`, relative to
+/// the inheriting file. The inherited file is loaded first (following its
+/// own `inherit`, if any), and then the inheriting file is merged on top:
+///
+/// - Tables are merged key by key, recursively, so a file can override a
+///   single field of an inherited task, or add a task. Inherited keys keep
+///   their order and new keys follow them, so the supervisor stays first.
+/// - Any other value, including an array, replaces the inherited one.
+/// - To edit an inherited array rather than replace it, write a table with
+///   `remove` and/or `add` arrays in its place, e.g.
+///   `features = {remove = ["h743"], add = ["h753"]}`. Matching entries are
+///   removed, and then added entries are appended unless already present.
+///
+/// Since tables always merge, a file can't take anything away from the file
+/// it inherits from other than array entries: there's no way to drop an
+/// inherited task, or to replace an inherited table (such as a task's
+/// `config`) with a different one rather than merging into it. Settings
+/// that only some configs want belong in those configs, not in the base.
+///
+/// Paths within the merged config, such as `chip`, are relative to the file
+/// being built, wherever they were written.
+pub struct ResolvedToml {
+    pub value: ordered_toml::Value,
+    /// Each file in the chain and its contents, starting with the file that
+    /// was loaded and ending with the base of the chain.
+    pub files: Vec<(PathBuf, Vec<u8>)>,
+}
+
+impl ResolvedToml {
+    pub fn load(cfg: &Path) -> Result<Self> {
+        let mut files = vec![];
+        let mut layers = vec![];
+        let mut seen = BTreeSet::new();
+        let mut next = Some(cfg.to_owned());
+
+        while let Some(path) = next.take() {
+            let canonical = path
+                .canonicalize()
+                .with_context(|| format!("reading {}", path.display()))?;
+            if !seen.insert(canonical) {
+                bail!("{} inherits from itself", path.display());
+            }
+            let contents = std::fs::read(&path)
+                .with_context(|| format!("reading {}", path.display()))?;
+            let value: ordered_toml::Value =
+                ordered_toml::from_slice(&contents)
+                    .with_context(|| format!("parsing {}", path.display()))?;
+            let table = match value {
+                ordered_toml::Value::Table(table) => table,
+                _ => bail!("{} is not a table", path.display()),
+            };
+
+            let mut layer = ordered_toml::value::Table::new();
+            for (key, value) in table {
+                if key != "inherit" {
+                    layer.insert(key, value);
+                    continue;
+                }
+                match value.as_str() {
+                    Some(base) => {
+                        next = Some(path.parent().unwrap().join(base));
+                    }
+                    None => bail!(
+                        "{}: `inherit` must be a path to another file",
+                        path.display()
+                    ),
+                }
+            }
+            layers.push(ordered_toml::Value::Table(layer));
+            files.push((path, contents));
+        }
+
+        let mut layers = layers.into_iter().rev();
+        let mut value = layers.next().unwrap();
+        for layer in layers {
+            merge_toml(&mut value, layer, "")?;
+        }
+        Ok(ResolvedToml { value, files })
+    }
+
+    /// Renders the merged config as a standalone `app.toml`.
+    pub fn to_toml_string(&self) -> Result<String> {
+        Ok(ordered_toml::to_string(&self.value)?)
+    }
+}
+
+/// Merges `layer` on top of `base`, following the rules in [`ResolvedToml`].
+/// `key` is the dotted path to the values being merged, for error messages.
+fn merge_toml(
+    base: &mut ordered_toml::Value,
+    layer: ordered_toml::Value,
+    key: &str,
+) -> Result<()> {
+    use ordered_toml::Value;
+
+    match (base, layer) {
+        (Value::Table(base), Value::Table(layer)) => {
+            for (k, v) in layer {
+                let path = if key.is_empty() {
+                    k.clone()
+                } else {
+                    format!("{}.{}", key, k)
+                };
+                match base.get_mut(&k) {
+                    Some(b) => merge_toml(b, v, &path)?,
+                    None => {
+                        base.insert(k, v);
+                    }
+                }
+            }
+        }
+        (Value::Array(base), Value::Table(edit)) => {
+            let mut remove = vec![];
+            let mut add = vec![];
+            for (k, v) in edit {
+                let list = match k.as_str() {
+                    "remove" => &mut remove,
+                    "add" => &mut add,
+                    _ => bail!(
+                        "`{}` edits an inherited array, so it can only \
+                         have `add` and `remove` keys, not `{}`",
+                        key,
+                        k
+                    ),
+                };
+                match v {
+                    Value::Array(v) => *list = v,
+                    _ => bail!("`{}.{}` must be an array", key, k),
+                }
+            }
+            base.retain(|v| !remove.contains(v));
+            for v in add {
+                if !base.contains(&v) {
+                    base.push(v);
+                }
+            }
+        }
+        (base, layer) => *base = layer,
+    }
+    Ok(())
 }
 
 /// Represents an MPU's desired alignment strategy
@@ -631,6 +915,19 @@ pub struct Signing {
     pub dice_inc_sec_epoch: bool,
 }
 
+/// Keys for the signature block appended to each image, which stage0 checks
+/// before booting it. Either ECDSA P-256 or Ed25519 keys may be used; the
+/// algorithm follows from the key. Paths are relative to the `app.toml`.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct ImageSigning {
+    /// Private key (PKCS#8, DER) used to sign each image built.
+    pub private_key: Option<PathBuf>,
+    /// Public key (SubjectPublicKeyInfo, DER) built into stage0, which then
+    /// only boots images signed by the matching private key.
+    pub public_key: Option<PathBuf>,
+}
+
 #[derive(Clone, Debug, Deserialize)]
 #[serde(rename_all = "kebab-case", deny_unknown_fields)]
 pub struct Kernel {
@@ -639,6 +936,10 @@ pub struct Kernel {
     pub stacksize: Option<u32>,
     #[serde(default)]
     pub features: Vec<String>,
+    /// Restrict each task to sending and posting to the tasks in its
+    /// `task-slots` and `ipc-grants`, with the kernel faulting any others.
+    #[serde(default)]
+    pub ipc_allowlist: bool,
 }
 
 fn default_name() -> String {
@@ -682,10 +983,22 @@ pub struct Task {
     pub sections: IndexMap<String, String>,
     #[serde(default, deserialize_with = "deserialize_task_slot")]
     pub task_slots: IndexMap<String, String>,
+    /// Tasks this task may send to or post to without having a task slot for
+    /// them, when the kernel enforces an IPC allowlist.
+    #[serde(default)]
+    pub ipc_grants: Vec<String>,
+    /// Tasks this task posts notifications to, as set up by its own
+    /// configuration; checked against the IPC allowlist when there is one.
+    #[serde(default)]
+    pub posts: Vec<String>,
     #[serde(default)]
     pub config: Option<ordered_toml::Value>,
     #[serde(default)]
     pub uses_secure_entry: bool,
+    /// Allows this task to read the registers and memory of faulted tasks
+    /// through the kernel, as a dump agent does.
+    #[serde(default)]
+    pub dump_access: bool,
 }
 
 #[derive(Clone, Debug, Serialize, Deserialize)]
@@ -697,6 +1010,38 @@ pub struct Peripheral {
     pub interrupts: BTreeMap<String, u32>,
 }
 
+/// A named region of RAM shared between tasks, declared in `app.toml` as
+/// ```toml
+/// [shared-memory.packets]
+/// size = 2048
+/// access = {net = "rw", udpecho = "r"}
+/// ```
+/// Tasks not listed in `access` can't see the region at all.
+#[derive(Clone, Debug, Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+pub struct SharedMemory {
+    pub size: u32,
+    pub access: IndexMap<String, SharedAccess>,
+}
+
+#[derive(Copy, Clone, Debug, PartialEq, Eq, PartialOrd, Ord, Deserialize)]
+pub enum SharedAccess {
+    #[serde(rename = "r")]
+    Read,
+    #[serde(rename = "rw")]
+    ReadWrite,
+}
+
+impl std::fmt::Display for SharedAccess {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let s = match self {
+            Self::Read => "r",
+            Self::ReadWrite => "rw",
+        };
+        write!(f, "{}", s)
+    }
+}
+
 /// In the common case, task slots map back to a task of the same name (e.g.
 /// `gpio_driver`, `rcc_driver`).  However, certain tasks need generic task
 /// slot names, e.g. they'll have a task slot named `spi_driver` which will
@@ -815,3 +1160,349 @@ impl BuildConfig<'_> {
         cmd
     }
 }
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use std::sync::atomic::{AtomicUsize, Ordering};
+
+    /// Makes a fresh directory for a test's files.
+    fn temp_dir() -> Result<PathBuf> {
+        static COUNT: AtomicUsize = AtomicUsize::new(0);
+        let dir = std::env::temp_dir().join(format!(
+            "xtask-test-{}-{}",
+            std::process::id(),
+            COUNT.fetch_add(1, Ordering::Relaxed)
+        ));
+        std::fs::create_dir_all(&dir)?;
+        Ok(dir)
+    }
+
+    /// Loads an `app.toml` made of `extra` appended to a skeleton app with a
+    /// kernel and two tasks, `a` and `b`, using the chip directory `chip`.
+    pub(crate) fn load(
+        target: &str,
+        chip: &str,
+        extra: &str,
+    ) -> Result<Config> {
+        load_with(target, chip, extra, |config| config)?
+    }
+
+    /// Like `load`, but passes the result to `f` while the `app.toml` is
+    /// still on disk.
+    pub(crate) fn load_with<R>(
+        target: &str,
+        chip: &str,
+        extra: &str,
+        f: impl FnOnce(Result<Config>) -> R,
+    ) -> Result<R> {
+        let dir = temp_dir()?;
+        let chips = Path::new(env!("CARGO_MANIFEST_DIR")).join("../../chips");
+        let app = format!(
+            r#"
+name = "test"
+target = "{}"
+board = "test"
+chip = "{}"
+stacksize = 1024
+
+[kernel]
+name = "kernel"
+requires = {{flash = 1024, ram = 1024}}
+
+[tasks.a]
+name = "task-a"
+priority = 0
+start = true
+
+[tasks.b]
+name = "task-b"
+priority = 1
+start = true
+
+{}
+"#,
+            target,
+            chips.join(chip).display(),
+            extra
+        );
+        let path = dir.join("app.toml");
+        std::fs::write(&path, app)?;
+        let result = f(Config::from_file(&path));
+        std::fs::remove_dir_all(&dir)?;
+        Ok(result)
+    }
+
+    #[test]
+    fn shared_memory_access() {
+        let toml = load(
+            "thumbv7em-none-eabihf",
+            "stm32h7",
+            r#"
+[shared-memory.buf]
+size = 1000
+access = {a = "rw", b = "r"}
+"#,
+        )
+        .unwrap();
+        assert_eq!(
+            toml.task_shared_memory("a").collect::<Vec<_>>(),
+            [("buf", SharedAccess::ReadWrite)]
+        );
+        assert_eq!(
+            toml.task_shared_memory("b").collect::<Vec<_>>(),
+            [("buf", SharedAccess::Read)]
+        );
+    }
+
+    #[test]
+    fn shared_memory_unknown_task() {
+        let e = load(
+            "thumbv7em-none-eabihf",
+            "stm32h7",
+            r#"
+[shared-memory.buf]
+size = 1000
+access = {c = "rw"}
+"#,
+        )
+        .unwrap_err();
+        assert!(e.to_string().contains("unknown task 'c'"), "{}", e);
+    }
+
+    #[test]
+    fn shared_memory_bad_name() {
+        let e = load(
+            "thumbv7em-none-eabihf",
+            "stm32h7",
+            r#"
+[shared-memory.my-buf]
+size = 1000
+access = {a = "rw"}
+"#,
+        )
+        .unwrap_err();
+        assert!(e.to_string().contains("'my-buf'"), "{}", e);
+    }
+
+    #[test]
+    fn configured_posts_need_grants() {
+        let mut toml = load(
+            "thumbv7em-none-eabihf",
+            "stm32h7",
+            r#"
+[tasks.jefe]
+name = "task-jefe"
+priority = 0
+start = true
+ipc-grants = ["a"]
+posts = ["a", "b"]
+
+[tasks.net]
+name = "task-net"
+priority = 1
+start = true
+task-slots = ["b"]
+posts = ["a", "b"]
+"#,
+        )
+        .unwrap();
+        // `load` only checks these when the allowlist is on.
+        toml.kernel.ipc_allowlist = true;
+        let e = toml.check_configured_posts().unwrap_err().to_string();
+        assert!(e.contains("jefe posts to b"), "{}", e);
+        assert!(e.contains("net posts to a"), "{}", e);
+        assert!(!e.contains("jefe posts to a"), "{}", e);
+        assert!(!e.contains("net posts to b"), "{}", e);
+
+        toml.tasks["jefe"].ipc_grants.push("b".to_string());
+        toml.tasks["net"].ipc_grants.push("a".to_string());
+        toml.check_configured_posts().unwrap();
+    }
+
+    /// Writes `files` (each a path relative to a fresh directory, and its
+    /// contents) and resolves the first of them.
+    fn resolve(files: &[(&str, &str)]) -> Result<ResolvedToml> {
+        let dir = temp_dir()?;
+        for (name, contents) in files {
+            let path = dir.join(name);
+            std::fs::create_dir_all(path.parent().unwrap())?;
+            std::fs::write(path, contents)?;
+        }
+        let result = ResolvedToml::load(&dir.join(files[0].0));
+        std::fs::remove_dir_all(&dir)?;
+        result
+    }
+
+    const BASE: &str = r#"
+name = "base"
+board = "base-board"
+
+[tasks.a]
+name = "task-a"
+priority = 0
+features = ["h743", "itm"]
+config = {rate = 1, depth = 2}
+
+[tasks.b]
+name = "task-b"
+priority = 1
+"#;
+
+    fn keys(value: &ordered_toml::Value) -> Vec<&str> {
+        value
+            .as_table()
+            .unwrap()
+            .keys()
+            .map(String::as_str)
+            .collect()
+    }
+
+    #[test]
+    fn inherit_merges_tables() {
+        let resolved = resolve(&[
+            (
+                "app.toml",
+                r#"
+inherit = "base.toml"
+name = "child"
+
+[tasks.a]
+priority = 2
+config = {rate = 3}
+
+[tasks.c]
+name = "task-c"
+priority = 3
+"#,
+            ),
+            ("base.toml", BASE),
+        ])
+        .unwrap();
+        let value = &resolved.value;
+        assert_eq!(value["name"].as_str(), Some("child"));
+        assert_eq!(value["board"].as_str(), Some("base-board"));
+        assert_eq!(keys(&value["tasks"]), ["a", "b", "c"]);
+
+        let a = &value["tasks"]["a"];
+        assert_eq!(a["name"].as_str(), Some("task-a"));
+        assert_eq!(a["priority"].as_integer(), Some(2));
+        assert_eq!(a["config"]["rate"].as_integer(), Some(3));
+        assert_eq!(a["config"]["depth"].as_integer(), Some(2));
+        assert!(!keys(value).contains(&"inherit"));
+    }
+
+    #[test]
+    fn inherit_edits_arrays() {
+        let features = |edit: &str| {
+            let child =
+                format!("inherit = \"base.toml\"\n[tasks.a]\n{}\n", edit);
+            resolve(&[("app.toml", &child), ("base.toml", BASE)]).map(|r| {
+                r.value["tasks"]["a"]["features"]
+                    .as_array()
+                    .unwrap()
+                    .iter()
+                    .map(|f| f.as_str().unwrap().to_owned())
+                    .collect::<Vec<_>>()
+            })
+        };
+
+        assert_eq!(features("features = [\"h753\"]").unwrap(), ["h753"]);
+        assert_eq!(
+            features("features = {remove = [\"h743\"], add = [\"h753\"]}")
+                .unwrap(),
+            ["itm", "h753"]
+        );
+        assert_eq!(
+            features("features = {add = [\"itm\", \"h753\"]}").unwrap(),
+            ["h743", "itm", "h753"]
+        );
+        assert_eq!(
+            features("features = {remove = [\"itm\", \"nope\"]}").unwrap(),
+            ["h743"]
+        );
+
+        let e = features("features = {replace = [\"h753\"]}").unwrap_err();
+        assert!(e.to_string().contains("not `replace`"), "{}", e);
+        let e = features("features = {add = \"h753\"}").unwrap_err();
+        assert!(
+            e.to_string()
+                .contains("`tasks.a.features.add` must be an array"),
+            "{}",
+            e
+        );
+    }
+
+    #[test]
+    fn inherit_cannot_remove() {
+        // An empty table merges into an inherited one rather than replacing
+        // it, so neither the task nor its config go away.
+        let resolved = resolve(&[
+            (
+                "app.toml",
+                "inherit = \"base.toml\"\n[tasks.b]\n[tasks.a.config]\n",
+            ),
+            ("base.toml", BASE),
+        ])
+        .unwrap();
+        assert_eq!(keys(&resolved.value["tasks"]), ["a", "b"]);
+        assert_eq!(
+            resolved.value["tasks"]["b"]["name"].as_str(),
+            Some("task-b")
+        );
+        assert_eq!(
+            keys(&resolved.value["tasks"]["a"]["config"]),
+            ["rate", "depth"]
+        );
+    }
+
+    #[test]
+    fn inherit_cycles() {
+        let e = resolve(&[("app.toml", "inherit = \"app.toml\"\n")])
+            .err()
+            .unwrap();
+        assert!(e.to_string().contains("inherits from itself"), "{}", e);
+
+        let e = resolve(&[
+            ("a.toml", "inherit = \"b.toml\"\n"),
+            ("b.toml", "inherit = \"c.toml\"\n"),
+            ("c.toml", "inherit = \"./a.toml\"\n"),
+        ])
+        .err()
+        .unwrap();
+        assert!(e.to_string().contains("inherits from itself"), "{}", e);
+
+        let e = resolve(&[("app.toml", "inherit = 1\n")]).err().unwrap();
+        assert!(e.to_string().contains("must be a path"), "{}", e);
+        let e = resolve(&[("app.toml", "inherit = \"missing.toml\"\n")])
+            .err()
+            .unwrap();
+        assert!(e.to_string().contains("missing.toml"), "{}", e);
+    }
+
+    #[test]
+    fn inherit_paths_are_relative() {
+        // Each `inherit` is relative to the file it's in.
+        let resolved = resolve(&[
+            (
+                "app/gimlet/app.toml",
+                "inherit = \"../../common/base.toml\"\nname = \"app\"\n",
+            ),
+            (
+                "common/base.toml",
+                "inherit = \"root.toml\"\nboard = \"b\"\n",
+            ),
+            ("common/root.toml", "name = \"root\"\nchip = \"c\"\n"),
+        ])
+        .unwrap();
+        assert_eq!(keys(&resolved.value), ["name", "chip", "board"]);
+        assert_eq!(resolved.value["name"].as_str(), Some("app"));
+
+        let files: Vec<_> = resolved
+            .files
+            .iter()
+            .map(|(path, _)| path.file_name().unwrap().to_str().unwrap())
+            .collect();
+        assert_eq!(files, ["app.toml", "base.toml", "root.toml"]);
+    }
+}
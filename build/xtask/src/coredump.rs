@@ -0,0 +1,435 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Converter from post-mortem task dumps to ELF core files.
+//!
+//! The input is the contents of the `dump` shared memory region written by
+//! `task-dump-agent` (see the `dump-format` crate for its layout), read off a
+//! target with a debugger. The output is an ELF core file holding the faulted
+//! task's registers and memory, which GDB can load along with the task's
+//! binary from the build archive.
+
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use dump_format::{DUMP_MAGIC, DUMP_VERSION, FLAG_TRUNCATED, HEADER_SIZE};
+
+use crate::Config;
+
+const EM_ARM: u16 = 40;
+const EM_RISCV: u16 = 243;
+
+const ET_CORE: u16 = 4;
+const PT_LOAD: u32 = 1;
+const PT_NOTE: u32 = 4;
+const PF_W: u32 = 2;
+const PF_R: u32 = 4;
+const NT_PRSTATUS: u32 = 1;
+
+const ELF_HEADER_SIZE: usize = 52;
+const PROGRAM_HEADER_SIZE: usize = 32;
+
+/// Size of the fields of a 32-bit Linux `elf_prstatus` that come before the
+/// registers. We only fill in the thread ID, which we make the task index
+/// plus one, as GDB treats zero as "no thread".
+const PRSTATUS_PREFIX_SIZE: usize = 72;
+const PRSTATUS_PID_OFFSET: usize = 24;
+
+struct Dump {
+    task: u32,
+    truncated: bool,
+    image_id: u64,
+    registers: Vec<u8>,
+    regions: Vec<(u32, Vec<u8>)>,
+}
+
+fn u32_at(buf: &[u8], offset: usize) -> Result<u32> {
+    match buf.get(offset..offset + 4) {
+        Some(bytes) => Ok(u32::from_le_bytes(bytes.try_into().unwrap())),
+        None => bail!("task dump is truncated at offset {:#x}", offset),
+    }
+}
+
+fn bytes_at(buf: &[u8], offset: usize, len: usize) -> Result<&[u8]> {
+    match buf.get(offset..offset + len) {
+        Some(bytes) => Ok(bytes),
+        None => bail!("task dump is truncated at offset {:#x}", offset),
+    }
+}
+
+fn align4(n: usize) -> usize {
+    (n + 3) & !3
+}
+
+fn parse(buf: &[u8]) -> Result<Dump> {
+    if buf.len() < HEADER_SIZE {
+        bail!("task dump is too short to hold a header");
+    }
+    let magic = u32_at(buf, 0)?;
+    if magic != DUMP_MAGIC {
+        bail!(
+            "bad task dump magic {:#010x}; has a task faulted since the \
+             dump area was cleared?",
+            magic
+        );
+    }
+    let version = u32_at(buf, 4)?;
+    if version != DUMP_VERSION {
+        bail!("unsupported task dump version {}", version);
+    }
+    let length = u32_at(buf, 8)? as usize;
+    let buf = match buf.get(..length) {
+        Some(buf) => buf,
+        None => bail!("task dump is truncated: expected {} bytes", length),
+    };
+    let task = u32_at(buf, 12)?;
+    let flags = u32_at(buf, 16)?;
+    let image_id =
+        u64::from(u32_at(buf, 20)?) | u64::from(u32_at(buf, 24)?) << 32;
+    let registers_len = u32_at(buf, 28)? as usize;
+    let region_count = u32_at(buf, 32)?;
+
+    let registers = bytes_at(buf, HEADER_SIZE, registers_len)?.to_vec();
+    let mut offset = align4(HEADER_SIZE + registers_len);
+    let mut regions = vec![];
+    for _ in 0..region_count {
+        let base = u32_at(buf, offset)?;
+        let size = u32_at(buf, offset + 4)? as usize;
+        regions.push((base, bytes_at(buf, offset + 8, size)?.to_vec()));
+        offset = align4(offset + 8 + size);
+    }
+
+    Ok(Dump {
+        task,
+        truncated: flags & FLAG_TRUNCATED != 0,
+        image_id,
+        registers,
+        regions,
+    })
+}
+
+impl Dump {
+    /// Reads a word of the task's memory, if it was dumped.
+    fn read_u32(&self, addr: u32) -> Option<u32> {
+        self.regions.iter().find_map(|(base, data)| {
+            let offset = addr.checked_sub(*base)? as usize;
+            let bytes = data.get(offset..offset + 4)?;
+            Some(u32::from_le_bytes(bytes.try_into().unwrap()))
+        })
+    }
+
+    fn saved_register(&self, n: usize) -> Result<u32> {
+        u32_at(&self.registers, n * 4)
+            .context("task dump doesn't hold all saved registers")
+    }
+
+    /// Builds the ARM `pr_reg` array: r0-r15, cpsr, and orig_r0.
+    ///
+    /// The kernel saves r4-r11, the stack pointer, and `EXC_RETURN`; the
+    /// rest were stacked by the hardware in an exception frame at the saved
+    /// stack pointer.
+    fn arm_registers(&self) -> Result<Vec<u32>> {
+        let psp = self.saved_register(8)?;
+        let exc_return = self.saved_register(9)?;
+        let frame = (0..8)
+            .map(|i| self.read_u32(psp + i * 4))
+            .collect::<Option<Vec<u32>>>()
+            .context("task's exception frame wasn't dumped")?;
+        let (r0_r3, r12, lr, pc, xpsr) =
+            (&frame[..4], frame[4], frame[5], frame[6], frame[7]);
+
+        // Work out the stack pointer before the exception: the frame is
+        // larger if it includes floating point state, and the hardware may
+        // have inserted a word of padding to align it.
+        let mut sp = psp + 0x20;
+        if exc_return & (1 << 4) == 0 {
+            sp += 0x48;
+        }
+        if xpsr & (1 << 9) != 0 {
+            sp += 4;
+        }
+
+        let mut regs = r0_r3.to_vec();
+        for n in 0..8 {
+            regs.push(self.saved_register(n)?);
+        }
+        regs.extend([r12, sp, lr, pc, xpsr, r0_r3[0]]);
+        Ok(regs)
+    }
+
+    /// Builds the RISC-V `pr_reg` array: pc, then x1-x31. The kernel saves
+    /// x1-x31 followed by the pc, so this is a rotation.
+    fn riscv_registers(&self) -> Result<Vec<u32>> {
+        let mut regs = vec![self.saved_register(31)?];
+        for n in 0..31 {
+            regs.push(self.saved_register(n)?);
+        }
+        Ok(regs)
+    }
+}
+
+/// Lays out a 32-bit little-endian ELF core file with one thread, whose
+/// registers are `regs`, and one loadable segment per dumped region.
+fn write_core(machine: u16, dump: &Dump, regs: &[u32]) -> Vec<u8> {
+    let mut prstatus = vec![0u8; PRSTATUS_PREFIX_SIZE];
+    prstatus[PRSTATUS_PID_OFFSET..PRSTATUS_PID_OFFSET + 4]
+        .copy_from_slice(&(dump.task + 1).to_le_bytes());
+    for reg in regs {
+        prstatus.extend(reg.to_le_bytes());
+    }
+    // pr_fpvalid
+    prstatus.extend(0u32.to_le_bytes());
+
+    let mut note = vec![];
+    note.extend(5u32.to_le_bytes());
+    note.extend((prstatus.len() as u32).to_le_bytes());
+    note.extend(NT_PRSTATUS.to_le_bytes());
+    note.extend(b"CORE\0\0\0\0");
+    note.extend(&prstatus);
+    note.resize(align4(note.len()), 0);
+
+    let phnum = 1 + dump.regions.len();
+    let mut offset = ELF_HEADER_SIZE + phnum * PROGRAM_HEADER_SIZE;
+
+    let mut out = vec![];
+    out.extend(b"\x7fELF");
+    // 32-bit, little-endian, version 1, System V ABI.
+    out.extend([1, 1, 1, 0]);
+    out.extend([0; 8]);
+    out.extend(ET_CORE.to_le_bytes());
+    out.extend(machine.to_le_bytes());
+    out.extend(1u32.to_le_bytes()); // e_version
+    out.extend(0u32.to_le_bytes()); // e_entry
+    out.extend((ELF_HEADER_SIZE as u32).to_le_bytes()); // e_phoff
+    out.extend(0u32.to_le_bytes()); // e_shoff
+    out.extend(0u32.to_le_bytes()); // e_flags
+    out.extend((ELF_HEADER_SIZE as u16).to_le_bytes());
+    out.extend((PROGRAM_HEADER_SIZE as u16).to_le_bytes());
+    out.extend((phnum as u16).to_le_bytes());
+    out.extend([0; 6]); // no section headers
+
+    let mut program_header =
+        |kind: u32, offset: usize, addr: u32, size: usize, flags: u32| {
+            let size = size as u32;
+            let memsz = if kind == PT_LOAD { size } else { 0 };
+            let words =
+                [kind, offset as u32, addr, addr, size, memsz, flags, 4];
+            for word in words {
+                out.extend(word.to_le_bytes());
+            }
+        };
+    program_header(PT_NOTE, offset, 0, note.len(), 0);
+    offset += note.len();
+    for (base, data) in &dump.regions {
+        program_header(PT_LOAD, offset, *base, data.len(), PF_R | PF_W);
+        offset += data.len();
+    }
+
+    out.extend(&note);
+    for (_, data) in &dump.regions {
+        out.extend(data);
+    }
+    out
+}
+
+pub fn run(cfg: &Path, dump: &Path, output: &Path) -> Result<()> {
+    let toml = Config::from_file(cfg)?;
+    let buf = std::fs::read(dump)
+        .with_context(|| format!("reading {}", dump.display()))?;
+    let dump = parse(&buf)?;
+
+    let task = match toml.tasks.get_index(dump.task as usize) {
+        Some((name, _)) => name,
+        None => bail!(
+            "task dump is of task #{}, which isn't in {}",
+            dump.task,
+            cfg.display()
+        ),
+    };
+    if dump.truncated {
+        println!(
+            "warning: the dump area was too small to hold all of {}'s memory",
+            task
+        );
+    }
+
+    let (machine, regs) = if toml.target.starts_with("thumb") {
+        (EM_ARM, dump.arm_registers()?)
+    } else if toml.target.starts_with("riscv32") {
+        (EM_RISCV, dump.riscv_registers()?)
+    } else {
+        bail!("core files for {} aren't supported", toml.target);
+    };
+
+    std::fs::write(output, write_core(machine, &dump, &regs))
+        .with_context(|| format!("writing {}", output.display()))?;
+
+    println!(
+        "wrote a core file for task {} (image ID {:#x}) to {}",
+        task,
+        dump.image_id,
+        output.display()
+    );
+    println!(
+        "load it with: gdb {} {}",
+        Path::new("target")
+            .join(&toml.name)
+            .join("dist")
+            .join(&toml.image_names[0])
+            .join(task)
+            .display(),
+        output.display()
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Lays out a dump the way `task-dump-agent` does.
+    fn encode(
+        task: u32,
+        flags: u32,
+        registers: &[u32],
+        regions: &[(u32, &[u8])],
+    ) -> Vec<u8> {
+        let mut buf = vec![0; HEADER_SIZE];
+        for reg in registers {
+            buf.extend(reg.to_le_bytes());
+        }
+        for (base, data) in regions {
+            buf.extend(base.to_le_bytes());
+            buf.extend((data.len() as u32).to_le_bytes());
+            buf.extend(*data);
+            buf.resize(align4(buf.len()), 0);
+        }
+        let image_id = 0x1122_3344_5566_7788u64;
+        let header = [
+            DUMP_MAGIC,
+            DUMP_VERSION,
+            buf.len() as u32,
+            task,
+            flags,
+            image_id as u32,
+            (image_id >> 32) as u32,
+            registers.len() as u32 * 4,
+            regions.len() as u32,
+        ];
+        for (i, word) in header.iter().enumerate() {
+            buf[i * 4..i * 4 + 4].copy_from_slice(&word.to_le_bytes());
+        }
+        buf
+    }
+
+    #[test]
+    fn round_trip() {
+        let buf = encode(3, 0, &[1, 2], &[(0x2000, b"abcde"), (0x3000, b"fg")]);
+        // Trailing garbage past the recorded length is ignored.
+        let mut padded = buf;
+        padded.extend([0xff; 16]);
+
+        let dump = parse(&padded).unwrap();
+        assert_eq!(dump.task, 3);
+        assert!(!dump.truncated);
+        assert_eq!(dump.image_id, 0x1122_3344_5566_7788);
+        assert_eq!(dump.registers, [1, 0, 0, 0, 2, 0, 0, 0]);
+        assert_eq!(
+            dump.regions,
+            [(0x2000, b"abcde".to_vec()), (0x3000, b"fg".to_vec())]
+        );
+    }
+
+    #[test]
+    fn truncated_flag() {
+        let buf = encode(0, FLAG_TRUNCATED, &[], &[]);
+        assert!(parse(&buf).unwrap().truncated);
+    }
+
+    #[test]
+    fn rejects_bad_header() {
+        let mut buf = encode(0, 0, &[], &[]);
+        buf[4] = 2;
+        assert!(parse(&buf).is_err());
+        buf[0] = 0;
+        assert!(parse(&buf).is_err());
+        assert!(parse(&buf[..HEADER_SIZE - 1]).is_err());
+    }
+
+    #[test]
+    fn rejects_short_dump() {
+        let buf = encode(0, 0, &[], &[(0x2000, &[0; 8])]);
+        assert!(parse(&buf[..buf.len() - 4]).is_err());
+    }
+
+    #[test]
+    fn arm_registers_from_exception_frame() {
+        let psp = 0x2000_0100;
+        // r4-r11, psp, and an EXC_RETURN for a frame without FP state.
+        let saved = [4, 5, 6, 7, 8, 9, 10, 11, psp, 0xffff_fffd];
+        // r0-r3, r12, lr, pc, and an xPSR with the alignment bit set.
+        let frame: Vec<u8> = [0, 1, 2, 3, 12, 0x800, 0x900, 1 << 9]
+            .iter()
+            .flat_map(|w: &u32| w.to_le_bytes())
+            .collect();
+        let buf = encode(0, 0, &saved, &[(psp, &frame)]);
+
+        let regs = parse(&buf).unwrap().arm_registers().unwrap();
+        assert_eq!(
+            regs,
+            [
+                0,
+                1,
+                2,
+                3,
+                4,
+                5,
+                6,
+                7,
+                8,
+                9,
+                10,
+                11,
+                12,
+                psp + 0x24,
+                0x800,
+                0x900,
+                1 << 9,
+                0
+            ]
+        );
+    }
+
+    #[test]
+    fn core_file_layout() {
+        let regs: Vec<u32> = (0..32).collect();
+        let buf = encode(2, 0, &regs, &[(0x8000_0000, b"memory!!")]);
+        let dump = parse(&buf).unwrap();
+        let core =
+            write_core(EM_RISCV, &dump, &dump.riscv_registers().unwrap());
+
+        let elf = goblin::elf::Elf::parse(&core).unwrap();
+        assert_eq!(elf.header.e_type, ET_CORE);
+        assert_eq!(elf.header.e_machine, EM_RISCV);
+        assert_eq!(elf.program_headers.len(), 2);
+
+        let note = &elf.program_headers[0];
+        assert_eq!(note.p_type, PT_NOTE);
+        let note = &core[note.file_range()];
+        let prstatus = &note[20..];
+        assert_eq!(
+            prstatus[PRSTATUS_PID_OFFSET..PRSTATUS_PID_OFFSET + 4],
+            3u32.to_le_bytes()
+        );
+        // The pc comes first, then x1-x31.
+        let pr_reg = &prstatus[PRSTATUS_PREFIX_SIZE..];
+        assert_eq!(pr_reg[..4], 31u32.to_le_bytes());
+        assert_eq!(pr_reg[4..8], 0u32.to_le_bytes());
+
+        let load = &elf.program_headers[1];
+        assert_eq!(load.p_type, PT_LOAD);
+        assert_eq!(load.p_vaddr, 0x8000_0000);
+        assert_eq!(&core[load.file_range()], b"memory!!");
+    }
+}
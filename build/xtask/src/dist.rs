@@ -18,7 +18,7 @@ use path_slash::PathBufExt;
 use zerocopy::AsBytes;
 
 use crate::{
-    config::{BuildConfig, Config},
+    config::{BuildConfig, Config, ResolvedToml, SharedAccess},
     elf,
     sizes::load_task_size,
     task_slot,
@@ -64,7 +64,7 @@ const ARM_CONSTS: ArchConsts<'static> = ArchConsts {
     tlink_script: "lds/arm/task-tlink.x",
 };
 
-const RISCV_CONSTS: ArchConsts<'static> = ArchConsts {
+const RV32_CONSTS: ArchConsts<'static> = ArchConsts {
     objcopy_cmd: "riscv64-unknown-elf-objcopy",
     objcopy_target: "elf32-littleriscv",
     link_script: "lds/rv32/task-link.x",
@@ -75,6 +75,15 @@ const RISCV_CONSTS: ArchConsts<'static> = ArchConsts {
     tlink_script: "lds/rv32/task-tlink.x",
 };
 
+const RV64_CONSTS: ArchConsts<'static> = ArchConsts {
+    objcopy_cmd: "riscv64-unknown-elf-objcopy",
+    objcopy_target: "elf64-littleriscv",
+    link_script: "lds/rv64/task-link.x",
+    kernel_link_script: "lds/rv64/kernel-link.x",
+    rlink_script: "lds/rv64/task-rlink.x",
+    tlink_script: "lds/rv64/task-tlink.x",
+};
+
 /// `PackageConfig` contains a bundle of data that's commonly used when
 /// building a full app image, grouped together to avoid passing a bunch
 /// of individual arguments to functions.
@@ -97,6 +106,9 @@ struct PackageConfig<'a> {
     /// Run `cargo tree --edges` before compiling, to show dependencies
     edges: bool,
 
+    /// Build tasks with `-Z emit-stack-sizes`, for `xtask stack`
+    stack_sizes: bool,
+
     /// Directory where the build artifacts are placed, in the form
     /// `target/$NAME/dist`.
     dist_dir: PathBuf,
@@ -124,7 +136,12 @@ struct PackageConfig<'a> {
 }
 
 impl PackageConfig<'_> {
-    fn new(app_toml_file: &Path, verbose: bool, edges: bool) -> Result<Self> {
+    fn new(
+        app_toml_file: &Path,
+        verbose: bool,
+        edges: bool,
+        stack_sizes: bool,
+    ) -> Result<Self> {
         let toml = Config::from_file(app_toml_file)?;
         let dist_dir = Path::new("target").join(&toml.name).join("dist");
         let app_src_dir = app_toml_file
@@ -166,7 +183,10 @@ impl PackageConfig<'_> {
 
         let arch_consts = match arch_target {
             ArchTarget::ARM => ARM_CONSTS,
-            ArchTarget::RISCV => RISCV_CONSTS,
+            ArchTarget::RISCV if toml.target.starts_with("riscv64") => {
+                RV64_CONSTS
+            }
+            ArchTarget::RISCV => RV32_CONSTS,
         };
 
         let mut extra_hash = fnv::FnvHasher::default();
@@ -185,6 +205,7 @@ impl PackageConfig<'_> {
             toml,
             verbose,
             edges,
+            stack_sizes,
             dist_dir,
             sysroot,
             host_triple,
@@ -273,11 +294,16 @@ type AllocationMap = (Allocations, IndexMap<String, Range<u32>>);
 pub fn package(
     verbose: bool,
     edges: bool,
+    stack_sizes: bool,
     app_toml: &Path,
     tasks_to_build: Option<Vec<String>>,
     dirty_ok: bool,
 ) -> Result<BTreeMap<String, AllocationMap>> {
-    let cfg = PackageConfig::new(app_toml, verbose, edges)?;
+    let cfg = PackageConfig::new(app_toml, verbose, edges, stack_sizes)?;
+
+    // Report everything that's structurally wrong with the config up front,
+    // rather than failing on the first problem partway through the build.
+    crate::check::check(&cfg.toml, false)?;
 
     // If we're using filters, we change behavior at the end. Record this in a
     // convenient flag, running other checks as well.
@@ -287,7 +313,6 @@ pub fn package(
             (true, task_names.iter().map(|p| p.as_str()).collect())
         } else {
             assert!(!cfg.toml.tasks.contains_key("kernel"));
-            check_task_priorities(&cfg.toml)?;
             (
                 false,
                 cfg.toml
@@ -419,6 +444,16 @@ pub fn package(
             );
         }
 
+        if let Some(key) = cfg
+            .toml
+            .image_signing
+            .as_ref()
+            .and_then(|s| s.private_key.as_ref())
+        {
+            let flash = &cfg.toml.memories(image_name)?["flash"];
+            sign_image(&cfg.toml, flash, key, &mut all_output_sections)?;
+        }
+
         // Generate combined SREC, which is our source of truth for combined images.
         let (kentry, _ksymbol_table) = kern_build.unwrap();
         write_srec(
@@ -668,6 +703,8 @@ fn build_archive(cfg: &PackageConfig, image_name: &str) -> Result<()> {
         - elf/tasks/ contains each task by name.\n\
         - elf/kernel is the kernel.\n\
         - img/ contains the final firmware images.\n\
+        - img/image-signing-key.der is the public key that the image's\n  \
+          signature block was made with, if it has one.\n\
         - debug/ contains OpenOCD and GDB scripts, if available.\n",
     )?;
 
@@ -682,7 +719,14 @@ fn build_archive(cfg: &PackageConfig, image_name: &str) -> Result<()> {
             println!("{} building outside a git repository, revision will not be avaliable in build", "warning".bold().yellow());
         }
     }
-    archive.copy(&cfg.app_toml_file, "app.toml")?;
+    if cfg.toml.inherited.is_empty() {
+        archive.copy(&cfg.app_toml_file, "app.toml")?;
+    } else {
+        // Tools reading the archive don't have the rest of the inheritance
+        // chain, so give them the merged config.
+        let resolved = ResolvedToml::load(&cfg.app_toml_file)?;
+        archive.text("app.toml", resolved.to_toml_string()?)?;
+    }
     let chip_dir = cfg.app_src_dir.join(cfg.toml.chip.clone());
     let chip_file = chip_dir.join("chip.toml");
     let chip_filename = chip_file.file_name().unwrap();
@@ -705,6 +749,21 @@ fn build_archive(cfg: &PackageConfig, image_name: &str) -> Result<()> {
         }
     }
 
+    // Record who signed the image, so `xtask verify` can check the archive
+    // without being handed the key.
+    if let Some(key) = cfg
+        .toml
+        .image_signing
+        .as_ref()
+        .and_then(|s| s.private_key.as_ref())
+    {
+        let der = crate::image_signing::signer(&cfg.toml, key)?.to_der()?;
+        let file = cfg.img_file("image-signing-key.der", image_name);
+        std::fs::write(&file, der)
+            .context(format!("Failed to write {:?}", file))?;
+        archive.copy(file, img_dir.join("image-signing-key.der"))?;
+    }
+
     //
     // To allow for the image to be flashed based only on the archive (e.g.,
     // by Humility), we pull in our flash configuration, flatten it to pull in
@@ -870,10 +929,16 @@ fn link_task(
 ) -> Result<()> {
     println!("linking task '{}'", name);
     let task_toml = &cfg.toml.tasks[name];
+    let shared_memory = cfg
+        .toml
+        .task_shared_memory(name)
+        .map(|(shm, _)| (shm, allocs.shared_memory[shm].clone()))
+        .collect();
     generate_task_linker_script(
         cfg.arch_target,
         "memory.x",
         &allocs.tasks[name],
+        &shared_memory,
         Some(&task_toml.sections),
         task_toml.stacksize.or(cfg.toml.stacksize).ok_or_else(|| {
             anyhow!("{}: no stack size specified and there is no default", name)
@@ -914,10 +979,18 @@ fn link_dummy_task(cfg: &PackageConfig, name: &str) -> Result<()> {
         .into_iter()
         .collect();
 
+    // Shared memory hasn't been allocated yet, so give it a dummy address.
+    let shared_memory = cfg
+        .toml
+        .task_shared_memory(name)
+        .map(|(shm, _)| (shm, 0..cfg.toml.shared_memory[shm].size))
+        .collect();
+
     generate_task_linker_script(
         cfg.arch_target,
         "memory.x",
         &memories, // ALL THE SPACE
+        &shared_memory,
         Some(&task_toml.sections),
         task_toml.stacksize.or(cfg.toml.stacksize).ok_or_else(|| {
             anyhow!("{}: no stack size specified and there is no default", name)
@@ -1010,6 +1083,7 @@ fn build_kernel(
         &allocs.kernel,
         cfg.toml.kernel.stacksize.unwrap_or(DEFAULT_KERNEL_STACK),
         &cfg.toml.image_memories("flash".to_string())?,
+        &cfg.toml.all_regions("flash".to_string())?,
     )?;
 
     fs::copy(&cfg.arch_consts.kernel_link_script, "target/link.x")?;
@@ -1017,19 +1091,24 @@ fn build_kernel(
     let image_id = image_id.finish();
 
     // Build the kernel.
-    let build_config = cfg.toml.kernel_build_config(
-        cfg.verbose,
-        &[
-            ("HUBRIS_KCONFIG", &kconfig),
-            ("HUBRIS_IMAGE_ID", &format!("{}", image_id)),
-        ],
-        Some(&cfg.sysroot),
-    );
+    let image_id = format!("{}", image_id);
+    let pubkey = crate::image_signing::stage0_public_key(&cfg.toml)?;
+    let mut env = vec![
+        ("HUBRIS_KCONFIG", kconfig.as_str()),
+        ("HUBRIS_IMAGE_ID", image_id.as_str()),
+    ];
+    if let Some(pubkey) = &pubkey {
+        env.push(("HUBRIS_IMAGE_PUBKEY", pubkey));
+    }
+    let build_config =
+        cfg.toml
+            .kernel_build_config(cfg.verbose, &env, Some(&cfg.sysroot));
     build(cfg, "kernel", build_config, false)?;
     if update_image_header(
         cfg,
         &cfg.dist_file("kernel"),
         &cfg.img_file("kernel.modified", image_name),
+        allocs,
         all_memories,
         all_output_sections,
         secure,
@@ -1058,6 +1137,89 @@ fn build_kernel(
     Ok((kentry, ksymbol_table))
 }
 
+/// Returns the end of the image in `flash`: the end of the highest section
+/// loaded into it.
+fn image_end(
+    flash: &Range<u32>,
+    all_output_sections: &BTreeMap<u32, LoadSegment>,
+) -> u32 {
+    let mut end = 0;
+
+    for (addr, sec) in all_output_sections {
+        if *addr > flash.start && *addr < flash.end && *addr > end {
+            end = addr + (sec.data.len() as u32);
+        }
+    }
+
+    end
+}
+
+/// Signs the image in `flash` with `key`, appending its `ImageSignature`.
+///
+/// The signature covers every byte from the start of `flash` to the end of
+/// the image, so gaps between sections are filled with erased flash (`0xff`)
+/// to make sure what's signed is exactly what gets programmed.
+fn sign_image(
+    toml: &Config,
+    flash: &Range<u32>,
+    key: &Path,
+    all_output_sections: &mut BTreeMap<u32, LoadSegment>,
+) -> Result<()> {
+    let end = image_end(flash, all_output_sections);
+
+    let mut fill = vec![];
+    let mut next = flash.start;
+    for (addr, sec) in all_output_sections.range(flash.start..end) {
+        if *addr > next {
+            fill.push((next, *addr - next));
+        }
+        next = next.max(addr + sec.data.len() as u32);
+    }
+    for (addr, len) in fill {
+        all_output_sections.insert(
+            addr,
+            LoadSegment {
+                source_file: "<fill>".into(),
+                data: vec![0xff; len as usize],
+            },
+        );
+    }
+
+    let mut image = Vec::with_capacity((end - flash.start) as usize);
+    for sec in all_output_sections.range(flash.start..end).map(|(_, s)| s) {
+        image.extend_from_slice(&sec.data);
+    }
+
+    let signature = crate::image_signing::sign(toml, key, &image)?;
+    let signature = signature.as_bytes();
+    let addr = (end + 3) & !3;
+    if addr + signature.len() as u32 > flash.end {
+        bail!(
+            "no room for the image signature at {:#x}; flash ends at {:#x}",
+            addr,
+            flash.end
+        );
+    }
+    if addr > end {
+        all_output_sections.insert(
+            end,
+            LoadSegment {
+                source_file: "<fill>".into(),
+                data: vec![0xff; (addr - end) as usize],
+            },
+        );
+    }
+    all_output_sections.insert(
+        addr,
+        LoadSegment {
+            source_file: "<signature>".into(),
+            data: signature.to_vec(),
+        },
+    );
+
+    Ok(())
+}
+
 /// Adjusts the hubris image header in the ELF file.
 /// Returns true if the header was found and updated,
 /// false otherwise.
@@ -1065,6 +1227,7 @@ fn update_image_header(
     cfg: &PackageConfig,
     input: &Path,
     output: &Path,
+    allocs: &Allocations,
     map: &IndexMap<String, Range<u32>>,
     all_output_sections: &mut BTreeMap<u32, LoadSegment>,
     secure: &Option<SecureData>,
@@ -1095,18 +1258,7 @@ fn update_image_header(
                 // Compute the total image size by finding the highest address
                 // from all the tasks built. Because this is the kernel all
                 // tasks must be built
-                let mut end = 0;
-
-                for (addr, sec) in all_output_sections {
-                    if (*addr as u32) > flash.start
-                        && (*addr as u32) < flash.end
-                        && (*addr as u32) > end
-                    {
-                        end = addr + (sec.data.len() as u32);
-                    }
-                }
-
-                let len = end - flash.start;
+                let len = image_end(flash, all_output_sections) - flash.start;
 
                 let mut header = abi::ImageHeader {
                     version: cfg.toml.version,
@@ -1202,11 +1354,26 @@ fn update_image_header(
                 header.sau_entries[last].rbar = 0x4000_0000;
                 header.sau_entries[last].rlar = 0x4fff_ffe0 | 1;
 
-                header
-                    .write_to_prefix(
-                        &mut file_image[(sec.sh_offset as usize)..],
-                    )
-                    .unwrap();
+                let manifest = task_manifest(cfg, allocs, all_output_sections)?;
+                fill_build_metadata(cfg, &mut header, manifest.len())?;
+                if (sec.sh_size as usize) < header.len_with_manifest() {
+                    bail!(
+                        "image header section is {} bytes, which is too \
+                         small for the {} bytes of header and task manifest",
+                        sec.sh_size,
+                        header.len_with_manifest(),
+                    );
+                }
+
+                let header_image = &mut file_image[(sec.sh_offset as usize)..];
+                header.write_to_prefix(&mut *header_image).unwrap();
+                let entry_len = core::mem::size_of::<abi::TaskManifestEntry>();
+                let entries = header_image
+                    [core::mem::size_of::<abi::ImageHeader>()..]
+                    .chunks_exact_mut(entry_len);
+                for (entry, out) in manifest.iter().zip(entries) {
+                    entry.write_to(out).unwrap();
+                }
                 std::fs::write(output, &file_image)?;
                 return Ok(true);
             }
@@ -1216,35 +1383,92 @@ fn update_image_header(
     Ok(false)
 }
 
-/// Prints warning messages about priority inversions
-fn check_task_priorities(toml: &Config) -> Result<()> {
-    let idle_priority = toml.tasks["idle"].priority;
-    for (i, (name, task)) in toml.tasks.iter().enumerate() {
-        for callee in task.task_slots.values() {
-            let p = toml
-                .tasks
-                .get(callee)
-                .ok_or_else(|| anyhow!("Invalid task-slot: {}", callee))?
-                .priority;
-            if p >= task.priority && name != callee {
-                // TODO: once all priority inversions are fixed, return an
-                // error so no more can be introduced
-                eprint!("{}", "Priority inversion: ".red());
-                eprintln!(
-                    "task {} (priority {}) calls into {} (priority {})",
-                    name, task.priority, callee, p
-                );
-            }
+/// Fills in the version 2 fields of the image header, other than the task
+/// manifest itself.
+fn fill_build_metadata(
+    cfg: &PackageConfig,
+    header: &mut abi::ImageHeader,
+    task_count: usize,
+) -> Result<()> {
+    header.header_version = abi::HEADER_VERSION;
+    header.task_count = task_count as u32;
+    copy_padded(&mut header.app_name, &cfg.toml.name, "app name")?;
+    copy_padded(&mut header.board, &cfg.toml.board, "board name")?;
+
+    // As with the archive, building outside git is allowed; the commit is
+    // just left zeroed.
+    if let Ok((git_rev, git_dirty)) = get_git_status() {
+        if git_rev.len() != 2 * header.git_commit.len() {
+            bail!("unexpected git commit hash {:?}", git_rev);
+        }
+        for (i, b) in header.git_commit.iter_mut().enumerate() {
+            let byte = &git_rev[2 * i..][..2];
+            *b = u8::from_str_radix(byte, 16)
+                .with_context(|| format!("bad git commit hash {}", git_rev))?;
+        }
+        if git_dirty {
+            header.flags |= abi::HEADER_FLAG_DIRTY;
         }
-        if task.priority >= idle_priority && name != "idle" {
-            bail!("task {} has priority that's >= idle priority", name);
-        } else if i == 0 && task.priority != 0 {
-            bail!("Supervisor task ({}) is not at priority 0", name);
-        } else if i != 0 && task.priority == 0 {
-            bail!("Task {} is not the supervisor, but has priority 0", name,);
+    }
+    Ok(())
+}
+
+/// Builds the task manifest for the image header: each task's name, crate
+/// version and the SHA3-256 of what it loads into flash, in task index order.
+fn task_manifest(
+    cfg: &PackageConfig,
+    allocs: &Allocations,
+    all_output_sections: &BTreeMap<u32, LoadSegment>,
+) -> Result<Vec<abi::TaskManifestEntry>> {
+    use sha3::{Digest, Sha3_256};
+
+    let metadata = cargo_metadata::MetadataCommand::new()
+        .manifest_path("Cargo.toml")
+        .no_deps()
+        .exec()
+        .context("reading cargo metadata for the task manifest")?;
+
+    let mut manifest = vec![];
+    for (name, task) in &cfg.toml.tasks {
+        let package = metadata
+            .packages
+            .iter()
+            .find(|p| p.name == task.name)
+            .ok_or_else(|| {
+                anyhow!("can't find crate {} for task {}", task.name, name)
+            })?;
+
+        let mut entry = abi::TaskManifestEntry::default();
+        copy_padded(&mut entry.name, name, "task name")?;
+        copy_padded(
+            &mut entry.crate_version,
+            &package.version.to_string(),
+            "crate version",
+        )?;
+
+        let flash = &allocs.tasks[name]["flash"];
+        let mut hasher = Sha3_256::new();
+        for (_, segment) in all_output_sections.range(flash.clone()) {
+            hasher.update(&segment.data);
         }
+        entry.hash = hasher.finalize().into();
+
+        manifest.push(entry);
     }
+    Ok(manifest)
+}
 
+/// Copies `s` into the NUL-padded field `dest` of the image header.
+fn copy_padded(dest: &mut [u8], s: &str, what: &str) -> Result<()> {
+    if s.len() > dest.len() {
+        bail!(
+            "{} {:?} is too long for the image header (max {} bytes)",
+            what,
+            s,
+            dest.len()
+        );
+    }
+    dest[..s.len()].copy_from_slice(s.as_bytes());
     Ok(())
 }
 
@@ -1270,6 +1494,7 @@ fn generate_task_linker_script(
     arch_target: ArchTarget,
     name: &str,
     map: &BTreeMap<String, Range<u32>>,
+    shared_memory: &BTreeMap<&str, Range<u32>>,
     sections: Option<&IndexMap<String, String>>,
     stacksize: u32,
     images: &IndexMap<String, Range<u32>>,
@@ -1330,6 +1555,19 @@ fn generate_task_linker_script(
             out.end
         )?;
     }
+    // Shared memory is found by userlib's `shared_memory!` through these.
+    for (name, range) in shared_memory {
+        writeln!(
+            linkscr,
+            "__SHARED_MEMORY_{}_BASE = {:#010x};",
+            name, range.start
+        )?;
+        writeln!(
+            linkscr,
+            "__SHARED_MEMORY_{}_END = {:#010x};",
+            name, range.end
+        )?;
+    }
 
     append_task_sections(&mut linkscr, sections)?;
     generate_linker_aliases(arch_target, &mut linkscr)?;
@@ -1361,6 +1599,7 @@ fn generate_kernel_linker_script(
     map: &BTreeMap<String, Range<u32>>,
     stacksize: u32,
     images: &IndexMap<String, Range<u32>>,
+    regions: &IndexMap<String, Range<u32>>,
 ) -> Result<()> {
     // Put the linker script somewhere the linker can find it
     let mut linkscr =
@@ -1433,6 +1672,22 @@ fn generate_kernel_linker_script(
         )
         .unwrap();
     }
+    // As for tasks, so that flash regions outside any image (such as the
+    // boot state page) can be found.
+    for (name, out) in regions {
+        writeln!(
+            linkscr,
+            "__IMAGE_{}_BASE = {:#010x};",
+            name.to_ascii_uppercase(),
+            out.start
+        )?;
+        writeln!(
+            linkscr,
+            "__IMAGE_{}_END = {:#010x};",
+            name.to_ascii_uppercase(),
+            out.end
+        )?;
+    }
 
     generate_linker_aliases(arch_target, &mut linkscr)?;
 
@@ -1486,6 +1741,12 @@ fn build(
         ArchTarget::ARM => {}
     }
 
+    // Record per-function frame sizes in tasks for `xtask stack`. The task
+    // linker scripts keep the resulting section; the kernel's doesn't.
+    if cfg.stack_sizes && name != "kernel" {
+        rustflags.push_str(" -Z emit-stack-sizes");
+    }
+
     cmd.env("RUSTFLAGS", &rustflags);
     cmd.arg("--");
     cmd.arg("-C")
@@ -1623,6 +1884,7 @@ fn link(
         "riscv32imc-unknown-none-elf" | "riscv32imac-unknown-none-elf" => {
             "elf32lriscv"
         }
+        "riscv64imac-unknown-none-elf" => "elf64lriscv",
         _ => bail!("No target emulation for '{}'", cfg.toml.target),
     };
     cmd.arg(src_file);
@@ -1652,13 +1914,16 @@ pub struct Allocations {
     pub kernel: BTreeMap<String, Range<u32>>,
     /// Map from task-name to memory-name to address-range
     pub tasks: BTreeMap<String, BTreeMap<String, Range<u32>>>,
+    /// Map from shared-memory-name to address-range
+    pub shared_memory: BTreeMap<String, Range<u32>>,
 }
 
 /// Allocates address space from all regions for the kernel and all tasks.
 ///
-/// The allocation strategy is slightly involved, because of the limitations of
-/// the ARMv7-M MPU. (Currently we use the same strategy on ARMv8-M even though
-/// it's more flexible.)
+/// Targets whose MPU only needs regions aligned to a small chunk size (RISC-V
+/// PMP and ARMv8-M) are packed by [`allocate_packed`]. The rest of this
+/// comment concerns ARMv6-M and ARMv7-M, where the allocation strategy is
+/// slightly involved because of the limitations of the MPU.
 ///
 /// Address space regions are required to be power-of-two in size and naturally
 /// aligned. In other words, all the addresses in a single region must have some
@@ -1740,6 +2005,24 @@ pub fn allocate_all(
             let mut k_req = kernel_requests.get(region.as_str());
             let mut t_reqs = task_requests.get_mut(region.as_str());
 
+            if !toml.mpu_power_of_two_required() {
+                // Largest first, and in task order for equal sizes.
+                let t_reqs = t_reqs
+                    .iter()
+                    .flat_map(|map| map.iter().rev())
+                    .flat_map(|(&sz, q)| q.iter().map(move |&t| (t, sz)))
+                    .collect::<Vec<_>>();
+                allocate_packed(
+                    toml,
+                    region,
+                    k_req.copied(),
+                    &t_reqs,
+                    &mut allocs,
+                    avail,
+                )?;
+                continue;
+            }
+
             fn reqs_map_not_empty(
                 om: &Option<&mut BTreeMap<u32, VecDeque<&str>>>,
             ) -> bool {
@@ -1812,6 +2095,19 @@ pub fn allocate_all(
                 // Panic here because otherwise it's a hang.
                 panic!("loop iteration without progess made!");
             }
+
+            // Shared memory comes out of RAM, after the tasks have been
+            // packed in.
+            if region == "ram" {
+                for (name, shared) in &toml.shared_memory {
+                    let sz = toml.suggest_shared_memory_size(shared.size);
+                    let align = toml.task_memory_alignment(sz);
+                    allocs.shared_memory.insert(
+                        name.to_string(),
+                        allocate_one(region, sz, align, avail)?,
+                    );
+                }
+            }
         }
 
         result.insert(image_name.to_string(), (allocs, free));
@@ -1819,6 +2115,104 @@ pub fn allocate_all(
     Ok(result)
 }
 
+/// Allocates one memory for targets that need regions aligned to a small
+/// chunk size, but not naturally aligned.
+///
+/// The kernel goes first, as on other targets. Then tasks, and shared memory
+/// if this is RAM, are placed largest first, each into the free space that
+/// it fits most tightly (best fit), which fills any gaps left by alignment
+/// before carving into the remaining space.
+///
+/// `avail` is left starting after the last allocation.
+fn allocate_packed(
+    toml: &Config,
+    region: &str,
+    kernel: Option<u32>,
+    tasks: &[(&str, u32)],
+    allocs: &mut Allocations,
+    avail: &mut Range<u32>,
+) -> Result<()> {
+    if let Some(sz) = kernel {
+        allocs
+            .kernel
+            .insert(region.to_string(), allocate_k(region, sz, avail)?);
+    }
+
+    let mut holes = vec![avail.clone()];
+    for &(task, sz) in tasks {
+        let align = toml.task_memory_alignment(sz);
+        allocs.tasks.entry(task.to_string()).or_default().insert(
+            region.to_string(),
+            allocate_best_fit(region, sz, align, &mut holes)?,
+        );
+    }
+
+    if region == "ram" {
+        let mut shared = toml
+            .shared_memory
+            .iter()
+            .map(|(name, shared)| {
+                (name, toml.suggest_shared_memory_size(shared.size))
+            })
+            .collect::<Vec<_>>();
+        shared.sort_by_key(|&(_, sz)| std::cmp::Reverse(sz));
+        for (name, sz) in shared {
+            let align = toml.task_memory_alignment(sz);
+            allocs.shared_memory.insert(
+                name.to_string(),
+                allocate_best_fit(region, sz, align, &mut holes)?,
+            );
+        }
+    }
+
+    avail.start = match holes.last() {
+        Some(tail) if tail.end == avail.end => tail.start,
+        _ => avail.end,
+    };
+    Ok(())
+}
+
+/// Allocates `size` bytes aligned to `align` from the free space in `holes`
+/// (kept in address order) that would have the least left over, taking the
+/// lowest such space when there's a tie.
+fn allocate_best_fit(
+    region: &str,
+    size: u32,
+    align: u32,
+    holes: &mut Vec<Range<u32>>,
+) -> Result<Range<u32>> {
+    assert!(align.is_power_of_two());
+    let mask = align - 1;
+
+    let fit = holes
+        .iter()
+        .enumerate()
+        .filter_map(|(i, hole)| {
+            let base = hole.start.checked_add(mask)? & !mask;
+            let left = hole.end.checked_sub(base)?.checked_sub(size)?;
+            Some((i, base, left))
+        })
+        .min_by_key(|&(_, _, left)| left);
+    let (i, base) = match fit {
+        Some((i, base, _)) => (i, base),
+        None => bail!(
+            "out of {}: can't fit {} more in any free space (largest is {})",
+            region,
+            size,
+            holes.iter().map(|h| h.end - h.start).max().unwrap_or(0)
+        ),
+    };
+
+    let hole = holes.remove(i);
+    let end = base + size;
+    let rest = [hole.start..base, end..hole.end];
+    for (j, r) in rest.into_iter().filter(|r| !r.is_empty()).enumerate() {
+        holes.insert(i + j, r);
+    }
+
+    Ok(base..end)
+}
+
 fn allocate_k(
     region: &str,
     size: u32,
@@ -1874,6 +2268,12 @@ fn allocate_one(
     Ok(base..end)
 }
 
+/// Names the shared region through which tasks with the given access see a
+/// shared memory region.
+fn shared_memory_region_name(name: &str, access: SharedAccess) -> String {
+    format!("shared-memory.{}.{}", name, access)
+}
+
 /// Generate the configuration data that's passed into the kernel's build
 /// system.
 pub fn make_kconfig(
@@ -1965,6 +2365,34 @@ pub fn make_kconfig(
         );
     }
 
+    // Shared memory appears once for each kind of access granted to it, so
+    // that tasks with different permissions get different region descriptors
+    // for the same memory.
+    let ram_dma = toml.outputs.get("ram").map_or(false, |outs| {
+        outs.iter().any(|o| o.name == image_name && o.dma)
+    });
+    for (name, range) in &all_allocations.shared_memory {
+        for &access in toml.shared_memory[name].access.values() {
+            flat_shared.insert(
+                shared_memory_region_name(name, access),
+                build_kconfig::RegionConfig {
+                    base: range.start,
+                    size: range.end - range.start,
+                    attributes: build_kconfig::RegionAttributes {
+                        read: true,
+                        write: access == SharedAccess::ReadWrite,
+                        execute: false,
+                        special_role: if ram_dma {
+                            Some(build_kconfig::SpecialRole::Dma)
+                        } else {
+                            None
+                        },
+                    },
+                },
+            );
+        }
+    }
+
     let mut used_shared_regions = BTreeSet::new();
     let task_allocations: &BTreeMap<String, BTreeMap<String, Range<u32>>> =
         &all_allocations.tasks;
@@ -1983,14 +2411,19 @@ pub fn make_kconfig(
             );
         };
 
-        // Mark off the regions this task uses.
-        for region in &task.uses {
-            used_shared_regions.insert(region);
-        }
-
-        // Prep this task's shared region name set.
+        // Prep this task's shared region name set, including any shared
+        // memory it has been granted.
         let shared_regions: std::collections::BTreeSet<String> =
-            task.uses.iter().cloned().collect();
+            task.uses
+                .iter()
+                .cloned()
+                .chain(toml.task_shared_memory(name).map(|(shm, access)| {
+                    shared_memory_region_name(shm, access)
+                }))
+                .collect();
+
+        // Mark off the regions this task uses.
+        used_shared_regions.extend(shared_regions.iter().cloned());
 
         let owned_regions = task_allocations[name].iter()
             .map(|(out_name, range)| {
@@ -2025,6 +2458,24 @@ pub fn make_kconfig(
             })
             .collect::<Result<BTreeMap<_, _>, _>>()?;
 
+        // The null region takes one slot in the task's region table.
+        let region_count = 1 + owned_regions.len() + shared_regions.len();
+        if region_count > abi::REGIONS_PER_TASK {
+            bail!(
+                "task {} needs {} memory regions (including peripherals and \
+                 shared memory), but only {} are available",
+                name,
+                region_count,
+                abi::REGIONS_PER_TASK
+            );
+        }
+
+        let ipc_allowlist = if toml.kernel.ipc_allowlist {
+            Some(toml.task_ipc_allowlist(name)?)
+        } else {
+            None
+        };
+
         tasks.push(build_kconfig::TaskConfig {
             owned_regions,
             shared_regions,
@@ -2038,6 +2489,8 @@ pub fn make_kconfig(
             },
             priority: task.priority,
             start_at_boot: task.start,
+            dump_access: task.dump_access,
+            ipc_allowlist,
         });
 
         // Interrupts.
@@ -2162,7 +2615,9 @@ pub fn make_kconfig(
         })
         .collect::<Result<BTreeMap<_, _>, _>>()?;
 
+    // In Supervisor mode the kernel reaches the timer through the SBI instead.
     if toml.target.as_str().contains("riscv")
+        && !toml.riscv_s_mode()
         && ((timer.0 == 0x0) || (timer.1 == 0x0))
     {
         bail!("mtime or mtimecmp has not been set.");
@@ -2250,7 +2705,17 @@ fn load_elf(
         // Note that we are using Physical, i.e. LOADADDR, rather than virtual.
         // This distinction is important for things like the rodata image, which
         // is loaded in flash but expected to be copied to RAM.
-        let addr = phdr.p_paddr as u32;
+        //
+        // The kernel ABI only has room for 32-bit addresses, so a 64-bit image
+        // has to be linked entirely below 4 GiB.
+        let addr = match u32::try_from(phdr.p_paddr) {
+            Ok(addr) => addr,
+            Err(_) => bail!(
+                "{}: segment at {:#x} is above 4 GiB",
+                input.display(),
+                phdr.p_paddr
+            ),
+        };
 
         flash += size;
 
@@ -2577,3 +3042,320 @@ fn resolve_task_slots(
 
     Ok(std::fs::write(task_bin, out_task_bin)?)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::config::tests::load;
+    use crate::image_signing::tests::fake_cert;
+    use zerocopy::FromBytes;
+
+    /// Allocates the test app's default image, with tasks `a` and `b` each
+    /// needing 1000 bytes of flash and of RAM.
+    fn allocate(toml: &Config) -> Allocations {
+        let sizes: IndexMap<&str, u64> =
+            [("flash", 1000), ("ram", 1000)].into_iter().collect();
+        let task_sizes =
+            [("a", sizes.clone()), ("b", sizes)].into_iter().collect();
+        let mut allocs = allocate_all(toml, &task_sizes).unwrap();
+        allocs.remove("default").unwrap().0
+    }
+
+    /// Checks that shared memory region `name` is at least `size` bytes,
+    /// aligned to `align`, and doesn't overlap anything else in RAM.
+    fn check_shared(allocs: &Allocations, name: &str, size: u32, align: u32) {
+        let shared = &allocs.shared_memory[name];
+        assert_eq!(shared.end - shared.start, size);
+        assert_eq!(shared.start % align, 0);
+        let others = allocs
+            .tasks
+            .values()
+            .chain(std::iter::once(&allocs.kernel))
+            .map(|regions| &regions["ram"])
+            .chain(
+                allocs
+                    .shared_memory
+                    .iter()
+                    .filter(|&(n, _)| n != name)
+                    .map(|(_, r)| r),
+            );
+        for other in others {
+            assert!(
+                other.end <= shared.start || shared.end <= other.start,
+                "{:x?} overlaps {:x?}",
+                shared,
+                other
+            );
+        }
+    }
+
+    #[test]
+    fn allocate_shared_memory_power_of_two() {
+        let toml = load(
+            "thumbv7em-none-eabihf",
+            "stm32h7",
+            r#"
+[shared-memory.small]
+size = 100
+access = {a = "rw"}
+
+[shared-memory.big]
+size = 3000
+access = {b = "rw"}
+"#,
+        )
+        .unwrap();
+        let allocs = allocate(&toml);
+        check_shared(&allocs, "small", 128, 128);
+        check_shared(&allocs, "big", 4096, 4096);
+    }
+
+    #[test]
+    fn allocate_shared_memory_packed() {
+        let toml = load(
+            "riscv32imac-unknown-none-elf",
+            "qemu-virt",
+            r#"
+[shared-memory.small]
+size = 100
+access = {a = "rw"}
+
+[shared-memory.big]
+size = 3000
+access = {b = "rw"}
+"#,
+        )
+        .unwrap();
+        let allocs = allocate(&toml);
+        check_shared(&allocs, "small", 112, 16);
+        check_shared(&allocs, "big", 3008, 16);
+    }
+
+    #[test]
+    fn best_fit_splits_holes() {
+        // Alignment leaves space before the allocation as well as after it.
+        let mut holes = vec![0x100..0x1000];
+        assert_eq!(
+            allocate_best_fit("ram", 0x100, 0x200, &mut holes).unwrap(),
+            0x200..0x300
+        );
+        assert_eq!(holes, [0x100..0x200, 0x300..0x1000]);
+
+        // That space is then the best fit for something that fills it.
+        assert_eq!(
+            allocate_best_fit("ram", 0x100, 0x100, &mut holes).unwrap(),
+            0x100..0x200
+        );
+        assert_eq!(holes, [0x300..0x1000]);
+    }
+
+    #[test]
+    fn best_fit_prefers_tightest_then_lowest() {
+        let mut holes = vec![0x0..0x100, 0x200..0x300, 0x400..0x480];
+        assert_eq!(
+            allocate_best_fit("ram", 0x80, 0x10, &mut holes).unwrap(),
+            0x400..0x480
+        );
+        assert_eq!(holes, [0x0..0x100, 0x200..0x300]);
+
+        // Both holes would have 0x80 left over.
+        assert_eq!(
+            allocate_best_fit("ram", 0x80, 0x10, &mut holes).unwrap(),
+            0x0..0x80
+        );
+        assert_eq!(holes, [0x80..0x100, 0x200..0x300]);
+    }
+
+    #[test]
+    fn best_fit_out_of_space() {
+        let mut holes = vec![0x0..0x100, 0x200..0x280];
+        let e = allocate_best_fit("ram", 0x180, 0x10, &mut holes).unwrap_err();
+        assert!(e.to_string().contains("largest is 256"), "{}", e);
+        assert_eq!(holes, [0x0..0x100, 0x200..0x280]);
+
+        // Big enough, but not once aligned.
+        let mut holes = vec![0x10..0x110];
+        assert!(allocate_best_fit("ram", 0x100, 0x100, &mut holes).is_err());
+        assert_eq!(holes, [0x10..0x110]);
+    }
+
+    #[test]
+    fn packed_leaves_avail_after_last_allocation() {
+        let toml =
+            load("riscv32imac-unknown-none-elf", "qemu-virt", "").unwrap();
+        let pack = |start: u32, tasks: &[(&str, u32)]| {
+            let mut allocs = Allocations::default();
+            let mut avail = start..0x2000;
+            allocate_packed(&toml, "ram", None, tasks, &mut allocs, &mut avail)
+                .unwrap();
+            avail
+        };
+
+        assert_eq!(pack(0x1000, &[("a", 0x100), ("b", 0x80)]), 0x1180..0x2000);
+        // The gap left by aligning the first task isn't available to later
+        // allocations from `avail`.
+        assert_eq!(pack(0x1008, &[("a", 0x100)]), 0x1110..0x2000);
+        assert_eq!(pack(0x1000, &[("a", 0x800), ("b", 0x800)]), 0x2000..0x2000);
+    }
+
+    /// Builds the kernel config for the test app's default image.
+    fn kconfig(toml: &Config) -> Result<build_kconfig::KernelConfig> {
+        let allocs = allocate(toml);
+        let entry_points = allocs
+            .tasks
+            .iter()
+            .map(|(name, regions)| (name.clone(), regions["flash"].start))
+            .collect();
+        make_kconfig(toml, &allocs, &entry_points, "default", &None)
+    }
+
+    #[test]
+    fn shared_memory_regions_follow_access() {
+        let toml = load(
+            "thumbv7em-none-eabihf",
+            "stm32h7",
+            r#"
+[shared-memory.buf]
+size = 1000
+access = {a = "rw", b = "r"}
+"#,
+        )
+        .unwrap();
+        let kconfig = kconfig(&toml).unwrap();
+
+        let rw = &kconfig.shared_regions["shared-memory.buf.rw"];
+        let r = &kconfig.shared_regions["shared-memory.buf.r"];
+        assert!(rw.attributes.read && rw.attributes.write);
+        assert!(r.attributes.read && !r.attributes.write);
+        // Both views are of the same memory.
+        assert_eq!((rw.base, rw.size), (r.base, r.size));
+
+        let names = |i: usize| {
+            kconfig.tasks[i]
+                .shared_regions
+                .iter()
+                .cloned()
+                .collect::<Vec<_>>()
+        };
+        assert_eq!(names(0), ["shared-memory.buf.rw"]);
+        assert_eq!(names(1), ["shared-memory.buf.r"]);
+    }
+
+    #[test]
+    fn shared_memory_counts_against_region_limit() {
+        // Task `a` has flash, RAM, and the null region, leaving room for five
+        // shared memory regions.
+        let extra = |count: usize| {
+            (0..count)
+                .map(|i| {
+                    format!(
+                        "[shared-memory.buf{}]\n\
+                         size = 32\n\
+                         access = {{a = \"rw\"}}\n",
+                        i
+                    )
+                })
+                .collect::<String>()
+        };
+        let toml = load("thumbv7em-none-eabihf", "stm32h7", &extra(5)).unwrap();
+        kconfig(&toml).unwrap();
+
+        let toml = load("thumbv7em-none-eabihf", "stm32h7", &extra(6)).unwrap();
+        let e = kconfig(&toml).unwrap_err();
+        assert!(e.to_string().contains("needs 9 memory regions"), "{}", e);
+    }
+
+    /// Loads sections `(addr, len)` with non-`0xff` contents, and signs the
+    /// image in `flash` with the fake P-256 key.
+    fn sign_sections(
+        flash: Range<u32>,
+        sections: &[(u32, usize)],
+    ) -> Result<BTreeMap<u32, LoadSegment>> {
+        let toml = load("thumbv7em-none-eabihf", "stm32h7", "").unwrap();
+        let key = fake_cert("p256-private-key.der");
+        let mut all_output_sections = sections
+            .iter()
+            .map(|&(addr, len)| {
+                let segment = LoadSegment {
+                    source_file: "test".into(),
+                    data: vec![0x5a; len],
+                };
+                (addr, segment)
+            })
+            .collect();
+        sign_image(&toml, &flash, &key, &mut all_output_sections)?;
+        Ok(all_output_sections)
+    }
+
+    #[test]
+    fn sign_image_fills_gaps() {
+        let sections =
+            sign_sections(0x1000..0x2000, &[(0x1000, 4), (0x1010, 3)]).unwrap();
+        let layout = sections
+            .iter()
+            .map(|(addr, s)| (*addr, s.data.len()))
+            .collect::<Vec<_>>();
+        let signature_len = core::mem::size_of::<abi::ImageSignature>();
+        assert_eq!(
+            layout,
+            [
+                (0x1000, 4),
+                (0x1004, 0xc),
+                (0x1010, 3),
+                (0x1013, 1),
+                (0x1014, signature_len)
+            ]
+        );
+        assert!(sections[&0x1004].data.iter().all(|&b| b == 0xff));
+        assert_eq!(sections[&0x1013].data, [0xff]);
+
+        // The signature covers the image up to its last section, including
+        // the fill between sections, but not the padding after it.
+        let image = sections
+            .range(0x1000..0x1013)
+            .flat_map(|(_, s)| s.data.iter().copied())
+            .collect::<Vec<_>>();
+        let signature =
+            abi::ImageSignature::read_from(&sections[&0x1014].data[..])
+                .unwrap();
+        assert_eq!(signature.image_len, 0x13);
+        let public = crate::image_signing::PublicKey::from_der_file(
+            &fake_cert("p256-public-key.der"),
+        )
+        .unwrap();
+        crate::image_signing::verify(&public, &signature, &image).unwrap();
+    }
+
+    #[test]
+    fn sign_image_aligns_signature() {
+        let sections = sign_sections(0x1000..0x2000, &[(0x1004, 8)]).unwrap();
+        // The image already ends on a 4-byte boundary, so there's no padding.
+        assert_eq!(
+            sections.keys().copied().collect::<Vec<_>>(),
+            [0x1000, 0x1004, 0x100c]
+        );
+        for end in 0x1009..0x100d {
+            let len = (end - 0x1004) as usize;
+            let sections =
+                sign_sections(0x1000..0x2000, &[(0x1004, len)]).unwrap();
+            let (addr, _) = sections.iter().next_back().unwrap();
+            assert_eq!(*addr, 0x100c);
+        }
+    }
+
+    #[test]
+    fn sign_image_needs_room() {
+        let signature_len = core::mem::size_of::<abi::ImageSignature>();
+        let end = 0x1100 - signature_len as u32;
+        // Exactly enough room...
+        sign_sections(0x1000..0x1100, &[(0x1004, (end - 0x1004) as usize)])
+            .unwrap();
+        // ...but not once the signature has to be pushed to the next word.
+        let e = sign_sections(
+            0x1000..0x1100,
+            &[(0x1004, (end - 0x1004) as usize + 1)],
+        )
+        .unwrap_err();
+        assert!(e.to_string().contains("no room"), "{}", e);
+    }
+}
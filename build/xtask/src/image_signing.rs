@@ -0,0 +1,342 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Keys and signatures for the detached signature block appended to each
+//! image (see `abi::ImageSignature`), which stage0 checks before booting an
+//! image and `xtask verify` checks in a build archive.
+//!
+//! Private keys are PKCS#8 and public keys SubjectPublicKeyInfo, both DER.
+//! The algorithm follows from the key: ECDSA P-256 or Ed25519.
+
+use std::path::{Path, PathBuf};
+
+use anyhow::{anyhow, bail, Context, Result};
+use ed25519::pkcs8::{
+    DecodePrivateKey as _, DecodePublicKey as _, EncodePublicKey as _,
+    KeypairBytes, PublicKeyBytes,
+};
+use ed25519_dalek::Signer as _;
+use p256::ecdsa::signature::Verifier as _;
+use p256::elliptic_curve::sec1::ToEncodedPoint;
+use p256::pkcs8::{
+    DecodePrivateKey as _, DecodePublicKey as _, EncodePublicKey as _,
+};
+use sha3::{Digest, Sha3_256};
+
+use crate::Config;
+
+fn key_path(toml: &Config, key: &Path) -> PathBuf {
+    toml.app_toml_path.parent().unwrap().join(key)
+}
+
+fn read_der(path: &Path) -> Result<Vec<u8>> {
+    std::fs::read(path).with_context(|| format!("reading {}", path.display()))
+}
+
+pub enum PrivateKey {
+    EcdsaP256(p256::ecdsa::SigningKey),
+    Ed25519(ed25519_dalek::Keypair),
+}
+
+impl PrivateKey {
+    pub fn from_der_file(path: &Path) -> Result<Self> {
+        let der = read_der(path)?;
+        if let Ok(key) = p256::SecretKey::from_pkcs8_der(&der) {
+            return Ok(Self::EcdsaP256(key.into()));
+        }
+        if let Ok(key) = KeypairBytes::from_pkcs8_der(&der) {
+            let secret = ed25519_dalek::SecretKey::from_bytes(&key.secret_key)
+                .map_err(|e| anyhow!("{}: {}", path.display(), e))?;
+            let public = (&secret).into();
+            return Ok(Self::Ed25519(ed25519_dalek::Keypair {
+                secret,
+                public,
+            }));
+        }
+        bail!(
+            "{} is not a P-256 or Ed25519 private key in PKCS#8 DER",
+            path.display()
+        )
+    }
+
+    /// Signs `image`, returning the `ImageSignature` to place after it.
+    pub fn sign(&self, image: &[u8]) -> abi::ImageSignature {
+        let mut out = abi::ImageSignature {
+            magic: abi::SIGNATURE_MAGIC,
+            algorithm: self.public().algorithm(),
+            image_len: image.len() as u32,
+            key_id: self.public().key_id(),
+            signature: [0; 64],
+        };
+        match self {
+            Self::EcdsaP256(key) => {
+                let signature: p256::ecdsa::Signature = key.sign(image);
+                out.signature.copy_from_slice(signature.as_ref());
+            }
+            Self::Ed25519(key) => {
+                out.signature = key.sign(image).to_bytes();
+            }
+        }
+        out
+    }
+
+    pub fn public(&self) -> PublicKey {
+        match self {
+            Self::EcdsaP256(key) => {
+                PublicKey::EcdsaP256(key.verifying_key().into())
+            }
+            Self::Ed25519(key) => PublicKey::Ed25519(key.public),
+        }
+    }
+}
+
+pub enum PublicKey {
+    EcdsaP256(p256::PublicKey),
+    Ed25519(ed25519_dalek::PublicKey),
+}
+
+impl PublicKey {
+    pub fn from_der(der: &[u8]) -> Result<Self> {
+        if let Ok(key) = p256::PublicKey::from_public_key_der(der) {
+            return Ok(Self::EcdsaP256(key));
+        }
+        if let Ok(key) = PublicKeyBytes::from_public_key_der(der) {
+            let key = ed25519_dalek::PublicKey::from_bytes(&key.0)
+                .map_err(|e| anyhow!("bad Ed25519 public key: {}", e))?;
+            return Ok(Self::Ed25519(key));
+        }
+        bail!("not a P-256 or Ed25519 SubjectPublicKeyInfo")
+    }
+
+    pub fn from_der_file(path: &Path) -> Result<Self> {
+        Self::from_der(&read_der(path)?)
+            .with_context(|| format!("reading {}", path.display()))
+    }
+
+    /// Returns the key as SubjectPublicKeyInfo DER.
+    pub fn to_der(&self) -> Result<Vec<u8>> {
+        // The two keys come from different versions of the `pkcs8` crate, so
+        // their documents are different types.
+        let der = match self {
+            Self::EcdsaP256(key) => key
+                .to_public_key_der()
+                .map(|doc| doc.as_ref().to_vec())
+                .map_err(|e| anyhow!("encoding public key: {}", e))?,
+            Self::Ed25519(key) => PublicKeyBytes(key.to_bytes())
+                .to_public_key_der()
+                .map(|doc| doc.as_bytes().to_vec())
+                .map_err(|e| anyhow!("encoding public key: {}", e))?,
+        };
+        Ok(der)
+    }
+
+    /// The raw key: an uncompressed SEC1 point for P-256, or the 32-byte
+    /// key for Ed25519.
+    fn raw(&self) -> Vec<u8> {
+        match self {
+            Self::EcdsaP256(key) => {
+                key.to_encoded_point(false).as_bytes().to_vec()
+            }
+            Self::Ed25519(key) => key.to_bytes().to_vec(),
+        }
+    }
+
+    pub fn key_id(&self) -> [u8; 32] {
+        Sha3_256::digest(self.raw()).into()
+    }
+
+    pub fn algorithm(&self) -> u32 {
+        match self {
+            Self::EcdsaP256(_) => abi::SIGNATURE_ECDSA_P256_SHA256,
+            Self::Ed25519(_) => abi::SIGNATURE_ED25519,
+        }
+    }
+}
+
+/// Returns a human-readable name for an `ImageSignature::algorithm`.
+pub fn algorithm_name(algorithm: u32) -> &'static str {
+    match algorithm {
+        abi::SIGNATURE_ECDSA_P256_SHA256 => "ECDSA P-256",
+        abi::SIGNATURE_ED25519 => "Ed25519",
+        _ => "unknown",
+    }
+}
+
+/// Returns the public key to build into stage0, if the config has one, as
+/// the hex of its raw form (see `PublicKey::raw`).
+pub fn stage0_public_key(toml: &Config) -> Result<Option<String>> {
+    let path = match toml
+        .image_signing
+        .as_ref()
+        .and_then(|s| s.public_key.as_ref())
+    {
+        Some(path) => key_path(toml, path),
+        None => return Ok(None),
+    };
+    let key = PublicKey::from_der_file(&path)?;
+    Ok(Some(
+        key.raw().iter().map(|b| format!("{:02x}", b)).collect(),
+    ))
+}
+
+/// Returns the public half of the private key at `key` (relative to the
+/// `app.toml`).
+pub fn signer(toml: &Config, key: &Path) -> Result<PublicKey> {
+    Ok(PrivateKey::from_der_file(&key_path(toml, key))?.public())
+}
+
+/// Signs `image` with the private key at `key` (relative to the `app.toml`),
+/// returning the `ImageSignature` to place after it.
+pub fn sign(
+    toml: &Config,
+    key: &Path,
+    image: &[u8],
+) -> Result<abi::ImageSignature> {
+    Ok(PrivateKey::from_der_file(&key_path(toml, key))?.sign(image))
+}
+
+/// Checks that `signature` is a valid signature of `image` by `key`.
+pub fn verify(
+    key: &PublicKey,
+    signature: &abi::ImageSignature,
+    image: &[u8],
+) -> Result<()> {
+    if signature.key_id != key.key_id() {
+        bail!("the image was signed with a different key");
+    }
+    if signature.algorithm != key.algorithm() {
+        bail!(
+            "the image has an {} signature, but the key is for {}",
+            algorithm_name(signature.algorithm),
+            algorithm_name(key.algorithm()),
+        );
+    }
+    if signature.image_len as usize != image.len() {
+        bail!(
+            "the signature covers {:#x} bytes, not {:#x}",
+            signature.image_len,
+            image.len()
+        );
+    }
+
+    match key {
+        PublicKey::EcdsaP256(key) => {
+            let sig =
+                p256::ecdsa::Signature::try_from(&signature.signature[..])
+                    .map_err(|e| anyhow!("malformed signature: {}", e))?;
+            p256::ecdsa::VerifyingKey::from(key)
+                .verify(image, &sig)
+                .map_err(|_| anyhow!("the signature does not match the image"))
+        }
+        PublicKey::Ed25519(key) => {
+            let sig =
+                ed25519_dalek::Signature::try_from(&signature.signature[..])
+                    .map_err(|e| anyhow!("malformed signature: {}", e))?;
+            key.verify_strict(image, &sig)
+                .map_err(|_| anyhow!("the signature does not match the image"))
+        }
+    }
+}
+
+#[cfg(test)]
+pub(crate) mod tests {
+    use super::*;
+    use crate::config::tests::load;
+
+    pub(crate) fn fake_cert(name: &str) -> PathBuf {
+        Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../../support/fake_certs")
+            .join(name)
+    }
+
+    /// Signs an image with `key`, and checks the signature against `public`
+    /// (the matching public key) and `other` (an unrelated key of the same
+    /// kind).
+    fn round_trip(key: PrivateKey, public: &str, other: PublicKey) {
+        let public = PublicKey::from_der_file(&fake_cert(public)).unwrap();
+        assert_eq!(public.key_id(), key.public().key_id());
+
+        let image: Vec<u8> = (0..1000).map(|i| (i * 7) as u8).collect();
+        let signature = key.sign(&image);
+        assert_eq!(signature.magic, abi::SIGNATURE_MAGIC);
+        assert_eq!(signature.algorithm, public.algorithm());
+        assert_eq!(signature.image_len, 1000);
+        verify(&public, &signature, &image).unwrap();
+
+        let mut tampered = image.clone();
+        tampered[500] ^= 1;
+        assert!(verify(&public, &signature, &tampered).is_err());
+        assert!(verify(&public, &signature, &image[..999]).is_err());
+
+        let mut bad = signature;
+        bad.signature[0] ^= 1;
+        assert!(verify(&public, &bad, &image).is_err());
+
+        // The wrong key is caught by its ID, and if the ID is forged, by the
+        // signature itself.
+        assert!(verify(&other, &signature, &image).is_err());
+        let mut forged = signature;
+        forged.key_id = other.key_id();
+        assert!(verify(&other, &forged, &image).is_err());
+    }
+
+    #[test]
+    fn p256_round_trip() {
+        let key = PrivateKey::from_der_file(&fake_cert("p256-private-key.der"))
+            .unwrap();
+        let other = p256::SecretKey::from_be_bytes(&[7; 32]).unwrap();
+        round_trip(
+            key,
+            "p256-public-key.der",
+            PublicKey::EcdsaP256(other.public_key()),
+        );
+    }
+
+    #[test]
+    fn ed25519_round_trip() {
+        let key =
+            PrivateKey::from_der_file(&fake_cert("ed25519-private-key.der"))
+                .unwrap();
+        let other = ed25519_dalek::SecretKey::from_bytes(&[7; 32]).unwrap();
+        round_trip(
+            key,
+            "ed25519-public-key.der",
+            PublicKey::Ed25519((&other).into()),
+        );
+    }
+
+    #[test]
+    fn wrong_algorithm() {
+        let p256 =
+            PrivateKey::from_der_file(&fake_cert("p256-private-key.der"))
+                .unwrap();
+        let ed25519 =
+            PrivateKey::from_der_file(&fake_cert("ed25519-private-key.der"))
+                .unwrap();
+        let image = [0x55; 64];
+        let mut signature = p256.sign(&image);
+        signature.key_id = ed25519.public().key_id();
+        assert!(verify(&ed25519.public(), &signature, &image).is_err());
+    }
+
+    #[test]
+    fn stage0_public_key_is_raw() {
+        let raw = |key: &str| {
+            let signing = format!(
+                "[image-signing]\npublic-key = {:?}\n",
+                fake_cert(key).display().to_string()
+            );
+            let toml =
+                load("riscv64imac-unknown-none-elf", "qemu-virt", &signing)
+                    .unwrap();
+            stage0_public_key(&toml).unwrap().unwrap()
+        };
+
+        // An uncompressed SEC1 point, and a bare Ed25519 key, in hex.
+        let p256 = raw("p256-public-key.der");
+        assert_eq!(p256.len(), 2 * 65);
+        assert!(p256.starts_with("04"));
+        assert_eq!(raw("ed25519-public-key.der").len(), 2 * 32);
+    }
+}
@@ -7,18 +7,24 @@ use std::path::PathBuf;
 use anyhow::{bail, Result};
 use clap::Parser;
 
-use crate::config::Config;
+use crate::config::{Config, ResolvedToml};
 
 mod auxflash;
+mod check;
 mod clippy;
 mod config;
+mod coredump;
 mod dist;
 mod elf;
 mod flash;
 mod graph;
 mod humility;
+mod image_signing;
 mod sizes;
+mod stack;
 mod task_slot;
+mod trace;
+mod verify;
 
 #[derive(Debug, Parser)]
 #[clap(max_term_width = 80, about = "extra tasks to help you work on Hubris")]
@@ -108,6 +114,73 @@ enum Xtask {
         dirty: bool,
     },
 
+    /// Runs `xtask dist` and estimates each task's worst-case stack usage,
+    /// suggesting a `stacksize` for each
+    Stack {
+        /// Request verbosity from tools we shell out to.
+        #[clap(short)]
+        verbose: bool,
+        /// Path to the image configuration file, in TOML.
+        cfg: PathBuf,
+        /// JSON file mapping task names to peak stack usage in bytes, as
+        /// measured on a running system, to combine with the estimates
+        #[clap(long)]
+        measured: Option<PathBuf>,
+        /// Allow operation in a dirty checkout, i.e. don't clean before
+        /// rebuilding even if it looks like we need to.
+        #[clap(long)]
+        dirty: bool,
+    },
+
+    /// Converts a dump of the kernel's trace buffer (the `KERNEL_TRACE`
+    /// symbol, when built with the kernel's `trace-buffer` feature) into a
+    /// Chrome/Perfetto trace
+    Trace {
+        /// Path to the image configuration file, in TOML.
+        cfg: PathBuf,
+        /// Raw dump of the `KERNEL_TRACE` symbol.
+        dump: PathBuf,
+        /// Where to write the trace JSON.
+        #[clap(short, long, default_value = "trace.json")]
+        output: PathBuf,
+    },
+
+    /// Converts a post-mortem task dump (the contents of the `dump` shared
+    /// memory region written by `task-dump-agent`) into an ELF core file
+    /// for GDB
+    Core {
+        /// Path to the image configuration file, in TOML.
+        cfg: PathBuf,
+        /// Raw dump of the `dump` shared memory region.
+        dump: PathBuf,
+        /// Where to write the core file.
+        #[clap(short, long, default_value = "core")]
+        output: PathBuf,
+    },
+
+    /// Checks an image configuration for structural errors, such as unknown
+    /// peripherals, conflicting interrupts, and task slot cycles, reporting
+    /// all of them at once. This is also run at the start of `dist`.
+    Check {
+        /// Path to the image configuration file, in TOML.
+        cfg: PathBuf,
+        /// Treat priority inversions (tasks with slots for tasks of equal or
+        /// lower priority) as errors rather than warnings.
+        #[clap(long)]
+        deny_inversions: bool,
+    },
+
+    /// Shows how an image configuration is assembled from the files it
+    /// inherits from
+    Config {
+        /// Path to the image configuration file, in TOML.
+        cfg: PathBuf,
+        /// Print the fully resolved configuration, with all inherited
+        /// files merged in.
+        #[clap(long)]
+        dump: bool,
+    },
+
     /// Runs `humility`, passing any arguments
     Humility {
         #[clap(flatten)]
@@ -147,6 +220,17 @@ enum Xtask {
         task_bin: PathBuf,
     },
 
+    /// Checks the signature block of the image in a build archive (see
+    /// `[image-signing]` in the app.toml)
+    Verify {
+        /// Path to the build archive (`build-<name>.zip`).
+        archive: PathBuf,
+        /// Public key (SubjectPublicKeyInfo, DER) the image must be signed
+        /// with. By default, the key recorded in the archive is used.
+        #[clap(long)]
+        key: Option<PathBuf>,
+    },
+
     /// Generate a graph of task_slot dependencies ordered by priority.
     ///
     /// Priority inversions are denoted by thick red arrows.
@@ -210,9 +294,10 @@ fn run(xtask: Xtask) -> Result<()> {
             cfg,
             dirty,
         } => {
-            let allocs = dist::package(verbose, edges, &cfg, None, dirty)?;
-            for (_, (a, _)) in allocs {
-                sizes::run(&cfg, &a, true, false, false)?;
+            let allocs =
+                dist::package(verbose, edges, false, &cfg, None, dirty)?;
+            for (_, (a, free)) in allocs {
+                sizes::run(&cfg, &a, &free, true, false, false)?;
             }
         }
         Xtask::Build {
@@ -226,11 +311,11 @@ fn run(xtask: Xtask) -> Result<()> {
             if list {
                 dist::list_tasks(&cfg)?;
             } else {
-                dist::package(verbose, edges, &cfg, Some(tasks), dirty)?;
+                dist::package(verbose, edges, false, &cfg, Some(tasks), dirty)?;
             }
         }
         Xtask::Flash { dirty, mut args } => {
-            dist::package(args.verbose, false, &args.cfg, None, dirty)?;
+            dist::package(args.verbose, false, false, &args.cfg, None, dirty)?;
             let toml = Config::from_file(&args.cfg)?;
             let chip = ["-c", crate::flash::chip_name(&toml.board)?];
             args.extra_options.push("--force".to_string());
@@ -253,9 +338,43 @@ fn run(xtask: Xtask) -> Result<()> {
             save,
             dirty,
         } => {
-            let allocs = dist::package(verbose, false, &cfg, None, dirty)?;
-            for (_, (a, _)) in allocs {
-                sizes::run(&cfg, &a, false, compare, save)?;
+            let allocs =
+                dist::package(verbose, false, false, &cfg, None, dirty)?;
+            for (_, (a, free)) in allocs {
+                sizes::run(&cfg, &a, &free, false, compare, save)?;
+            }
+        }
+        Xtask::Stack {
+            verbose,
+            cfg,
+            measured,
+            dirty,
+        } => {
+            dist::package(verbose, false, true, &cfg, None, dirty)?;
+            stack::run(&cfg, measured.as_deref())?;
+        }
+        Xtask::Trace { cfg, dump, output } => {
+            trace::run(&cfg, &dump, &output)?;
+        }
+        Xtask::Core { cfg, dump, output } => {
+            coredump::run(&cfg, &dump, &output)?;
+        }
+        Xtask::Check {
+            cfg,
+            deny_inversions,
+        } => {
+            check::run(&cfg, deny_inversions)?;
+        }
+        Xtask::Config { cfg, dump } => {
+            if dump {
+                let resolved = ResolvedToml::load(&cfg)?;
+                print!("{}", resolved.to_toml_string()?);
+            } else {
+                let toml = Config::from_file(&cfg)?;
+                println!("{}", cfg.display());
+                for path in &toml.inherited {
+                    println!("  inherits {}", path.display());
+                }
             }
         }
         Xtask::Humility { args } => {
@@ -281,7 +400,14 @@ fn run(xtask: Xtask) -> Result<()> {
                 &toml.image_names[0]
             };
             if !noflash {
-                dist::package(args.verbose, false, &args.cfg, None, false)?;
+                dist::package(
+                    args.verbose,
+                    false,
+                    false,
+                    &args.cfg,
+                    None,
+                    false,
+                )?;
                 // Delegate flashing to `humility gdb`, which also modifies
                 // the GDB startup script slightly (adding `stepi`)
                 args.extra_options.push("--load".to_string());
@@ -320,6 +446,9 @@ fn run(xtask: Xtask) -> Result<()> {
         Xtask::Graph { output, cfg } => {
             graph::task_graph(&cfg, &output)?;
         }
+        Xtask::Verify { archive, key } => {
+            verify::run(&archive, key.as_deref())?;
+        }
     }
 
     Ok(())
@@ -5,6 +5,7 @@
 use std::collections::{BTreeMap, BTreeSet};
 use std::fs;
 use std::io::Write;
+use std::ops::Range;
 use std::path::Path;
 use std::process;
 
@@ -31,6 +32,7 @@ struct TaskSizes<'a> {
 pub fn run(
     cfg: &Path,
     allocs: &Allocations,
+    free: &IndexMap<String, Range<u32>>,
     only_suggest: bool,
     compare: bool,
     save: bool,
@@ -66,6 +68,8 @@ pub fn run(
         print_memory_map(&toml, &map)?;
         print!("\n\n");
         print_task_table(&toml, &map)?;
+        print!("\n\n");
+        print_memory_summary(&map, free)?;
     }
 
     // Because tasks are autosized, the only place where we can improve
@@ -168,9 +172,53 @@ fn build_memory_map<'a>(
             );
         }
     }
+
+    for (name, alloc) in &allocs.shared_memory {
+        let size = alloc.end - alloc.start;
+        map.entry("ram").or_default().insert(
+            alloc.start,
+            MemoryChunk {
+                used_size: size.into(),
+                total_size: size,
+                owner: name,
+                recommended: Some(Recommended::FixedSize(size)),
+            },
+        );
+    }
     Ok(map)
 }
 
+/// Prints, for each memory, how much of it is used, and how much is wasted
+/// as slack (allocated to a program but unused by it), as padding between
+/// allocations, or left free at the end.
+fn print_memory_summary(
+    map: &BTreeMap<&str, BTreeMap<u32, MemoryChunk>>,
+    free: &IndexMap<String, Range<u32>>,
+) -> Result<()> {
+    println!(
+        "{:<8}  {:>8}  {:>8}  {:>8}  {:>8}",
+        "MEMORY", "USED", "SLACK", "PADDING", "FREE"
+    );
+    for (mem_name, map) in map {
+        let used: u64 = map.values().map(|c| c.used_size).sum();
+        let slack: u64 = map
+            .values()
+            .map(|c| u64::from(c.total_size) - c.used_size)
+            .sum();
+        let padding: u32 = map
+            .iter()
+            .zip(map.keys().skip(1))
+            .map(|((start, chunk), next)| next - (start + chunk.total_size))
+            .sum();
+        let free = free.get(*mem_name).map_or(0, |r| r.end - r.start);
+        println!(
+            "{:<8}  {:>8}  {:>8}  {:>8}  {:>8}",
+            mem_name, used, slack, padding, free
+        );
+    }
+    Ok(())
+}
+
 fn print_task_table(
     toml: &Config,
     map: &BTreeMap<&str, BTreeMap<u32, MemoryChunk>>,
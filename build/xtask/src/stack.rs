@@ -0,0 +1,579 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Stack size estimation.
+//!
+//! `xtask stack` builds tasks with `-Z emit-stack-sizes`, which records the
+//! size of each function's stack frame in a `.stack_sizes` section. We
+//! combine that with a call graph recovered by decoding direct calls in
+//! `.text` to estimate the worst-case stack depth starting from the task's
+//! entry point.
+//!
+//! The estimate is a lower bound whenever the graph is incomplete: indirect
+//! calls (trait objects, function pointers) can't be followed, functions
+//! without a `.stack_sizes` entry (hand-written assembly, mostly) are counted
+//! as zero, and recursion is only counted once around the loop. Such
+//! estimates are flagged in the output. To cover those cases, the estimate
+//! can be combined with peak usage measured on a running system, as reported
+//! by the kernel's `ReadStackUsage` kipc.
+
+use std::collections::{BTreeMap, BTreeSet};
+use std::path::Path;
+
+use anyhow::{anyhow, bail, Context, Result};
+use colored::*;
+use goblin::elf::Elf;
+
+use crate::Config;
+
+/// Space the architecture may push onto a task's stack on exception entry, on
+/// top of whatever the task itself is using.
+fn exception_frame_size(toml: &Config) -> u64 {
+    if toml.target.starts_with("thumbv") {
+        // ExtendedExceptionFrame with FPU state, the worst case.
+        26 * 4
+    } else {
+        // RISC-V saves all state into the task's TCB, not its stack.
+        0
+    }
+}
+
+#[derive(Copy, Clone, Debug, Default)]
+struct Estimate {
+    /// Worst-case stack depth in bytes, along the deepest known path.
+    bytes: u64,
+    /// Whether anything reachable defeated the analysis (see module docs).
+    complete: bool,
+    /// Whether the analysis hit recursion back into the current call path,
+    /// which makes the estimate depend on that path.
+    recursive: bool,
+}
+
+struct Function {
+    /// Frame size from `.stack_sizes`, if the compiler recorded one.
+    frame: Option<u64>,
+    /// Addresses of functions this one calls directly.
+    callees: BTreeSet<u64>,
+    /// Whether this function makes calls we couldn't resolve.
+    indirect: bool,
+}
+
+/// Estimates the worst-case stack depth of the ELF file in `buffer`,
+/// starting from its entry point.
+fn estimate(buffer: &[u8]) -> Result<Estimate> {
+    let elf = Elf::parse(buffer)?;
+    let thumb = elf.header.e_machine == goblin::elf::header::EM_ARM;
+
+    let frames = parse_stack_sizes(&elf, buffer)?;
+
+    // Collect every function symbol along with the instructions it covers.
+    let mut functions = BTreeMap::new();
+    for sym in elf.syms.iter() {
+        if !sym.is_function() || sym.st_size == 0 {
+            continue;
+        }
+        let addr = sym.st_value & !1;
+        if functions.contains_key(&addr) {
+            continue;
+        }
+        let text = match file_range(&elf, addr, sym.st_size) {
+            Some(range) => &buffer[range],
+            None => continue,
+        };
+        let range = addr..addr + sym.st_size;
+        let calls = if thumb {
+            thumb_calls(addr, text)
+        } else {
+            riscv_calls(addr, text, elf.is_64)
+        };
+        let mut callees = BTreeSet::new();
+        let mut indirect = false;
+        for call in calls {
+            match call {
+                Call::Direct(target) if !range.contains(&target) => {
+                    callees.insert(target);
+                }
+                Call::Direct(_) => (),
+                Call::Indirect => indirect = true,
+            }
+        }
+        functions.insert(
+            addr,
+            Function {
+                frame: frames.get(&addr).copied(),
+                callees,
+                indirect,
+            },
+        );
+    }
+
+    let entry = elf.entry & !1;
+    if !functions.contains_key(&entry) {
+        bail!("entry point {:#x} is not a known function", entry);
+    }
+    let mut memo = BTreeMap::new();
+    Ok(depth(entry, &functions, &mut memo, &mut BTreeSet::new()))
+}
+
+fn depth(
+    addr: u64,
+    functions: &BTreeMap<u64, Function>,
+    memo: &mut BTreeMap<u64, Estimate>,
+    path: &mut BTreeSet<u64>,
+) -> Estimate {
+    if let Some(e) = memo.get(&addr) {
+        return *e;
+    }
+    let f = match functions.get(&addr) {
+        Some(f) => f,
+        // Calls into something without a symbol, e.g. a veneer.
+        None => {
+            return Estimate {
+                bytes: 0,
+                complete: false,
+                recursive: false,
+            }
+        }
+    };
+    if !path.insert(addr) {
+        // Recursion: we've already counted this frame once on this path.
+        return Estimate {
+            bytes: 0,
+            complete: false,
+            recursive: true,
+        };
+    }
+
+    let mut deepest = Estimate {
+        bytes: 0,
+        complete: true,
+        recursive: false,
+    };
+    for &callee in &f.callees {
+        let e = depth(callee, functions, memo, path);
+        deepest.bytes = deepest.bytes.max(e.bytes);
+        deepest.complete &= e.complete;
+        deepest.recursive |= e.recursive;
+    }
+    path.remove(&addr);
+
+    let result = Estimate {
+        bytes: f.frame.unwrap_or(0) + deepest.bytes,
+        complete: deepest.complete && f.frame.is_some() && !f.indirect,
+        recursive: deepest.recursive,
+    };
+    // Only memoize results that don't depend on the current path, i.e. ones
+    // that didn't run into recursion. Results that are incomplete for other
+    // reasons are the same from every path.
+    if !result.recursive {
+        memo.insert(addr, result);
+    }
+    result
+}
+
+/// Parses the `.stack_sizes` section, which is a sequence of (function
+/// address, ULEB128 frame size) pairs.
+fn parse_stack_sizes(elf: &Elf, buffer: &[u8]) -> Result<BTreeMap<u64, u64>> {
+    let sh = elf
+        .section_headers
+        .iter()
+        .find(|sh| elf.shdr_strtab.get_at(sh.sh_name) == Some(".stack_sizes"))
+        .context("no .stack_sizes section; was the task built by xtask?")?;
+    let mut data = &buffer[sh.file_range().unwrap_or_default()];
+
+    let addr_size = if elf.is_64 { 8 } else { 4 };
+    let mut frames = BTreeMap::new();
+    while !data.is_empty() {
+        if data.len() < addr_size {
+            bail!("truncated .stack_sizes section");
+        }
+        let (addr, rest) = data.split_at(addr_size);
+        let mut addr_bytes = [0; 8];
+        addr_bytes[..addr_size].copy_from_slice(addr);
+        let addr = u64::from_le_bytes(addr_bytes);
+
+        let mut size = 0u64;
+        let mut shift = 0;
+        let mut used = 0;
+        for &b in rest {
+            size |= u64::from(b & 0x7f) << shift;
+            shift += 7;
+            used += 1;
+            if b & 0x80 == 0 {
+                break;
+            }
+        }
+        data = &rest[used..];
+        frames.insert(addr & !1, size);
+    }
+    Ok(frames)
+}
+
+/// Maps a virtual address range to a range of the ELF file.
+fn file_range(
+    elf: &Elf,
+    addr: u64,
+    size: u64,
+) -> Option<std::ops::Range<usize>> {
+    elf.section_headers
+        .iter()
+        .filter(|sh| sh.sh_type == goblin::elf::section_header::SHT_PROGBITS)
+        .find(|sh| addr >= sh.sh_addr && addr + size <= sh.sh_addr + sh.sh_size)
+        .map(|sh| {
+            let start = (sh.sh_offset + addr - sh.sh_addr) as usize;
+            start..start + size as usize
+        })
+}
+
+#[derive(Debug, PartialEq)]
+enum Call {
+    Direct(u64),
+    Indirect,
+}
+
+/// Finds calls (and tail calls) in a run of Thumb code.
+fn thumb_calls(addr: u64, text: &[u8]) -> Vec<Call> {
+    let mut calls = vec![];
+    let mut pc = 0;
+    while pc + 2 <= text.len() {
+        let hw1 = u16::from_le_bytes([text[pc], text[pc + 1]]) as u32;
+        let wide = matches!(hw1 >> 11, 0b11101..=0b11111);
+        if !wide {
+            // BLX <reg>, or BX <reg> other than BX LR.
+            if hw1 & 0xff87 == 0x4780
+                || (hw1 & 0xff87 == 0x4700 && (hw1 >> 3) & 0xf != 14)
+            {
+                calls.push(Call::Indirect);
+            }
+            pc += 2;
+            continue;
+        }
+        if pc + 4 > text.len() {
+            break;
+        }
+        let hw2 = u16::from_le_bytes([text[pc + 2], text[pc + 3]]) as u32;
+        // BL (11x1 in the top of hw2) and B.W encoding T4 (10x1).
+        if hw1 >> 11 == 0b11110
+            && (hw2 & 0xd000 == 0xd000 || hw2 & 0xd000 == 0x9000)
+        {
+            let s = (hw1 >> 10) & 1;
+            let j1 = (hw2 >> 13) & 1;
+            let j2 = (hw2 >> 11) & 1;
+            let i1 = !(j1 ^ s) & 1;
+            let i2 = !(j2 ^ s) & 1;
+            let imm = (s << 24)
+                | (i1 << 23)
+                | (i2 << 22)
+                | ((hw1 & 0x3ff) << 12)
+                | ((hw2 & 0x7ff) << 1);
+            // Sign-extend from 25 bits.
+            let imm = ((imm << 7) as i32 >> 7) as i64;
+            let target = (addr as i64 + pc as i64 + 4 + imm) as u64;
+            calls.push(Call::Direct(target));
+        }
+        pc += 4;
+    }
+    calls
+}
+
+/// Finds calls (and tail calls) in a run of RISC-V code.
+fn riscv_calls(addr: u64, text: &[u8], rv64: bool) -> Vec<Call> {
+    let mut calls = vec![];
+    let mut pc = 0;
+    // Most recent AUIPC, as (destination register, computed value), so that
+    // we can resolve `call` sequences that weren't relaxed to a JAL.
+    let mut auipc: Option<(u32, u64)> = None;
+    while pc + 2 <= text.len() {
+        let here = addr + pc as u64;
+        let lo = u16::from_le_bytes([text[pc], text[pc + 1]]) as u32;
+        if lo & 0b11 != 0b11 {
+            // Compressed. C.JAL and C.J share an immediate layout; C.JALR
+            // and C.JR are indirect. On RV64, C.JAL's encoding is C.ADDIW.
+            let funct3 = lo >> 13;
+            let op = lo & 0b11;
+            if op == 0b01 && ((funct3 == 0b001 && !rv64) || funct3 == 0b101) {
+                let imm = ((lo >> 12) & 1) << 11
+                    | ((lo >> 11) & 1) << 4
+                    | ((lo >> 9) & 0b11) << 8
+                    | ((lo >> 8) & 1) << 10
+                    | ((lo >> 7) & 1) << 6
+                    | ((lo >> 6) & 1) << 7
+                    | ((lo >> 3) & 0b111) << 1
+                    | ((lo >> 2) & 1) << 5;
+                let imm = ((imm << 20) as i32 >> 20) as i64;
+                calls.push(Call::Direct((here as i64 + imm) as u64));
+            } else if op == 0b10
+                && funct3 == 0b100
+                && (lo >> 7) & 0x1f != 0
+                && (lo >> 2) & 0x1f == 0
+            {
+                // C.JR/C.JALR. C.JR ra is a return.
+                let rs1 = (lo >> 7) & 0x1f;
+                if (lo >> 12) & 1 == 1 || rs1 != 1 {
+                    calls.push(Call::Indirect);
+                }
+            }
+            pc += 2;
+            continue;
+        }
+        if pc + 4 > text.len() {
+            break;
+        }
+        let insn = u32::from_le_bytes([
+            text[pc],
+            text[pc + 1],
+            text[pc + 2],
+            text[pc + 3],
+        ]);
+        let opcode = insn & 0x7f;
+        let rd = (insn >> 7) & 0x1f;
+        let rs1 = (insn >> 15) & 0x1f;
+        match opcode {
+            // JAL
+            0x6f => {
+                let imm = ((insn >> 31) & 1) << 20
+                    | ((insn >> 21) & 0x3ff) << 1
+                    | ((insn >> 20) & 1) << 11
+                    | ((insn >> 12) & 0xff) << 12;
+                let imm = ((imm << 11) as i32 >> 11) as i64;
+                calls.push(Call::Direct((here as i64 + imm) as u64));
+            }
+            // AUIPC
+            0x17 => {
+                let imm = (insn & 0xffff_f000) as i32 as i64;
+                auipc = Some((rd, (here as i64 + imm) as u64));
+                pc += 4;
+                continue;
+            }
+            // JALR
+            0x67 => {
+                let imm = (insn as i32 >> 20) as i64;
+                match auipc {
+                    Some((reg, base)) if reg == rs1 => {
+                        calls.push(Call::Direct((base as i64 + imm) as u64));
+                    }
+                    // `ret`
+                    _ if rd == 0 && rs1 == 1 && imm == 0 => (),
+                    _ => calls.push(Call::Indirect),
+                }
+            }
+            _ => (),
+        }
+        auipc = None;
+        pc += 4;
+    }
+    calls
+}
+
+/// Prints estimated and (optionally) measured stack usage for each task,
+/// along with a suggested `stacksize`.
+///
+/// `measured` is a JSON file mapping task names to peak stack usage in bytes,
+/// as read from a running system through the `ReadStackUsage` kipc.
+pub fn run(cfg: &Path, measured: Option<&Path>) -> Result<()> {
+    let toml = Config::from_file(cfg)?;
+    let measured: BTreeMap<String, u64> = match measured {
+        Some(path) => serde_json::from_slice(
+            &std::fs::read(path)
+                .with_context(|| format!("reading {}", path.display()))?,
+        )?,
+        None => BTreeMap::new(),
+    };
+    let exception_frame = exception_frame_size(&toml);
+
+    let task_pad = toml.tasks.keys().map(|k| k.len()).max().unwrap_or(0);
+    println!(
+        "{:<task$}  {:>9}  {:>9}  {:>9}  {:>9}",
+        "TASK",
+        "STACKSIZE",
+        "ESTIMATE",
+        "MEASURED",
+        "SUGGESTED",
+        task = task_pad,
+    );
+
+    let mut any_incomplete = false;
+    for (name, task) in &toml.tasks {
+        let stacksize = task.stacksize.or(toml.stacksize).ok_or_else(|| {
+            anyhow!("{}: no stack size specified and there is no default", name)
+        })? as u64;
+        let elf_name = Path::new("target")
+            .join(&toml.name)
+            .join("dist")
+            .join(format!("{}.tmp", name));
+        let buffer = std::fs::read(&elf_name)
+            .with_context(|| format!("reading {}", elf_name.display()))?;
+        let estimate = estimate(&buffer)
+            .with_context(|| format!("estimating stack for {}", name))?;
+        any_incomplete |= !estimate.complete;
+
+        let static_bytes = estimate.bytes + exception_frame;
+        let measured = measured.get(name).copied();
+        let needed = static_bytes.max(measured.unwrap_or(0));
+        // Leave an eighth again as margin, and keep the 8-byte alignment the
+        // stack requires.
+        let suggested = (needed + needed / 8 + 7) & !7;
+
+        let estimate_str = format!(
+            "{}{}",
+            static_bytes,
+            if estimate.complete { "" } else { "+" }
+        );
+        let measured_str = measured
+            .map(|m| m.to_string())
+            .unwrap_or_else(|| "-".into());
+        let suggested_str = format!("{:>9}", suggested);
+        println!(
+            "{:<task$}  {:>9}  {:>9}  {:>9}  {}",
+            name,
+            stacksize,
+            estimate_str,
+            measured_str,
+            if suggested > stacksize {
+                suggested_str.red()
+            } else if suggested < stacksize {
+                suggested_str.green()
+            } else {
+                suggested_str.normal()
+            },
+            task = task_pad,
+        );
+    }
+
+    if any_incomplete {
+        println!(
+            "\n{}: estimates marked `+` involve indirect calls, recursion, \
+             or code without frame information, and may be too low",
+            "note".bold()
+        );
+    }
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    fn function(
+        frame: Option<u64>,
+        callees: &[u64],
+        indirect: bool,
+    ) -> Function {
+        Function {
+            frame,
+            callees: callees.iter().copied().collect(),
+            indirect,
+        }
+    }
+
+    #[test]
+    fn depth_memoizes_all_but_recursion() {
+        let functions = [
+            (0, function(Some(8), &[1, 2], false)),
+            (1, function(Some(16), &[3], false)),
+            (2, function(Some(32), &[2], false)),
+            (3, function(Some(64), &[], true)),
+        ]
+        .into_iter()
+        .collect();
+        let mut memo = BTreeMap::new();
+        let e = depth(0, &functions, &mut memo, &mut BTreeSet::new());
+        assert_eq!(e.bytes, 8 + 16 + 64);
+        assert!(!e.complete);
+
+        // The indirect call makes 1 and 3 incomplete wherever they're called
+        // from, but 2's estimate, and its caller's, came from recursion.
+        assert_eq!(memo.keys().copied().collect::<Vec<_>>(), [1, 3]);
+    }
+
+    #[test]
+    fn thumb() {
+        let text = [
+            0x00, 0xf0, 0x7e, 0xf8, // bl    start+0x100
+            0xff, 0xf7, 0xf4, 0xbf, // b.w   start-0x10
+            0x98, 0x47, // blx r3
+            0x10, 0x47, // bx  r2
+            0x70, 0x47, // bx  lr
+            0xff, 0xf7, 0xeb, 0xff, // bl    start-0x18
+        ];
+        let start = 0x0800_0000;
+        assert_eq!(
+            thumb_calls(start, &text),
+            [
+                Call::Direct(start + 0x100),
+                Call::Direct(start - 0x10),
+                Call::Indirect,
+                Call::Indirect,
+                Call::Direct(start - 0x18),
+            ]
+        );
+    }
+
+    #[test]
+    fn riscv() {
+        let text = [
+            0xef, 0x00, 0x00, 0x10, // jal   ra, start+0x100
+            0x6f, 0xf0, 0x1f, 0xfe, // jal   zero, start-0x1c
+            0x97, 0x10, 0x00, 0x00, // auipc ra, 1
+            0xe7, 0x80, 0x00, 0x01, // jalr  ra, 16(ra)
+            0xe7, 0x80, 0x07, 0x00, // jalr  ra, 0(a5)
+            0x67, 0x80, 0x00, 0x00, // jalr  zero, 0(ra)
+        ];
+        let start = 0x8000_0000;
+        assert_eq!(
+            riscv_calls(start, &text, false),
+            [
+                Call::Direct(start + 0x100),
+                Call::Direct(start - 0x1c),
+                Call::Direct(start + 0x1018),
+                Call::Indirect,
+            ]
+        );
+    }
+
+    #[test]
+    fn riscv_compressed() {
+        let text = [
+            0x01, 0x22, // c.jal   start+0x100
+            0xc5, 0xb7, // c.j     start-0x1e
+            0x97, 0x10, 0x00, 0x00, // auipc ra, 1
+            0xe7, 0x80, 0x00, 0x01, // jalr  ra, 16(ra)
+            0x82, 0x97, // c.jalr  a5
+            0x82, 0x80, // c.jr    ra
+            0x81, 0xa0, // c.j     start+0x50
+            0xe5, 0x3f, // c.jal   start+0xa
+            0x02, 0x95, // c.jalr  a0
+            0x82, 0x85, // c.jr    a1
+        ];
+        let start = 0x8000_0000;
+        assert_eq!(
+            riscv_calls(start, &text, false),
+            [
+                Call::Direct(start + 0x100),
+                Call::Direct(start - 0x1e),
+                Call::Direct(start + 0x1014),
+                Call::Indirect,
+                Call::Direct(start + 0x50),
+                Call::Direct(start + 0xa),
+                Call::Indirect,
+                Call::Indirect,
+            ]
+        );
+    }
+
+    #[test]
+    fn riscv64_compressed() {
+        let text = [
+            0x01, 0x22, // c.addiw tp, 0
+            0xc5, 0xb7, // c.j     start-0x1e
+            0x82, 0x97, // c.jalr  a5
+            0x82, 0x80, // c.jr    ra
+        ];
+        let start = 0x8000_0000;
+        assert_eq!(
+            riscv_calls(start, &text, true),
+            [Call::Direct(start - 0x1e), Call::Indirect]
+        );
+    }
+}
@@ -0,0 +1,315 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Decoder for the kernel's trace buffer (`kern::profiling::trace`).
+//!
+//! The input is a raw dump of the `KERNEL_TRACE` symbol from a running (or
+//! halted) system. The output is a trace in the Chrome trace event format,
+//! which can be loaded into Perfetto or `chrome://tracing`: each task gets a
+//! track showing when it was scheduled, and the kernel gets a track showing
+//! syscalls and interrupts.
+
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use serde_json::{json, Value};
+
+use crate::Config;
+
+// These must match `kern::profiling::trace`.
+const TRACE_MAGIC: u32 = 0x5452_4143;
+const HEADER_SIZE: usize = 16;
+const RECORD_SIZE: usize = 16;
+
+const SYSCALL_ENTER: u32 = 1;
+const SYSCALL_EXIT: u32 = 2;
+const SECONDARY_SYSCALL_ENTER: u32 = 3;
+const SECONDARY_SYSCALL_EXIT: u32 = 4;
+const ISR_ENTER: u32 = 5;
+const ISR_EXIT: u32 = 6;
+const TIMER_ISR_ENTER: u32 = 7;
+const TIMER_ISR_EXIT: u32 = 8;
+const CONTEXT_SWITCH: u32 = 9;
+
+/// Thread ID used for the kernel's track; tasks use their index + 1.
+const KERNEL_TID: u32 = 0;
+
+struct Record {
+    timestamp: u64,
+    kind: u32,
+    arg: u32,
+}
+
+fn u32_at(buf: &[u8], offset: usize) -> u32 {
+    u32::from_le_bytes(buf[offset..offset + 4].try_into().unwrap())
+}
+
+/// Parses a dump of the trace buffer into its records, oldest first, along
+/// with the number of run-time counter ticks per millisecond.
+fn parse(buf: &[u8]) -> Result<(u32, Vec<Record>)> {
+    if buf.len() < HEADER_SIZE {
+        bail!("trace dump is too short to hold a header");
+    }
+    let magic = u32_at(buf, 0);
+    if magic != TRACE_MAGIC {
+        bail!(
+            "bad trace magic {:#010x}; was the trace buffer installed?",
+            magic
+        );
+    }
+    let counts_per_ms = u32_at(buf, 4);
+    let capacity = u32_at(buf, 8) as usize;
+    let written = u32_at(buf, 12) as usize;
+    if capacity == 0 {
+        bail!("trace buffer has no room for records");
+    }
+    if buf.len() < HEADER_SIZE + capacity * RECORD_SIZE {
+        bail!("trace dump is truncated: expected {} records", capacity);
+    }
+
+    let (start, count) = if written > capacity {
+        (written % capacity, capacity)
+    } else {
+        (0, written)
+    };
+    let records = (0..count)
+        .map(|i| {
+            let offset = HEADER_SIZE + ((start + i) % capacity) * RECORD_SIZE;
+            Record {
+                timestamp: u64::from(u32_at(buf, offset))
+                    | u64::from(u32_at(buf, offset + 4)) << 32,
+                kind: u32_at(buf, offset + 8),
+                arg: u32_at(buf, offset + 12),
+            }
+        })
+        .collect();
+    Ok((counts_per_ms, records))
+}
+
+fn syscall_name(nr: u32) -> String {
+    use abi::Sysnum;
+    match Sysnum::try_from(nr) {
+        Ok(Sysnum::Send) => "send",
+        Ok(Sysnum::Recv) => "recv",
+        Ok(Sysnum::Reply) => "reply",
+        Ok(Sysnum::SetTimer) => "set_timer",
+        Ok(Sysnum::BorrowRead) => "borrow_read",
+        Ok(Sysnum::BorrowWrite) => "borrow_write",
+        Ok(Sysnum::BorrowInfo) => "borrow_info",
+        Ok(Sysnum::IrqControl) => "irq_control",
+        Ok(Sysnum::Panic) => "panic",
+        Ok(Sysnum::GetTimer) => "get_timer",
+        Ok(Sysnum::RefreshTaskId) => "refresh_task_id",
+        Ok(Sysnum::Post) => "post",
+        Ok(Sysnum::ReplyFault) => "reply_fault",
+        Ok(Sysnum::SendWithTimeout) => "send_with_timeout",
+        Err(()) => return format!("syscall {}", nr),
+    }
+    .to_string()
+}
+
+fn kernel_event(ph: &str, name: String, ts: f64) -> Value {
+    json!({
+        "ph": ph, "name": name, "pid": 0, "tid": KERNEL_TID, "ts": ts,
+    })
+}
+
+/// Converts a dump of the kernel trace buffer into a Chrome trace.
+pub fn run(cfg: &Path, dump: &Path, output: &Path) -> Result<()> {
+    let toml = Config::from_file(cfg)?;
+    let buf = std::fs::read(dump)
+        .with_context(|| format!("reading {}", dump.display()))?;
+    let (counts_per_ms, records) = parse(&buf)?;
+    let task_names: Vec<&str> = toml.tasks.keys().map(|k| k.as_str()).collect();
+    let events = trace_events(&task_names, counts_per_ms, &records)?;
+
+    let trace = json!({ "traceEvents": events, "displayTimeUnit": "ns" });
+    std::fs::write(output, serde_json::to_string(&trace)?)
+        .with_context(|| format!("writing {}", output.display()))?;
+    let first = records.first().unwrap().timestamp;
+    let last = records.last().unwrap().timestamp;
+    println!(
+        "wrote {} records ({:.3} ms) to {}",
+        records.len(),
+        last.wrapping_sub(first) as f64 / f64::from(counts_per_ms),
+        output.display()
+    );
+    Ok(())
+}
+
+/// Converts trace records into Chrome trace events. Records naming a task
+/// that isn't in `task_names` (such as the idle loop) land on a shared
+/// "unknown" track after the tasks' own.
+fn trace_events(
+    task_names: &[&str],
+    counts_per_ms: u32,
+    records: &[Record],
+) -> Result<Vec<Value>> {
+    if counts_per_ms == 0 {
+        bail!("trace buffer does not record its counter rate");
+    }
+    let epoch = match records.first() {
+        Some(first) => first.timestamp,
+        None => bail!("trace buffer is empty"),
+    };
+    // Chrome traces are in (fractional) microseconds.
+    let to_us = |t: u64| {
+        (t.wrapping_sub(epoch)) as f64 * 1000.0 / f64::from(counts_per_ms)
+    };
+
+    let mut events: Vec<Value> = vec![json!({
+        "ph": "M", "name": "thread_name", "pid": 0, "tid": KERNEL_TID,
+        "args": { "name": "kernel" },
+    })];
+    for (i, name) in task_names.iter().enumerate() {
+        events.push(json!({
+            "ph": "M", "name": "thread_name", "pid": 0, "tid": i + 1,
+            "args": { "name": name },
+        }));
+    }
+    let unknown_tid = task_names.len() + 1;
+    let mut unknown_named = false;
+
+    // The task running at the start of the trace isn't known until the first
+    // context switch, so task slices start there.
+    let mut running: Option<(u32, f64)> = None;
+    for r in records {
+        let ts = to_us(r.timestamp);
+        let kernel = |ph: &str, name: String| kernel_event(ph, name, ts);
+        match r.kind {
+            SYSCALL_ENTER => events.push(kernel("B", syscall_name(r.arg))),
+            SECONDARY_SYSCALL_ENTER => {
+                events.push(kernel("B", "secondary syscall".into()))
+            }
+            ISR_ENTER => events.push(kernel("B", "isr".into())),
+            TIMER_ISR_ENTER => events.push(kernel("B", "timer isr".into())),
+            SYSCALL_EXIT
+            | SECONDARY_SYSCALL_EXIT
+            | ISR_EXIT
+            | TIMER_ISR_EXIT => events.push(json!({
+                "ph": "E", "pid": 0, "tid": KERNEL_TID, "ts": ts,
+            })),
+            CONTEXT_SWITCH => {
+                if let Some((task, start)) = running {
+                    let (name, tid) = match task_names.get(task as usize) {
+                        Some(name) => (*name, task as usize + 1),
+                        None => {
+                            if !unknown_named {
+                                unknown_named = true;
+                                events.push(json!({
+                                    "ph": "M", "name": "thread_name",
+                                    "pid": 0, "tid": unknown_tid,
+                                    "args": { "name": "unknown" },
+                                }));
+                            }
+                            ("unknown", unknown_tid)
+                        }
+                    };
+                    events.push(json!({
+                        "ph": "X", "name": name, "pid": 0, "tid": tid,
+                        "ts": start, "dur": ts - start,
+                    }));
+                }
+                running = Some((r.arg, ts));
+            }
+            0 => (),
+            k => bail!("unknown trace record kind {}", k),
+        }
+    }
+    Ok(events)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    /// Builds a dump of a trace buffer with room for `capacity` records,
+    /// after `written` records have been written. Record `n` (counting from
+    /// zero, in the order they were written) is a context switch to task `n`
+    /// at time `n * 10`.
+    fn dump(capacity: u32, written: u32) -> Vec<u8> {
+        let mut buf = vec![];
+        for word in [TRACE_MAGIC, 1000, capacity, written] {
+            buf.extend_from_slice(&word.to_le_bytes());
+        }
+        buf.resize(HEADER_SIZE + capacity as usize * RECORD_SIZE, 0);
+        for n in 0..written {
+            let offset = HEADER_SIZE + (n % capacity) as usize * RECORD_SIZE;
+            let record = &mut buf[offset..offset + RECORD_SIZE];
+            record[..8].copy_from_slice(&(u64::from(n) * 10).to_le_bytes());
+            record[8..12].copy_from_slice(&CONTEXT_SWITCH.to_le_bytes());
+            record[12..].copy_from_slice(&n.to_le_bytes());
+        }
+        buf
+    }
+
+    fn summary(records: &[Record]) -> Vec<(u64, u32, u32)> {
+        records
+            .iter()
+            .map(|r| (r.timestamp, r.kind, r.arg))
+            .collect()
+    }
+
+    #[test]
+    fn parse_partial_buffer() {
+        let (counts_per_ms, records) = parse(&dump(4, 2)).unwrap();
+        assert_eq!(counts_per_ms, 1000);
+        assert_eq!(
+            summary(&records),
+            [(0, CONTEXT_SWITCH, 0), (10, CONTEXT_SWITCH, 1)]
+        );
+    }
+
+    #[test]
+    fn parse_wrapped_buffer() {
+        // The two oldest records have been overwritten.
+        let (_, records) = parse(&dump(3, 5)).unwrap();
+        assert_eq!(
+            summary(&records),
+            [
+                (20, CONTEXT_SWITCH, 2),
+                (30, CONTEXT_SWITCH, 3),
+                (40, CONTEXT_SWITCH, 4),
+            ]
+        );
+    }
+
+    #[test]
+    fn parse_rejects_bad_dumps() {
+        let mut buf = dump(4, 2);
+        assert!(parse(&buf[..HEADER_SIZE - 1]).is_err());
+        assert!(parse(&buf[..buf.len() - 1]).is_err());
+        buf[0] = 0;
+        assert!(parse(&buf).is_err());
+    }
+
+    #[test]
+    fn parse_rejects_zero_capacity() {
+        let mut buf = dump(0, 0);
+        buf[12..16].copy_from_slice(&3u32.to_le_bytes());
+        assert!(parse(&buf).is_err());
+    }
+
+    #[test]
+    fn unknown_tasks_get_their_own_track() {
+        let record = |timestamp, arg| Record {
+            timestamp,
+            kind: CONTEXT_SWITCH,
+            arg,
+        };
+        let records = [record(0, u32::MAX), record(10, 0), record(20, 7)];
+        let events = trace_events(&["a", "b"], 1000, &records).unwrap();
+        let slices: Vec<_> = events
+            .iter()
+            .filter(|e| e["ph"] == "X")
+            .map(|e| (e["name"].as_str().unwrap(), e["tid"].as_u64().unwrap()))
+            .collect();
+        assert_eq!(slices, [("unknown", 3), ("a", 1)]);
+        let unknown_tracks = events
+            .iter()
+            .filter(|e| e["ph"] == "M" && e["args"]["name"] == "unknown")
+            .count();
+        assert_eq!(unknown_tracks, 1);
+    }
+}
@@ -0,0 +1,202 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Checks the signature block (`abi::ImageSignature`) of the image in a build
+//! archive.
+//!
+//! The image is read from `img/combined.srec`, which is what was signed:
+//! chip-specific signing (such as the LPC55's `[signing]`) may wrap the image
+//! again on its way to `final`. The signature block is the last thing in the
+//! image's flash, so it's found at the end of the first contiguous run of
+//! data.
+
+use std::collections::BTreeMap;
+use std::fs::File;
+use std::io::Read;
+use std::path::Path;
+
+use anyhow::{bail, Context, Result};
+use colored::*;
+use zerocopy::FromBytes;
+
+use crate::image_signing::{self, PublicKey};
+
+fn read_file(
+    zip: &mut zip::ZipArchive<File>,
+    name: &str,
+) -> Result<Option<Vec<u8>>> {
+    let mut file = match zip.by_name(name) {
+        Ok(file) => file,
+        Err(zip::result::ZipError::FileNotFound) => return Ok(None),
+        Err(e) => return Err(e.into()),
+    };
+    let mut out = vec![];
+    file.read_to_end(&mut out)?;
+    Ok(Some(out))
+}
+
+/// Returns the address of the lowest record in `srec`, and the data from
+/// there up to the first gap.
+fn first_run(srec: &str) -> Result<(u32, Vec<u8>)> {
+    let mut records = BTreeMap::new();
+    for record in srec::reader::read_records(srec) {
+        if let srec::Record::S3(data) = record? {
+            records.insert(data.address.0, data.data);
+        }
+    }
+
+    let start = match records.keys().next() {
+        Some(start) => *start,
+        None => bail!("the image is empty"),
+    };
+    let mut image = vec![];
+    for (addr, data) in records {
+        let next = start + image.len() as u32;
+        if addr > next {
+            break;
+        } else if addr < next {
+            bail!("image records overlap at {:#010x}", addr);
+        }
+        image.extend_from_slice(&data);
+    }
+    Ok((start, image))
+}
+
+/// Checks the signature block at the end of `image` against `key`, returning
+/// the block.
+fn check(image: &[u8], key: &PublicKey) -> Result<abi::ImageSignature> {
+    let block_len = core::mem::size_of::<abi::ImageSignature>();
+    if image.len() < block_len {
+        bail!("the image is too short to have a signature block");
+    }
+    let (signed, block) = image.split_at(image.len() - block_len);
+    let signature = abi::ImageSignature::read_from(block).unwrap();
+    if signature.magic != abi::SIGNATURE_MAGIC {
+        bail!(
+            "the image has no signature block; was it built with \
+             `[image-signing] private-key`?"
+        );
+    }
+    let image_len = signature.image_len as usize;
+    if (image_len + 3) & !3 != signed.len() {
+        bail!(
+            "the signature block is at {:#x} in the image, but says it \
+             covers {:#x} bytes",
+            signed.len(),
+            image_len
+        );
+    }
+
+    image_signing::verify(key, &signature, &signed[..image_len])?;
+    Ok(signature)
+}
+
+pub fn run(archive: &Path, key: Option<&Path>) -> Result<()> {
+    let file = File::open(archive)
+        .with_context(|| format!("opening {}", archive.display()))?;
+    let mut zip = zip::ZipArchive::new(file)
+        .with_context(|| format!("reading {}", archive.display()))?;
+
+    let srec = read_file(&mut zip, "img/combined.srec")?
+        .context("the archive has no img/combined.srec")?;
+    let (start, image) = first_run(std::str::from_utf8(&srec)?)?;
+
+    let key = match key {
+        Some(path) => PublicKey::from_der_file(path)?,
+        None => {
+            let der = read_file(&mut zip, "img/image-signing-key.der")?
+                .context(
+                    "the archive doesn't say what key signed it; \
+                     pass one with --key",
+                )?;
+            println!(
+                "{}: checking against the key in the archive, which only \
+                 shows the image is intact",
+                "note".bold()
+            );
+            PublicKey::from_der(&der)?
+        }
+    };
+
+    let signature = check(&image, &key)?;
+    println!(
+        "{} signature over {:#010x}..{:#010x} is valid",
+        image_signing::algorithm_name(signature.algorithm),
+        start,
+        start + signature.image_len
+    );
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::image_signing::PrivateKey;
+    use zerocopy::AsBytes;
+
+    fn key() -> PrivateKey {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../../support/fake_certs/p256-private-key.der");
+        PrivateKey::from_der_file(&path).unwrap()
+    }
+
+    /// Lays out a signed image as `dist` does: `len` bytes of image, padding
+    /// to a 4-byte boundary, then the signature block.
+    fn signed_image(len: usize) -> Vec<u8> {
+        let mut image: Vec<u8> = (0..len).map(|i| (i * 3) as u8).collect();
+        let signature = key().sign(&image);
+        image.resize((len + 3) & !3, 0xff);
+        image.extend_from_slice(signature.as_bytes());
+        image
+    }
+
+    #[test]
+    fn round_trip() {
+        let public = key().public();
+        for len in [1000, 1001, 1003] {
+            let signature = check(&signed_image(len), &public).unwrap();
+            assert_eq!(signature.image_len as usize, len);
+        }
+    }
+
+    #[test]
+    fn tampered() {
+        let public = key().public();
+
+        let mut image = signed_image(1000);
+        image[10] ^= 1;
+        assert!(check(&image, &public).is_err());
+
+        // Padding isn't signed, but it has to be where the block says.
+        let mut image = signed_image(1001);
+        image.insert(0, 0);
+        assert!(check(&image, &public).is_err());
+
+        let mut image = signed_image(1000);
+        image.pop();
+        assert!(check(&image, &public).is_err());
+        assert!(check(&[0; 16], &public).is_err());
+    }
+
+    #[test]
+    fn wrong_key() {
+        let path = Path::new(env!("CARGO_MANIFEST_DIR"))
+            .join("../../support/fake_certs/ed25519-public-key.der");
+        let other = PublicKey::from_der_file(&path).unwrap();
+        assert!(check(&signed_image(1000), &other).is_err());
+    }
+
+    #[test]
+    fn first_run_stops_at_gap() {
+        let srec: String = [
+            "S3090800000001020304E4",
+            "S3090800000405060708D0",
+            "S309080010001122334434",
+        ]
+        .join("\n");
+        let (start, image) = first_run(&srec).unwrap();
+        assert_eq!(start, 0x0800_0000);
+        assert_eq!(image, [1, 2, 3, 4, 5, 6, 7, 8]);
+    }
+}
@@ -0,0 +1 @@
+qemu-system-riscv32 -M virt -m 128M -bios default -nographic -serial mon:stdio -kernel final.elf -semihosting -semihosting-config enable=on,userspace=on
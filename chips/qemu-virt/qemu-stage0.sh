@@ -0,0 +1,3 @@
+# Run from target/<app>/dist after building stage0 and the A/B images: QEMU
+# enters stage0 at the start of DRAM, and it boots whichever image it picks.
+qemu-system-riscv64 -M virt -m 128M -bios none -nographic -serial mon:stdio -device loader,file=stage0/final.ihex,cpu-num=0 -device loader,file=a/final.ihex -device loader,file=b/final.ihex -semihosting -semihosting-config enable=on,userspace=on
@@ -0,0 +1 @@
+qemu-system-riscv64 -M virt -m 128M -bios none -nographic -serial mon:stdio -device loader,file=final.ihex,cpu-num=0 -semihosting -semihosting-config enable=on,userspace=on
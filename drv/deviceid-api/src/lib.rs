@@ -0,0 +1,26 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Client API for getting the DICE DeviceId key endorsed: fetching the CSR
+//! that stage0 made for it, and installing the cert chain our CA signs in
+//! response.
+
+#![no_std]
+
+use derive_idol_err::IdolError;
+use userlib::{sys_send, FromPrimitive};
+
+#[derive(Clone, Copy, FromPrimitive, IdolError)]
+#[repr(u32)]
+pub enum DeviceIdError {
+    BadLength = 1,
+    /// stage0 didn't hand off a DeviceId, so DICE isn't enabled.
+    NoDeviceId = 2,
+    /// The chain's leaf cert isn't for this device's DeviceId key.
+    BadCertChain = 3,
+    OutOfBounds = 4,
+    FlashError = 5,
+}
+
+include!(concat!(env!("OUT_DIR"), "/client_stub.rs"));
@@ -488,6 +488,13 @@ impl<Driver: Ecp5Driver> FpgaUserDesign for Ecp5<Driver> {
         self.driver.user_design_write(data)
     }
 
+    fn user_design_write_leased(
+        &self,
+        data: Lease<'_>,
+    ) -> Result<(), FpgaError> {
+        self.driver.user_design_write_leased(data)
+    }
+
     fn user_design_lock(&self) -> Result<(), FpgaError> {
         self.driver.user_design_lock()
     }
@@ -7,6 +7,7 @@ use crate::FpgaUserDesign;
 use drv_fpga_api::FpgaError;
 use drv_spi_api::{self as spi_api, SpiDevice, SpiError};
 use drv_stm32xx_sys_api::{self as sys_api, GpioError, Sys};
+use userlib::Lease;
 
 /// `Ecp5UsingSpi` is the simplest implementation of the Ecp5Impl interface using
 /// the SPI and Sys APIs. It assumes the PROGRAM_N, INIT_N and DONE signals are
@@ -166,6 +167,13 @@ impl FpgaUserDesign for Ecp5UsingSpi {
         Ok(self.user_design.write(data)?)
     }
 
+    fn user_design_write_leased(
+        &self,
+        data: Lease<'_>,
+    ) -> Result<(), FpgaError> {
+        Ok(self.user_design.write_leased(data)?)
+    }
+
     fn user_design_lock(&self) -> Result<(), FpgaError> {
         Ok(self.user_design.lock(spi_api::CsState::Asserted)?)
     }
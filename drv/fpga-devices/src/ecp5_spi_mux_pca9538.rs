@@ -9,6 +9,7 @@ use drv_i2c_api::ResponseCode;
 use drv_i2c_devices::pca9538;
 use drv_spi_api::{self as spi_api, SpiDevice, SpiError};
 use drv_stm32xx_sys_api::{self as sys_api, GpioError, Sys};
+use userlib::Lease;
 
 /// This module implements an ECP5 driver which exposes two physical devices,
 /// which share a single SPI bus using a mux and are controlled through a shared
@@ -345,6 +346,20 @@ impl<'a> FpgaUserDesign for DeviceInstance<'a> {
             .map_err(Into::into)
     }
 
+    fn user_design_write_leased(
+        &self,
+        data: Lease<'_>,
+    ) -> Result<(), FpgaError> {
+        self.driver
+            .select_device(self.device_id)
+            .map_err(Error::from)?;
+        self.driver
+            .config
+            .user_design
+            .write_leased(data)
+            .map_err(Into::into)
+    }
+
     fn user_design_lock(&self) -> Result<(), FpgaError> {
         self.driver
             .select_device(self.device_id)
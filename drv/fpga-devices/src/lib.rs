@@ -7,6 +7,7 @@
 #![no_std]
 
 use drv_fpga_api::{DeviceState, FpgaError};
+use userlib::Lease;
 
 pub mod ecp5;
 pub mod ecp5_spi;
@@ -62,6 +63,11 @@ pub trait FpgaUserDesign {
     fn user_design_read(&self, buf: &mut [u8]) -> Result<(), FpgaError>;
     fn user_design_write(&self, buf: &[u8]) -> Result<(), FpgaError>;
 
+    /// Write the contents of a lease, such as one forwarded from a client of
+    /// the FPGA server, to the user design.
+    fn user_design_write_leased(&self, buf: Lease<'_>)
+        -> Result<(), FpgaError>;
+
     /// Lock the user design for multiple uninterrupted operations.
     ///
     /// Note: the semantics of this are not well defined and need work.
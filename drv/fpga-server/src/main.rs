@@ -470,8 +470,13 @@ impl<'a, Device: Fpga<'a> + FpgaUserDesign> idl::InOrderFpgaImpl
         addr: u16,
         data: ReadDataLease,
     ) -> Result<(), RequestError> {
-        data.read_range(0..data.len(), &mut self.buffer[..data.len()])
-            .map_err(|_| RequestError::Fail(ClientError::WentAway))?;
+        // Pass the client's lease on to the SPI server rather than copying it
+        // through our buffer.
+        let caller = hl::Caller::<()>::from(msg.sender);
+        let source = caller
+            .borrow(0)
+            .forward(0..data.len(), LeaseAttributes::READ)
+            .ok_or(RequestError::Fail(ClientError::WentAway))?;
 
         let header = UserDesignRequestHeader {
             cmd: u8::from(op),
@@ -485,7 +490,7 @@ impl<'a, Device: Fpga<'a> + FpgaUserDesign> idl::InOrderFpgaImpl
             .user_design_write(header.as_bytes())
             .map_err(FpgaError::from)?;
         lock.0
-            .user_design_write(&self.buffer[..data.len()])
+            .user_design_write_leased(source)
             .map_err(FpgaError::from)?;
 
         Ok(())
@@ -22,6 +22,7 @@
 
 #![no_std]
 
+use core::convert::TryFrom;
 use zerocopy::{AsBytes, FromBytes};
 
 use derive_idol_err::IdolError;
@@ -34,9 +35,10 @@ pub enum Op {
 }
 
 /// The response code returned from the I2C controller (or from the kernel in
-/// the case of [`ResponseCode::Dead`]).  These response codes pretty specific,
-/// not because the caller is expected to necessarily handle them differently,
-/// but to give upstack software some modicum of context surrounding the error.
+/// the case of [`ResponseCode::Dead`] and [`ResponseCode::TimedOut`]).  These
+/// response codes pretty specific, not because the caller is expected to
+/// necessarily handle them differently, but to give upstack software some
+/// modicum of context surrounding the error.
 #[derive(Copy, Clone, Debug, FromPrimitive, Eq, PartialEq, IdolError)]
 #[repr(u32)]
 pub enum ResponseCode {
@@ -84,6 +86,9 @@ pub enum ResponseCode {
     ControllerLocked = 21,
     /// I2C bus error
     BusError = 22,
+    /// Server didn't finish within the caller's send timeout
+    #[idol(timed_out)]
+    TimedOut = 23,
 }
 
 ///
@@ -181,6 +186,10 @@ pub struct I2cDevice {
     pub port: PortIndex,
     pub segment: Option<(Mux, Segment)>,
     pub address: u8,
+    /// Ticks to wait for the I2C server on each operation before giving up
+    /// with [`ResponseCode::TimedOut`], or `None` to wait for as long as it
+    /// takes.
+    pub timeout: Option<u32>,
 }
 
 type I2cMessage = (u8, Controller, PortIndex, Option<(Mux, Segment)>);
@@ -263,6 +272,41 @@ impl I2cDevice {
             port,
             segment,
             address,
+            timeout: None,
+        }
+    }
+
+    ///
+    /// Returns this device with a timeout of `ticks` on each operation, so
+    /// that a wedged bus (or I2C server) can't wedge the caller too.
+    ///
+    pub fn with_timeout(self, ticks: u32) -> Self {
+        Self {
+            timeout: Some(ticks),
+            ..self
+        }
+    }
+
+    ///
+    /// Sends `op` for this device to the I2C server, subject to our timeout.
+    ///
+    fn send(
+        &self,
+        op: Op,
+        incoming: &mut [u8],
+        leases: &[Lease<'_>],
+    ) -> (u32, usize) {
+        let outgoing = Marshal::marshal(&(
+            self.address,
+            self.controller,
+            self.port,
+            self.segment,
+        ));
+        match self.timeout {
+            None => sys_send(self.task, op as u16, &outgoing, incoming, leases),
+            Some(ticks) => sys_send_with_timeout(
+                self.task, op as u16, &outgoing, incoming, leases, ticks,
+            ),
         }
     }
 }
@@ -292,22 +336,15 @@ impl I2cDevice {
         let mut val = V::new_zeroed();
         let mut response = 0_usize;
 
-        let (code, _) = sys_send(
-            self.task,
-            Op::WriteRead as u16,
-            &Marshal::marshal(&(
-                self.address,
-                self.controller,
-                self.port,
-                self.segment,
-            )),
+        let (code, _) = self.send(
+            Op::WriteRead,
             response.as_bytes_mut(),
             &[Lease::from(reg.as_bytes()), Lease::from(val.as_bytes_mut())],
         );
 
         if code != 0 {
-            Err(ResponseCode::from_u32(code)
-                .ok_or(ResponseCode::BadResponse)?)
+            Err(ResponseCode::try_from(code)
+                .unwrap_or(ResponseCode::BadResponse))
         } else {
             Ok(val)
         }
@@ -325,22 +362,15 @@ impl I2cDevice {
     ) -> Result<usize, ResponseCode> {
         let mut response = 0_usize;
 
-        let (code, _) = sys_send(
-            self.task,
-            Op::WriteRead as u16,
-            &Marshal::marshal(&(
-                self.address,
-                self.controller,
-                self.port,
-                self.segment,
-            )),
+        let (code, _) = self.send(
+            Op::WriteRead,
             response.as_bytes_mut(),
             &[Lease::from(reg.as_bytes()), Lease::from(buf)],
         );
 
         if code != 0 {
-            Err(ResponseCode::from_u32(code)
-                .ok_or(ResponseCode::BadResponse)?)
+            Err(ResponseCode::try_from(code)
+                .unwrap_or(ResponseCode::BadResponse))
         } else {
             Ok(response)
         }
@@ -360,22 +390,15 @@ impl I2cDevice {
     ) -> Result<usize, ResponseCode> {
         let mut response = 0_usize;
 
-        let (code, _) = sys_send(
-            self.task,
-            Op::WriteReadBlock as u16,
-            &Marshal::marshal(&(
-                self.address,
-                self.controller,
-                self.port,
-                self.segment,
-            )),
+        let (code, _) = self.send(
+            Op::WriteReadBlock,
             response.as_bytes_mut(),
             &[Lease::from(reg.as_bytes()), Lease::from(buf)],
         );
 
         if code != 0 {
-            Err(ResponseCode::from_u32(code)
-                .ok_or(ResponseCode::BadResponse)?)
+            Err(ResponseCode::try_from(code)
+                .unwrap_or(ResponseCode::BadResponse))
         } else {
             Ok(response)
         }
@@ -393,22 +416,15 @@ impl I2cDevice {
         let mut val = V::new_zeroed();
         let mut response = 0_usize;
 
-        let (code, _) = sys_send(
-            self.task,
-            Op::WriteRead as u16,
-            &Marshal::marshal(&(
-                self.address,
-                self.controller,
-                self.port,
-                self.segment,
-            )),
+        let (code, _) = self.send(
+            Op::WriteRead,
             response.as_bytes_mut(),
             &[Lease::from(&empty[0..0]), Lease::from(val.as_bytes_mut())],
         );
 
         if code != 0 {
-            Err(ResponseCode::from_u32(code)
-                .ok_or(ResponseCode::BadResponse)?)
+            Err(ResponseCode::try_from(code)
+                .unwrap_or(ResponseCode::BadResponse))
         } else {
             Ok(val)
         }
@@ -423,22 +439,15 @@ impl I2cDevice {
         let empty = [0u8; 1];
         let mut response = 0_usize;
 
-        let (code, _) = sys_send(
-            self.task,
-            Op::WriteRead as u16,
-            &Marshal::marshal(&(
-                self.address,
-                self.controller,
-                self.port,
-                self.segment,
-            )),
+        let (code, _) = self.send(
+            Op::WriteRead,
             response.as_bytes_mut(),
             &[Lease::from(&empty[0..0]), Lease::from(buf)],
         );
 
         if code != 0 {
-            Err(ResponseCode::from_u32(code)
-                .ok_or(ResponseCode::BadResponse)?)
+            Err(ResponseCode::try_from(code)
+                .unwrap_or(ResponseCode::BadResponse))
         } else {
             Ok(response)
         }
@@ -452,22 +461,15 @@ impl I2cDevice {
         let empty = [0u8; 1];
         let mut response = 0_usize;
 
-        let (code, _) = sys_send(
-            self.task,
-            Op::WriteRead as u16,
-            &Marshal::marshal(&(
-                self.address,
-                self.controller,
-                self.port,
-                self.segment,
-            )),
+        let (code, _) = self.send(
+            Op::WriteRead,
             response.as_bytes_mut(),
             &[Lease::from(buffer), Lease::from(&empty[0..0])],
         );
 
         if code != 0 {
-            Err(ResponseCode::from_u32(code)
-                .ok_or(ResponseCode::BadResponse)?)
+            Err(ResponseCode::try_from(code)
+                .unwrap_or(ResponseCode::BadResponse))
         } else {
             Ok(())
         }
@@ -0,0 +1,151 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+//
+// Hands out the CSR for the DICE DeviceId key that stage0 made, and stores
+// the cert chain our CA signs in response, which stage0 then hands off in
+// place of the self-signed DeviceId cert.
+#![no_std]
+#![no_main]
+
+use core::convert::TryInto;
+use dice::{
+    CertChainHeader, DeviceIdCsrData, HandoffData, CERT_CHAIN_CAPACITY,
+};
+use drv_deviceid_api::DeviceIdError;
+use hypocalls::*;
+use idol_runtime::{ClientError, Leased, RequestError, R, W};
+use userlib::*;
+use zerocopy::AsBytes;
+
+cfg_if::cfg_if! {
+    if #[cfg(target_board = "lpcxpresso55s69")] {
+        declare_tz_table!();
+    } else {
+        declare_not_tz_table!();
+    }
+}
+
+struct ServerImpl;
+
+fn hypo_result(result: HypoStatus) -> Result<(), DeviceIdError> {
+    match result {
+        HypoStatus::Success => Ok(()),
+        HypoStatus::OutOfBounds => Err(DeviceIdError::OutOfBounds),
+        HypoStatus::RunningImage
+        | HypoStatus::FlashError(_)
+        | HypoStatus::NotProgrammed => Err(DeviceIdError::FlashError),
+    }
+}
+
+fn write_certs_page(
+    page_num: u32,
+    page: &mut [u8; FLASH_PAGE_SIZE],
+) -> Result<(), DeviceIdError> {
+    // The write_certs_page API takes raw pointers due to TrustZone ABI
+    // requirements which makes this function unsafe.
+    hypo_result(unsafe {
+        tz_table!().write_certs_page(page_num, page.as_mut_ptr())
+    })
+}
+
+/// Returns the DeviceId public key from the CSR stage0 handed off.
+fn deviceid_public_key() -> Result<[u8; 32], DeviceIdError> {
+    let csr = DeviceIdCsrData::from_mem().ok_or(DeviceIdError::NoDeviceId)?;
+    csr.csr
+        .get_pub()
+        .try_into()
+        .map_err(|_| DeviceIdError::NoDeviceId)
+}
+
+impl idl::InOrderDeviceIdImpl for ServerImpl {
+    fn csr(
+        &mut self,
+        _: &RecvMessage,
+        csr: Leased<W, [u8]>,
+    ) -> Result<usize, RequestError<DeviceIdError>> {
+        let data =
+            DeviceIdCsrData::from_mem().ok_or(DeviceIdError::NoDeviceId)?;
+        let bytes = data.csr.as_bytes();
+
+        if csr.len() < bytes.len() {
+            return Err(DeviceIdError::BadLength.into());
+        }
+        csr.write_range(0..bytes.len(), bytes)
+            .map_err(|_| RequestError::Fail(ClientError::WentAway))?;
+
+        Ok(bytes.len())
+    }
+
+    fn install_certs(
+        &mut self,
+        _: &RecvMessage,
+        certs: Leased<R, [u8]>,
+    ) -> Result<(), RequestError<DeviceIdError>> {
+        const HEADER_SIZE: usize = core::mem::size_of::<CertChainHeader>();
+
+        let len = certs.len();
+        if len == 0 || len > CERT_CHAIN_CAPACITY {
+            return Err(DeviceIdError::BadLength.into());
+        }
+
+        // The chain is stored after a header, so the first page holds a
+        // little less of it than the rest.
+        let first = len.min(FLASH_PAGE_SIZE - HEADER_SIZE);
+        let mut page = [0u8; FLASH_PAGE_SIZE];
+        let read_first = |page: &mut [u8; FLASH_PAGE_SIZE]| {
+            certs
+                .read_range(0..first, &mut page[HEADER_SIZE..][..first])
+                .map_err(|_| RequestError::Fail(ClientError::WentAway))
+        };
+
+        // Only take a chain for our own DeviceId key. The key comes early
+        // in the leaf cert, so the first page is enough to find it.
+        read_first(&mut page)?;
+        let leaf = dice::leaf_public_key(&page[HEADER_SIZE..][..first]);
+        if leaf != Some(&deviceid_public_key()?) {
+            return Err(DeviceIdError::BadCertChain.into());
+        }
+
+        // Invalidate any chain that's already installed first, so that
+        // stage0 never finds its header in front of a partly written chain.
+        page.fill(0);
+        write_certs_page(0, &mut page)?;
+
+        let mut offset = first;
+        let mut page_num = 1;
+        while offset < len {
+            let n = (len - offset).min(FLASH_PAGE_SIZE);
+            page.fill(0);
+            certs
+                .read_range(offset..offset + n, &mut page[..n])
+                .map_err(|_| RequestError::Fail(ClientError::WentAway))?;
+            write_certs_page(page_num, &mut page)?;
+
+            offset += n;
+            page_num += 1;
+        }
+
+        page.fill(0);
+        read_first(&mut page)?;
+        page[..HEADER_SIZE]
+            .copy_from_slice(CertChainHeader::new(len).as_bytes());
+        write_certs_page(0, &mut page).map_err(RequestError::from)
+    }
+}
+
+#[export_name = "main"]
+fn main() -> ! {
+    let mut server = ServerImpl;
+    let mut incoming = [0u8; idl::INCOMING_SIZE];
+
+    loop {
+        idol_runtime::dispatch(&mut incoming, &mut server);
+    }
+}
+
+mod idl {
+    use super::DeviceIdError;
+
+    include!(concat!(env!("OUT_DIR"), "/server_stub.rs"));
+}
@@ -9,11 +9,17 @@
 #![no_std]
 #![no_main]
 
-use drv_update_api::{ImageVersion, UpdateError, UpdateTarget};
+use drv_update_api::{ImageVersion, UpdateError, UpdateTarget, SHA256_SZ};
 use hypocalls::*;
-use idol_runtime::{ClientError, Leased, LenLimit, RequestError, R};
+use idol_runtime::{
+    ClientError, Leased, LenLimit, NotificationHandler, RequestError, R, W,
+};
+use task_jefe_api::Jefe;
+use update_flash::{Flash, FlashError, Updater};
 use userlib::*;
 
+task_slot!(JEFE, jefe);
+
 cfg_if::cfg_if! {
     if #[cfg(target_board = "lpcxpresso55s69")] {
         declare_tz_table!();
@@ -22,59 +28,226 @@ cfg_if::cfg_if! {
     }
 }
 
-enum UpdateState {
-    NoUpdate,
-    InProgress,
-    Finished,
-}
-
 struct ServerImpl {
-    state: UpdateState,
-    image: Option<UpdateTarget>,
+    updater: Updater<UpdateTarget, BLOCK_SIZE_BYTES, BITMAP_SIZE>,
+    /// Whether we've confirmed the running image since we started. The
+    /// secure side leaves the boot state alone if it already records the
+    /// image as confirmed, so doing this on every boot doesn't wear the flash.
+    confirmed: bool,
 }
 
 const BLOCK_SIZE_BYTES: usize = FLASH_PAGE_SIZE;
 
+// Enough blocks for a whole image slot (256 KiB).
+const BITMAP_SIZE: usize = 64;
+
 const MAX_LEASE: usize = 1024;
 
+// Until the running image is confirmed, we ask Jefe whether the system is
+// healthy this often.
+const TIMER_INTERVAL: u64 = 1000;
+const TIMER_MASK: u32 = 1 << 0;
+
+fn hypo_result(result: HypoStatus) -> Result<(), UpdateError> {
+    match result {
+        HypoStatus::Success => Ok(()),
+        HypoStatus::OutOfBounds => Err(UpdateError::OutOfBounds),
+        HypoStatus::RunningImage => Err(UpdateError::RunningImage),
+        // Should probably encode the LPC55 flash status into the update
+        // error for good measure but that takes effort...
+        HypoStatus::FlashError(_) => Err(UpdateError::FlashError),
+        HypoStatus::NotProgrammed => Err(UpdateError::FlashError),
+    }
+}
+
+/// An image slot, which the secure world erases and programs for us a page at
+/// a time.
+struct SlotFlash {
+    target: UpdateTarget,
+}
+
+fn flash_result(
+    result: HypoStatus,
+    op_err: FlashError,
+) -> Result<(), FlashError> {
+    match result {
+        HypoStatus::Success => Ok(()),
+        HypoStatus::OutOfBounds => Err(FlashError::OutOfBounds),
+        // The secure world won't let us touch the image we're running.
+        HypoStatus::RunningImage => Err(FlashError::WriteProtected),
+        HypoStatus::FlashError(_) => Err(op_err),
+        HypoStatus::NotProgrammed => Err(FlashError::ReadFailed),
+    }
+}
+
+impl Flash for SlotFlash {
+    const SECTOR_SIZE: usize = FLASH_PAGE_SIZE;
+    const WRITE_SIZE: usize = FLASH_PAGE_SIZE;
+
+    fn capacity(&self) -> usize {
+        // The secure world checks each page against the real bounds of the
+        // slot.
+        8 * BITMAP_SIZE * BLOCK_SIZE_BYTES
+    }
+
+    fn erase_sector(&mut self, _sector: usize) -> Result<(), FlashError> {
+        // write_to_flash erases each page before programming it.
+        Ok(())
+    }
+
+    fn program(
+        &mut self,
+        offset: usize,
+        data: &[u8],
+    ) -> Result<(), FlashError> {
+        if offset % FLASH_PAGE_SIZE != 0 || data.len() % FLASH_PAGE_SIZE != 0 {
+            return Err(FlashError::Unaligned);
+        }
+
+        let mut page = [0u8; FLASH_PAGE_SIZE];
+        for (i, c) in data.chunks(FLASH_PAGE_SIZE).enumerate() {
+            page.copy_from_slice(c);
+            let page_num = (offset / FLASH_PAGE_SIZE + i) as u32;
+
+            // The write_to_flash API takes raw pointers due to TrustZone
+            // ABI requirements which makes this function unsafe.
+            flash_result(
+                unsafe {
+                    tz_table!().write_to_flash(
+                        self.target,
+                        page_num,
+                        page.as_mut_ptr(),
+                    )
+                },
+                FlashError::ProgramFailed,
+            )?;
+        }
+        Ok(())
+    }
+
+    fn read(
+        &mut self,
+        offset: usize,
+        buf: &mut [u8],
+    ) -> Result<(), FlashError> {
+        if offset % FLASH_PAGE_SIZE != 0 {
+            return Err(FlashError::Unaligned);
+        }
+
+        let mut page = [0u8; FLASH_PAGE_SIZE];
+        for (i, c) in buf.chunks_mut(FLASH_PAGE_SIZE).enumerate() {
+            let page_num = (offset / FLASH_PAGE_SIZE + i) as u32;
+
+            // The read_from_flash API takes raw pointers due to TrustZone
+            // ABI requirements which makes this function unsafe.
+            flash_result(
+                unsafe {
+                    tz_table!().read_from_flash(
+                        self.target,
+                        page_num,
+                        page.as_mut_ptr(),
+                    )
+                },
+                FlashError::ReadFailed,
+            )?;
+            c.copy_from_slice(&page[..c.len()]);
+        }
+        Ok(())
+    }
+}
+
+/// Computes the SHA-256 of the first `len` bytes of an image. There's no hash
+/// engine driver on the LPC55, so this is done in software.
+fn image_sha256(
+    flash: &mut SlotFlash,
+    len: usize,
+) -> Result<[u8; SHA256_SZ], UpdateError> {
+    Ok(update_flash::image_sha256(flash, len)?)
+}
+
+impl ServerImpl {
+    /// Starts or resumes an update, checked against `sha256` when it's done
+    /// if the client gave one.
+    fn prep(
+        &mut self,
+        image_type: UpdateTarget,
+        image_len: u32,
+        sha256: Option<[u8; SHA256_SZ]>,
+    ) -> Result<(), UpdateError> {
+        self.updater.prep(
+            &mut SlotFlash { target: image_type },
+            image_type,
+            image_len as usize,
+            sha256,
+        )?;
+        Ok(())
+    }
+
+    /// The slot being updated.
+    fn slot(&self) -> Result<SlotFlash, UpdateError> {
+        let target = self.updater.target()?;
+        Ok(SlotFlash { target })
+    }
+
+    /// Marks the running image as good if Jefe says every task is healthy,
+    /// so that stage0 stops counting its boots.
+    fn confirm(&mut self) -> Result<(), UpdateError> {
+        if !self.confirmed {
+            if !Jefe::from(JEFE.get_task_id()).tasks_healthy() {
+                return Err(UpdateError::NotHealthy);
+            }
+            hypo_result(tz_table!().confirm_image())?;
+            self.confirmed = true;
+        }
+        Ok(())
+    }
+}
+
 impl idl::InOrderUpdateImpl for ServerImpl {
     fn prep_image_update(
         &mut self,
         _: &RecvMessage,
         image_type: UpdateTarget,
+        image_len: u32,
+        sha256: [u8; SHA256_SZ],
     ) -> Result<(), RequestError<UpdateError>> {
-        // The LPC55 doesn't have an easily accessible mass erase mechanism
-        // so this is just bookkeeping
-        match self.state {
-            UpdateState::InProgress => {
-                return Err(UpdateError::UpdateInProgress.into())
-            }
-            UpdateState::Finished => {
-                return Err(UpdateError::UpdateAlreadyFinished.into())
-            }
-            UpdateState::NoUpdate => (),
-        }
-
-        self.image = Some(image_type);
-        self.state = UpdateState::InProgress;
+        self.prep(image_type, image_len, Some(sha256))?;
         Ok(())
     }
 
-    fn abort_update(
+    fn prep_unverified_image_update(
         &mut self,
         _: &RecvMessage,
+        image_type: UpdateTarget,
+        image_len: u32,
     ) -> Result<(), RequestError<UpdateError>> {
-        match self.state {
-            UpdateState::NoUpdate => {
-                return Err(UpdateError::UpdateNotStarted.into())
-            }
-            UpdateState::Finished => {
-                return Err(UpdateError::UpdateAlreadyFinished.into())
-            }
-            UpdateState::InProgress => (),
+        self.prep(image_type, image_len, None)?;
+        Ok(())
+    }
+
+    fn written_blocks(
+        &mut self,
+        _: &RecvMessage,
+        bitmap: Leased<W, [u8]>,
+    ) -> Result<u32, RequestError<UpdateError>> {
+        let (written, block_count) =
+            self.updater.written_blocks().map_err(UpdateError::from)?;
+
+        if bitmap.len() < written.len() {
+            return Err(UpdateError::BadLength.into());
         }
+        bitmap
+            .write_range(0..written.len(), written)
+            .map_err(|_| RequestError::Fail(ClientError::WentAway))?;
+
+        Ok(block_count as u32)
+    }
 
-        self.state = UpdateState::NoUpdate;
+    fn abort_update(
+        &mut self,
+        _: &RecvMessage,
+    ) -> Result<(), RequestError<UpdateError>> {
+        self.updater.abort().map_err(UpdateError::from)?;
         Ok(())
     }
 
@@ -84,16 +257,7 @@ impl idl::InOrderUpdateImpl for ServerImpl {
         block_num: usize,
         block: LenLimit<Leased<R, [u8]>, MAX_LEASE>,
     ) -> Result<(), RequestError<UpdateError>> {
-        match self.state {
-            UpdateState::NoUpdate => {
-                return Err(UpdateError::UpdateNotStarted.into())
-            }
-            UpdateState::Finished => {
-                return Err(UpdateError::UpdateAlreadyFinished.into())
-            }
-            UpdateState::InProgress => (),
-        }
-
+        let mut slot = self.slot()?;
         let len = block.len();
 
         // The max lease length is longer than our block size, double
@@ -109,49 +273,64 @@ impl idl::InOrderUpdateImpl for ServerImpl {
             .read_range(0..len as usize, &mut flash_page)
             .map_err(|_| RequestError::Fail(ClientError::WentAway))?;
 
-        flash_page[len..].fill(0);
+        self.updater
+            .write_block(&mut slot, block_num, &mut flash_page, len)
+            .map_err(UpdateError::from)?;
+        Ok(())
+    }
+
+    fn write_blocks(
+        &mut self,
+        _: &RecvMessage,
+        block_num: usize,
+        blocks: Leased<R, [u8]>,
+    ) -> Result<(), RequestError<UpdateError>> {
+        let mut slot = self.slot()?;
+        let len = blocks.len();
+        if len == 0 {
+            return Err(UpdateError::BadLength.into());
+        }
 
-        let img = self.image.unwrap_lite();
+        // Blocks are a flash page each, but the lease may hold several
+        // consecutive ones; `write_block` checks each one's length.
+        let mut flash_page: [u8; BLOCK_SIZE_BYTES] = [0; BLOCK_SIZE_BYTES];
+        for (i, start) in (0..len).step_by(BLOCK_SIZE_BYTES).enumerate() {
+            let n = (len - start).min(BLOCK_SIZE_BYTES);
+            blocks
+                .read_range(start..start + n, &mut flash_page[..n])
+                .map_err(|_| RequestError::Fail(ClientError::WentAway))?;
 
-        let result = unsafe {
-            // The write_to_flash API takes raw pointers due to TrustZone
-            // ABI requirements which makes this function unsafe.
-            tz_table!().write_to_flash(
-                img,
-                block_num as u32,
-                flash_page.as_mut_ptr(),
-            )
-        };
-
-        match result {
-            HypoStatus::Success => Ok(()),
-            HypoStatus::OutOfBounds => Err(UpdateError::OutOfBounds.into()),
-            HypoStatus::RunningImage => Err(UpdateError::RunningImage.into()),
-            // Should probably encode the LPC55 flash status into the update
-            // error for good measure but that takes effort...
-            HypoStatus::FlashError(_) => Err(UpdateError::FlashError.into()),
+            self.updater
+                .write_block(&mut slot, block_num + i, &mut flash_page, n)
+                .map_err(UpdateError::from)?;
         }
+        Ok(())
     }
 
     fn finish_image_update(
         &mut self,
         _: &RecvMessage,
     ) -> Result<(), RequestError<UpdateError>> {
-        match self.state {
-            UpdateState::NoUpdate => {
-                return Err(UpdateError::UpdateNotStarted.into())
+        // Check what actually made it to flash before it's ever booted. On
+        // a mismatch the client has to start over.
+        let mut slot = self.slot()?;
+        self.updater.finish(&mut slot, image_sha256, |_, img| {
+            // Have stage0 try the new image on the next boot.
+            if img != UpdateTarget::Bootloader {
+                hypo_result(tz_table!().prefer_image(img))?;
             }
-            UpdateState::Finished => {
-                return Err(UpdateError::UpdateAlreadyFinished.into())
-            }
-            UpdateState::InProgress => (),
-        }
-
-        self.state = UpdateState::Finished;
-        self.image = None;
+            Ok(())
+        })?;
         Ok(())
     }
 
+    fn confirm_image(
+        &mut self,
+        _: &RecvMessage,
+    ) -> Result<(), RequestError<UpdateError>> {
+        self.confirm().map_err(RequestError::from)
+    }
+
     fn block_size(
         &mut self,
         _: &RecvMessage,
@@ -170,22 +349,43 @@ impl idl::InOrderUpdateImpl for ServerImpl {
     }
 }
 
+impl NotificationHandler for ServerImpl {
+    fn current_notification_mask(&self) -> u32 {
+        if self.confirmed {
+            0
+        } else {
+            TIMER_MASK
+        }
+    }
+
+    fn handle_notification(&mut self, _bits: u32) {
+        if self.confirm().is_err() {
+            sys_set_timer(
+                Some(sys_get_timer().now + TIMER_INTERVAL),
+                TIMER_MASK,
+            );
+        }
+    }
+}
+
 #[export_name = "main"]
 fn main() -> ! {
     let mut server = ServerImpl {
-        state: UpdateState::NoUpdate,
-        image: None,
+        updater: Updater::new(),
+        confirmed: false,
     };
     let mut incoming = [0u8; idl::INCOMING_SIZE];
 
+    sys_set_timer(Some(sys_get_timer().now + TIMER_INTERVAL), TIMER_MASK);
+
     loop {
-        idol_runtime::dispatch(&mut incoming, &mut server);
+        idol_runtime::dispatch_n(&mut incoming, &mut server);
     }
 }
 
 include!(concat!(env!("OUT_DIR"), "/consts.rs"));
 mod idl {
-    use super::{ImageVersion, UpdateError, UpdateTarget};
+    use super::{ImageVersion, UpdateError, UpdateTarget, SHA256_SZ};
 
     include!(concat!(env!("OUT_DIR"), "/server_stub.rs"));
 }
@@ -114,6 +114,13 @@ impl SpiDevice {
         self.server.write(self.device_index, source)
     }
 
+    /// Like `write`, but clocks out the bytes of a lease, such as one
+    /// forwarded from our own caller with `hl::Borrow::forward`, so that they
+    /// needn't be copied into our own memory first.
+    pub fn write_leased(&self, source: Lease<'_>) -> Result<(), SpiError> {
+        self.server.write_leased(self.device_index, source)
+    }
+
     /// Clock bytes from the device into `dest`.
     ///
     /// If the controller is not locked, this will assert CS before driving the
@@ -169,3 +176,4 @@ impl SpiDevice {
 }
 
 include!(concat!(env!("OUT_DIR"), "/client_stub.rs"));
+include!(concat!(env!("OUT_DIR"), "/client_ext.rs"));
@@ -2,12 +2,31 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
+//! Client API for the `Update` interface, which writes a new image into a
+//! slot that isn't running.
+//!
+//! A client starts an update with `prep_image_update`, giving the image's
+//! length and SHA-256, and writes it a block at a time. If the transfer is
+//! interrupted, `written_blocks` tells the client where to resume. Finally,
+//! `finish_image_update` rejects the image if its digest doesn't match.
+//!
+//! `prep_unverified_image_update` skips the digest check. It exists for
+//! `task-mgmt-gateway`, because the MGS protocol's update request doesn't
+//! carry a digest (Hiffy also offers it, when no digest is given). Adding one
+//! is a change to that protocol and to MGS, and is out of scope for this
+//! interface; until it happens, updates through the management network are
+//! only checked for completeness.
+
 #![no_std]
 
 use derive_idol_err::IdolError;
 use userlib::{sys_send, FromPrimitive};
 use zerocopy::{AsBytes, FromBytes};
 
+use update_flash::FlashError;
+
+pub use update_flash::SHA256_SZ;
+
 #[repr(u8)]
 #[derive(FromPrimitive, AsBytes, Eq, PartialEq, Clone, Copy)]
 pub enum UpdateTarget {
@@ -36,21 +55,55 @@ pub enum UpdateError {
     UpdateInProgress = 2,
     OutOfBounds = 3,
     Timeout = 4,
-    // Specific to STM32H7
-    EccDoubleErr = 5,
-    EccSingleErr = 6,
-    SecureErr = 7,   // If we get this something has gone very wrong
-    ReadProtErr = 8, // If we get this something has gone very wrong
-    WriteEraseErr = 9,
-    InconsistencyErr = 10,
-    StrobeErr = 11,
-    ProgSeqErr = 12,
-    WriteProtErr = 13,
+    // 5 through 13 were STM32H7 flash controller errors, which are now
+    // reported as the generic flash errors below.
     BadImageType = 14,
     UpdateAlreadyFinished = 15,
     UpdateNotStarted = 16,
     RunningImage = 17,
     FlashError = 18,
+    NotHealthy = 19,
+    // 20 through 22 were DeviceId errors, which now belong to the DeviceId
+    // interface (see drv-deviceid-api).
+    IncompleteImage = 23,
+    BadDigest = 24,
+    HashFailed = 25,
+    EraseFailed = 26,
+    ProgramFailed = 27,
+    ReadFailed = 28,
+    WriteProtected = 29,
+    BlockMismatch = 30,
+}
+
+impl From<FlashError> for UpdateError {
+    fn from(e: FlashError) -> Self {
+        match e {
+            FlashError::OutOfBounds => Self::OutOfBounds,
+            FlashError::Unaligned => Self::BadLength,
+            FlashError::EraseFailed => Self::EraseFailed,
+            FlashError::ProgramFailed => Self::ProgramFailed,
+            FlashError::ReadFailed => Self::ReadFailed,
+            FlashError::WriteProtected => Self::WriteProtected,
+        }
+    }
+}
+
+impl From<update_flash::Error> for UpdateError {
+    fn from(e: update_flash::Error) -> Self {
+        use update_flash::Error;
+
+        match e {
+            Error::NotStarted => Self::UpdateNotStarted,
+            Error::InProgress => Self::UpdateInProgress,
+            Error::AlreadyFinished => Self::UpdateAlreadyFinished,
+            Error::BadLength => Self::BadLength,
+            Error::OutOfBounds => Self::OutOfBounds,
+            Error::IncompleteImage => Self::IncompleteImage,
+            Error::BadDigest => Self::BadDigest,
+            Error::BlockMismatch => Self::BlockMismatch,
+            Error::Flash(e) => e.into(),
+        }
+    }
 }
 
 pub mod stm32h7 {
@@ -0,0 +1,250 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+//
+// Functions for writing to flash for updates
+//
+// This driver is intended to carry as little state as possible. Most of the
+// heavy work and decision making should be handled in other tasks.
+//
+// The update state machine is chip-independent (see the update-flash crate);
+// all that differs between chips is the flash backend, which writes images to
+// a single alternate region and knows how to make it the one we boot from.
+#![no_std]
+#![no_main]
+
+use drv_update_api::{ImageVersion, UpdateError, UpdateTarget, SHA256_SZ};
+use idol_runtime::{ClientError, Leased, LenLimit, RequestError, R, W};
+use update_flash::Updater;
+use userlib::*;
+
+cfg_if::cfg_if! {
+    if #[cfg(any(feature = "h743", feature = "h753"))] {
+        mod stm32h7;
+        use stm32h7 as backend;
+    } else {
+        compile_error!("no flash backend selected for this chip");
+    }
+}
+
+use backend::{BackendFlash, BITMAP_SIZE, BLOCK_SIZE};
+
+#[cfg(feature = "hash")]
+task_slot!(HASH, hash_driver);
+
+struct ServerImpl {
+    flash: BackendFlash,
+    updater: Updater<UpdateTarget, BLOCK_SIZE, BITMAP_SIZE>,
+}
+
+/// Computes the SHA-256 of the first `len` bytes of the image using the hash
+/// engine.
+#[cfg(feature = "hash")]
+fn image_sha256(
+    flash: &mut BackendFlash,
+    len: usize,
+) -> Result<[u8; SHA256_SZ], UpdateError> {
+    let hash_driver = drv_hash_api::Hash::from(HASH.get_task_id());
+    hash_driver
+        .init_sha256()
+        .map_err(|_| UpdateError::HashFailed)?;
+
+    update_flash::read_image(flash, len, |chunk| {
+        hash_driver
+            .update(chunk.len() as u32, chunk)
+            .map_err(|_| UpdateError::HashFailed)
+    })?;
+
+    hash_driver
+        .finalize_sha256()
+        .map_err(|_| UpdateError::HashFailed)
+}
+
+/// Computes the SHA-256 of the first `len` bytes of the image in software,
+/// for chips without a hash engine.
+#[cfg(not(feature = "hash"))]
+fn image_sha256(
+    flash: &mut BackendFlash,
+    len: usize,
+) -> Result<[u8; SHA256_SZ], UpdateError> {
+    Ok(update_flash::image_sha256(flash, len)?)
+}
+
+impl ServerImpl {
+    /// Starts or resumes an update, checked against `sha256` when it's done
+    /// if the client gave one.
+    fn prep(
+        &mut self,
+        img_type: UpdateTarget,
+        image_len: u32,
+        sha256: Option<[u8; SHA256_SZ]>,
+    ) -> Result<(), UpdateError> {
+        match img_type {
+            UpdateTarget::Alternate => (),
+            _ => return Err(UpdateError::BadImageType),
+        }
+
+        self.updater.prep(
+            &mut self.flash,
+            img_type,
+            image_len as usize,
+            sha256,
+        )?;
+        Ok(())
+    }
+}
+
+impl idl::InOrderUpdateImpl for ServerImpl {
+    fn prep_image_update(
+        &mut self,
+        _: &RecvMessage,
+        img_type: UpdateTarget,
+        image_len: u32,
+        sha256: [u8; SHA256_SZ],
+    ) -> Result<(), RequestError<UpdateError>> {
+        self.prep(img_type, image_len, Some(sha256))?;
+        Ok(())
+    }
+
+    fn prep_unverified_image_update(
+        &mut self,
+        _: &RecvMessage,
+        img_type: UpdateTarget,
+        image_len: u32,
+    ) -> Result<(), RequestError<UpdateError>> {
+        self.prep(img_type, image_len, None)?;
+        Ok(())
+    }
+
+    fn written_blocks(
+        &mut self,
+        _: &RecvMessage,
+        bitmap: Leased<W, [u8]>,
+    ) -> Result<u32, RequestError<UpdateError>> {
+        let (written, block_count) =
+            self.updater.written_blocks().map_err(UpdateError::from)?;
+
+        if bitmap.len() < written.len() {
+            return Err(UpdateError::BadLength.into());
+        }
+        bitmap
+            .write_range(0..written.len(), written)
+            .map_err(|_| RequestError::Fail(ClientError::WentAway))?;
+
+        Ok(block_count as u32)
+    }
+
+    fn abort_update(
+        &mut self,
+        _: &RecvMessage,
+    ) -> Result<(), RequestError<UpdateError>> {
+        self.updater.abort().map_err(UpdateError::from)?;
+        Ok(())
+    }
+
+    fn write_one_block(
+        &mut self,
+        _: &RecvMessage,
+        block_num: usize,
+        block: LenLimit<Leased<R, [u8]>, BLOCK_SIZE>,
+    ) -> Result<(), RequestError<UpdateError>> {
+        let len = block.len();
+        let mut flash_page: [u8; BLOCK_SIZE] = [0; BLOCK_SIZE];
+
+        block
+            .read_range(0..len as usize, &mut flash_page)
+            .map_err(|_| RequestError::Fail(ClientError::WentAway))?;
+
+        self.updater
+            .write_block(&mut self.flash, block_num, &mut flash_page, len)
+            .map_err(UpdateError::from)?;
+        Ok(())
+    }
+
+    fn write_blocks(
+        &mut self,
+        _: &RecvMessage,
+        block_num: usize,
+        blocks: Leased<R, [u8]>,
+    ) -> Result<(), RequestError<UpdateError>> {
+        let len = blocks.len();
+        if len == 0 {
+            return Err(UpdateError::BadLength.into());
+        }
+
+        // The lease may cover any number of blocks (a server forwarding a
+        // client's buffer needn't split it up), so it's copied into flash a
+        // block at a time. Only the image's last block may be short.
+        let mut flash_page: [u8; BLOCK_SIZE] = [0; BLOCK_SIZE];
+        for (i, start) in (0..len).step_by(BLOCK_SIZE).enumerate() {
+            let n = (len - start).min(BLOCK_SIZE);
+            blocks
+                .read_range(start..start + n, &mut flash_page[..n])
+                .map_err(|_| RequestError::Fail(ClientError::WentAway))?;
+
+            self.updater
+                .write_block(&mut self.flash, block_num + i, &mut flash_page, n)
+                .map_err(UpdateError::from)?;
+        }
+        Ok(())
+    }
+
+    fn finish_image_update(
+        &mut self,
+        _: &RecvMessage,
+    ) -> Result<(), RequestError<UpdateError>> {
+        // Never activate an image that doesn't match what the client
+        // promised. On a mismatch the client has to start over.
+        self.updater
+            .finish(&mut self.flash, image_sha256, |flash, _| {
+                flash.activate()
+            })?;
+        Ok(())
+    }
+
+    fn block_size(
+        &mut self,
+        _: &RecvMessage,
+    ) -> Result<usize, RequestError<UpdateError>> {
+        Ok(BLOCK_SIZE)
+    }
+
+    fn confirm_image(
+        &mut self,
+        _: &RecvMessage,
+    ) -> Result<(), RequestError<UpdateError>> {
+        // Activating an image commits to it; there's nothing that would roll
+        // it back, so there's nothing to confirm.
+        Ok(())
+    }
+
+    fn current_version(
+        &mut self,
+        _: &RecvMessage,
+    ) -> Result<ImageVersion, RequestError<UpdateError>> {
+        Ok(ImageVersion {
+            epoch: HUBRIS_BUILD_EPOCH,
+            version: HUBRIS_BUILD_VERSION,
+        })
+    }
+}
+
+#[export_name = "main"]
+fn main() -> ! {
+    let mut server = ServerImpl {
+        flash: BackendFlash::claim(),
+        updater: Updater::new(),
+    };
+    let mut incoming = [0u8; idl::INCOMING_SIZE];
+
+    loop {
+        idol_runtime::dispatch(&mut incoming, &mut server);
+    }
+}
+
+include!(concat!(env!("OUT_DIR"), "/consts.rs"));
+mod idl {
+    use super::{ImageVersion, UpdateError, UpdateTarget, SHA256_SZ};
+
+    include!(concat!(env!("OUT_DIR"), "/server_stub.rs"));
+}
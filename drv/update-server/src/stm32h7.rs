@@ -0,0 +1,312 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Flash backend for the STM32H7: images are written to bank 2, and activated
+//! by swapping the banks.
+
+use drv_update_api::stm32h7::{BLOCK_SIZE_BYTES, FLASH_WORD_BYTES};
+use drv_update_api::UpdateError;
+use ringbuf::*;
+use update_flash::{Flash, FlashError};
+use userlib::*;
+
+#[cfg(feature = "h743")]
+use stm32h7::stm32h743 as device;
+
+#[cfg(feature = "h753")]
+use stm32h7::stm32h753 as device;
+
+pub const BLOCK_SIZE: usize = BLOCK_SIZE_BYTES;
+
+// Enough blocks for the whole bank (1 MiB).
+pub const BITMAP_SIZE: usize = 128;
+
+// Keys constants are defined in RM0433 Rev 7
+// Section 4.9.2
+const FLASH_KEY1: u32 = 0x4567_0123;
+const FLASH_KEY2: u32 = 0xCDEF_89AB;
+
+// Keys constants are defined in RM0433 Rev 7
+// Section 4.9.3
+const FLASH_OPT_KEY1: u32 = 0x0819_2A3B;
+const FLASH_OPT_KEY2: u32 = 0x4C5D_6E7F;
+
+const BANK_ADDR: u32 = 0x08100000;
+const BANK_END: u32 = 0x08200000;
+
+// RM0433 Rev 7 section 4.2: each bank has 8 sectors of 128 KiB.
+const SECTOR_SIZE: usize = 128 * 1024;
+
+// Must match app.toml!
+const FLASH_IRQ: u32 = 1 << 0;
+
+#[derive(Copy, Clone, PartialEq)]
+enum Trace {
+    EraseStart(usize),
+    EraseEnd,
+    WriteStart,
+    WriteEnd,
+    FinishStart,
+    FinishEnd,
+    /// The bank 2 status register, when it reports an error.
+    Status(u32),
+    None,
+}
+
+ringbuf!(Trace, 64, Trace::None);
+
+pub struct BackendFlash {
+    flash: &'static device::flash::RegisterBlock,
+}
+
+impl BackendFlash {
+    pub fn claim() -> Self {
+        Self {
+            flash: unsafe { &*device::FLASH::ptr() },
+        }
+    }
+
+    /// Makes the image in bank 2 the one we boot from.
+    // See RM0433 Rev 7 section 4.3.13
+    pub fn activate(&mut self) -> Result<(), UpdateError> {
+        ringbuf_entry!(Trace::FinishStart);
+        if self.flash.optsr_cur().read().swap_bank_opt().bit() {
+            self.flash
+                .optsr_prg()
+                .modify(|_, w| w.swap_bank_opt().clear_bit());
+        } else {
+            self.flash
+                .optsr_prg()
+                .modify(|_, w| w.swap_bank_opt().set_bit());
+        }
+
+        self.flash.optcr().modify(|_, w| w.optstart().set_bit());
+
+        loop {
+            if !self.flash.optsr_cur().read().opt_busy().bit() {
+                break;
+            }
+        }
+
+        ringbuf_entry!(Trace::FinishEnd);
+        Ok(())
+    }
+
+    fn poll_flash_done(&mut self) -> Result<(), FlashError> {
+        // This method should implement step 5 of the Single Write Sequence from
+        // RM0433 Rev 7 section 4.3.9, which states
+        //
+        // > Check that QW1 (respectively QW2) has been raised and wait until it
+        // > is reset to 0.
+        //
+        // However, checking that QW2 has been raised is inherently racy: it's
+        // possible it was raised and lowered before we get to this method. We
+        // have observed this race in practice, so we omit the check that QW2
+        // has been raised and only wait until QW2 is reset to 0.
+        loop {
+            if !self.flash.bank2().sr.read().qw().bit() {
+                break;
+            }
+        }
+
+        self.bank2_status(FlashError::ProgramFailed)
+    }
+
+    /// Checks the status of the last operation, reporting any failure of the
+    /// operation itself as `op_err`. The details are only kept in the
+    /// ringbuf.
+    fn bank2_status(&self, op_err: FlashError) -> Result<(), FlashError> {
+        let err = self.flash.bank2().sr.read();
+
+        let result = if err.dbeccerr().bit() || err.sneccerr1().bit() {
+            Err(FlashError::ReadFailed)
+        } else if err.rdserr().bit() || err.rdperr().bit() {
+            // If we get this something has gone very wrong
+            Err(FlashError::ReadFailed)
+        } else if err.wrperr().bit() {
+            Err(FlashError::WriteProtected)
+        } else if err.operr().bit()
+            || err.incerr().bit()
+            || err.strberr().bit()
+            || err.pgserr().bit()
+        {
+            Err(op_err)
+        } else {
+            Ok(())
+        };
+
+        if result.is_err() {
+            ringbuf_entry!(Trace::Status(err.bits()));
+        }
+        result
+    }
+
+    // RM0433 Rev 7 section 4.3.9
+    // Following Single write sequence
+    fn write_word(
+        &mut self,
+        start: u32,
+        bytes: &[u8],
+    ) -> Result<(), FlashError> {
+        ringbuf_entry!(Trace::WriteStart);
+
+        self.flash.bank2().cr.write(|w| {
+            // SAFETY
+            // The `psize().bits(_)` function is marked unsafe in the stm32
+            // crate because it allows arbitrary bit patterns. `0b11`
+            // corresponds to 64-bit parallelism.
+            unsafe { w.psize().bits(0b11) }.pg().set_bit()
+        });
+
+        for (i, c) in bytes.chunks_exact(4).enumerate() {
+            let mut word: [u8; 4] = [0; 4];
+            word.copy_from_slice(c);
+
+            // SAFETY
+            // This code is running out of bank #1. The programming for bank #2
+            // is completely separate so it will not affect running code.
+            // The address is bounds checked against the start and end of
+            // the bank limits by `program`.
+            unsafe {
+                core::ptr::write_volatile(
+                    (start + (i * 4) as u32) as *mut u32,
+                    u32::from_le_bytes(word),
+                );
+            }
+        }
+
+        let b = self.poll_flash_done();
+        ringbuf_entry!(Trace::WriteEnd);
+        b
+    }
+
+    // All sequences can be found in RM0433 Rev 7
+    fn unlock(&mut self) {
+        if !self.flash.bank2().cr.read().lock().bit() {
+            return;
+        }
+
+        self.flash
+            .bank2()
+            .keyr
+            .write(|w| unsafe { w.keyr().bits(FLASH_KEY1) });
+        self.flash
+            .bank2()
+            .keyr
+            .write(|w| unsafe { w.keyr().bits(FLASH_KEY2) });
+
+        self.flash
+            .optkeyr()
+            .write(|w| unsafe { w.optkeyr().bits(FLASH_OPT_KEY1) });
+        self.flash
+            .optkeyr()
+            .write(|w| unsafe { w.optkeyr().bits(FLASH_OPT_KEY2) });
+    }
+
+    fn check_range(offset: usize, len: usize) -> Result<u32, FlashError> {
+        match offset.checked_add(len) {
+            Some(end) if end <= (BANK_END - BANK_ADDR) as usize => {
+                Ok(BANK_ADDR + offset as u32)
+            }
+            _ => Err(FlashError::OutOfBounds),
+        }
+    }
+}
+
+impl Flash for BackendFlash {
+    const SECTOR_SIZE: usize = SECTOR_SIZE;
+    const WRITE_SIZE: usize = FLASH_WORD_BYTES;
+
+    fn capacity(&self) -> usize {
+        (BANK_END - BANK_ADDR) as usize
+    }
+
+    // RM0433 Rev 7 section 4.3.10
+    // Following Flash sector erase sequence
+    fn erase_sector(&mut self, sector: usize) -> Result<(), FlashError> {
+        Self::check_range(sector * SECTOR_SIZE, SECTOR_SIZE)?;
+        ringbuf_entry!(Trace::EraseStart(sector));
+
+        self.unlock();
+
+        // Enable relevant interrupts for completion (or failure) of erasing
+        // bank2.
+        sys_irq_control(FLASH_IRQ, true);
+        self.flash.bank2().cr.modify(|_, w| {
+            w.eopie()
+                .set_bit()
+                .wrperrie()
+                .set_bit()
+                .pgserrie()
+                .set_bit()
+                .strberrie()
+                .set_bit()
+                .incerrie()
+                .set_bit()
+                .operrie()
+                .set_bit()
+        });
+
+        self.flash.bank2().cr.modify(|_, w| {
+            // SAFETY
+            // The `snb().bits(_)` function is marked unsafe in the stm32
+            // crate because it allows arbitrary bit patterns. `sector` was
+            // checked against the size of the bank above, so it's 0-7.
+            unsafe { w.snb().bits(sector as u8) }
+                .ser()
+                .set_bit()
+                .start()
+                .set_bit()
+        });
+
+        // Wait for EOP notification via interrupt.
+        loop {
+            sys_recv_closed(&mut [], FLASH_IRQ, TaskId::KERNEL).unwrap_lite();
+            if self.flash.bank2().sr.read().eop().bit() {
+                break;
+            } else {
+                sys_irq_control(FLASH_IRQ, true);
+            }
+        }
+
+        let b = self.bank2_status(FlashError::EraseFailed);
+        ringbuf_entry!(Trace::EraseEnd);
+        b
+    }
+
+    fn program(
+        &mut self,
+        offset: usize,
+        data: &[u8],
+    ) -> Result<(), FlashError> {
+        if offset % FLASH_WORD_BYTES != 0 || data.len() % FLASH_WORD_BYTES != 0
+        {
+            return Err(FlashError::Unaligned);
+        }
+        let start = Self::check_range(offset, data.len())?;
+
+        self.unlock();
+        for (i, c) in data.chunks(FLASH_WORD_BYTES).enumerate() {
+            self.write_word(start + (i * FLASH_WORD_BYTES) as u32, c)?;
+        }
+        Ok(())
+    }
+
+    fn read(
+        &mut self,
+        offset: usize,
+        buf: &mut [u8],
+    ) -> Result<(), FlashError> {
+        let start = Self::check_range(offset, buf.len())?;
+
+        // SAFETY
+        // Bank 2 is mapped into our address space (see `uses` in app.toml)
+        // and the range was checked against it above.
+        let bank = unsafe {
+            core::slice::from_raw_parts(start as *const u8, buf.len())
+        };
+        buf.copy_from_slice(bank);
+        Ok(())
+    }
+}
@@ -123,6 +123,34 @@
           hubris = self.packages.${system}.tests-hifive-inventor;
           port = "1234";
         };
+        demo-rv64-qemu-virt = hubris {
+          app = "demo-rv64-qemu-virt";
+          toml = "app/demo-rv64-qemu-virt/app.toml";
+          doCheck = true;
+        };
+        tests-rv64-qemu-virt = hubris {
+          app = "tests-rv64-qemu-virt";
+          toml = "test/tests-rv64-qemu-virt/app.toml";
+          # don't do check, test suite is NOT clippy clean
+        };
+        tests-rv64-qemu-virt-runner = hubris-test-suite-runner {
+          hubris = self.packages.${system}.tests-rv64-qemu-virt;
+          port = "1235";
+        };
+        demo-rv32-qemu-virt-smode = hubris {
+          app = "demo-rv32-qemu-virt-smode";
+          toml = "app/demo-rv32-qemu-virt-smode/app.toml";
+          doCheck = true;
+        };
+        tests-rv32-qemu-virt-smode = hubris {
+          app = "tests-rv32-qemu-virt-smode";
+          toml = "test/tests-rv32-qemu-virt-smode/app.toml";
+          # don't do check, test suite is NOT clippy clean
+        };
+        tests-rv32-qemu-virt-smode-runner = hubris-test-suite-runner {
+          hubris = self.packages.${system}.tests-rv32-qemu-virt-smode;
+          port = "1236";
+        };
       };
 
       devShells.default = pkgs.mkShell {
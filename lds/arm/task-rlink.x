@@ -57,6 +57,13 @@ SECTIONS
     __sheap = .;
   }
 
+  /* ## .stack_sizes */
+  /* Per-function stack frame sizes emitted by `-Z emit-stack-sizes`. Used by
+     `xtask stack` to estimate worst-case stack depth. */
+  .stack_sizes (INFO) : {
+    KEEP(*(.stack_sizes));
+  }
+
   /* ## .task_slot_table */
   /* Table of TaskSlot instances and their names. Used to resolve task
      dependencies during packaging. */
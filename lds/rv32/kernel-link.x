@@ -1,6 +1,5 @@
 INCLUDE memory.x
 
-PROVIDE(_stext = ORIGIN(REGION_TEXT));
 PROVIDE(_stack_start = ORIGIN(REGION_STACK) + LENGTH(REGION_STACK));
 PROVIDE(_max_hart_id = 0);
 PROVIDE(_hart_stack_size = 896);
@@ -44,6 +43,24 @@ PROVIDE(_start_trap = default_start_trap);
 
 SECTIONS
 {
+  /* Images start with a jump over the header, so they can be entered at
+     their first byte. The bootloader assumes that the header immediately
+     follows the jump, as it does the vector table on Arm. */
+  .image_entry ORIGIN(REGION_TEXT) :
+  {
+    KEEP(*(.image_entry));
+  } > REGION_TEXT
+
+  __vector_size = SIZEOF(.image_entry);
+  /* Header containing data needed by the bootloader */
+  .header : ALIGN(4)
+  {
+    __header_start = .;
+    KEEP(*(.image_header));
+  } > REGION_TEXT
+
+  PROVIDE(_stext = ALIGN(ADDR(.header) + SIZEOF(.header), 4));
+
   .text.dummy (NOLOAD) :
   {
     /* This section is intended to make _stext address work */
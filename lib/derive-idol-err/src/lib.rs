@@ -4,7 +4,7 @@
 
 use proc_macro::TokenStream;
 use quote::quote;
-use syn::{parse_macro_input, DeriveInput};
+use syn::{parse_macro_input, Attribute, Data, DeriveInput, Ident};
 
 /// Adds three `impl` blocks for the given error type:
 /// - `From<E> for u16` (Idol encoding)
@@ -14,10 +14,43 @@ use syn::{parse_macro_input, DeriveInput};
 /// The given type must also derive `FromPrimitive`, which is used in the
 /// `TryFrom<u32>` implementation.  Sadly, this cannot be automatically added
 /// to the type by this macro.
-#[proc_macro_derive(IdolError)]
+///
+/// One variant may be marked `#[idol(timed_out)]`, in which case decoding
+/// also maps the kernel's `userlib::TIMED_OUT` response code to it, so that
+/// a client whose send was cancelled by its timeout (see
+/// `userlib::sys_send_with_timeout`) can report it. The variant is also
+/// available as `E::TIMED_OUT`, which the timed clients generated by
+/// `build-idol-ext` return.
+#[proc_macro_derive(IdolError, attributes(idol))]
 pub fn derive(input: TokenStream) -> TokenStream {
-    let DeriveInput { ident, .. } = parse_macro_input!(input);
+    let DeriveInput { ident, data, .. } = parse_macro_input!(input);
+
+    let timed_out = match &data {
+        Data::Enum(e) => e
+            .variants
+            .iter()
+            .find(|v| v.attrs.iter().any(is_timed_out))
+            .map(|v| {
+                let variant = &v.ident;
+                (
+                    quote! {
+                        impl #ident {
+                            pub const TIMED_OUT: Self = Self::#variant;
+                        }
+                    },
+                    quote! {
+                        if v == userlib::TIMED_OUT {
+                            return Ok(Self::#variant);
+                        }
+                    },
+                )
+            }),
+        _ => None,
+    };
+    let (timed_out_const, timed_out) = timed_out.unzip();
+
     let output = quote! {
+        #timed_out_const
         impl From<#ident> for u16 {
             fn from(v: #ident) -> Self {
                 v as u16
@@ -31,9 +64,18 @@ pub fn derive(input: TokenStream) -> TokenStream {
         impl core::convert::TryFrom<u32> for #ident {
             type Error = ();
             fn try_from(v: u32) -> Result<Self, Self::Error> {
+                #timed_out
                 Self::from_u32(v).ok_or(())
             }
         }
     };
     output.into()
 }
+
+/// Checks for `#[idol(timed_out)]`.
+fn is_timed_out(attr: &Attribute) -> bool {
+    attr.path.is_ident("idol")
+        && attr
+            .parse_args::<Ident>()
+            .map_or(false, |arg| arg == "timed_out")
+}
@@ -0,0 +1,519 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Generates the DER templates for the certificates & CSRs produced by DICE
+//! from the description in templates.toml. Each template is written to its
+//! own module in OUT_DIR along with the ranges of the fields that are filled
+//! in at runtime.
+
+use serde::Deserialize;
+use std::collections::BTreeMap;
+use std::error::Error;
+use std::fs::File;
+use std::io::Write;
+use std::ops::Range;
+use std::path::{Path, PathBuf};
+
+const TEMPLATES: &str = "templates.toml";
+
+// The length of the serialNumber in issuer & subject names.
+const SN_LENGTH: usize = 12;
+const PUBLICKEY_LENGTH: usize = 32;
+const SIGNATURE_LENGTH: usize = 64;
+
+const BOOLEAN: u8 = 0x01;
+const INTEGER: u8 = 0x02;
+const BIT_STRING: u8 = 0x03;
+const OCTET_STRING: u8 = 0x04;
+const OID: u8 = 0x06;
+const UTF8_STRING: u8 = 0x0c;
+const PRINTABLE_STRING: u8 = 0x13;
+const UTC_TIME: u8 = 0x17;
+const GENERALIZED_TIME: u8 = 0x18;
+const SEQUENCE: u8 = 0x30;
+const SET: u8 = 0x31;
+const CONTEXT_0: u8 = 0xa0;
+const CONTEXT_3: u8 = 0xa3;
+const CONTEXT_6: u8 = 0xa6;
+
+const ED25519: &str = "1.3.101.112";
+const EXTENSION_REQUEST: &str = "1.2.840.113549.1.9.14";
+const BASIC_CONSTRAINTS: &str = "2.5.29.19";
+const KEY_USAGE: &str = "2.5.29.15";
+const CERTIFICATE_POLICIES: &str = "2.5.29.32";
+const TCG_DICE_TCB_INFO: &str = "2.23.133.5.4.1";
+const SHA3_256: &str = "2.16.840.1.101.3.4.2.8";
+const SHA3_256_LENGTH: usize = 32;
+
+// The named bits of the keyUsage extension (RFC 5280 §4.2.1.3), in order.
+const KEY_USAGES: [&str; 9] = [
+    "digital-signature",
+    "non-repudiation",
+    "key-encipherment",
+    "data-encipherment",
+    "key-agreement",
+    "key-cert-sign",
+    "crl-sign",
+    "encipher-only",
+    "decipher-only",
+];
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct Templates {
+    name: NameConfig,
+    #[serde(default)]
+    certs: BTreeMap<String, CertConfig>,
+    #[serde(default)]
+    csrs: BTreeMap<String, CsrConfig>,
+}
+
+/// The attributes of the distinguished name that are the same for every
+/// issuer & subject.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct NameConfig {
+    country: String,
+    state: String,
+    locality: String,
+    organization: String,
+    organizational_unit: String,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct CertConfig {
+    /// The commonName of the issuer.
+    issuer: String,
+    /// The commonName of the subject.
+    subject: String,
+    /// A UTCTime or GeneralizedTime, e.g. "220731163337Z".
+    not_before: String,
+    not_after: String,
+    #[serde(default)]
+    extensions: Extensions,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct CsrConfig {
+    /// The commonName of the subject.
+    subject: String,
+    /// The extensions to ask the CA for.
+    #[serde(default)]
+    extensions: Extensions,
+}
+
+#[derive(Default, Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct Extensions {
+    basic_constraints: Option<BasicConstraints>,
+    key_usage: Option<KeyUsage>,
+    certificate_policies: Option<CertificatePolicies>,
+    tcb_info: Option<TcbInfo>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct BasicConstraints {
+    critical: bool,
+    ca: bool,
+    path_len: Option<u8>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct KeyUsage {
+    critical: bool,
+    usages: Vec<String>,
+}
+
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct CertificatePolicies {
+    critical: bool,
+    /// Policy OIDs in dotted decimal.
+    policies: Vec<String>,
+}
+
+/// The TCG DICE TcbInfo extension, holding the FWID measured by the issuer.
+#[derive(Deserialize)]
+#[serde(deny_unknown_fields, rename_all = "kebab-case")]
+struct TcbInfo {
+    critical: bool,
+    fwid_hash: String,
+}
+
+/// A DER element, along with the ranges of the fields within it that are
+/// filled in at runtime.
+struct Der {
+    bytes: Vec<u8>,
+    fields: Vec<(&'static str, Range<usize>)>,
+}
+
+impl Der {
+    fn new(tag: u8, value: &[u8]) -> Self {
+        let mut bytes = header(tag, value.len());
+        bytes.extend_from_slice(value);
+
+        Self {
+            bytes,
+            fields: Vec::new(),
+        }
+    }
+
+    /// An element whose value is `prefix` followed by `len` zeros, which are
+    /// the field `name`.
+    fn field(tag: u8, prefix: &[u8], name: &'static str, len: usize) -> Self {
+        let mut value = prefix.to_vec();
+        value.resize(prefix.len() + len, 0);
+        let mut der = Self::new(tag, &value);
+        let end = der.bytes.len();
+        der.fields.push((name, end - len..end));
+
+        der
+    }
+
+    /// An element whose value is the concatenation of `children`.
+    fn wrap(tag: u8, children: Vec<Der>) -> Self {
+        let len = children.iter().map(|c| c.bytes.len()).sum();
+        let mut der = Self {
+            bytes: header(tag, len),
+            fields: Vec::new(),
+        };
+        for child in children {
+            let offset = der.bytes.len();
+            der.bytes.extend_from_slice(&child.bytes);
+            der.fields.extend(
+                child
+                    .fields
+                    .into_iter()
+                    .map(|(name, r)| (name, r.start + offset..r.end + offset)),
+            );
+        }
+
+        der
+    }
+
+    /// Makes the whole element the field `name`.
+    fn mark(mut self, name: &'static str) -> Self {
+        self.fields.push((name, 0..self.bytes.len()));
+
+        self
+    }
+}
+
+fn header(tag: u8, len: usize) -> Vec<u8> {
+    match len {
+        0..=0x7f => vec![tag, len as u8],
+        0x80..=0xff => vec![tag, 0x81, len as u8],
+        _ => {
+            let len = u16::try_from(len).expect("DER element too large");
+            let [hi, lo] = len.to_be_bytes();
+            vec![tag, 0x82, hi, lo]
+        }
+    }
+}
+
+fn sequence(children: Vec<Der>) -> Der {
+    Der::wrap(SEQUENCE, children)
+}
+
+fn oid(dotted: &str) -> Result<Der, Box<dyn Error>> {
+    let arcs = dotted
+        .split('.')
+        .map(str::parse::<u64>)
+        .collect::<Result<Vec<_>, _>>()
+        .map_err(|_| format!("bad OID: {}", dotted))?;
+    if arcs.len() < 2 || arcs[0] > 2 || (arcs[0] < 2 && arcs[1] >= 40) {
+        return Err(format!("bad OID: {}", dotted).into());
+    }
+
+    let mut value = Vec::new();
+    let first = arcs[0] * 40 + arcs[1];
+    for arc in std::iter::once(first).chain(arcs[2..].iter().copied()) {
+        let mut base128 = vec![(arc & 0x7f) as u8];
+        let mut rest = arc >> 7;
+        while rest != 0 {
+            base128.push((rest & 0x7f) as u8 | 0x80);
+            rest >>= 7;
+        }
+        value.extend(base128.iter().rev());
+    }
+
+    Ok(Der::new(OID, &value))
+}
+
+fn integer(i: u8) -> Der {
+    if i < 0x80 {
+        Der::new(INTEGER, &[i])
+    } else {
+        Der::new(INTEGER, &[0, i])
+    }
+}
+
+fn time(time: &str) -> Result<Der, Box<dyn Error>> {
+    let tag = match time.len() {
+        13 => UTC_TIME,
+        15 => GENERALIZED_TIME,
+        _ => return Err(format!("bad time: {}", time).into()),
+    };
+    let digits = &time[..time.len() - 1];
+    if !time.ends_with('Z') || !digits.bytes().all(|b| b.is_ascii_digit()) {
+        return Err(format!("bad time: {}", time).into());
+    }
+
+    Ok(Der::new(tag, time.as_bytes()))
+}
+
+fn ed25519() -> Result<Der, Box<dyn Error>> {
+    Ok(sequence(vec![oid(ED25519)?]))
+}
+
+fn name(
+    config: &NameConfig,
+    common_name: &str,
+    sn: &'static str,
+) -> Result<Der, Box<dyn Error>> {
+    let attribute = |id: &str, value: Der| -> Result<Der, Box<dyn Error>> {
+        Ok(Der::wrap(SET, vec![sequence(vec![oid(id)?, value])]))
+    };
+    let utf8 = |s: &str| Der::new(UTF8_STRING, s.as_bytes());
+
+    Ok(sequence(vec![
+        attribute(
+            "2.5.4.6",
+            Der::new(PRINTABLE_STRING, config.country.as_bytes()),
+        )?,
+        attribute("2.5.4.8", utf8(&config.state))?,
+        attribute("2.5.4.7", utf8(&config.locality))?,
+        attribute("2.5.4.10", utf8(&config.organization))?,
+        attribute("2.5.4.11", utf8(&config.organizational_unit))?,
+        attribute("2.5.4.3", utf8(common_name))?,
+        attribute("2.5.4.5", Der::field(PRINTABLE_STRING, &[], sn, SN_LENGTH))?,
+    ]))
+}
+
+fn subject_public_key_info() -> Result<Der, Box<dyn Error>> {
+    Ok(sequence(vec![
+        ed25519()?,
+        Der::field(BIT_STRING, &[0], "PUB", PUBLICKEY_LENGTH),
+    ]))
+}
+
+fn extension(
+    id: &str,
+    critical: bool,
+    value: Der,
+) -> Result<Der, Box<dyn Error>> {
+    let mut children = vec![oid(id)?];
+    if critical {
+        children.push(Der::new(BOOLEAN, &[0xff]));
+    }
+    children.push(Der::wrap(OCTET_STRING, vec![value]));
+
+    Ok(sequence(children))
+}
+
+fn extensions(config: &Extensions) -> Result<Option<Der>, Box<dyn Error>> {
+    let mut extensions = Vec::new();
+
+    if let Some(bc) = &config.basic_constraints {
+        let mut value = Vec::new();
+        if bc.ca {
+            value.push(Der::new(BOOLEAN, &[0xff]));
+        }
+        if let Some(path_len) = bc.path_len {
+            if !bc.ca {
+                return Err("path-len is only meaningful for a CA".into());
+            }
+            value.push(integer(path_len));
+        }
+        extensions.push(extension(
+            BASIC_CONSTRAINTS,
+            bc.critical,
+            sequence(value),
+        )?);
+    }
+
+    if let Some(ku) = &config.key_usage {
+        let mut bits = 0u16;
+        for usage in &ku.usages {
+            let bit = KEY_USAGES
+                .iter()
+                .position(|u| u == usage)
+                .ok_or_else(|| format!("unknown key usage: {}", usage))?;
+            bits |= 0x8000 >> bit;
+        }
+        if bits == 0 {
+            return Err("key-usage needs at least one usage".into());
+        }
+        // DER drops trailing zero bits, and any byte left holding none.
+        let [hi, lo] = bits.to_be_bytes();
+        let value = if lo == 0 {
+            vec![hi.trailing_zeros() as u8, hi]
+        } else {
+            vec![lo.trailing_zeros() as u8, hi, lo]
+        };
+        extensions.push(extension(
+            KEY_USAGE,
+            ku.critical,
+            Der::new(BIT_STRING, &value),
+        )?);
+    }
+
+    if let Some(cp) = &config.certificate_policies {
+        let policies = cp
+            .policies
+            .iter()
+            .map(|p| Ok(sequence(vec![oid(p)?])))
+            .collect::<Result<Vec<_>, Box<dyn Error>>>()?;
+        extensions.push(extension(
+            CERTIFICATE_POLICIES,
+            cp.critical,
+            sequence(policies),
+        )?);
+    }
+
+    if let Some(tcb) = &config.tcb_info {
+        if tcb.fwid_hash != "sha3-256" {
+            return Err(
+                format!("unsupported FWID hash: {}", tcb.fwid_hash).into()
+            );
+        }
+        // DiceTcbInfo with only the fwids field, holding a single FWID.
+        let fwid = sequence(vec![
+            oid(SHA3_256)?,
+            Der::field(OCTET_STRING, &[], "FWID", SHA3_256_LENGTH),
+        ]);
+        extensions.push(extension(
+            TCG_DICE_TCB_INFO,
+            tcb.critical,
+            sequence(vec![Der::wrap(CONTEXT_6, vec![fwid])]),
+        )?);
+    }
+
+    if extensions.is_empty() {
+        Ok(None)
+    } else {
+        Ok(Some(sequence(extensions)))
+    }
+}
+
+fn signature() -> Der {
+    Der::field(BIT_STRING, &[0], "SIG", SIGNATURE_LENGTH)
+}
+
+fn cert(
+    templates: &Templates,
+    config: &CertConfig,
+) -> Result<Der, Box<dyn Error>> {
+    let mut tbs = vec![
+        Der::wrap(CONTEXT_0, vec![integer(2)]),
+        Der::field(INTEGER, &[], "SERIAL_NUMBER", 1),
+        ed25519()?,
+        name(&templates.name, &config.issuer, "ISSUER_SN")?,
+        sequence(vec![time(&config.not_before)?, time(&config.not_after)?]),
+        name(&templates.name, &config.subject, "SUBJECT_SN")?,
+        subject_public_key_info()?,
+    ];
+    if let Some(extensions) = extensions(&config.extensions)? {
+        tbs.push(Der::wrap(CONTEXT_3, vec![extensions]));
+    }
+
+    Ok(sequence(vec![
+        sequence(tbs).mark("SIGNDATA"),
+        ed25519()?,
+        signature(),
+    ]))
+}
+
+fn csr(
+    templates: &Templates,
+    config: &CsrConfig,
+) -> Result<Der, Box<dyn Error>> {
+    let mut attributes = Vec::new();
+    if let Some(extensions) = extensions(&config.extensions)? {
+        attributes.push(sequence(vec![
+            oid(EXTENSION_REQUEST)?,
+            Der::wrap(SET, vec![extensions]),
+        ]));
+    }
+    let info = sequence(vec![
+        integer(0),
+        name(&templates.name, &config.subject, "SUBJECT_SN")?,
+        subject_public_key_info()?,
+        Der::wrap(CONTEXT_0, attributes),
+    ]);
+
+    Ok(sequence(vec![
+        info.mark("SIGNDATA"),
+        ed25519()?,
+        signature(),
+    ]))
+}
+
+fn write_template(
+    path: &Path,
+    tmpl_name: &str,
+    der: &Der,
+) -> Result<(), Box<dyn Error>> {
+    let mut out = File::create(path)?;
+
+    writeln!(out, "// Generated by build.rs from {}.", TEMPLATES)?;
+    writeln!(out)?;
+    writeln!(out, "use core::ops::Range;")?;
+    writeln!(out)?;
+    writeln!(out, "pub const SIZE: usize = {};", der.bytes.len())?;
+    for (name, range) in &der.fields {
+        writeln!(
+            out,
+            "pub const {}_RANGE: Range<usize> = {}..{};",
+            name, range.start, range.end
+        )?;
+    }
+    writeln!(
+        out,
+        "pub const {}: [u8; {}] = [",
+        tmpl_name,
+        der.bytes.len()
+    )?;
+    for line in der.bytes.chunks(12) {
+        let line: Vec<String> =
+            line.iter().map(|b| format!("{:#04x},", b)).collect();
+        writeln!(out, "    {}", line.join(" "))?;
+    }
+    writeln!(out, "];")?;
+
+    Ok(())
+}
+
+fn main() -> Result<(), Box<dyn Error>> {
+    println!("cargo:rerun-if-changed={}", TEMPLATES);
+
+    let templates: Templates =
+        toml::from_str(&std::fs::read_to_string(TEMPLATES)?)?;
+    let out_dir = PathBuf::from(std::env::var("OUT_DIR")?);
+
+    for (name, config) in &templates.certs {
+        let der =
+            cert(&templates, config).map_err(|e| format!("{}: {}", name, e))?;
+        write_template(
+            &out_dir.join(format!("{}.rs", name)),
+            "CERT_TMPL",
+            &der,
+        )?;
+    }
+    for (name, config) in &templates.csrs {
+        let der =
+            csr(&templates, config).map_err(|e| format!("{}: {}", name, e))?;
+        write_template(
+            &out_dir.join(format!("{}.rs", name)),
+            "CSR_TMPL",
+            &der,
+        )?;
+    }
+
+    Ok(())
+}
@@ -0,0 +1,325 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::{
+    AliasCertBuilder, AliasData, AliasOkm, Cdi, CdiL1, CertSerialNumber,
+    DeviceIdCertChain, DeviceIdCertsData, DeviceIdCsrBuilder, DeviceIdCsrData,
+    DeviceIdOkm, DeviceIdSelfCertBuilder, Handoff, RngData, RngSeed,
+    SerialNumber, SpMeasureCertBuilder, SpMeasureData, SpMeasureOkm,
+    TrustQuorumDheCertBuilder, TrustQuorumDheOkm, SEED_LENGTH,
+};
+use salty::signature::Keypair;
+
+/// Everything stage0 derives from the CDI and hands off to hubris tasks: the
+/// keys and certificates for layer 1 (L1), the image being booted, with the
+/// DeviceId certificate they chain to, and what's needed to have the DeviceId
+/// key certified externally.
+pub struct Artifacts {
+    pub alias: AliasData,
+    pub spmeasure: SpMeasureData,
+    pub rng: RngData,
+    pub deviceid_csr: DeviceIdCsrData,
+    pub deviceid_certs: DeviceIdCertsData,
+}
+
+impl Artifacts {
+    /// Derives the DICE hierarchy from `cdi` for an L1 image measured as
+    /// `fwid`. The certificates' subjects are identified by `dname_sn`.
+    ///
+    /// `certs` is the externally signed DeviceId cert chain installed in
+    /// persistent storage, if any. It's handed off only if it certifies the
+    /// DeviceId key derived from `cdi`, so a chain left over from another
+    /// device (or a different UDS) is ignored.
+    pub fn derive(
+        cdi: &Cdi,
+        dname_sn: &SerialNumber,
+        fwid: &[u8; SEED_LENGTH],
+        certs: Option<DeviceIdCertChain>,
+    ) -> Self {
+        let devid_okm = DeviceIdOkm::from_cdi(cdi);
+        let deviceid_keypair = Keypair::from(devid_okm.as_bytes());
+        let mut cert_sn = CertSerialNumber::default();
+
+        let deviceid_csr = DeviceIdCsrData::new(
+            DeviceIdCsrBuilder::new(dname_sn, &deviceid_keypair.public)
+                .sign(&deviceid_keypair),
+        );
+        let deviceid_certs = DeviceIdCertsData::new(certs.filter(|certs| {
+            certs.leaf_public_key() == deviceid_keypair.public.as_bytes()
+        }));
+
+        let deviceid_cert = DeviceIdSelfCertBuilder::new(
+            &cert_sn.next(),
+            dname_sn,
+            &deviceid_keypair.public,
+        )
+        .sign(&deviceid_keypair);
+
+        // create CDI for layer 1 (L1) firmware (the hubris image we're booting)
+        let cdi_l1 = CdiL1::new(cdi, fwid);
+
+        // derive alias key
+        let alias_okm = AliasOkm::from_cdi(&cdi_l1);
+        let alias_keypair = Keypair::from(alias_okm.as_bytes());
+
+        let alias_cert = AliasCertBuilder::new(
+            &cert_sn.next(),
+            dname_sn,
+            &alias_keypair.public,
+            fwid,
+        )
+        .sign(&deviceid_keypair);
+
+        let tqdhe_okm = TrustQuorumDheOkm::from_cdi(&cdi_l1);
+        let tqdhe_keypair = Keypair::from(tqdhe_okm.as_bytes());
+
+        let tqdhe_cert = TrustQuorumDheCertBuilder::new(
+            &cert_sn.next(),
+            dname_sn,
+            &tqdhe_keypair.public,
+            fwid,
+        )
+        .sign(&deviceid_keypair);
+
+        let alias = AliasData::new(
+            alias_okm,
+            alias_cert,
+            tqdhe_okm,
+            tqdhe_cert,
+            deviceid_cert.clone(),
+        );
+
+        let spmeasure_okm = SpMeasureOkm::from_cdi(&cdi_l1);
+        let spmeasure_keypair = Keypair::from(spmeasure_okm.as_bytes());
+
+        let spmeasure_cert = SpMeasureCertBuilder::new(
+            &cert_sn.next(),
+            dname_sn,
+            &spmeasure_keypair.public,
+            fwid,
+        )
+        .sign(&deviceid_keypair);
+
+        let spmeasure =
+            SpMeasureData::new(spmeasure_okm, spmeasure_cert, deviceid_cert);
+
+        let rng = RngData::new(RngSeed::from_cdi(&cdi_l1));
+
+        Self {
+            alias,
+            spmeasure,
+            rng,
+            deviceid_csr,
+            deviceid_certs,
+        }
+    }
+
+    pub fn store(&self, handoff: &mut Handoff<'_>) {
+        handoff.store(&self.alias);
+        handoff.store(&self.spmeasure);
+        handoff.store(&self.rng);
+        handoff.store(&self.deviceid_csr);
+        handoff.store(&self.deviceid_certs);
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        Cert, HandoffData, SeedBuf, SeedSource, SoftwareSeed, HANDOFF_SIZE,
+    };
+    use core::str::FromStr;
+    use salty::constants::{
+        PUBLICKEY_SERIALIZED_LENGTH, SIGNATURE_SERIALIZED_LENGTH,
+    };
+    use salty::signature::{PublicKey, Signature};
+
+    const UDS: [u8; SEED_LENGTH] = [0x5a; SEED_LENGTH];
+    const FWID: [u8; SEED_LENGTH] = [0x11; SEED_LENGTH];
+
+    fn derive_with(
+        uds: &[u8; SEED_LENGTH],
+        fwid: &[u8; SEED_LENGTH],
+        certs: Option<DeviceIdCertChain>,
+    ) -> Artifacts {
+        let cdi = SoftwareSeed::new(uds).get_cdi().expect("get_cdi");
+        let sn = SerialNumber::from_str("0123456789ab").expect("SN from_str");
+
+        Artifacts::derive(&cdi, &sn, fwid, certs)
+    }
+
+    fn derive(uds: &[u8; SEED_LENGTH], fwid: &[u8; SEED_LENGTH]) -> Artifacts {
+        derive_with(uds, fwid, None)
+    }
+
+    fn assert_signed_by<C: Cert>(cert: &C, signer: &[u8]) {
+        let signer: &[u8; PUBLICKEY_SERIALIZED_LENGTH] =
+            signer.try_into().expect("public key length");
+        let signer = PublicKey::try_from(signer).expect("public key");
+        let sig: &[u8; SIGNATURE_SERIALIZED_LENGTH] =
+            cert.get_sig().try_into().expect("signature length");
+
+        signer
+            .verify(cert.get_signdata(), &Signature::from(sig))
+            .expect("signature");
+    }
+
+    #[test]
+    fn chain_verifies() {
+        let artifacts = derive(&UDS, &FWID);
+        let deviceid = &artifacts.alias.deviceid_cert;
+
+        assert_signed_by(deviceid, deviceid.get_pub());
+        assert_signed_by(&artifacts.alias.alias_cert, deviceid.get_pub());
+        assert_signed_by(&artifacts.alias.tqdhe_cert, deviceid.get_pub());
+        assert_signed_by(
+            &artifacts.spmeasure.spmeasure_cert,
+            deviceid.get_pub(),
+        );
+        assert_eq!(
+            artifacts.spmeasure.deviceid_cert.as_bytes(),
+            deviceid.as_bytes()
+        );
+    }
+
+    #[test]
+    fn certs_carry_fwid() {
+        let artifacts = derive(&UDS, &FWID);
+
+        assert_eq!(artifacts.alias.alias_cert.get_fwid(), FWID);
+        assert_eq!(artifacts.alias.tqdhe_cert.get_fwid(), FWID);
+        assert_eq!(artifacts.spmeasure.spmeasure_cert.get_fwid(), FWID);
+    }
+
+    #[test]
+    fn serial_numbers_are_distinct() {
+        let artifacts = derive(&UDS, &FWID);
+        let sns = [
+            artifacts.alias.deviceid_cert.get_serial_number(),
+            artifacts.alias.alias_cert.get_serial_number(),
+            artifacts.alias.tqdhe_cert.get_serial_number(),
+            artifacts.spmeasure.spmeasure_cert.get_serial_number(),
+        ];
+
+        for (i, a) in sns.iter().enumerate() {
+            for b in &sns[i + 1..] {
+                assert_ne!(a, b);
+            }
+        }
+    }
+
+    #[test]
+    fn deviceid_depends_only_on_uds() {
+        let a = derive(&UDS, &FWID);
+        let b = derive(&UDS, &[0x22; SEED_LENGTH]);
+        let c = derive(&[0xa5; SEED_LENGTH], &FWID);
+
+        assert_eq!(
+            a.alias.deviceid_cert.as_bytes(),
+            b.alias.deviceid_cert.as_bytes()
+        );
+        assert_ne!(
+            a.alias.deviceid_cert.get_pub(),
+            c.alias.deviceid_cert.get_pub()
+        );
+    }
+
+    #[test]
+    fn alias_depends_on_fwid() {
+        let a = derive(&UDS, &FWID);
+        let b = derive(&UDS, &FWID);
+        let c = derive(&UDS, &[0x22; SEED_LENGTH]);
+
+        assert_eq!(
+            a.alias.alias_cert.as_bytes(),
+            b.alias.alias_cert.as_bytes()
+        );
+        assert_ne!(a.alias.alias_cert.get_pub(), c.alias.alias_cert.get_pub());
+        assert_ne!(a.rng.seed.as_bytes(), c.rng.seed.as_bytes());
+    }
+
+    #[test]
+    fn handoff_round_trip() {
+        let artifacts = derive(&UDS, &FWID);
+        let mut mem = vec![0u8; HANDOFF_SIZE];
+
+        assert!(AliasData::from_bytes(&mem).is_none());
+
+        artifacts.store(&mut Handoff::new(&mut mem));
+
+        let alias = AliasData::from_bytes(&mem).expect("AliasData");
+        assert_eq!(
+            alias.alias_cert.as_bytes(),
+            artifacts.alias.alias_cert.as_bytes()
+        );
+        assert_eq!(
+            alias.deviceid_cert.as_bytes(),
+            artifacts.alias.deviceid_cert.as_bytes()
+        );
+
+        let spmeasure = SpMeasureData::from_bytes(&mem).expect("SpMeasureData");
+        assert_eq!(
+            spmeasure.spmeasure_cert.as_bytes(),
+            artifacts.spmeasure.spmeasure_cert.as_bytes()
+        );
+
+        let rng = RngData::from_bytes(&mem).expect("RngData");
+        assert_eq!(rng.seed.as_bytes(), artifacts.rng.seed.as_bytes());
+
+        let csr = DeviceIdCsrData::from_bytes(&mem).expect("DeviceIdCsrData");
+        assert_eq!(csr.csr.as_bytes(), artifacts.deviceid_csr.csr.as_bytes());
+
+        let certs =
+            DeviceIdCertsData::from_bytes(&mem).expect("DeviceIdCertsData");
+        assert!(certs.certs.is_none());
+    }
+
+    #[test]
+    fn csr_is_for_deviceid() {
+        let artifacts = derive(&UDS, &FWID);
+        let csr = &artifacts.deviceid_csr.csr;
+        let deviceid = &artifacts.alias.deviceid_cert;
+
+        assert_eq!(csr.get_pub(), deviceid.get_pub());
+        assert_eq!(
+            csr.get_subject_sn().as_bytes(),
+            deviceid.get_subject_sn().as_bytes()
+        );
+    }
+
+    // Stands in for a chain signed by our CA: the self-signed DeviceId cert
+    // is a certificate for the right key, which is all stage0 checks.
+    fn installed_certs(uds: &[u8; SEED_LENGTH]) -> DeviceIdCertChain {
+        let artifacts = derive(uds, &FWID);
+
+        DeviceIdCertChain::new(artifacts.alias.deviceid_cert.as_bytes())
+            .expect("DeviceIdCertChain")
+    }
+
+    #[test]
+    fn installed_certs_are_handed_off() {
+        let artifacts = derive_with(&UDS, &FWID, Some(installed_certs(&UDS)));
+        let certs = artifacts.deviceid_certs.certs.as_ref().expect("certs");
+
+        assert_eq!(certs.as_bytes(), artifacts.alias.deviceid_cert.as_bytes());
+
+        let mut mem = vec![0u8; HANDOFF_SIZE];
+        artifacts.store(&mut Handoff::new(&mut mem));
+        let handed_off =
+            DeviceIdCertsData::from_bytes(&mem).expect("DeviceIdCertsData");
+        assert_eq!(
+            handed_off.certs.expect("certs").as_bytes(),
+            certs.as_bytes()
+        );
+    }
+
+    #[test]
+    fn certs_for_another_key_are_ignored() {
+        let certs = installed_certs(&[0xa5; SEED_LENGTH]);
+        let artifacts = derive_with(&UDS, &FWID, Some(certs));
+
+        assert!(artifacts.deviceid_certs.certs.is_none());
+    }
+}
@@ -26,6 +26,8 @@ pub enum CertError {
     TooSmall,
     NotFound,
     NoCn,
+    TooLarge,
+    Malformed,
 }
 
 pub trait Cert {
@@ -437,12 +439,17 @@ impl Cert for TrustQuorumDheCert {
 mod tests {
     use super::*;
     use core::str::FromStr;
+    use salty::signature::Signature;
+    use x509_parser::prelude::{FromDer, X509Certificate, X509Name};
 
     #[test]
     fn serial_number_from_new() {
         let sn = CertSerialNumber::new(0x10);
-        let cert = DeviceIdSelfCert([0u8; deviceid_cert_tmpl::SIZE])
-            .set_serial_number(&sn);
+        let cert = DeviceIdSelfCert(
+            DeviceIdSelfCertBuilder([0u8; deviceid_cert_tmpl::SIZE])
+                .set_serial_number(&sn)
+                .0,
+        );
 
         assert_eq!(sn, cert.get_serial_number());
     }
@@ -450,8 +457,11 @@ mod tests {
     #[test]
     fn issuer_sn_from_new() {
         let sn = SerialNumber::from_str("0123456789ab").expect("SN from_str");
-        let cert = DeviceIdSelfCert([0u8; deviceid_cert_tmpl::SIZE])
-            .set_issuer_sn(&sn);
+        let cert = DeviceIdSelfCert(
+            DeviceIdSelfCertBuilder([0u8; deviceid_cert_tmpl::SIZE])
+                .set_issuer_sn(&sn)
+                .0,
+        );
 
         assert_eq!(cert.get_issuer_sn().as_bytes(), sn.as_bytes());
     }
@@ -459,8 +469,11 @@ mod tests {
     #[test]
     fn subject_sn_from_new() {
         let sn = SerialNumber::from_str("0123456789ab").expect("SN from_str");
-        let cert = DeviceIdSelfCert([0u8; deviceid_cert_tmpl::SIZE])
-            .set_subject_sn(&sn);
+        let cert = DeviceIdSelfCert(
+            DeviceIdSelfCertBuilder([0u8; deviceid_cert_tmpl::SIZE])
+                .set_subject_sn(&sn)
+                .0,
+        );
 
         assert_eq!(cert.get_subject_sn().as_bytes(), sn.as_bytes());
     }
@@ -484,7 +497,8 @@ mod tests {
 
         let sn = SerialNumber::from_str("0123456789ab").expect("SN from_str");
         let cert_sn = CertSerialNumber::new(0);
-        let cert = DeviceIdSelfCert::new(&cert_sn, &sn, &keypair);
+        let cert = DeviceIdSelfCertBuilder::new(&cert_sn, &sn, &keypair.public)
+            .sign(&keypair);
 
         for (index, byte) in cert.as_bytes()[deviceid_cert_tmpl::SIG_RANGE]
             .iter()
@@ -498,4 +512,191 @@ mod tests {
         }
         assert_eq!(cert.0[deviceid_cert_tmpl::SIG_RANGE], SIG_EXPECTED);
     }
+    const FWID: [u8; 32] = [0x5a; 32];
+
+    fn keypair(seed: u8) -> Keypair {
+        Keypair::from(&[seed; 32])
+    }
+
+    fn dname_sn() -> SerialNumber {
+        SerialNumber::from_str("0123456789ab").expect("SN from_str")
+    }
+
+    // Parses `der` with an X.509 parser that knows nothing of our templates,
+    // checks the signature by `issuer` over the TBSCertificate it finds, and
+    // checks the fields common to every cert.
+    fn parse_cert<'a>(
+        der: &'a [u8],
+        issuer: &PublicKey,
+        subject: &PublicKey,
+        subject_cn: &str,
+    ) -> X509Certificate<'a> {
+        let (rest, cert) = X509Certificate::from_der(der).expect("parse cert");
+        assert!(rest.is_empty());
+
+        let sig: &[u8; SIGNATURE_SERIALIZED_LENGTH] = cert
+            .signature_value
+            .data
+            .try_into()
+            .expect("signature length");
+        issuer
+            .verify(cert.tbs_certificate.as_ref(), &Signature::from(sig))
+            .expect("signature");
+
+        assert_eq!(
+            cert.public_key().subject_public_key.data,
+            subject.as_bytes()
+        );
+        assert_eq!(common_name(cert.issuer()), Some("device-id"));
+        assert_eq!(common_name(cert.subject()), Some(subject_cn));
+        let sn = dname_sn();
+        for name in [cert.issuer(), cert.subject()] {
+            assert!(name.to_string().contains(
+                core::str::from_utf8(sn.as_bytes()).expect("SN is ASCII")
+            ));
+        }
+
+        cert
+    }
+
+    fn common_name<'a>(name: &'a X509Name<'_>) -> Option<&'a str> {
+        name.iter_common_name()
+            .next()
+            .and_then(|cn| cn.as_str().ok())
+    }
+
+    // Returns the OID & criticality of each extension, in order.
+    fn extensions(cert: &X509Certificate<'_>) -> Vec<(String, bool)> {
+        cert.extensions()
+            .iter()
+            .map(|e| (e.oid.to_id_string(), e.critical))
+            .collect()
+    }
+
+    fn tcb_info<'a>(cert: &X509Certificate<'a>) -> &'a [u8] {
+        cert.extensions()
+            .iter()
+            .find(|e| e.oid.to_id_string() == "2.23.133.5.4.1")
+            .expect("TcbInfo")
+            .value
+    }
+
+    #[test]
+    fn deviceid_cert_parses() {
+        let deviceid = keypair(42);
+        let cert = DeviceIdSelfCertBuilder::new(
+            &CertSerialNumber::new(7),
+            &dname_sn(),
+            &deviceid.public,
+        )
+        .sign(&deviceid);
+        let x509 = parse_cert(
+            cert.as_bytes(),
+            &deviceid.public,
+            &deviceid.public,
+            "device-id",
+        );
+
+        assert_eq!(x509.tbs_certificate.raw_serial(), &[7]);
+        assert_eq!(
+            extensions(&x509),
+            [("2.5.29.19".into(), true), ("2.5.29.15".into(), true)]
+        );
+        assert!(x509.tbs_certificate.is_ca());
+    }
+
+    #[test]
+    fn alias_cert_parses() {
+        let deviceid = keypair(42);
+        let alias = keypair(1);
+        let cert = AliasCertBuilder::new(
+            &CertSerialNumber::new(1),
+            &dname_sn(),
+            &alias.public,
+            &FWID,
+        )
+        .sign(&deviceid);
+        let x509 = parse_cert(
+            cert.as_bytes(),
+            &deviceid.public,
+            &alias.public,
+            "alias",
+        );
+
+        assert_eq!(
+            extensions(&x509),
+            [
+                ("2.5.29.19".into(), false),
+                ("2.5.29.15".into(), true),
+                ("2.5.29.32".into(), true),
+                ("2.23.133.5.4.1".into(), true),
+            ]
+        );
+        assert!(!x509.tbs_certificate.is_ca());
+        assert!(tcb_info(&x509).ends_with(&FWID));
+        assert_eq!(cert.get_fwid(), FWID);
+    }
+
+    #[test]
+    fn spmeasure_cert_parses() {
+        let deviceid = keypair(42);
+        let spmeasure = keypair(2);
+        let cert = SpMeasureCertBuilder::new(
+            &CertSerialNumber::new(2),
+            &dname_sn(),
+            &spmeasure.public,
+            &FWID,
+        )
+        .sign(&deviceid);
+        let x509 = parse_cert(
+            cert.as_bytes(),
+            &deviceid.public,
+            &spmeasure.public,
+            "sp-measure",
+        );
+
+        assert_eq!(
+            extensions(&x509),
+            [
+                ("2.5.29.19".into(), true),
+                ("2.5.29.15".into(), true),
+                ("2.5.29.32".into(), false),
+                ("2.23.133.5.4.1".into(), true),
+            ]
+        );
+        assert!(x509.tbs_certificate.is_ca());
+        assert!(tcb_info(&x509).ends_with(&FWID));
+        assert_eq!(cert.get_fwid(), FWID);
+    }
+
+    #[test]
+    fn trust_quorum_dhe_cert_parses() {
+        let deviceid = keypair(42);
+        let tqdhe = keypair(3);
+        let cert = TrustQuorumDheCertBuilder::new(
+            &CertSerialNumber::new(3),
+            &dname_sn(),
+            &tqdhe.public,
+            &FWID,
+        )
+        .sign(&deviceid);
+        let x509 = parse_cert(
+            cert.as_bytes(),
+            &deviceid.public,
+            &tqdhe.public,
+            "trust-quorum-dhe",
+        );
+
+        assert_eq!(
+            extensions(&x509),
+            [
+                ("2.5.29.19".into(), true),
+                ("2.5.29.15".into(), true),
+                ("2.23.133.5.4.1".into(), true),
+            ]
+        );
+        assert!(!x509.tbs_certificate.is_ca());
+        assert!(tcb_info(&x509).ends_with(&FWID));
+        assert_eq!(cert.get_fwid(), FWID);
+    }
 }
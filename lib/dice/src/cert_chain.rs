@@ -0,0 +1,310 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::CertError;
+use core::mem;
+use hubpack::SerializedSize;
+use salty::constants::PUBLICKEY_SERIALIZED_LENGTH;
+use serde::{Deserialize, Serialize};
+use serde_big_array::BigArray;
+use unwrap_lite::UnwrapLite;
+use zerocopy::{AsBytes, FromBytes};
+
+/// Size of the persistent storage holding an installed DeviceId cert chain:
+/// a `CertChainHeader` followed by the chain itself. On the LPC55 this must
+/// match the `certs` flash region in chips/lpc55/memory.toml.
+pub const CERT_CHAIN_STORE_SIZE: usize = 0x600;
+/// The most DER a DeviceId cert chain may hold. This is enough for the
+/// DeviceId cert and a couple of intermediates; the root is known to
+/// verifiers and needn't be included.
+pub const CERT_CHAIN_CAPACITY: usize =
+    CERT_CHAIN_STORE_SIZE - mem::size_of::<CertChainHeader>();
+pub const CERT_CHAIN_MAGIC: u32 = 0xce27_c4a1;
+
+const SEQUENCE: u8 = 0x30;
+const CONTEXT_0: u8 = 0xa0;
+// The value of an Ed25519 SubjectPublicKeyInfo up to the key itself (RFC
+// 8410 §4): the AlgorithmIdentifier, then the BIT STRING header.
+const ED25519_SPKI_PREFIX: [u8; 10] =
+    [0x30, 0x05, 0x06, 0x03, 0x2b, 0x65, 0x70, 0x03, 0x21, 0x00];
+
+/// The header of an installed DeviceId cert chain in persistent storage,
+/// which is followed by `len` bytes of DER certificates, leaf first.
+#[repr(C)]
+#[derive(AsBytes, FromBytes, Clone, Copy)]
+pub struct CertChainHeader {
+    pub magic: u32,
+    pub len: u32,
+}
+
+impl CertChainHeader {
+    pub fn new(len: usize) -> Self {
+        Self {
+            magic: CERT_CHAIN_MAGIC,
+            len: len as u32,
+        }
+    }
+
+    /// Returns the length of the chain following the header, if the header
+    /// is valid.
+    pub fn chain_len(&self) -> Option<usize> {
+        let len = self.len as usize;
+        if self.magic == CERT_CHAIN_MAGIC && len <= CERT_CHAIN_CAPACITY {
+            Some(len)
+        } else {
+            None
+        }
+    }
+}
+
+// Splits the DER element at the start of `der` into its tag, the length of
+// its value, and the bytes after its header, which may hold less than the
+// whole value.
+fn header(der: &[u8]) -> Option<(u8, usize, &[u8])> {
+    let (&tag, rest) = der.split_first()?;
+    let (&first, rest) = rest.split_first()?;
+
+    // Certificates are never large enough to need more than two bytes of
+    // length.
+    match first {
+        0..=0x7f => Some((tag, usize::from(first), rest)),
+        0x81 => {
+            let (&len, rest) = rest.split_first()?;
+            Some((tag, usize::from(len), rest))
+        }
+        0x82 => {
+            let len = rest.get(..2)?;
+            let len = usize::from(u16::from_be_bytes([len[0], len[1]]));
+            Some((tag, len, rest.get(2..)?))
+        }
+        _ => None,
+    }
+}
+
+// Splits the DER element at the start of `der` into its tag, its value, and
+// the bytes after it.
+fn element(der: &[u8]) -> Option<(u8, &[u8], &[u8])> {
+    let (tag, len, rest) = header(der)?;
+    if len > rest.len() {
+        return None;
+    }
+    let (value, rest) = rest.split_at(len);
+
+    Some((tag, value, rest))
+}
+
+/// Returns the Ed25519 public key certified by the first certificate in
+/// `der`. Only the certificate up to its subjectPublicKeyInfo need be
+/// present, so this can be used on the start of a chain that's still being
+/// received.
+pub fn leaf_public_key(
+    der: &[u8],
+) -> Option<&[u8; PUBLICKEY_SERIALIZED_LENGTH]> {
+    let (tag, _, cert) = header(der)?;
+    if tag != SEQUENCE {
+        return None;
+    }
+    let (tag, _, tbs) = header(cert)?;
+    if tag != SEQUENCE {
+        return None;
+    }
+
+    // The version is only present in v2 & v3 certificates.
+    let mut rest = tbs;
+    let (tag, _, after) = element(rest)?;
+    if tag == CONTEXT_0 {
+        rest = after;
+    }
+    // Skip the serialNumber, signature, issuer, validity & subject.
+    for _ in 0..5 {
+        rest = element(rest)?.2;
+    }
+
+    let (tag, spki, _) = element(rest)?;
+    if tag != SEQUENCE {
+        return None;
+    }
+    spki.strip_prefix(&ED25519_SPKI_PREFIX[..])?.try_into().ok()
+}
+
+/// Iterates over the DER certificates in a `DeviceIdCertChain`.
+pub struct Certs<'a>(&'a [u8]);
+
+impl<'a> Iterator for Certs<'a> {
+    type Item = &'a [u8];
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // The chain was checked when it was made, so this only fails once
+        // we've run out of certificates.
+        let (_, _, rest) = element(self.0)?;
+        let (cert, rest) = self.0.split_at(self.0.len() - rest.len());
+        self.0 = rest;
+
+        Some(cert)
+    }
+}
+
+/// This type represents a DeviceId certificate chain signed by an external
+/// CA: DER certificates back to back, starting with the DeviceId cert. Once
+/// one is installed, stage0 hands it off in place of the self-signed
+/// DeviceId cert whenever it certifies the DeviceId key.
+#[derive(Clone, Deserialize, Serialize, SerializedSize)]
+pub struct DeviceIdCertChain {
+    len: u32,
+    #[serde(with = "BigArray")]
+    der: [u8; CERT_CHAIN_CAPACITY],
+}
+
+impl DeviceIdCertChain {
+    /// Checks that `der` is a sequence of certificates, the first of which
+    /// certifies an Ed25519 key. The signatures aren't checked: whoever
+    /// relies on the chain has to do that anyway.
+    pub fn new(der: &[u8]) -> Result<Self, CertError> {
+        if der.is_empty() {
+            return Err(CertError::NotFound);
+        }
+        if der.len() > CERT_CHAIN_CAPACITY {
+            return Err(CertError::TooLarge);
+        }
+
+        let mut rest = der;
+        while !rest.is_empty() {
+            match element(rest) {
+                Some((SEQUENCE, _, after)) => rest = after,
+                _ => return Err(CertError::Malformed),
+            }
+        }
+        if leaf_public_key(der).is_none() {
+            return Err(CertError::NoPubKey);
+        }
+
+        let mut chain = Self {
+            len: der.len() as u32,
+            der: [0u8; CERT_CHAIN_CAPACITY],
+        };
+        chain.der[..der.len()].copy_from_slice(der);
+
+        Ok(chain)
+    }
+
+    /// Reads a chain out of persistent storage, where it's preceded by a
+    /// `CertChainHeader`.
+    pub fn from_stored(stored: &[u8]) -> Result<Self, CertError> {
+        if stored.len() < mem::size_of::<CertChainHeader>() {
+            return Err(CertError::TooSmall);
+        }
+        let (header, der) = stored.split_at(mem::size_of::<CertChainHeader>());
+        let len = CertChainHeader::read_from(header)
+            .and_then(|h| h.chain_len())
+            .ok_or(CertError::NotFound)?;
+
+        Self::new(der.get(..len).ok_or(CertError::TooSmall)?)
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.der[..(self.len as usize).min(CERT_CHAIN_CAPACITY)]
+    }
+
+    pub fn certs(&self) -> Certs<'_> {
+        Certs(self.as_bytes())
+    }
+
+    /// The key certified by the first certificate in the chain, which must
+    /// be the DeviceId key for the chain to be used.
+    pub fn leaf_public_key(&self) -> &[u8; PUBLICKEY_SERIALIZED_LENGTH] {
+        // `new` checked that there is one.
+        leaf_public_key(self.as_bytes()).unwrap_lite()
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::{
+        AliasCertBuilder, Cert, CertSerialNumber, DeviceIdSelfCertBuilder,
+        SerialNumber,
+    };
+    use core::str::FromStr;
+    use salty::signature::Keypair;
+
+    fn keypair(seed: u8) -> Keypair {
+        Keypair::from(&[seed; 32])
+    }
+
+    // Stands in for an externally signed chain: the DeviceId cert for
+    // `leaf`, followed by an alias cert (any certificate will do).
+    fn chain_der(leaf: &Keypair) -> Vec<u8> {
+        let sn = SerialNumber::from_str("0123456789ab").expect("SN from_str");
+        let ca = keypair(1);
+        let deviceid = DeviceIdSelfCertBuilder::new(
+            &CertSerialNumber::new(0),
+            &sn,
+            &leaf.public,
+        )
+        .sign(&ca);
+        let alias = AliasCertBuilder::new(
+            &CertSerialNumber::new(1),
+            &sn,
+            &ca.public,
+            &[0x11; 32],
+        )
+        .sign(&ca);
+
+        [deviceid.as_bytes(), alias.as_bytes()].concat()
+    }
+
+    fn stored(der: &[u8]) -> Vec<u8> {
+        [CertChainHeader::new(der.len()).as_bytes(), der].concat()
+    }
+
+    #[test]
+    fn leaf_key_from_cert() {
+        let leaf = keypair(42);
+        let der = chain_der(&leaf);
+
+        assert_eq!(leaf_public_key(&der), Some(leaf.public.as_bytes()));
+        // Everything after the key can be missing.
+        assert_eq!(leaf_public_key(&der[..420]), Some(leaf.public.as_bytes()));
+        assert_eq!(leaf_public_key(&der[..400]), None);
+    }
+
+    #[test]
+    fn chain_from_stored() {
+        let leaf = keypair(42);
+        let der = chain_der(&leaf);
+        let chain =
+            DeviceIdCertChain::from_stored(&stored(&der)).expect("chain");
+
+        assert_eq!(chain.as_bytes(), &der[..]);
+        assert_eq!(chain.leaf_public_key(), leaf.public.as_bytes());
+        assert_eq!(chain.certs().count(), 2);
+        assert_eq!(chain.certs().next().map(<[u8]>::len), Some(531));
+    }
+
+    #[test]
+    fn bad_chains() {
+        let der = chain_der(&keypair(42));
+
+        assert!(matches!(
+            DeviceIdCertChain::from_stored(&[0xff; CERT_CHAIN_STORE_SIZE]),
+            Err(CertError::NotFound)
+        ));
+        assert!(matches!(
+            DeviceIdCertChain::from_stored(&stored(&der)[..600]),
+            Err(CertError::TooSmall)
+        ));
+        assert!(matches!(
+            DeviceIdCertChain::new(&der[..600]),
+            Err(CertError::Malformed)
+        ));
+        assert!(matches!(
+            DeviceIdCertChain::new(&[der.as_slice(), &der[..]].concat()),
+            Err(CertError::TooLarge)
+        ));
+        assert!(matches!(
+            DeviceIdCertChain::new(&[0x30, 0x00]),
+            Err(CertError::NoPubKey)
+        ));
+    }
+}
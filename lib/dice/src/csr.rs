@@ -0,0 +1,165 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::{deviceid_csr_tmpl, SerialNumber};
+use core::ops::Range;
+use hubpack::SerializedSize;
+use salty::signature::{Keypair, PublicKey};
+use serde::{Deserialize, Serialize};
+use serde_big_array::BigArray;
+use unwrap_lite::UnwrapLite;
+use zerocopy::AsBytes;
+
+pub struct DeviceIdCsrBuilder([u8; deviceid_csr_tmpl::SIZE]);
+
+impl DeviceIdCsrBuilder {
+    pub fn new(dname_sn: &SerialNumber, public_key: &PublicKey) -> Self {
+        Self(deviceid_csr_tmpl::CSR_TMPL)
+            .set_range(deviceid_csr_tmpl::SUBJECT_SN_RANGE, dname_sn)
+            .set_range(deviceid_csr_tmpl::PUB_RANGE, public_key.as_bytes())
+    }
+
+    fn set_range<T: AsBytes>(mut self, r: Range<usize>, t: &T) -> Self {
+        self.0[r].copy_from_slice(t.as_bytes());
+
+        self
+    }
+
+    /// Signs the request with the DeviceId key, proving possession of it.
+    pub fn sign(self, keypair: &Keypair) -> DeviceIdCsr {
+        let signdata = &self.0[deviceid_csr_tmpl::SIGNDATA_RANGE];
+        let sig = keypair.sign(signdata);
+        let tmp = self.set_range(deviceid_csr_tmpl::SIG_RANGE, &sig.to_bytes());
+
+        DeviceIdCsr(tmp.0)
+    }
+}
+
+/// This type represents a PKCS#10 certificate signing request for the
+/// DeviceId key, with the platform serial number in its subject. Manufacturing
+/// has this signed by our CA, and installs the resulting chain (see
+/// `DeviceIdCertChain`) in place of the self-signed DeviceId cert.
+#[derive(Clone, Deserialize, Serialize, SerializedSize)]
+pub struct DeviceIdCsr(
+    #[serde(with = "BigArray")] [u8; deviceid_csr_tmpl::SIZE],
+);
+
+impl DeviceIdCsr {
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0
+    }
+
+    fn get_range<'a, T>(&'a self, r: Range<usize>) -> T
+    where
+        T: TryFrom<&'a [u8]>,
+    {
+        self.0[r].try_into().unwrap_lite()
+    }
+
+    pub fn get_subject_sn(&self) -> SerialNumber {
+        SerialNumber::from_bytes(
+            self.get_range(deviceid_csr_tmpl::SUBJECT_SN_RANGE),
+        )
+    }
+
+    pub fn get_pub(&self) -> &[u8] {
+        self.get_range(deviceid_csr_tmpl::PUB_RANGE)
+    }
+
+    pub fn get_sig(&self) -> &[u8] {
+        self.get_range(deviceid_csr_tmpl::SIG_RANGE)
+    }
+
+    pub fn get_signdata(&self) -> &[u8] {
+        self.get_range(deviceid_csr_tmpl::SIGNDATA_RANGE)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use core::str::FromStr;
+    use salty::constants::{
+        PUBLICKEY_SERIALIZED_LENGTH, SIGNATURE_SERIALIZED_LENGTH,
+    };
+    use salty::signature::Signature;
+    use x509_parser::certification_request::X509CertificationRequest;
+    use x509_parser::prelude::FromDer;
+
+    fn csr() -> (Keypair, DeviceIdCsr) {
+        // well known seed
+        let seed: [u8; 32] = [42; 32];
+        let keypair = Keypair::from(&seed);
+        let sn = SerialNumber::from_str("0123456789ab").expect("SN from_str");
+        let csr = DeviceIdCsrBuilder::new(&sn, &keypair.public).sign(&keypair);
+
+        (keypair, csr)
+    }
+
+    #[test]
+    fn subject_from_new() {
+        let (keypair, csr) = csr();
+
+        assert_eq!(csr.get_subject_sn().as_bytes(), b"0123456789ab");
+        assert_eq!(csr.get_pub(), keypair.public.as_bytes());
+    }
+
+    #[test]
+    fn signature_verifies() {
+        let (_, csr) = csr();
+        let public: &[u8; PUBLICKEY_SERIALIZED_LENGTH] =
+            csr.get_pub().try_into().expect("public key length");
+        let public = PublicKey::try_from(public).expect("public key");
+        let sig: &[u8; SIGNATURE_SERIALIZED_LENGTH] =
+            csr.get_sig().try_into().expect("signature length");
+
+        public
+            .verify(csr.get_signdata(), &Signature::from(sig))
+            .expect("signature");
+    }
+
+    #[test]
+    fn signdata_is_request_info() {
+        let (_, csr) = csr();
+        // The CertificationRequestInfo is the first thing in the outer
+        // SEQUENCE, whose header is 4 bytes long.
+        let info = &csr.as_bytes()[4..];
+        let len = usize::from(u16::from_be_bytes([info[2], info[3]]));
+
+        assert_eq!(info[0], 0x30);
+        assert_eq!(csr.get_signdata(), &info[..4 + len]);
+    }
+
+    #[test]
+    fn request_parses() {
+        let (keypair, csr) = csr();
+        let (rest, request) =
+            X509CertificationRequest::from_der(csr.as_bytes()).expect("parse");
+        assert!(rest.is_empty());
+
+        let info = &request.certification_request_info;
+        let sig: &[u8; SIGNATURE_SERIALIZED_LENGTH] = request
+            .signature_value
+            .data
+            .try_into()
+            .expect("signature length");
+        keypair
+            .public
+            .verify(info.raw, &Signature::from(sig))
+            .expect("signature");
+
+        assert_eq!(
+            info.subject_pki.subject_public_key.data,
+            keypair.public.as_bytes()
+        );
+        assert_eq!(
+            info.subject
+                .iter_common_name()
+                .next()
+                .and_then(|cn| cn.as_str().ok()),
+            Some("device-id")
+        );
+        assert!(info.subject.to_string().contains("0123456789ab"));
+    }
+}
@@ -3,77 +3,104 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use crate::{
-    AliasCert, AliasOkm, DeviceIdSelfCert, RngSeed, SpMeasureCert,
-    SpMeasureOkm, TrustQuorumDheCert, TrustQuorumDheOkm,
+    AliasCert, AliasOkm, DeviceIdCertChain, DeviceIdCsr, DeviceIdSelfCert,
+    RngSeed, SpMeasureCert, SpMeasureOkm, TrustQuorumDheCert,
+    TrustQuorumDheOkm,
 };
 use core::ops::Range;
 use hubpack::SerializedSize;
+#[cfg(feature = "lpc55")]
 use lpc55_pac::syscon::RegisterBlock;
 use serde::{Deserialize, Serialize};
 use static_assertions as sa;
 
-// This memory is the USB peripheral SRAM that's 0x4000 bytes long. Changes
-// to this address must be coordinated with the [dice_*] tables in
-// chips/lpc55/chip.toml
-// TODO: get from app.toml -> chip.toml at build time
-const MEM_RANGE: Range<usize> = 0x4010_0000..0x4010_4000;
-const ALIAS_RANGE: Range<usize> = MEM_RANGE.start..(MEM_RANGE.start + 0x800);
+/// Size of the memory used to hand off DICE artifacts. The ranges below are
+/// offsets into it.
+pub const HANDOFF_SIZE: usize = 0x4000;
+const ALIAS_RANGE: Range<usize> = 0..0x800;
 const SPMEASURE_RANGE: Range<usize> =
     ALIAS_RANGE.end..(ALIAS_RANGE.end + 0x800);
 const RNG_RANGE: Range<usize> =
     SPMEASURE_RANGE.end..(SPMEASURE_RANGE.end + 0x100);
+const DEVICEID_CSR_RANGE: Range<usize> = RNG_RANGE.end..(RNG_RANGE.end + 0x200);
+const DEVICEID_CERTS_RANGE: Range<usize> =
+    DEVICEID_CSR_RANGE.end..(DEVICEID_CSR_RANGE.end + 0x700);
 
-// ensure memory ranges are within MEM_RANGE and do not overlap
-sa::const_assert!(MEM_RANGE.start <= ALIAS_RANGE.start);
+// ensure memory ranges are within HANDOFF_SIZE and do not overlap
 sa::const_assert!(ALIAS_RANGE.end <= SPMEASURE_RANGE.start);
 sa::const_assert!(SPMEASURE_RANGE.end <= RNG_RANGE.start);
-sa::const_assert!(RNG_RANGE.end <= MEM_RANGE.end);
+sa::const_assert!(RNG_RANGE.end <= DEVICEID_CSR_RANGE.start);
+sa::const_assert!(DEVICEID_CSR_RANGE.end <= DEVICEID_CERTS_RANGE.start);
+sa::const_assert!(DEVICEID_CERTS_RANGE.end <= HANDOFF_SIZE);
+
+// On the LPC55 this memory is the USB peripheral SRAM that's 0x4000 bytes
+// long. Changes to this address must be coordinated with the [dice_*] tables
+// in chips/lpc55/chip.toml
+// TODO: get from app.toml -> chip.toml at build time
+#[cfg(feature = "lpc55")]
+const LPC55_HANDOFF_BASE: usize = 0x4010_0000;
 
 /// The Handoff type is a thin wrapper over the memory region used to transfer
 /// DICE artifacts (seeds & certs) from stage0 to hubris tasks. It is intended
 /// for use by stage0 to write these artifacts to memory where they will later
 /// be read out by a hubris task.
-pub struct Handoff<'a>(&'a RegisterBlock);
+///
+/// The memory can be anywhere: on the LPC55 it's the USB SRAM (see
+/// `turn_on`), while under emulation or in tests it can be any buffer of at
+/// least `HANDOFF_SIZE` bytes.
+pub struct Handoff<'a>(&'a mut [u8]);
 
 impl<'a> Handoff<'a> {
+    pub fn new(mem: &'a mut [u8]) -> Self {
+        assert!(mem.len() >= HANDOFF_SIZE);
+        Self(mem)
+    }
+
+    pub fn store<T>(&mut self, t: &T) -> usize
+    where
+        T: HandoffData + SerializedSize + Serialize,
+    {
+        // TODO: error handling
+        hubpack::serialize(&mut self.0[T::MEM_RANGE], t).expect("handoff store")
+    }
+
+    pub fn as_bytes(&self) -> &[u8] {
+        &self.0[..HANDOFF_SIZE]
+    }
+}
+
+#[cfg(feature = "lpc55")]
+impl Handoff<'static> {
     // Handing off DICE artifacts through the USB SRAM requires we power it on.
     // We implement this as a constructor on the producer side of the handoff
     // to ensure this memory is enabled before consumers attempt access.
     // Attempts to access this memory region before powering it on will fault.
-    pub fn turn_on(syscon: &'a RegisterBlock) -> Self {
+    pub fn turn_on(syscon: &RegisterBlock) -> Self {
         syscon.ahbclkctrl2.modify(|_, w| w.usb1_ram().enable());
         syscon
             .presetctrl2
             .modify(|_, w| w.usb1_ram_rst().released());
 
-        Self(syscon)
+        // SAFETY: This is where the LPC55 maps the USB SRAM, which we've just
+        // powered on, and nothing else in stage0 uses it.
+        Self(unsafe { lpc55_handoff_mem() })
     }
 
-    pub fn turn_off(self) {
-        self.0
+    pub fn turn_off(self, syscon: &RegisterBlock) {
+        syscon
             .presetctrl2
             .modify(|_, w| w.usb1_ram_rst().asserted());
-        self.0.ahbclkctrl2.modify(|_, w| w.usb1_ram().disable());
+        syscon.ahbclkctrl2.modify(|_, w| w.usb1_ram().disable());
     }
+}
 
-    pub fn store<T>(&self, t: &T) -> usize
-    where
-        T: HandoffData + SerializedSize + Serialize,
-    {
-        // Cast MEM_RANGE from HandoffData to a mutable slice.
-        //
-        // SAFETY: This unsafe block relies on implementers of the HandoffData
-        // trait to validate the memory range denoted by Self::MEM_RANGE. Each
-        // implementation in this module is checked by static assertion.
-        let dst = unsafe {
-            core::slice::from_raw_parts_mut(
-                T::MEM_RANGE.start as *mut u8,
-                <T as SerializedSize>::MAX_SIZE,
-            )
-        };
-        // TODO: error handling
-        hubpack::serialize(dst, t).expect("handoff store")
-    }
+/// # Safety
+///
+/// The USB SRAM must be powered on, and the caller mustn't let the returned
+/// slice alias another reference to it.
+#[cfg(feature = "lpc55")]
+unsafe fn lpc55_handoff_mem() -> &'static mut [u8] {
+    core::slice::from_raw_parts_mut(LPC55_HANDOFF_BASE as *mut u8, HANDOFF_SIZE)
 }
 
 // Types that can be transfered through the memory region used to pass DICE
@@ -89,26 +116,18 @@ impl<'a> Handoff<'a> {
 // this trait
 pub unsafe trait HandoffData {
     const EXPECTED_MAGIC: [u8; 16];
+    /// Where this type lives, as an offset into the handoff memory.
     const MEM_RANGE: Range<usize>;
 
     fn get_magic(&self) -> [u8; 16];
 
-    fn from_mem() -> Option<Self>
+    /// Reads an instance out of `mem`, the whole of the handoff memory.
+    fn from_bytes(mem: &[u8]) -> Option<Self>
     where
         Self: SerializedSize + Sized,
         for<'d> Self: Deserialize<'d>,
     {
-        // Cast the MEM_START address to a slice of bytes of MAX_SIZE length.
-        //
-        // SAFETY: This unsafe block relies on implementers of the trait to
-        // validate the memory range denoted by Self::MEM_RANGE. Each
-        // implementation in this module is checked by static assertion.
-        let src = unsafe {
-            core::slice::from_raw_parts_mut(
-                Self::MEM_RANGE.start as *mut u8,
-                <Self as SerializedSize>::MAX_SIZE,
-            )
-        };
+        let src = mem.get(Self::MEM_RANGE)?;
 
         match hubpack::deserialize::<Self>(src).ok() {
             Some((data, _)) => {
@@ -121,6 +140,18 @@ pub unsafe trait HandoffData {
             None => None,
         }
     }
+
+    /// Reads an instance out of the LPC55's handoff memory.
+    #[cfg(feature = "lpc55")]
+    fn from_mem() -> Option<Self>
+    where
+        Self: SerializedSize + Sized,
+        for<'d> Self: Deserialize<'d>,
+    {
+        // SAFETY: stage0 leaves the USB SRAM powered on for us, and we only
+        // read it here.
+        Self::from_bytes(unsafe { lpc55_handoff_mem() })
+    }
 }
 
 /// Type to represent DICE derived artifacts used by the root of trust for
@@ -277,3 +308,92 @@ impl RngData {
         }
     }
 }
+
+/// Type to represent the certificate signing request for the DeviceId key,
+/// which stage0 produces on every boot so that a task can hand it to
+/// manufacturing. Stage0 will construct an instance of this type and write
+/// it to memory using the Handoff type above. The receiving hubris task will
+/// then construct an instance from the serialized value using the 'from_mem'
+/// constructor.
+#[derive(Deserialize, Serialize, SerializedSize)]
+pub struct DeviceIdCsrData {
+    pub magic: [u8; 16],
+    pub csr: DeviceIdCsr,
+}
+
+// Handoff the DeviceId CSR.
+//
+// SAFETY: The memory range denoted by MEM_RANGE is checked to be nonoverlapping
+// by static assertion above. We ensure this region is sufficiently large to
+// hold DeviceIdCsrData with another static assert.
+unsafe impl HandoffData for DeviceIdCsrData {
+    const EXPECTED_MAGIC: [u8; 16] = [
+        0x5d, 0x0e, 0x91, 0x6c, 0x27, 0xa3, 0xf4, 0x18, 0xc9, 0x62, 0x3b, 0x80,
+        0xe5, 0x47, 0x1a, 0xd2,
+    ];
+    const MEM_RANGE: Range<usize> = DEVICEID_CSR_RANGE;
+
+    fn get_magic(&self) -> [u8; 16] {
+        self.magic
+    }
+}
+
+// ensure DeviceIdCsrData handoff memory is large enough store data
+sa::const_assert!(
+    DeviceIdCsrData::MEM_RANGE.end - DeviceIdCsrData::MEM_RANGE.start
+        >= <DeviceIdCsrData as SerializedSize>::MAX_SIZE
+);
+
+impl DeviceIdCsrData {
+    pub fn new(csr: DeviceIdCsr) -> Self {
+        Self {
+            magic: Self::EXPECTED_MAGIC,
+            csr,
+        }
+    }
+}
+
+/// Type to represent the externally signed DeviceId cert chain, if one has
+/// been installed and it certifies the DeviceId key. Tasks reporting the
+/// DeviceId should present this chain in place of the self-signed DeviceId
+/// cert when there is one. Stage0 will construct an instance of this type
+/// and write it to memory using the Handoff type above. The receiving hubris
+/// task will then construct an instance from the serialized value using the
+/// 'from_mem' constructor.
+#[derive(Deserialize, Serialize, SerializedSize)]
+pub struct DeviceIdCertsData {
+    pub magic: [u8; 16],
+    pub certs: Option<DeviceIdCertChain>,
+}
+
+// Handoff the installed DeviceId cert chain.
+//
+// SAFETY: The memory range denoted by MEM_RANGE is checked to be nonoverlapping
+// by static assertion above. We ensure this region is sufficiently large to
+// hold DeviceIdCertsData with another static assert.
+unsafe impl HandoffData for DeviceIdCertsData {
+    const EXPECTED_MAGIC: [u8; 16] = [
+        0x81, 0x3c, 0x6a, 0xf0, 0x14, 0xd9, 0x72, 0x5e, 0xab, 0x08, 0xc3, 0x3f,
+        0x96, 0x2d, 0x4b, 0xe7,
+    ];
+    const MEM_RANGE: Range<usize> = DEVICEID_CERTS_RANGE;
+
+    fn get_magic(&self) -> [u8; 16] {
+        self.magic
+    }
+}
+
+// ensure DeviceIdCertsData handoff memory is large enough store data
+sa::const_assert!(
+    DeviceIdCertsData::MEM_RANGE.end - DeviceIdCertsData::MEM_RANGE.start
+        >= <DeviceIdCertsData as SerializedSize>::MAX_SIZE
+);
+
+impl DeviceIdCertsData {
+    pub fn new(certs: Option<DeviceIdCertChain>) -> Self {
+        Self {
+            magic: Self::EXPECTED_MAGIC,
+            certs,
+        }
+    }
+}
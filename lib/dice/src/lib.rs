@@ -4,7 +4,9 @@
 
 #![cfg_attr(not(test), no_std)]
 
-use core::{mem, slice, str::FromStr};
+use core::str::FromStr;
+#[cfg(feature = "lpc55")]
+use core::{mem, slice};
 use hkdf::Hkdf;
 use hubpack::SerializedSize;
 use salty::constants::SECRETKEY_SEED_LENGTH;
@@ -19,22 +21,53 @@ pub use crate::cert::{
     DeviceIdSelfCertBuilder, SpMeasureCert, SpMeasureCertBuilder,
     TrustQuorumDheCert, TrustQuorumDheCertBuilder,
 };
-mod alias_cert_tmpl;
-mod deviceid_cert_tmpl;
+mod artifacts;
+mod cert_chain;
+mod csr;
 mod handoff;
-mod spmeasure_cert_tmpl;
-mod trust_quorum_dhe_cert_tmpl;
+mod seed;
+
+// The certificate & CSR templates are generated by build.rs from the
+// description in templates.toml.
+mod alias_cert_tmpl {
+    include!(concat!(env!("OUT_DIR"), "/alias_cert_tmpl.rs"));
+}
+mod deviceid_cert_tmpl {
+    include!(concat!(env!("OUT_DIR"), "/deviceid_cert_tmpl.rs"));
+}
+mod deviceid_csr_tmpl {
+    include!(concat!(env!("OUT_DIR"), "/deviceid_csr_tmpl.rs"));
+}
+mod spmeasure_cert_tmpl {
+    include!(concat!(env!("OUT_DIR"), "/spmeasure_cert_tmpl.rs"));
+}
+mod trust_quorum_dhe_cert_tmpl {
+    include!(concat!(env!("OUT_DIR"), "/trust_quorum_dhe_cert_tmpl.rs"));
+}
+
+pub use crate::artifacts::Artifacts;
+pub use crate::cert_chain::{
+    leaf_public_key, CertChainHeader, Certs, DeviceIdCertChain,
+    CERT_CHAIN_CAPACITY, CERT_CHAIN_MAGIC, CERT_CHAIN_STORE_SIZE,
+};
+pub use crate::csr::{DeviceIdCsr, DeviceIdCsrBuilder};
 pub use crate::handoff::{
-    AliasData, Handoff, HandoffData, RngData, SpMeasureData,
+    AliasData, DeviceIdCertsData, DeviceIdCsrData, Handoff, HandoffData,
+    RngData, SpMeasureData, HANDOFF_SIZE,
 };
+#[cfg(feature = "lpc55")]
+pub use crate::seed::Lpc55Seed;
+pub use crate::seed::{SeedSource, SoftwareSeed};
 
 pub const SEED_LENGTH: usize = SECRETKEY_SEED_LENGTH;
 // We define the length of the serial number using the values from the alias
 // cert template though it's consistent across all templates.
 pub const SN_LENGTH: usize = alias_cert_tmpl::ISSUER_SN_RANGE.end
     - alias_cert_tmpl::ISSUER_SN_RANGE.start;
+#[cfg(feature = "lpc55")]
 const REG_ADDR_NONSEC: u32 = 0x40000900;
 
+#[cfg(feature = "lpc55")]
 fn get_cdi_reg_slice() -> &'static mut [u32] {
     // SAFETY: Dereferencing this raw pointer is necessary to read the CDI
     // from the LPC55 DICE registers. This pointer will always reference a
@@ -58,15 +91,18 @@ fn get_cdi_reg_slice() -> &'static mut [u32] {
 /// CDI). To ensure the DICE registers are cleared, this type derives the
 /// ZeroizeOnDrop trait. When an instance of this object goes out of scope
 /// the register is cleared through the slice held.
+#[cfg(feature = "lpc55")]
 #[derive(Zeroize, ZeroizeOnDrop)]
 struct CdiReg(&'static mut [u32]);
 
+#[cfg(feature = "lpc55")]
 impl Default for CdiReg {
     fn default() -> Self {
         Self(get_cdi_reg_slice())
     }
 }
 
+#[cfg(feature = "lpc55")]
 impl CdiReg {
     fn is_clear(&self) -> bool {
         self.0.iter().all(|&w| w == 0)
@@ -78,10 +114,10 @@ pub trait SeedBuf {
 }
 
 /// This type is a thin wrapper around a byte array holding the CDI.
-/// It's populated from the DICE registers by calling the 'from_reg'
-/// constructor. The DICE registers are cleared immediately after the
-/// CDI is read out from them and future attempts to construct a Cdi
-/// instance through 'from_reg' will return None.
+/// It's populated by a `SeedSource`: on the LPC55, from the DICE registers
+/// by calling the 'from_reg' constructor. The DICE registers are cleared
+/// immediately after the CDI is read out from them and future attempts to
+/// construct a Cdi instance through 'from_reg' will return None.
 #[derive(Zeroize, ZeroizeOnDrop)]
 pub struct Cdi([u8; SEED_LENGTH]);
 
@@ -91,6 +127,7 @@ impl SeedBuf for Cdi {
     }
 }
 
+#[cfg(feature = "lpc55")]
 impl Cdi {
     /// If DICE is disabled this function will return None. Otherwise it
     /// copies the contents of the DICE registers into a Cdi instance that's
@@ -153,7 +190,7 @@ impl DeviceIdOkm {
 }
 
 #[repr(C)]
-#[derive(AsBytes, Default)]
+#[derive(AsBytes, Debug, Default, PartialEq)]
 pub struct CertSerialNumber(u8);
 
 impl CertSerialNumber {
@@ -0,0 +1,51 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::{okm_from_seed_no_extract, Cdi, SeedBuf, SEED_LENGTH};
+use zeroize::{Zeroize, ZeroizeOnDrop};
+
+/// A source for the CDI at the root of the DICE hierarchy. This is the only
+/// part of deriving the hierarchy that depends on the hardware.
+pub trait SeedSource {
+    /// Returns the CDI, or None if there isn't one (e.g. because DICE is
+    /// disabled, or the CDI has already been consumed).
+    fn get_cdi(&mut self) -> Option<Cdi>;
+}
+
+/// The CDI computed by the LPC55 ROM from the UDS in its PUF, read out of
+/// (and then cleared from) the DICE registers.
+#[cfg(feature = "lpc55")]
+pub struct Lpc55Seed;
+
+#[cfg(feature = "lpc55")]
+impl SeedSource for Lpc55Seed {
+    fn get_cdi(&mut self) -> Option<Cdi> {
+        Cdi::from_reg()
+    }
+}
+
+/// A fixed unique device secret (UDS) standing in for hardware that can
+/// keep one, for emulation and tests. The CDI is derived from the UDS alone,
+/// since there's no ROM to measure the first stage. Anyone who knows the UDS
+/// can derive every key in the hierarchy, so this offers no security.
+#[derive(Zeroize, ZeroizeOnDrop)]
+pub struct SoftwareSeed([u8; SEED_LENGTH]);
+
+impl SeedBuf for SoftwareSeed {
+    fn as_bytes(&self) -> &[u8; SEED_LENGTH] {
+        &self.0
+    }
+}
+
+impl SoftwareSeed {
+    pub fn new(uds: &[u8; SEED_LENGTH]) -> Self {
+        Self(*uds)
+    }
+}
+
+impl SeedSource for SoftwareSeed {
+    fn get_cdi(&mut self) -> Option<Cdi> {
+        Some(Cdi(okm_from_seed_no_extract(self, "cdi".as_bytes())))
+    }
+}
@@ -0,0 +1,36 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! The layout of a post-mortem task dump, shared between `task-dump-agent`,
+//! which writes dumps, and `cargo xtask core`, which reads them.
+//!
+//! All fields are little-endian `u32`s, and everything after the header is
+//! padded to a multiple of 4 bytes:
+//!
+//! ```text
+//! header:   magic, version, length (in bytes, including the header),
+//!           task index, flags, image ID (low word, high word),
+//!           register length, region count
+//! registers (the architecture's raw `SavedState`)
+//! regions:  base, size, contents, repeated `region count` times
+//! ```
+//!
+//! If the dump area is too small for everything, the last region is cut
+//! short, any further regions are left out, and `FLAG_TRUNCATED` is set. The
+//! magic number is written last, so a dump that was interrupted partway
+//! through won't be mistaken for a complete one.
+
+#![no_std]
+
+/// "DUMP", read as a little-endian word.
+pub const DUMP_MAGIC: u32 = 0x504d_5544;
+
+/// Bumped whenever the layout changes.
+pub const DUMP_VERSION: u32 = 1;
+
+/// Size of the header, in bytes; the registers start here.
+pub const HEADER_SIZE: usize = 36;
+
+/// Set in the header's flags if the dump didn't fit in the area.
+pub const FLAG_TRUNCATED: u32 = 1 << 0;
@@ -8,7 +8,8 @@
 //! Hypovisor calls
 
 pub use lpc55_flash::{
-    HypoStatus, UpdateTarget, __write_block, FLASH_PAGE_SIZE,
+    HypoStatus, UpdateTarget, __confirm_image, __prefer_image, __read_block,
+    __write_block, __write_certs_page, FLASH_PAGE_SIZE,
 };
 
 pub const TABLE_MAGIC: u32 = 0xabcd_abcd;
@@ -22,6 +23,10 @@ macro_rules! declare_tz_table {
         static TZ_TABLE: SecureTable = SecureTable {
             magic: 0,
             write_to_flash: None,
+            confirm_image: None,
+            prefer_image: None,
+            write_certs_page: None,
+            read_from_flash: None,
         };
     };
 }
@@ -35,6 +40,10 @@ macro_rules! declare_not_tz_table {
         static TZ_TABLE: SecureTable = SecureTable {
             magic: TABLE_MAGIC,
             write_to_flash: Some(__write_block),
+            confirm_image: Some(__confirm_image),
+            prefer_image: Some(__prefer_image),
+            write_certs_page: Some(__write_certs_page),
+            read_from_flash: Some(__read_block),
         };
     };
 }
@@ -54,6 +63,12 @@ pub struct SecureTable {
     // function
     pub write_to_flash:
         Option<unsafe extern "C" fn(UpdateTarget, u32, *mut u8) -> HypoStatus>,
+    pub confirm_image: Option<unsafe extern "C" fn() -> HypoStatus>,
+    pub prefer_image: Option<unsafe extern "C" fn(UpdateTarget) -> HypoStatus>,
+    pub write_certs_page:
+        Option<unsafe extern "C" fn(u32, *mut u8) -> HypoStatus>,
+    pub read_from_flash:
+        Option<unsafe extern "C" fn(UpdateTarget, u32, *mut u8) -> HypoStatus>,
 }
 
 impl SecureTable {
@@ -84,4 +99,66 @@ impl SecureTable {
         }
         unreachable!()
     }
+
+    /// Marks the running image as good in the persistent boot state.
+    pub fn confirm_image(&self) -> HypoStatus {
+        // SAFETY: As for `write_to_flash`; this function takes no pointers.
+        unsafe {
+            self.check_magic();
+            if let Some(func) = core::ptr::read_volatile(&self.confirm_image) {
+                return func();
+            }
+        }
+        unreachable!()
+    }
+
+    /// Makes `img` the image to try first on the next boot.
+    pub fn prefer_image(&self, img: UpdateTarget) -> HypoStatus {
+        // SAFETY: As for `write_to_flash`; this function takes no pointers.
+        unsafe {
+            self.check_magic();
+            if let Some(func) = core::ptr::read_volatile(&self.prefer_image) {
+                return func(img);
+            }
+        }
+        unreachable!()
+    }
+
+    /// Writes page `page_num` of the persistent DeviceId cert chain from the
+    /// `FLASH_PAGE_SIZE` bytes at `buf`.
+    pub unsafe fn write_certs_page(
+        &self,
+        page_num: u32,
+        buf: *mut u8,
+    ) -> HypoStatus {
+        // SAFETY: As for `write_to_flash`, including the raw pointer.
+        self.check_magic();
+        if let Some(func) = core::ptr::read_volatile(&self.write_certs_page) {
+            return func(page_num, buf);
+        }
+        unreachable!()
+    }
+
+    /// Reads block `block_num` of `img` into the `FLASH_PAGE_SIZE` bytes at
+    /// `buf`.
+    pub unsafe fn read_from_flash(
+        &self,
+        img: UpdateTarget,
+        block_num: u32,
+        buf: *mut u8,
+    ) -> HypoStatus {
+        // SAFETY: As for `write_to_flash`, including the raw pointer.
+        self.check_magic();
+        if let Some(func) = core::ptr::read_volatile(&self.read_from_flash) {
+            return func(img, block_num, buf);
+        }
+        unreachable!()
+    }
+
+    unsafe fn check_magic(&self) {
+        let magic = core::ptr::read_volatile(&self.magic);
+        if magic != TABLE_MAGIC {
+            panic!();
+        }
+    }
 }
@@ -3,7 +3,9 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 #![no_std]
+use abi::{BootState, BOOT_SLOT_A, BOOT_SLOT_B, BOOT_STATE_MAGIC};
 use lpc55_romapi::*;
+use zerocopy::AsBytes;
 
 pub use drv_update_api::UpdateTarget;
 pub use lpc55_romapi::FLASH_PAGE_SIZE;
@@ -15,6 +17,7 @@ pub enum HypoStatus {
     RunningImage,
     OutOfBounds,
     FlashError(FlashStatus),
+    NotProgrammed,
 }
 
 // All these symbols are defined with no space allocated. This is best
@@ -32,6 +35,11 @@ extern "C" {
     static __IMAGE_STAGE0_BASE: ();
     static __IMAGE_STAGE0_END: ();
 
+    static __IMAGE_BOOTSTATE_BASE: ();
+
+    static __IMAGE_CERTS_BASE: ();
+    static __IMAGE_CERTS_END: ();
+
     // This references the base of the currently running image
     static __this_image: ();
 }
@@ -78,6 +86,24 @@ macro_rules! image_stage0_end {
     };
 }
 
+macro_rules! boot_state_base {
+    () => {
+        core::ptr::addr_of!(__IMAGE_BOOTSTATE_BASE) as u32
+    };
+}
+
+macro_rules! certs_base {
+    () => {
+        core::ptr::addr_of!(__IMAGE_CERTS_BASE) as u32
+    };
+}
+
+macro_rules! certs_end {
+    () => {
+        core::ptr::addr_of!(__IMAGE_CERTS_END) as u32
+    };
+}
+
 fn get_base(which: UpdateTarget) -> u32 {
     match which {
         UpdateTarget::ImageA => unsafe { image_a_base!() },
@@ -156,3 +182,150 @@ pub unsafe extern "C" fn __write_block(
 
     HypoStatus::Success
 }
+
+/// Reads back page `page_num` of an image into the `FLASH_PAGE_SIZE` bytes at
+/// `buffer`, so that it can be checked once an update has been written.
+#[no_mangle]
+pub unsafe extern "C" fn __read_block(
+    image_num: UpdateTarget,
+    page_num: u32,
+    buffer: *mut u8,
+) -> HypoStatus {
+    let read_addr = match target_addr(image_num, page_num) {
+        Ok(addr) => addr,
+        Err(e) => return e,
+    };
+
+    // Reading an erased page would fault, so check first.
+    if !validate_programmed(read_addr, FLASH_PAGE_SIZE as u32) {
+        return HypoStatus::NotProgrammed;
+    }
+
+    // As for `__write_block`, the failure mode for a bad buffer is a fault.
+    core::ptr::copy_nonoverlapping(
+        read_addr as *const u8,
+        buffer,
+        FLASH_PAGE_SIZE,
+    );
+
+    HypoStatus::Success
+}
+
+fn boot_slot(which: UpdateTarget) -> Option<u32> {
+    match which {
+        UpdateTarget::ImageA => Some(BOOT_SLOT_A),
+        UpdateTarget::ImageB => Some(BOOT_SLOT_B),
+        _ => None,
+    }
+}
+
+/// Reads the boot state page, falling back to the default state if it has
+/// never been written.
+pub fn read_boot_state() -> BootState {
+    let addr = unsafe { boot_state_base!() };
+
+    // Reading an erased page would fault, so check first.
+    if !validate_programmed(addr, FLASH_PAGE_SIZE as u32) {
+        return BootState::default();
+    }
+
+    // SAFETY: The page is programmed and page aligned, and its address comes
+    // from our linker script, which we trust.
+    let state = unsafe { core::ptr::read_volatile(addr as *const BootState) };
+    if state.magic == BOOT_STATE_MAGIC {
+        state
+    } else {
+        BootState::default()
+    }
+}
+
+fn write_boot_state(state: &BootState) -> HypoStatus {
+    let addr = unsafe { boot_state_base!() };
+
+    let mut page = [0u8; FLASH_PAGE_SIZE];
+    page[..core::mem::size_of::<BootState>()].copy_from_slice(state.as_bytes());
+
+    if let Err(result) = unsafe { flash_erase(addr, FLASH_PAGE_SIZE as u32) } {
+        return HypoStatus::FlashError(result);
+    }
+
+    if let Err(result) =
+        unsafe { flash_write(addr, page.as_mut_ptr(), FLASH_PAGE_SIZE as u32) }
+    {
+        return HypoStatus::FlashError(result);
+    }
+
+    HypoStatus::Success
+}
+
+/// Marks the running image as good, so that stage0 stops counting its boots
+/// and will keep booting it.
+#[no_mangle]
+pub unsafe extern "C" fn __confirm_image() -> HypoStatus {
+    let slot = [UpdateTarget::ImageA, UpdateTarget::ImageB]
+        .iter()
+        .copied()
+        .find(|t| same_image(*t))
+        .and_then(boot_slot);
+
+    let slot = match slot {
+        Some(slot) => slot,
+        // We're not running from A or B, so there's nothing to confirm.
+        None => return HypoStatus::OutOfBounds,
+    };
+
+    // This is called on every boot, so only touch the flash the first time:
+    // rewriting the page each time would wear it out, and losing power
+    // mid-erase would lose `min_epoch`.
+    let state = read_boot_state();
+    if state.preferred == slot && state.confirmed != 0 {
+        return HypoStatus::Success;
+    }
+
+    write_boot_state(&state.confirm(slot))
+}
+
+/// Makes `image_num` the image stage0 tries first, on probation until it
+/// confirms itself.
+#[no_mangle]
+pub unsafe extern "C" fn __prefer_image(image_num: UpdateTarget) -> HypoStatus {
+    // The running image confirms itself instead.
+    if same_image(image_num) {
+        return HypoStatus::RunningImage;
+    }
+
+    match boot_slot(image_num) {
+        Some(slot) => write_boot_state(&read_boot_state().prefer(slot)),
+        None => HypoStatus::OutOfBounds,
+    }
+}
+
+/// Writes a page of the DeviceId cert chain that stage0 hands off in place of
+/// the self-signed DeviceId cert (see `dice::DeviceIdCertChain`).
+#[no_mangle]
+pub unsafe extern "C" fn __write_certs_page(
+    page_num: u32,
+    buffer: *mut u8,
+) -> HypoStatus {
+    // This is safely calculating addr = base + page_num * PAGE_SIZE
+    let write_addr = match page_num
+        .checked_mul(FLASH_PAGE_SIZE as u32)
+        .and_then(|offset| offset.checked_add(certs_base!()))
+    {
+        Some(addr) if addr < certs_end!() => addr,
+        _ => return HypoStatus::OutOfBounds,
+    };
+
+    // As for `__write_block`, the failure mode for a bad buffer is a fault.
+
+    if let Err(result) = flash_erase(write_addr, FLASH_PAGE_SIZE as u32) {
+        return HypoStatus::FlashError(result);
+    }
+
+    if let Err(result) = flash_write(write_addr, buffer, FLASH_PAGE_SIZE as u32)
+    {
+        return HypoStatus::FlashError(result);
+    }
+
+    HypoStatus::Success
+}
@@ -0,0 +1,648 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! The chip-independent half of an update server.
+//!
+//! An update server writes an image, a block at a time, into a region of flash
+//! that isn't running, then checks it and makes it bootable. Everything but
+//! the last step is the same on every chip given a way to erase, program and
+//! read back that region, which is what the `Flash` trait provides. `Updater`
+//! is the update state machine built on it: it erases the region when an
+//! update starts, tracks which blocks have been written so an interrupted
+//! transfer can be resumed, and checks the image's SHA-256 before letting the
+//! server activate it.
+//!
+//! `RamFlash` is a fake `Flash` with the semantics of NOR flash, so the whole
+//! state machine can be tested on the host.
+
+#![cfg_attr(not(test), no_std)]
+
+mod progress;
+mod ram;
+
+pub use progress::ImageProgress;
+pub use ram::{RamFlash, ERASED};
+
+use sha2::{Digest, Sha256};
+
+/// The length of the SHA-256 digest of an image.
+pub const SHA256_SZ: usize = 32;
+
+/// How much of an image `read_image` reads at a time. This is a multiple of
+/// the page size of every flash we read a page at a time, and the most the
+/// STM32H7 hash server takes in one go.
+pub const READ_CHUNK_SIZE: usize = 512;
+
+/// A region of flash, addressed from 0, that's erased a sector at a time and
+/// programmed in multiples of `WRITE_SIZE`.
+pub trait Flash {
+    /// The size of the smallest region that can be erased, in bytes.
+    const SECTOR_SIZE: usize;
+
+    /// Programs must start at a multiple of this, and be a multiple of it
+    /// long. Reads may also need to start at a multiple of it.
+    const WRITE_SIZE: usize;
+
+    /// The size of the region in bytes.
+    fn capacity(&self) -> usize;
+
+    /// Erases bytes `sector * SECTOR_SIZE..(sector + 1) * SECTOR_SIZE`.
+    fn erase_sector(&mut self, sector: usize) -> Result<(), FlashError>;
+
+    /// Programs `data` at `offset`, which must have been erased first.
+    fn program(&mut self, offset: usize, data: &[u8])
+        -> Result<(), FlashError>;
+
+    /// Reads `buf.len()` bytes at `offset` into `buf`.
+    fn read(&mut self, offset: usize, buf: &mut [u8])
+        -> Result<(), FlashError>;
+}
+
+/// Why a flash operation failed. Chip backends map their own status bits onto
+/// these, so they're all a client sees.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum FlashError {
+    /// The operation would go past the end of the region.
+    OutOfBounds,
+    /// The offset or length isn't a multiple of `WRITE_SIZE`.
+    Unaligned,
+    EraseFailed,
+    ProgramFailed,
+    /// The data read back is bad, e.g. it has an uncorrectable ECC error.
+    ReadFailed,
+    /// The flash (or this part of it) is locked against erasing and
+    /// programming.
+    WriteProtected,
+}
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub enum Error {
+    /// There's no update in progress.
+    NotStarted,
+    /// A different update is already in progress.
+    InProgress,
+    /// The update has been finished and nothing more can be done with it.
+    AlreadyFinished,
+    BadLength,
+    OutOfBounds,
+    /// Some blocks of the image haven't been written.
+    IncompleteImage,
+    /// The image doesn't have the SHA-256 the client gave when starting the
+    /// update.
+    BadDigest,
+    /// A block was written again with different data, in an update with no
+    /// digest to catch it.
+    BlockMismatch,
+    Flash(FlashError),
+}
+
+impl From<FlashError> for Error {
+    fn from(e: FlashError) -> Self {
+        Self::Flash(e)
+    }
+}
+
+enum UpdateState<T, const N: usize> {
+    NoUpdate,
+    InProgress(ImageProgress<T, N>),
+    Finished,
+}
+
+/// The update state machine for images of up to `8 * N` blocks of
+/// `BLOCK_SIZE` bytes, going to one of several targets named by `T`.
+///
+/// The flash to write is passed to each call, rather than held, so a server
+/// can pick it based on the target (e.g. which image slot on the LPC55).
+pub struct Updater<T, const BLOCK_SIZE: usize, const N: usize> {
+    state: UpdateState<T, N>,
+}
+
+impl<T: Copy + PartialEq, const BLOCK_SIZE: usize, const N: usize>
+    Updater<T, BLOCK_SIZE, N>
+{
+    pub const fn new() -> Self {
+        Self {
+            state: UpdateState::NoUpdate,
+        }
+    }
+
+    fn progress(&mut self) -> Result<&mut ImageProgress<T, N>, Error> {
+        match &mut self.state {
+            UpdateState::NoUpdate => Err(Error::NotStarted),
+            UpdateState::Finished => Err(Error::AlreadyFinished),
+            UpdateState::InProgress(progress) => Ok(progress),
+        }
+    }
+
+    /// The target of the update in progress.
+    pub fn target(&self) -> Result<T, Error> {
+        match &self.state {
+            UpdateState::NoUpdate => Err(Error::NotStarted),
+            UpdateState::Finished => Err(Error::AlreadyFinished),
+            UpdateState::InProgress(progress) => Ok(progress.target()),
+        }
+    }
+
+    /// Starts an update, erasing as much of `flash` as the image needs. If
+    /// this is the update already in progress, it's resumed instead: nothing
+    /// is erased and the blocks written so far are kept.
+    ///
+    /// `sha256` is the SHA-256 of the image, if the client has it. Without
+    /// it, `finish` can only check that every block was written, and
+    /// `write_block` checks rewritten blocks against flash instead.
+    pub fn prep<F: Flash>(
+        &mut self,
+        flash: &mut F,
+        target: T,
+        image_len: usize,
+        sha256: Option<[u8; SHA256_SZ]>,
+    ) -> Result<(), Error> {
+        match &self.state {
+            UpdateState::InProgress(progress) => {
+                if progress.matches(target, image_len, sha256) {
+                    return Ok(());
+                }
+                return Err(Error::InProgress);
+            }
+            UpdateState::Finished => return Err(Error::AlreadyFinished),
+            UpdateState::NoUpdate => (),
+        }
+
+        // Every block, including a short last one, is programmed whole.
+        assert!(BLOCK_SIZE % F::WRITE_SIZE == 0);
+
+        let progress =
+            ImageProgress::new(target, image_len, sha256, BLOCK_SIZE)?;
+        let padded_len = progress.block_count() * BLOCK_SIZE;
+        if padded_len > flash.capacity() {
+            return Err(Error::BadLength);
+        }
+
+        let sectors = (padded_len + F::SECTOR_SIZE - 1) / F::SECTOR_SIZE;
+        for sector in 0..sectors {
+            flash.erase_sector(sector)?;
+        }
+
+        self.state = UpdateState::InProgress(progress);
+        Ok(())
+    }
+
+    /// Writes block `block_num`, the first `len` bytes of `block`; the rest
+    /// is overwritten with padding. Writing a block again does nothing, since
+    /// flash can't be reprogrammed without an erase: it's a retry from a
+    /// client that missed our reply. If the data differs, the digest check
+    /// in `finish` will catch it; without a digest, the block is read back
+    /// and a rewrite that doesn't match is refused with `BlockMismatch`.
+    pub fn write_block<F: Flash>(
+        &mut self,
+        flash: &mut F,
+        block_num: usize,
+        block: &mut [u8; BLOCK_SIZE],
+        len: usize,
+    ) -> Result<(), Error> {
+        let progress = self.progress()?;
+        progress.check_block(block_num, len)?;
+        block[len..].fill(0);
+        if progress.is_written(block_num) {
+            if progress.sha256().is_none()
+                && !holds(flash, block_num * BLOCK_SIZE, block)?
+            {
+                return Err(Error::BlockMismatch);
+            }
+            return Ok(());
+        }
+
+        flash.program(block_num * BLOCK_SIZE, block)?;
+
+        progress.set_written(block_num);
+        Ok(())
+    }
+
+    /// The bitmap of the blocks written so far (see
+    /// `ImageProgress::written`), and the number of blocks in the image.
+    pub fn written_blocks(&mut self) -> Result<(&[u8], usize), Error> {
+        let progress = self.progress()?;
+        Ok((progress.written(), progress.block_count()))
+    }
+
+    pub fn abort(&mut self) -> Result<(), Error> {
+        self.progress()?;
+        self.state = UpdateState::NoUpdate;
+        Ok(())
+    }
+
+    /// Finishes the update: checks that the whole image has been written, that
+    /// `hash` of what's in flash is the SHA-256 the client gave (if it gave
+    /// one), and then has `activate` make it bootable. Returns the target that
+    /// was updated.
+    ///
+    /// If the digest doesn't match, the update is aborted and the client has
+    /// to start over. If hashing or activating fails, the update stays in
+    /// progress, so finishing can be retried.
+    pub fn finish<F, E>(
+        &mut self,
+        flash: &mut F,
+        hash: impl FnOnce(&mut F, usize) -> Result<[u8; SHA256_SZ], E>,
+        activate: impl FnOnce(&mut F, T) -> Result<(), E>,
+    ) -> Result<T, E>
+    where
+        F: Flash,
+        E: From<Error>,
+    {
+        let progress = self.progress()?;
+        if !progress.is_complete() {
+            return Err(Error::IncompleteImage.into());
+        }
+
+        if progress.sha256().is_some() {
+            let digest = hash(flash, progress.image_len())?;
+            if let Err(e) = progress.check_digest(&digest) {
+                self.state = UpdateState::NoUpdate;
+                return Err(e.into());
+            }
+        }
+
+        let target = progress.target();
+        activate(flash, target)?;
+
+        self.state = UpdateState::Finished;
+        Ok(target)
+    }
+}
+
+impl<T: Copy + PartialEq, const BLOCK_SIZE: usize, const N: usize> Default
+    for Updater<T, BLOCK_SIZE, N>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+/// Reads the first `len` bytes of `flash`, `READ_CHUNK_SIZE` bytes at a time,
+/// passing each chunk to `f`.
+pub fn read_image<F: Flash, E: From<Error>>(
+    flash: &mut F,
+    len: usize,
+    mut f: impl FnMut(&[u8]) -> Result<(), E>,
+) -> Result<(), E> {
+    let mut chunk = [0u8; READ_CHUNK_SIZE];
+    let mut offset = 0;
+
+    while offset < len {
+        let n = (len - offset).min(READ_CHUNK_SIZE);
+        flash
+            .read(offset, &mut chunk[..n])
+            .map_err(|e| E::from(Error::from(e)))?;
+        f(&chunk[..n])?;
+        offset += n;
+    }
+
+    Ok(())
+}
+
+/// Checks whether `flash` holds `data` at `offset`, reading it
+/// `READ_CHUNK_SIZE` bytes at a time.
+fn holds<F: Flash>(
+    flash: &mut F,
+    offset: usize,
+    data: &[u8],
+) -> Result<bool, FlashError> {
+    let mut chunk = [0u8; READ_CHUNK_SIZE];
+    for (i, expected) in data.chunks(READ_CHUNK_SIZE).enumerate() {
+        let chunk = &mut chunk[..expected.len()];
+        flash.read(offset + i * READ_CHUNK_SIZE, chunk)?;
+        if chunk != expected {
+            return Ok(false);
+        }
+    }
+    Ok(true)
+}
+
+/// Computes the SHA-256 of the first `len` bytes of `flash` in software, for
+/// chips without a hash engine.
+pub fn image_sha256<F: Flash>(
+    flash: &mut F,
+    len: usize,
+) -> Result<[u8; SHA256_SZ], Error> {
+    let mut sha = Sha256::new();
+    read_image(flash, len, |chunk| {
+        sha.update(chunk);
+        Ok::<_, Error>(())
+    })?;
+    Ok(sha.finalize().into())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const BLOCK_SIZE: usize = 256;
+
+    // Four sectors of four blocks each.
+    type TestFlash = RamFlash<4096, 1024, 32>;
+    type TestUpdater = Updater<u8, BLOCK_SIZE, 2>;
+
+    fn image(len: usize) -> Vec<u8> {
+        (0..len).map(|i| (i * 7 + i / 256) as u8).collect()
+    }
+
+    /// The digest a client gives `prep` for `data`.
+    fn sha256(data: &[u8]) -> Option<[u8; SHA256_SZ]> {
+        Some(Sha256::digest(data).into())
+    }
+
+    fn write(
+        updater: &mut TestUpdater,
+        flash: &mut TestFlash,
+        image: &[u8],
+        block_num: usize,
+    ) -> Result<(), Error> {
+        let data = image.chunks(BLOCK_SIZE).nth(block_num).unwrap();
+        let mut block = [0xaa; BLOCK_SIZE];
+        block[..data.len()].copy_from_slice(data);
+        updater.write_block(flash, block_num, &mut block, data.len())
+    }
+
+    fn finish(
+        updater: &mut TestUpdater,
+        flash: &mut TestFlash,
+    ) -> Result<u8, Error> {
+        updater.finish(flash, image_sha256, |_, _| Ok(()))
+    }
+
+    #[test]
+    fn whole_update() {
+        let image = image(1500);
+        let mut flash = TestFlash::new();
+        let mut updater = TestUpdater::new();
+
+        updater.prep(&mut flash, 1, 1500, sha256(&image)).unwrap();
+        // 6 blocks span two sectors.
+        assert_eq!(flash.erase_count, 2);
+
+        for b in 0..6 {
+            write(&mut updater, &mut flash, &image, b).unwrap();
+        }
+
+        let mut activated = None;
+        let target = updater
+            .finish(&mut flash, image_sha256, |_, target| {
+                activated = Some(target);
+                Ok::<_, Error>(())
+            })
+            .unwrap();
+        assert_eq!(target, 1);
+        assert_eq!(activated, Some(1));
+
+        assert_eq!(&flash.contents()[..1500], &image[..]);
+        // The short last block is padded.
+        assert!(flash.contents()[1500..1536].iter().all(|&b| b == 0));
+        // Sectors the image doesn't reach are left alone.
+        assert!(flash.contents()[2048..].iter().all(|&b| b == 0x42));
+
+        assert_eq!(
+            write(&mut updater, &mut flash, &image, 0),
+            Err(Error::AlreadyFinished)
+        );
+        assert_eq!(
+            updater.prep(&mut flash, 1, 1500, sha256(&image)),
+            Err(Error::AlreadyFinished)
+        );
+    }
+
+    #[test]
+    fn resume() {
+        let image = image(2000);
+        let digest = sha256(&image);
+        let mut flash = TestFlash::new();
+        let mut updater = TestUpdater::new();
+
+        updater.prep(&mut flash, 1, 2000, digest).unwrap();
+        for b in [0, 1, 4] {
+            write(&mut updater, &mut flash, &image, b).unwrap();
+        }
+        assert_eq!(
+            finish(&mut updater, &mut flash),
+            Err(Error::IncompleteImage)
+        );
+
+        // A different update can't start...
+        assert_eq!(
+            updater.prep(&mut flash, 2, 2000, digest),
+            Err(Error::InProgress)
+        );
+        assert_eq!(
+            updater.prep(&mut flash, 1, 1999, digest),
+            Err(Error::InProgress)
+        );
+
+        // ...but the same one picks up where it left off.
+        updater.prep(&mut flash, 1, 2000, digest).unwrap();
+        assert_eq!(flash.erase_count, 2);
+        assert_eq!(updater.written_blocks(), Ok((&[0b10011][..], 8)));
+
+        for b in [2, 3, 5, 6, 7] {
+            write(&mut updater, &mut flash, &image, b).unwrap();
+        }
+        assert_eq!(finish(&mut updater, &mut flash), Ok(1));
+        assert_eq!(&flash.contents()[..2000], &image[..]);
+    }
+
+    #[test]
+    fn rewrite_is_ignored() {
+        let image = image(512);
+        let mut flash = TestFlash::new();
+        let mut updater = TestUpdater::new();
+
+        updater.prep(&mut flash, 1, 512, sha256(&image)).unwrap();
+        write(&mut updater, &mut flash, &image, 0).unwrap();
+        // RamFlash would fail this if it were programmed again.
+        write(&mut updater, &mut flash, &image, 0).unwrap();
+        assert_eq!(flash.program_count, 1);
+
+        // With a digest, a differing rewrite is ignored too: `finish` checks
+        // what actually went into flash.
+        let mut other = image.clone();
+        other[10] ^= 1;
+        write(&mut updater, &mut flash, &other, 0).unwrap();
+        write(&mut updater, &mut flash, &image, 1).unwrap();
+        assert_eq!(finish(&mut updater, &mut flash), Ok(1));
+    }
+
+    #[test]
+    fn bad_blocks() {
+        let image = image(600);
+        let mut flash = TestFlash::new();
+        let mut updater = TestUpdater::new();
+
+        updater.prep(&mut flash, 1, 600, sha256(&image)).unwrap();
+
+        let mut block = [0; BLOCK_SIZE];
+        assert_eq!(
+            updater.write_block(&mut flash, 3, &mut block, 0),
+            Err(Error::OutOfBounds)
+        );
+        assert_eq!(
+            updater.write_block(&mut flash, 2, &mut block, BLOCK_SIZE),
+            Err(Error::BadLength)
+        );
+        assert_eq!(
+            updater.write_block(&mut flash, 0, &mut block, 100),
+            Err(Error::BadLength)
+        );
+        assert_eq!(flash.program_count, 0);
+    }
+
+    #[test]
+    fn bad_digest_aborts() {
+        let image = image(1024);
+        let mut flash = TestFlash::new();
+        let mut updater = TestUpdater::new();
+
+        updater.prep(&mut flash, 1, 1024, sha256(&image)).unwrap();
+        for b in 0..4 {
+            write(&mut updater, &mut flash, &image, b).unwrap();
+        }
+        flash.corrupt(700);
+
+        let mut activated = false;
+        assert_eq!(
+            updater.finish(&mut flash, image_sha256, |_, _| {
+                activated = true;
+                Ok(())
+            }),
+            Err(Error::BadDigest)
+        );
+        assert!(!activated);
+        assert_eq!(updater.written_blocks(), Err(Error::NotStarted));
+
+        // Starting over erases the bad image.
+        updater.prep(&mut flash, 1, 1024, sha256(&image)).unwrap();
+        for b in 0..4 {
+            write(&mut updater, &mut flash, &image, b).unwrap();
+        }
+        assert_eq!(finish(&mut updater, &mut flash), Ok(1));
+    }
+
+    #[test]
+    fn unverified_update() {
+        let image = image(300);
+        let mut flash = TestFlash::new();
+        let mut updater = TestUpdater::new();
+
+        updater.prep(&mut flash, 1, 300, None).unwrap();
+        // Giving a digest makes it a different update.
+        assert_eq!(
+            updater.prep(&mut flash, 1, 300, sha256(&image)),
+            Err(Error::InProgress)
+        );
+        write(&mut updater, &mut flash, &image, 0).unwrap();
+        assert_eq!(
+            finish(&mut updater, &mut flash),
+            Err(Error::IncompleteImage)
+        );
+        write(&mut updater, &mut flash, &image, 1).unwrap();
+
+        // Without a digest, a rewrite has to match what's in flash.
+        write(&mut updater, &mut flash, &image, 0).unwrap();
+        let mut other = image.clone();
+        other[10] ^= 1;
+        assert_eq!(
+            write(&mut updater, &mut flash, &other, 0),
+            Err(Error::BlockMismatch)
+        );
+        // What's past the end of a short last block doesn't matter, as it's
+        // replaced with padding either way.
+        let mut block = [0x55; BLOCK_SIZE];
+        block[..44].copy_from_slice(&image[256..]);
+        updater.write_block(&mut flash, 1, &mut block, 44).unwrap();
+        assert_eq!(flash.program_count, 2);
+
+        // There's nothing to check the image against, so it isn't hashed.
+        assert_eq!(
+            updater.finish(
+                &mut flash,
+                |_, _| -> Result<_, Error> { panic!("hashed the image") },
+                |_, _| Ok(()),
+            ),
+            Ok(1)
+        );
+    }
+
+    #[test]
+    fn failed_activation_can_be_retried() {
+        let image = image(256);
+        let mut flash = TestFlash::new();
+        let mut updater = TestUpdater::new();
+
+        updater.prep(&mut flash, 1, 256, sha256(&image)).unwrap();
+        write(&mut updater, &mut flash, &image, 0).unwrap();
+
+        assert_eq!(
+            updater.finish(&mut flash, image_sha256, |_, _| {
+                Err(Error::Flash(FlashError::WriteProtected))
+            }),
+            Err(Error::Flash(FlashError::WriteProtected))
+        );
+        assert_eq!(finish(&mut updater, &mut flash), Ok(1));
+    }
+
+    #[test]
+    fn image_too_big() {
+        let mut flash = RamFlash::<1024, 1024, 32>::new();
+        let mut updater = TestUpdater::new();
+
+        // Fits the bitmap but not the flash.
+        assert_eq!(
+            updater.prep(&mut flash, 1, 1025, None),
+            Err(Error::BadLength)
+        );
+        // Fits neither.
+        assert_eq!(
+            updater.prep(&mut flash, 1, 16 * BLOCK_SIZE + 1, None),
+            Err(Error::BadLength)
+        );
+        assert_eq!(flash.erase_count, 0);
+        assert_eq!(updater.target(), Err(Error::NotStarted));
+    }
+
+    #[test]
+    fn flash_errors() {
+        let image = image(256);
+        let mut flash = TestFlash::new();
+        let mut updater = TestUpdater::new();
+
+        flash.write_protected = true;
+        assert_eq!(
+            updater.prep(&mut flash, 1, 256, sha256(&image)),
+            Err(Error::Flash(FlashError::WriteProtected))
+        );
+        assert_eq!(updater.target(), Err(Error::NotStarted));
+
+        flash.write_protected = false;
+        updater.prep(&mut flash, 1, 256, sha256(&image)).unwrap();
+        flash.write_protected = true;
+        assert_eq!(
+            write(&mut updater, &mut flash, &image, 0),
+            Err(Error::Flash(FlashError::WriteProtected))
+        );
+        assert_eq!(updater.written_blocks(), Ok((&[0][..], 1)));
+    }
+
+    #[test]
+    fn abort() {
+        let mut flash = TestFlash::new();
+        let mut updater = TestUpdater::new();
+
+        assert_eq!(updater.abort(), Err(Error::NotStarted));
+        updater.prep(&mut flash, 1, 256, None).unwrap();
+        assert_eq!(updater.target(), Ok(1));
+        updater.abort().unwrap();
+        assert_eq!(updater.target(), Err(Error::NotStarted));
+
+        // A new update can start right away.
+        updater.prep(&mut flash, 2, 256, None).unwrap();
+        assert_eq!(updater.target(), Ok(2));
+    }
+}
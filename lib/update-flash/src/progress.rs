@@ -0,0 +1,185 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::{Error, SHA256_SZ};
+
+/// The bookkeeping for an image update in progress: what the client promised
+/// when starting it, and which blocks it has written so far, which is what
+/// lets it resume an interrupted transfer. The bitmap is `N` bytes, so an
+/// image can have at most `8 * N` blocks.
+pub struct ImageProgress<T, const N: usize> {
+    target: T,
+    image_len: usize,
+    sha256: Option<[u8; SHA256_SZ]>,
+    block_size: usize,
+    written: [u8; N],
+}
+
+impl<T: Copy + PartialEq, const N: usize> ImageProgress<T, N> {
+    pub fn new(
+        target: T,
+        image_len: usize,
+        sha256: Option<[u8; SHA256_SZ]>,
+        block_size: usize,
+    ) -> Result<Self, Error> {
+        if image_len == 0 || image_len > 8 * N * block_size {
+            return Err(Error::BadLength);
+        }
+
+        Ok(Self {
+            target,
+            image_len,
+            sha256,
+            block_size,
+            written: [0; N],
+        })
+    }
+
+    /// Returns true if this is the update a client would start with these
+    /// arguments, i.e. the client is resuming it.
+    pub fn matches(
+        &self,
+        target: T,
+        image_len: usize,
+        sha256: Option<[u8; SHA256_SZ]>,
+    ) -> bool {
+        self.target == target
+            && self.image_len == image_len
+            && self.sha256 == sha256
+    }
+
+    pub fn target(&self) -> T {
+        self.target
+    }
+
+    pub fn image_len(&self) -> usize {
+        self.image_len
+    }
+
+    /// The SHA-256 the client gave for the image, if it gave one.
+    pub fn sha256(&self) -> Option<&[u8; SHA256_SZ]> {
+        self.sha256.as_ref()
+    }
+
+    pub fn block_count(&self) -> usize {
+        (self.image_len + self.block_size - 1) / self.block_size
+    }
+
+    /// Checks that `len` is the length of block `block_num`: a whole block,
+    /// or whatever's left of the image for the last one.
+    pub fn check_block(
+        &self,
+        block_num: usize,
+        len: usize,
+    ) -> Result<(), Error> {
+        if block_num >= self.block_count() {
+            return Err(Error::OutOfBounds);
+        }
+        let left = self.image_len - block_num * self.block_size;
+        if len != left.min(self.block_size) {
+            return Err(Error::BadLength);
+        }
+        Ok(())
+    }
+
+    pub fn is_written(&self, block_num: usize) -> bool {
+        self.written[block_num / 8] & (1 << (block_num % 8)) != 0
+    }
+
+    pub fn set_written(&mut self, block_num: usize) {
+        self.written[block_num / 8] |= 1 << (block_num % 8);
+    }
+
+    pub fn is_complete(&self) -> bool {
+        (0..self.block_count()).all(|b| self.is_written(b))
+    }
+
+    /// The bitmap of the blocks written so far: bit `n % 8` of byte `n / 8`
+    /// is set once block `n` has been.
+    pub fn written(&self) -> &[u8] {
+        &self.written[..(self.block_count() + 7) / 8]
+    }
+
+    /// Checks `digest`, the SHA-256 of the image as written, against the one
+    /// the client gave. Any digest passes if the client didn't give one.
+    pub fn check_digest(&self, digest: &[u8; SHA256_SZ]) -> Result<(), Error> {
+        match &self.sha256 {
+            Some(sha256) if sha256 != digest => Err(Error::BadDigest),
+            _ => Ok(()),
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    type Progress = ImageProgress<u8, 2>;
+
+    #[test]
+    fn block_lengths() {
+        let p = Progress::new(0, 1000, None, 256).unwrap();
+        assert_eq!(p.block_count(), 4);
+
+        assert_eq!(p.check_block(0, 256), Ok(()));
+        assert_eq!(p.check_block(3, 232), Ok(()));
+        assert_eq!(p.check_block(3, 256), Err(Error::BadLength));
+        assert_eq!(p.check_block(1, 100), Err(Error::BadLength));
+        assert_eq!(p.check_block(4, 256), Err(Error::OutOfBounds));
+    }
+
+    #[test]
+    fn image_must_fit_bitmap() {
+        assert!(Progress::new(0, 16 * 256, None, 256).is_ok());
+        assert_eq!(
+            Progress::new(0, 16 * 256 + 1, None, 256).err(),
+            Some(Error::BadLength)
+        );
+        assert_eq!(
+            Progress::new(0, 0, None, 256).err(),
+            Some(Error::BadLength)
+        );
+    }
+
+    #[test]
+    fn bitmap() {
+        let mut p = Progress::new(0, 10 * 256, None, 256).unwrap();
+        assert_eq!(p.written(), &[0, 0]);
+
+        for b in [0, 3, 9] {
+            p.set_written(b);
+        }
+        assert_eq!(p.written(), &[0b1001, 0b10]);
+        assert!(p.is_written(3));
+        assert!(!p.is_written(4));
+        assert!(!p.is_complete());
+
+        for b in 0..10 {
+            p.set_written(b);
+        }
+        assert!(p.is_complete());
+    }
+
+    #[test]
+    fn digest() {
+        let sha256 = [0x5a; SHA256_SZ];
+        let p = Progress::new(1, 100, Some(sha256), 256).unwrap();
+        assert!(p.matches(1, 100, Some(sha256)));
+        assert!(!p.matches(2, 100, Some(sha256)));
+        assert!(!p.matches(1, 100, None));
+
+        assert_eq!(p.check_digest(&sha256), Ok(()));
+        assert_eq!(p.check_digest(&[0xa5; SHA256_SZ]), Err(Error::BadDigest));
+
+        // An all-zero digest is just a digest.
+        let p = Progress::new(1, 100, Some([0; SHA256_SZ]), 256).unwrap();
+        assert_eq!(p.check_digest(&[0xa5; SHA256_SZ]), Err(Error::BadDigest));
+
+        let p = Progress::new(1, 100, None, 256).unwrap();
+        assert!(p.matches(1, 100, None));
+        assert!(!p.matches(1, 100, Some(sha256)));
+        assert_eq!(p.sha256(), None);
+        assert_eq!(p.check_digest(&[0xa5; SHA256_SZ]), Ok(()));
+    }
+}
@@ -0,0 +1,121 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::{Flash, FlashError};
+
+/// The value of an erased byte of flash.
+pub const ERASED: u8 = 0xff;
+
+/// A fake flash backed by RAM, for testing the update state machine on the
+/// host. It behaves like NOR flash: erasing sets a sector to all ones, and
+/// programming can only clear bits, so programming anything that hasn't been
+/// erased since it was last programmed fails.
+pub struct RamFlash<const SIZE: usize, const SECTOR: usize, const WRITE: usize>
+{
+    mem: [u8; SIZE],
+    programmed: [bool; SIZE],
+    /// Makes every erase and program fail, as when flash is locked.
+    pub write_protected: bool,
+    pub erase_count: usize,
+    pub program_count: usize,
+}
+
+impl<const SIZE: usize, const SECTOR: usize, const WRITE: usize>
+    RamFlash<SIZE, SECTOR, WRITE>
+{
+    /// Makes a fake flash with arbitrary contents, as if it had held some
+    /// other image.
+    pub fn new() -> Self {
+        Self {
+            mem: [0x42; SIZE],
+            programmed: [true; SIZE],
+            write_protected: false,
+            erase_count: 0,
+            program_count: 0,
+        }
+    }
+
+    pub fn contents(&self) -> &[u8] {
+        &self.mem
+    }
+
+    /// Flips the bits of byte `offset`, as a stand-in for a write that went
+    /// wrong without the flash controller noticing.
+    pub fn corrupt(&mut self, offset: usize) {
+        self.mem[offset] ^= 0xff;
+    }
+
+    fn range(
+        offset: usize,
+        len: usize,
+    ) -> Result<core::ops::Range<usize>, FlashError> {
+        match offset.checked_add(len) {
+            Some(end) if end <= SIZE => Ok(offset..end),
+            _ => Err(FlashError::OutOfBounds),
+        }
+    }
+}
+
+impl<const SIZE: usize, const SECTOR: usize, const WRITE: usize> Default
+    for RamFlash<SIZE, SECTOR, WRITE>
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const SIZE: usize, const SECTOR: usize, const WRITE: usize> Flash
+    for RamFlash<SIZE, SECTOR, WRITE>
+{
+    const SECTOR_SIZE: usize = SECTOR;
+    const WRITE_SIZE: usize = WRITE;
+
+    fn capacity(&self) -> usize {
+        SIZE
+    }
+
+    fn erase_sector(&mut self, sector: usize) -> Result<(), FlashError> {
+        let range = Self::range(sector * SECTOR, SECTOR)?;
+        if self.write_protected {
+            return Err(FlashError::WriteProtected);
+        }
+
+        self.mem[range.clone()].fill(ERASED);
+        self.programmed[range].fill(false);
+        self.erase_count += 1;
+        Ok(())
+    }
+
+    fn program(
+        &mut self,
+        offset: usize,
+        data: &[u8],
+    ) -> Result<(), FlashError> {
+        if offset % WRITE != 0 || data.len() % WRITE != 0 {
+            return Err(FlashError::Unaligned);
+        }
+        let range = Self::range(offset, data.len())?;
+        if self.write_protected {
+            return Err(FlashError::WriteProtected);
+        }
+        if self.programmed[range.clone()].iter().any(|&p| p) {
+            return Err(FlashError::ProgramFailed);
+        }
+
+        self.mem[range.clone()].copy_from_slice(data);
+        self.programmed[range].fill(true);
+        self.program_count += 1;
+        Ok(())
+    }
+
+    fn read(
+        &mut self,
+        offset: usize,
+        buf: &mut [u8],
+    ) -> Result<(), FlashError> {
+        let range = Self::range(offset, buf.len())?;
+        buf.copy_from_slice(&self.mem[range]);
+        Ok(())
+    }
+}
@@ -23,5 +23,41 @@ fn main() -> Result<(), Box<dyn std::error::Error>> {
         image_id
     )?;
 
+    // The key we check images' signatures against, in hex, from
+    // `[image-signing] public-key` in the app.toml: either an uncompressed
+    // SEC1 point for ECDSA P-256, or the 32-byte key for Ed25519.
+    let key = build_util::env_var("HUBRIS_IMAGE_PUBKEY").map_err(|_| {
+        "stage0 needs a public key to check images against; \
+         set `public-key` in the app.toml's `[image-signing]` table"
+    })?;
+    let key = (0..key.len())
+        .step_by(2)
+        .map(|i| {
+            key.get(i..i + 2)
+                .and_then(|b| u8::from_str_radix(b, 16).ok())
+        })
+        .collect::<Option<Vec<u8>>>()
+        .ok_or("HUBRIS_IMAGE_PUBKEY is not hex")?;
+    let algorithm = match key.len() {
+        65 if key[0] == 0x04 => "SIGNATURE_ECDSA_P256_SHA256",
+        32 => "SIGNATURE_ED25519",
+        _ => {
+            return Err("HUBRIS_IMAGE_PUBKEY is neither an uncompressed \
+                 P-256 point nor an Ed25519 key"
+                .into())
+        }
+    };
+    writeln!(
+        const_file,
+        "pub static IMAGE_PUBLIC_KEY: [u8; {}] = {:?};",
+        key.len(),
+        key
+    )?;
+    writeln!(
+        const_file,
+        "pub const IMAGE_SIGNATURE_ALGORITHM: u32 = abi::{};",
+        algorithm
+    )?;
+
     Ok(())
 }
@@ -0,0 +1,30 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Architecture-specific support.
+//!
+//! Each architecture module provides the entry point, which brings the
+//! hardware to a known state before calling `crate::boot`, along with the
+//! same set of names for the portable code to use:
+//!
+//! - `is_programmed(start, len)`, whether flash can be read without faulting
+//! - `read_boot_state()` and `write_boot_state(state)`, which persist the
+//!   `abi::BootState` if the platform has somewhere to keep it
+//! - `branch_to_image(image)`, which enters a validated image
+//! - with the `dice` feature, `dice_seed()` and `dice_handoff()`, the
+//!   `dice::SeedSource` for the CDI and the memory to leave DICE artifacts in,
+//!   and `read_deviceid_certs()`, the persistent storage holding an installed
+//!   `dice::DeviceIdCertChain`, if there is one
+
+cfg_if::cfg_if! {
+    if #[cfg(target_arch = "arm")] {
+        mod arm_m;
+        pub use arm_m::*;
+    } else if #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))] {
+        mod riscv;
+        pub use riscv::*;
+    } else {
+        compile_error!("support for this architecture not implemented");
+    }
+}
@@ -0,0 +1,283 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Cortex-M support, which for now means the LPC55.
+
+use core::arch;
+
+use abi::{BootState, ImageVectors};
+use cortex_m::peripheral::Peripherals;
+use cortex_m_rt::entry;
+use lpc55_romapi::FLASH_PAGE_SIZE;
+use zerocopy::AsBytes;
+
+use crate::image_header::Image;
+
+extern crate lpc55_pac;
+
+extern "C" {
+    // Like `__vector_size`, this is a symbol with no space allocated.
+    #[allow(improper_ctypes)]
+    static __IMAGE_BOOTSTATE_BASE: ();
+    #[allow(improper_ctypes)]
+    static __IMAGE_CERTS_BASE: ();
+}
+
+// FLASH_PAGE_SIZE is a usize so redefine the constant here to avoid having
+// to do the u32 change everywhere
+const PAGE_SIZE: u32 = FLASH_PAGE_SIZE as u32;
+
+/// Initial entry point for handling a memory management fault.
+#[allow(non_snake_case)]
+#[no_mangle]
+pub unsafe extern "C" fn MemoryManagement() {
+    loop {}
+}
+
+/// Initial entry point for handling a bus fault.
+#[allow(non_snake_case)]
+#[no_mangle]
+pub unsafe extern "C" fn BusFault() {
+    loop {}
+}
+
+/// Initial entry point for handling a usage fault.
+#[allow(non_snake_case)]
+#[no_mangle]
+pub unsafe extern "C" fn UsageFault() {
+    loop {}
+}
+
+#[allow(non_snake_case)]
+#[no_mangle]
+pub unsafe extern "C" fn SecureFault() {
+    loop {}
+}
+
+const ROM_VER: u32 = 1;
+
+#[cfg(feature = "tz_support")]
+pub unsafe fn branch_to_image(image: Image) -> ! {
+    let sau_ctrl: *mut u32 = 0xe000edd0 as *mut u32;
+    let sau_rbar: *mut u32 = 0xe000eddc as *mut u32;
+    let sau_rlar: *mut u32 = 0xe000ede0 as *mut u32;
+    let sau_rnr: *mut u32 = 0xe000edd8 as *mut u32;
+
+    for i in 0..8 {
+        if let Some(r) = image.get_sau_entry(i) {
+            core::ptr::write_volatile(sau_rnr, i as u32);
+            core::ptr::write_volatile(sau_rbar, r.rbar);
+            core::ptr::write_volatile(sau_rlar, r.rlar);
+        }
+    }
+
+    core::ptr::write_volatile(sau_ctrl, 1);
+
+    let mut peripherals = Peripherals::steal();
+
+    // let co processor be non-secure
+    core::ptr::write_volatile(0xE000ED8C as *mut u32, 0xc00);
+
+    peripherals
+        .SCB
+        .enable(cortex_m::peripheral::scb::Exception::UsageFault);
+    peripherals
+        .SCB
+        .enable(cortex_m::peripheral::scb::Exception::BusFault);
+
+    peripherals
+        .SCB
+        .enable(cortex_m::peripheral::scb::Exception::SecureFault);
+
+    // Make our exceptions NS
+    core::ptr::write_volatile(0xe000ed0c as *mut u32, 0x05fa2000);
+
+    // Write the NS_VTOR
+    core::ptr::write_volatile(0xE002ED08 as *mut u32, image.get_vectors());
+
+    // Route all interrupts to the NS world
+    // TODO use only the interrupts we've enabled
+    core::ptr::write_volatile(0xe000e380 as *mut u32, 0xffffffff);
+    core::ptr::write_volatile(0xe000e384 as *mut u32, 0xffffffff);
+
+    // SAFETY: The image has been validated, so its vector table is
+    // programmed
+    let vectors = &*(image.get_vectors() as *const ImageVectors);
+
+    // For secure we do not set the thumb bit!
+    let entry_pt = vectors.entry & !1u32;
+    let stack = vectors.sp;
+
+    // and branch
+    arch::asm!("
+            msr MSP_NS, {stack}
+            bxns {entry}",
+        stack = in(reg) stack,
+        entry = in(reg) entry_pt,
+        options(noreturn),
+    );
+}
+
+#[cfg(not(feature = "tz_support"))]
+pub unsafe fn branch_to_image(image: Image) -> ! {
+    let mut peripherals = Peripherals::steal();
+
+    peripherals
+        .SCB
+        .enable(cortex_m::peripheral::scb::Exception::UsageFault);
+    peripherals
+        .SCB
+        .enable(cortex_m::peripheral::scb::Exception::BusFault);
+
+    // Write the VTOR
+    core::ptr::write_volatile(0xE000ED08 as *mut u32, image.get_vectors());
+
+    // SAFETY: The image has been validated, so its vector table is
+    // programmed
+    let vectors = &*(image.get_vectors() as *const ImageVectors);
+
+    let entry_pt = vectors.entry;
+    let stack = vectors.sp;
+
+    // and branch
+    arch::asm!("
+            msr MSP, {stack}
+            bx {entry}",
+        stack = in(reg) stack,
+        entry = in(reg) entry_pt,
+        options(noreturn),
+    );
+}
+
+fn check_system_freq() {
+    // corresponds to FRO 96 MHz, see 4.5.34 in user manual
+    const EXPECTED_MAINCLKSELA: u32 = 3;
+    // corresponds to Main Clock A, see 4.5.45 in user manual
+    const EXPECTED_MAINCLKSELB: u32 = 0;
+    // corresponds to divide by 2, see 4.5.50 in user manual
+    const EXPECTED_AHBCLKDIV: u32 = 1;
+
+    let syscon = unsafe { &*lpc55_pac::SYSCON::ptr() };
+
+    let a = syscon.mainclksela.read().bits();
+    let b = syscon.mainclkselb.read().bits();
+    let div = syscon.ahbclkdiv.read().bits();
+
+    // Very very ugly check! We are assuming that the system is running at
+    // 48Mh with these settings. This is a short term verification until we have
+    // all our expected infrastructure to make sure the system is running at
+    // what we expect.
+    if a != EXPECTED_MAINCLKSELA
+        && b != EXPECTED_MAINCLKSELB
+        && div != EXPECTED_AHBCLKDIV
+    {
+        panic!();
+    }
+}
+
+/// The LPC55 will hard fault if it reads an erased page, so this checks
+/// every page touched by `start..start + len` with the ROM.
+pub fn is_programmed(start: u32, len: u32) -> bool {
+    let first = start & !(PAGE_SIZE - 1);
+    let end = match start.checked_add(len) {
+        Some(end) => end,
+        None => return false,
+    };
+    let len = (end - first + (PAGE_SIZE - 1)) & !(PAGE_SIZE - 1);
+
+    lpc55_romapi::validate_programmed(first, len)
+}
+
+fn boot_state_base() -> u32 {
+    // SAFETY: This is generated by the linker script which we trust
+    unsafe { core::ptr::addr_of!(__IMAGE_BOOTSTATE_BASE) as u32 }
+}
+
+pub fn read_boot_state() -> Option<BootState> {
+    // Reading an erased page would fault, so check first.
+    if !is_programmed(boot_state_base(), PAGE_SIZE) {
+        return None;
+    }
+
+    // SAFETY: The page is programmed and page aligned.
+    Some(unsafe {
+        core::ptr::read_volatile(boot_state_base() as *const BootState)
+    })
+}
+
+pub fn write_boot_state(state: &BootState) {
+    let mut page = [0u8; FLASH_PAGE_SIZE];
+    page[..core::mem::size_of::<BootState>()].copy_from_slice(state.as_bytes());
+
+    // If this fails we'll boot the same way next time, which is the best we
+    // can do.
+    //
+    // SAFETY: The boot state page belongs to no image, so we can't be
+    // erasing anything we're running from.
+    unsafe {
+        if lpc55_romapi::flash_erase(boot_state_base(), PAGE_SIZE).is_ok() {
+            let _ = lpc55_romapi::flash_write(
+                boot_state_base(),
+                page.as_mut_ptr(),
+                PAGE_SIZE,
+            );
+        }
+    }
+}
+
+/// Returns the installed DeviceId cert chain, still in its persistent form,
+/// if there's one that's fully programmed.
+#[cfg(feature = "dice")]
+pub fn read_deviceid_certs() -> Option<&'static [u8]> {
+    use dice_crate::CertChainHeader;
+
+    // SAFETY: This is generated by the linker script which we trust
+    let base = unsafe { core::ptr::addr_of!(__IMAGE_CERTS_BASE) as u32 };
+
+    // Reading an erased page would fault, so check first.
+    if !is_programmed(base, PAGE_SIZE) {
+        return None;
+    }
+
+    // SAFETY: The page is programmed and page aligned.
+    let header =
+        unsafe { core::ptr::read_volatile(base as *const CertChainHeader) };
+    let len = core::mem::size_of::<CertChainHeader>() + header.chain_len()?;
+    if !is_programmed(base, len as u32) {
+        return None;
+    }
+
+    // SAFETY: We've checked that all of this is programmed, and it belongs
+    // to no image, so nothing writes to it while stage0 runs.
+    Some(unsafe { core::slice::from_raw_parts(base as *const u8, len) })
+}
+
+#[cfg(feature = "dice")]
+pub fn dice_seed() -> impl dice_crate::SeedSource {
+    dice_crate::Lpc55Seed
+}
+
+/// Powers on the USB SRAM, where tasks look for DICE artifacts.
+#[cfg(feature = "dice")]
+pub fn dice_handoff() -> dice_crate::Handoff<'static> {
+    use unwrap_lite::UnwrapLite;
+
+    let syscon = lpc55_pac::Peripherals::take().unwrap_lite().SYSCON;
+    dice_crate::Handoff::turn_on(&syscon)
+}
+
+#[entry]
+fn main() -> ! {
+    // This is the SYSCON_DIEID register on LPC55 which contains the ROM
+    // version. Make sure our configuration matches!
+    let val = unsafe { core::ptr::read_volatile(0x50000ffc as *const u32) };
+
+    if val & 1 != ROM_VER {
+        panic!()
+    }
+
+    check_system_freq();
+
+    crate::boot()
+}
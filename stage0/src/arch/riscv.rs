@@ -0,0 +1,94 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! RISC-V support.
+//!
+//! We're entered in Machine mode with everything else in reset, and leave
+//! the images to do all of their own setup, so there's very little to do
+//! here beyond jumping to the right place.
+
+use abi::BootState;
+use riscv_rt::entry;
+
+use crate::image_header::Image;
+
+// Like the kernel (see `kern::header`), start with a jump over the image
+// header, so that the header is at the same offset in stage0 as in the
+// images it boots.
+core::arch::global_asm!(
+    "
+    .pushsection .image_entry, \"ax\"
+    .option push
+    .option norvc
+    j _start
+    .option pop
+    .popsection
+    "
+);
+
+/// Memory doesn't fault when read here, whether or not anything has been
+/// loaded into it.
+pub fn is_programmed(_start: u32, _len: u32) -> bool {
+    true
+}
+
+/// There's nowhere to persist boot state yet, so every boot looks like the
+/// first.
+pub fn read_boot_state() -> Option<BootState> {
+    None
+}
+
+pub fn write_boot_state(_state: &BootState) {}
+
+/// The UDS of every emulated device. It's no secret, so neither is anything
+/// derived from it (see `SoftwareSeed`).
+#[cfg(feature = "dice")]
+const UDS: [u8; 32] = *b"hubris emulated UDS, not secret!";
+
+/// Where DICE artifacts are handed off to tasks. This must match `dice` in
+/// chips/qemu-virt/memory-stage0.toml.
+#[cfg(feature = "dice")]
+const DICE_HANDOFF_BASE: usize = 0x8050_0000;
+
+/// There's nowhere to install a DeviceId cert chain yet either, so the
+/// self-signed DeviceId cert is all there is.
+#[cfg(feature = "dice")]
+pub fn read_deviceid_certs() -> Option<&'static [u8]> {
+    None
+}
+
+#[cfg(feature = "dice")]
+pub fn dice_seed() -> impl dice_crate::SeedSource {
+    dice_crate::SoftwareSeed::new(&UDS)
+}
+
+#[cfg(feature = "dice")]
+pub fn dice_handoff() -> dice_crate::Handoff<'static> {
+    // SAFETY: This RAM belongs to no image, and this is the only reference
+    // stage0 makes to it.
+    dice_crate::Handoff::new(unsafe {
+        core::slice::from_raw_parts_mut(
+            DICE_HANDOFF_BASE as *mut u8,
+            dice_crate::HANDOFF_SIZE,
+        )
+    })
+}
+
+pub unsafe fn branch_to_image(image: Image) -> ! {
+    // Images are entered at their first byte, which jumps over the header
+    // to `_start`. That resets everything riscv-rt relies on, so all we need
+    // to do is make sure interrupts are off.
+    riscv::interrupt::disable();
+
+    core::arch::asm!(
+        "jr {entry}",
+        entry = in(reg) image.get_vectors() as usize,
+        options(noreturn),
+    );
+}
+
+#[entry]
+fn main() -> ! {
+    crate::boot()
+}
@@ -0,0 +1,47 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Choosing between images A and B using the persistent boot state (see
+//! `abi::BootState`).
+
+use abi::{BootImage, BootState, BOOT_SLOT_A, BOOT_STATE_MAGIC};
+
+use crate::arch;
+use crate::image_header::Image;
+
+fn read() -> BootState {
+    match arch::read_boot_state() {
+        Some(state) if state.magic == BOOT_STATE_MAGIC => state,
+        _ => BootState::default(),
+    }
+}
+
+fn boot_image(img: &Option<Image>) -> Option<BootImage> {
+    img.as_ref().map(|img| BootImage {
+        version: img.get_version(),
+        epoch: img.get_epoch(),
+    })
+}
+
+/// Picks the image to boot from those that validated, recording the boot in
+/// the boot state.
+pub fn select(a: Option<Image>, b: Option<Image>) -> Image {
+    let state = read();
+
+    let (slot, next) = match state.select(boot_image(&a), boot_image(&b)) {
+        Some(choice) => choice,
+        None => panic!(),
+    };
+
+    if next != state {
+        arch::write_boot_state(&next);
+    }
+
+    // `select` only picks a slot that holds an image.
+    let image = if slot == BOOT_SLOT_A { a } else { b };
+    match image {
+        Some(image) => image,
+        None => panic!(),
+    }
+}
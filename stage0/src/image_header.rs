@@ -2,50 +2,58 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use abi::{ImageHeader, ImageVectors};
-use lpc55_romapi::FLASH_PAGE_SIZE;
-
+use abi::{ImageHeader, ImageSignature};
+use core::convert::{TryFrom, TryInto};
+use p256::ecdsa::signature::{Signature as _, Verifier};
+use p256::ecdsa::{Signature, VerifyingKey};
+use salty::constants::PUBLICKEY_SERIALIZED_LENGTH;
+
+use crate::arch;
+
+// All these symbols are defined with no space allocated. This is best
+// represented as a zero sized type which gets accessed by addr_of! as below.
+// The issue here is that () is not a valid C type and this is a C extern
+// block. We don't actually care about compatibility with C here though so we
+// can safely allow an improper ctype here.
+#[allow(improper_ctypes)]
 extern "C" {
-    static IMAGEA: abi::ImageVectors;
-    static IMAGEB: abi::ImageVectors;
+    static IMAGEA: ();
+    static IMAGEB: ();
+    static __IMAGE_A_END: ();
+    static __IMAGE_B_END: ();
     // __vector size is currently defined in the linker script as
     //
     // __vector_size = SIZEOF(.vector_table);
     //
-    // which is a symbol whose value is the size of the vector table (i.e.
-    // there is no actual space allocated). This is best represented as a zero
-    // sized type which gets accessed by addr_of! as below. The issue here is
-    // that () is not a valid C type and this is a C extern block. We don't
-    // actually care about compatibility with C here though so we can safely
-    // allow an improper ctype here.
-    #[allow(improper_ctypes)]
+    // on Arm, and as the size of the jump over the header on RISC-V.
     static __vector_size: ();
 }
 
-pub struct Image(&'static ImageVectors);
-
-// FLASH_PAGE_SIZE is a usize so redefine the constant here to avoid having
-// to do the u32 change everywhere
-const PAGE_SIZE: u32 = FLASH_PAGE_SIZE as u32;
+/// An image in one of the slots, which may be entered at its first byte.
+pub struct Image {
+    start: u32,
+    end: u32,
+}
 
 // Implicit in this design is that all functions on Image are considered safe.
 // We ensure this by only returning an Image through this interface after
 // verifying all parts of it are valid.
 //
 // It would technically be possible to create an instance of Image with an
-// invalid set of ImageVectors but that would require going far outside the
-// bounds of the expected design.
+// invalid slot but that would require going far outside the bounds of the
+// expected design.
 
-// Safety: These accesses are unsafe because `IMAGEA` and `IMAGEB`
-// are coming from an extern, and might violate alignment rules or even be
-// modified externally and subject to data races. In our case
-// we have to assume that neither of these is true, since it's
-// being furnished by our linker script, which we trust.
+// Safety: These accesses are unsafe because the symbols are coming from an
+// extern. Their addresses are furnished by our linker script, which we trust,
+// and we never read through them.
 
 pub fn get_image_b() -> Option<Image> {
-    let imageb = unsafe { &IMAGEB };
-
-    let img = Image(imageb);
+    let img = unsafe {
+        Image {
+            start: core::ptr::addr_of!(IMAGEB) as u32,
+            end: core::ptr::addr_of!(__IMAGE_B_END) as u32,
+        }
+    };
 
     if img.validate() {
         Some(img)
@@ -55,9 +63,12 @@ pub fn get_image_b() -> Option<Image> {
 }
 
 pub fn get_image_a() -> Option<Image> {
-    let imagea = unsafe { &IMAGEA };
-
-    let img = Image(imagea);
+    let img = unsafe {
+        Image {
+            start: core::ptr::addr_of!(IMAGEA) as u32,
+            end: core::ptr::addr_of!(__IMAGE_A_END) as u32,
+        }
+    };
 
     if img.validate() {
         Some(img)
@@ -68,52 +79,83 @@ pub fn get_image_a() -> Option<Image> {
 
 impl Image {
     fn get_img_start(&self) -> u32 {
-        self.0 as *const ImageVectors as u32
+        self.start
     }
 
-    #[cfg(feature = "dice")]
-    fn get_img_size(&self) -> Option<usize> {
-        use core::convert::TryFrom;
-
-        usize::try_from((unsafe { &*self.get_header() }).total_image_len).ok()
+    fn get_img_size(&self) -> u32 {
+        // SAFETY: We only call this once the header has been checked
+        unsafe { &*self.get_header() }.total_image_len
     }
 
-    #[cfg(feature = "dice")]
     pub fn as_bytes(&self) -> &[u8] {
-        use unwrap_lite::UnwrapLite;
-
-        let img_ptr = self.get_img_start() as *const u8;
-        let img_size = self.get_img_size().unwrap_lite();
+        let img_ptr = self.get_img_start() as usize as *const u8;
+        let img_size = self.get_img_size() as usize;
+        // SAFETY: `validate` has checked that all of this is programmed
         unsafe { core::slice::from_raw_parts(img_ptr, img_size) }
     }
 
+    fn get_signature_addr(&self) -> u32 {
+        self.get_img_start() + ((self.get_img_size() + 3) & !3)
+    }
+
+    pub fn get_signature(&self) -> &ImageSignature {
+        // SAFETY: `validate` has checked that this is programmed, and it's
+        // 4-byte aligned by construction
+        unsafe {
+            &*(self.get_signature_addr() as usize as *const ImageSignature)
+        }
+    }
+
+    /// Checks the image's detached signature against the key baked into
+    /// stage0. The image itself must already have been checked.
+    fn verify_signature(&self) -> bool {
+        let len = core::mem::size_of::<ImageSignature>() as u32;
+        match self.get_signature_addr().checked_add(len) {
+            Some(end) if end <= self.end => (),
+            _ => return false,
+        }
+        if !arch::is_programmed(self.get_signature_addr(), len) {
+            return false;
+        }
+
+        let signature = self.get_signature();
+        if signature.magic != abi::SIGNATURE_MAGIC
+            || signature.algorithm != crate::IMAGE_SIGNATURE_ALGORITHM
+            || signature.image_len != self.get_img_size()
+        {
+            return false;
+        }
+
+        match signature.algorithm {
+            abi::SIGNATURE_ECDSA_P256_SHA256 => {
+                verify_p256(self.as_bytes(), &signature.signature)
+            }
+            abi::SIGNATURE_ED25519 => {
+                verify_ed25519(self.as_bytes(), &signature.signature)
+            }
+            _ => false,
+        }
+    }
+
     fn get_header(&self) -> *const ImageHeader {
         // SAFETY: This generated by the linker script which we trust
         // Note that this is generated from _this_ image's linker script
         // as opposed to the _image_ linker script but those two _must_
         // be the same value!
         let vector_size = unsafe { core::ptr::addr_of!(__vector_size) as u32 };
-        (self.get_img_start() + vector_size) as *const ImageHeader
+        (self.get_img_start() + vector_size) as usize as *const ImageHeader
     }
 
     /// Make sure all of the image flash is programmed
     fn validate(&self) -> bool {
         let img_start = self.get_img_start();
-
-        // Start by making sure we can access the page where the vectors live
-        let valid = lpc55_romapi::validate_programmed(img_start, PAGE_SIZE);
-
-        if !valid {
-            return false;
-        }
-
         let header_ptr = self.get_header();
+        let header_end =
+            header_ptr as u32 + core::mem::size_of::<ImageHeader>() as u32;
 
-        // Next validate the header location is programmed
-        let valid =
-            lpc55_romapi::validate_programmed(header_ptr as u32, PAGE_SIZE);
-
-        if !valid {
+        // Start by making sure we can access everything up to the end of the
+        // header
+        if !arch::is_programmed(img_start, header_end - img_start) {
             return false;
         }
 
@@ -122,41 +164,39 @@ impl Image {
         // which we trust.
         let header = unsafe { &*header_ptr };
 
-        // Next make sure the marked image length is programmed
-        let valid = lpc55_romapi::validate_programmed(
-            img_start,
-            (header.total_image_len + (PAGE_SIZE - 1)) & !(PAGE_SIZE - 1),
-        );
-
-        if !valid {
+        // Does this look correct?
+        if header.magic != abi::HEADER_MAGIC {
             return false;
         }
 
-        // Does this look correct?
-        if header.magic != abi::HEADER_MAGIC {
+        // Next make sure the marked image length fits in the slot and is
+        // programmed
+        if header.total_image_len > self.end - img_start
+            || !arch::is_programmed(img_start, header.total_image_len)
+        {
             return false;
         }
 
-        return true;
+        // Finally, was it built by us?
+        self.verify_signature()
     }
 
     pub fn get_vectors(&self) -> u32 {
         self.get_img_start()
     }
 
-    pub fn get_pc(&self) -> u32 {
-        self.0.entry
-    }
+    pub fn get_version(&self) -> u32 {
+        // SAFETY: We checked this previously
+        let header = unsafe { &*self.get_header() };
 
-    pub fn get_sp(&self) -> u32 {
-        self.0.sp
+        header.version
     }
 
-    pub fn get_version(&self) -> u32 {
+    pub fn get_epoch(&self) -> u32 {
         // SAFETY: We checked this previously
         let header = unsafe { &*self.get_header() };
 
-        header.version
+        header.epoch
     }
 
     #[cfg(feature = "tz_support")]
@@ -167,3 +207,29 @@ impl Image {
         header.sau_entries.get(i)
     }
 }
+
+fn verify_p256(image: &[u8], signature: &[u8; 64]) -> bool {
+    let key = match VerifyingKey::from_sec1_bytes(&crate::IMAGE_PUBLIC_KEY) {
+        Ok(key) => key,
+        Err(_) => return false,
+    };
+    let signature = match Signature::from_bytes(signature) {
+        Ok(signature) => signature,
+        Err(_) => return false,
+    };
+    key.verify(image, &signature).is_ok()
+}
+
+fn verify_ed25519(image: &[u8], signature: &[u8; 64]) -> bool {
+    let key: &[u8; PUBLICKEY_SERIALIZED_LENGTH] =
+        match crate::IMAGE_PUBLIC_KEY[..].try_into() {
+            Ok(key) => key,
+            Err(_) => return false,
+        };
+    let key = match salty::signature::PublicKey::try_from(key) {
+        Ok(key) => key,
+        Err(_) => return false,
+    };
+    key.verify(image, &salty::signature::Signature::from(signature))
+        .is_ok()
+}
@@ -2,202 +2,42 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-#![feature(cmse_nonsecure_entry)]
+//! The first stage bootloader, which picks between the A and B images.
+//!
+//! Finding, validating and choosing between images is portable; everything
+//! that depends on the hardware, from the entry point to the final branch,
+//! lives in the `arch` module.
+
+#![cfg_attr(target_arch = "arm", feature(cmse_nonsecure_entry))]
 #![feature(naked_functions)]
 #![feature(array_methods)]
 #![no_main]
 #![no_std]
 
-use core::arch;
-
-extern crate lpc55_pac;
 extern crate panic_halt;
-use cortex_m::peripheral::Peripherals;
-use cortex_m_rt::entry;
 
+mod arch;
+mod boot_state;
 #[cfg(feature = "dice")]
 mod dice;
 // FIXME Need to fixup the secure interface calls
 //mod hypo;
 mod image_header;
 
-use crate::image_header::Image;
-
-/// Initial entry point for handling a memory management fault.
-#[allow(non_snake_case)]
-#[no_mangle]
-pub unsafe extern "C" fn MemoryManagement() {
-    loop {}
-}
-
-/// Initial entry point for handling a bus fault.
-#[allow(non_snake_case)]
-#[no_mangle]
-pub unsafe extern "C" fn BusFault() {
-    loop {}
-}
-
-/// Initial entry point for handling a usage fault.
-#[allow(non_snake_case)]
-#[no_mangle]
-pub unsafe extern "C" fn UsageFault() {
-    loop {}
-}
-
-#[allow(non_snake_case)]
-#[no_mangle]
-pub unsafe extern "C" fn SecureFault() {
-    loop {}
-}
-
-const ROM_VER: u32 = 1;
-
-#[cfg(feature = "tz_support")]
-unsafe fn branch_to_image(image: Image) -> ! {
-    let sau_ctrl: *mut u32 = 0xe000edd0 as *mut u32;
-    let sau_rbar: *mut u32 = 0xe000eddc as *mut u32;
-    let sau_rlar: *mut u32 = 0xe000ede0 as *mut u32;
-    let sau_rnr: *mut u32 = 0xe000edd8 as *mut u32;
-
-    for i in 0..8 {
-        if let Some(r) = image.get_sau_entry(i) {
-            core::ptr::write_volatile(sau_rnr, i as u32);
-            core::ptr::write_volatile(sau_rbar, r.rbar);
-            core::ptr::write_volatile(sau_rlar, r.rlar);
-        }
-    }
-
-    core::ptr::write_volatile(sau_ctrl, 1);
-
-    let mut peripherals = Peripherals::steal();
-
-    // let co processor be non-secure
-    core::ptr::write_volatile(0xE000ED8C as *mut u32, 0xc00);
-
-    peripherals
-        .SCB
-        .enable(cortex_m::peripheral::scb::Exception::UsageFault);
-    peripherals
-        .SCB
-        .enable(cortex_m::peripheral::scb::Exception::BusFault);
-
-    peripherals
-        .SCB
-        .enable(cortex_m::peripheral::scb::Exception::SecureFault);
-
-    // Make our exceptions NS
-    core::ptr::write_volatile(0xe000ed0c as *mut u32, 0x05fa2000);
-
-    // Write the NS_VTOR
-    core::ptr::write_volatile(0xE002ED08 as *mut u32, image.get_vectors());
-
-    // Route all interrupts to the NS world
-    // TODO use only the interrupts we've enabled
-    core::ptr::write_volatile(0xe000e380 as *mut u32, 0xffffffff);
-    core::ptr::write_volatile(0xe000e384 as *mut u32, 0xffffffff);
-
-    // For secure we do not set the thumb bit!
-    let entry_pt = image.get_pc() & !1u32;
-    let stack = image.get_sp();
-
-    // and branch
-    arch::asm!("
-            msr MSP_NS, {stack}
-            bxns {entry}",
-        stack = in(reg) stack,
-        entry = in(reg) entry_pt,
-        options(noreturn),
-    );
-}
-
-#[cfg(not(feature = "tz_support"))]
-unsafe fn branch_to_image(image: Image) -> ! {
-    let mut peripherals = Peripherals::steal();
-
-    peripherals
-        .SCB
-        .enable(cortex_m::peripheral::scb::Exception::UsageFault);
-    peripherals
-        .SCB
-        .enable(cortex_m::peripheral::scb::Exception::BusFault);
-
-    // Write the VTOR
-    core::ptr::write_volatile(0xE000ED08 as *mut u32, image.get_vectors());
-
-    let entry_pt = image.get_pc();
-    let stack = image.get_sp();
-
-    // and branch
-    arch::asm!("
-            msr MSP, {stack}
-            bx {entry}",
-        stack = in(reg) stack,
-        entry = in(reg) entry_pt,
-        options(noreturn),
-    );
-}
-
-fn check_system_freq() {
-    // corresponds to FRO 96 MHz, see 4.5.34 in user manual
-    const EXPECTED_MAINCLKSELA: u32 = 3;
-    // corresponds to Main Clock A, see 4.5.45 in user manual
-    const EXPECTED_MAINCLKSELB: u32 = 0;
-    // corresponds to divide by 2, see 4.5.50 in user manual
-    const EXPECTED_AHBCLKDIV: u32 = 1;
-
-    let syscon = unsafe { &*lpc55_pac::SYSCON::ptr() };
-
-    let a = syscon.mainclksela.read().bits();
-    let b = syscon.mainclkselb.read().bits();
-    let div = syscon.ahbclkdiv.read().bits();
-
-    // Very very ugly check! We are assuming that the system is running at
-    // 48Mh with these settings. This is a short term verification until we have
-    // all our expected infrastructure to make sure the system is running at
-    // what we expect.
-    if a != EXPECTED_MAINCLKSELA
-        && b != EXPECTED_MAINCLKSELB
-        && div != EXPECTED_AHBCLKDIV
-    {
-        panic!();
-    }
-}
-
-#[entry]
-fn main() -> ! {
-    // This is the SYSCON_DIEID register on LPC55 which contains the ROM
-    // version. Make sure our configuration matches!
-    let val = unsafe { core::ptr::read_volatile(0x50000ffc as *const u32) };
-
-    if val & 1 != ROM_VER {
-        panic!()
-    }
-
-    check_system_freq();
-
+/// Called by the architecture's entry point once the hardware is in a state
+/// we expect.
+fn boot() -> ! {
     let (imagea, imageb) =
         (image_header::get_image_a(), image_header::get_image_b());
 
-    // Image selection is very simple at the moment
-    // Future work: check persistent state and epochs
-    let image = match (imagea, imageb) {
-        (None, None) => panic!(),
-        (Some(a), None) => a,
-        (None, Some(b)) => b,
-        (Some(a), Some(b)) => {
-            if a.get_version() > b.get_version() {
-                a
-            } else {
-                b
-            }
-        }
-    };
+    // Only images with a valid signature are returned here.
+    let image = boot_state::select(imagea, imageb);
 
     #[cfg(feature = "dice")]
     dice::run(&image);
 
     unsafe {
-        branch_to_image(image);
+        arch::branch_to_image(image);
     }
 }
 
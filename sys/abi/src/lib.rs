@@ -4,13 +4,18 @@
 
 //! Kernel ABI definitions, shared between kernel and applications.
 
-#![no_std]
+#![cfg_attr(not(test), no_std)]
 
 use serde::{Deserialize, Serialize};
 use zerocopy::{AsBytes, FromBytes};
 
 pub const TASK_ID_INDEX_BITS: usize = 10;
 
+/// Number of entries in each task's memory region table, including the null
+/// region that pads out unused entries. The build system must not give a task
+/// more regions than this.
+pub const REGIONS_PER_TASK: usize = 8;
+
 /// Names a particular incarnation of a task.
 ///
 /// A `TaskId` combines two fields, a task index (which can be predicted at
@@ -184,6 +189,55 @@ pub struct ULease {
     pub length: u32,
 }
 
+impl ULease {
+    /// Bit position of the original lease number in the attributes of a
+    /// forwarded lease.
+    const FORWARD_LEASE_SHIFT: u32 = 8;
+    /// Bit position of the original lender's `TaskId` in the attributes of a
+    /// forwarded lease.
+    const FORWARD_TASK_SHIFT: u32 = 16;
+
+    /// Describes a lease that forwards part of a lease the sender has itself
+    /// received, rather than lending the sender's own memory.
+    ///
+    /// `lender` and `lease_number` name the original lease, as they would be
+    /// passed to `BORROW_READ`; `offset` and `length` select a sub-range of
+    /// it. `attributes` must be a subset of the original lease's attributes.
+    /// The kernel checks all of this when the lease is used, at which point
+    /// `lender` must still be blocked waiting for a reply from the sender.
+    pub fn forwarded(
+        lender: TaskId,
+        lease_number: u8,
+        attributes: LeaseAttributes,
+        offset: u32,
+        length: u32,
+    ) -> Self {
+        let bits = (attributes & LeaseAttributes::ACCESS).bits()
+            | LeaseAttributes::FORWARDED.bits()
+            | u32::from(lease_number) << Self::FORWARD_LEASE_SHIFT
+            | u32::from(lender.0) << Self::FORWARD_TASK_SHIFT;
+        Self {
+            attributes: LeaseAttributes::from_bits_truncate(bits),
+            base_address: offset,
+            length,
+        }
+    }
+
+    /// If this lease was built by `ULease::forwarded`, returns the original
+    /// lender and lease number. In that case `base_address` is an offset into
+    /// the original lease rather than an address.
+    pub fn forwarded_from(&self) -> Option<(TaskId, usize)> {
+        if !self.attributes.contains(LeaseAttributes::FORWARDED) {
+            return None;
+        }
+        let bits = self.attributes.bits();
+        Some((
+            TaskId((bits >> Self::FORWARD_TASK_SHIFT) as u16),
+            ((bits >> Self::FORWARD_LEASE_SHIFT) & 0xFF) as usize,
+        ))
+    }
+}
+
 bitflags::bitflags! {
     #[derive(FromBytes)]
     #[repr(transparent)]
@@ -192,6 +246,16 @@ bitflags::bitflags! {
         const READ = 1 << 0;
         /// Allow the borrower to write this memory.
         const WRITE = 1 << 1;
+        /// The lease refers to a lease received by the sender, not to the
+        /// sender's own memory; see `ULease::forwarded`.
+        const FORWARDED = 1 << 2;
+        /// Bits holding the original lease number of a forwarded lease.
+        const FORWARD_LEASE = 0xFF << 8;
+        /// Bits holding the original lender of a forwarded lease.
+        const FORWARD_TASK = 0xFFFF << 16;
+
+        /// The access rights granted by a lease.
+        const ACCESS = Self::READ.bits | Self::WRITE.bits;
     }
 }
 
@@ -218,6 +282,12 @@ pub const fn extract_new_generation(code: u32) -> Option<Generation> {
 /// Response code returned by the kernel if a lender has defected.
 pub const DEFECT: u32 = 1;
 
+/// Response code returned by the kernel if a SEND was cancelled because it ran
+/// past the timeout it was made with.
+///
+/// This sits just below the dead codes, so it can't be mistaken for one.
+pub const TIMED_OUT: u32 = 0xffff_fe00;
+
 /// State used to make scheduling decisions.
 #[derive(Copy, Clone, Debug, Eq, PartialEq, Deserialize, Serialize)]
 pub enum TaskState {
@@ -390,6 +460,16 @@ pub enum UsageError {
     NoIrq,
     BadKernelMessage,
     BadReplyFaultReason,
+    /// A program sent a forwarded lease that names a lease it doesn't hold,
+    /// extends past the end of the original lease, or asks for more access
+    /// than the original lease granted.
+    BadForwardedLease,
+    /// A program sent or posted to a task that isn't in its IPC allowlist.
+    /// This can only happen in applications that enable the allowlist.
+    IpcNotAllowed,
+    /// A program used a kipc that is reserved for the supervisor and tasks
+    /// granted `dump-access`.
+    NotPrivileged,
 }
 
 /// Origin of a fault.
@@ -431,6 +511,51 @@ pub enum ReplyFaultReason {
     AccessViolation = 5,
 }
 
+/// CPU accounting for a single task, as returned by the `ReadTaskStats` kipc.
+///
+/// These counters are cumulative since boot and are *not* reset when the task
+/// is restarted, so that a supervisor can compute load by differencing two
+/// samples.
+#[derive(
+    Copy, Clone, Debug, Default, Eq, PartialEq, Deserialize, Serialize,
+)]
+pub struct TaskStats {
+    /// Total time this task has spent scheduled, in units of the
+    /// architecture's run-time counter. These units are only meaningful
+    /// relative to the run time of other tasks (e.g. the idle task).
+    pub run_time: u64,
+    /// Number of times this task has been switched in.
+    pub switches: u32,
+}
+
+/// Stack usage for a single task, as returned by the `ReadStackUsage` kipc.
+///
+/// The kernel paints each task's stack with a known pattern whenever the task
+/// is (re)initialized, so `peak` is the high-water mark since the task last
+/// started.
+#[derive(
+    Copy, Clone, Debug, Default, Eq, PartialEq, Deserialize, Serialize,
+)]
+pub struct StackUsage {
+    /// Deepest extent of the stack touched by the task, in bytes.
+    pub peak: u32,
+    /// Total size of the task's stack, in bytes. This is zero if the kernel
+    /// couldn't locate the task's stack, in which case `peak` is also zero.
+    pub size: u32,
+}
+
+/// A span of a faulted task's memory, as returned by the `GetTaskDumpRegion`
+/// kipc and passed back to `ReadTaskDumpRegion` to copy it out.
+#[derive(
+    Copy, Clone, Debug, Default, Eq, PartialEq, Deserialize, Serialize,
+)]
+pub struct TaskDumpRegion {
+    /// Address of the start of the region.
+    pub base: u32,
+    /// Size of the region, in bytes.
+    pub size: u32,
+}
+
 /// Enumeration of syscall numbers.
 #[repr(u32)]
 pub enum Sysnum {
@@ -447,6 +572,7 @@ pub enum Sysnum {
     RefreshTaskId = 10,
     Post = 11,
     ReplyFault = 12,
+    SendWithTimeout = 13,
 }
 
 /// We're using an explicit `TryFrom` impl for `Sysnum` instead of
@@ -470,6 +596,7 @@ impl core::convert::TryFrom<u32> for Sysnum {
             10 => Ok(Self::RefreshTaskId),
             11 => Ok(Self::Post),
             12 => Ok(Self::ReplyFault),
+            13 => Ok(Self::SendWithTimeout),
             _ => Err(()),
         }
     }
@@ -483,6 +610,12 @@ pub enum Kipcnum {
     ReadImageId = 4,
     Reset = 5,
     ExitCurrentTask = 6,
+    ReadTaskStats = 7,
+    ReadStackUsage = 8,
+    ReadTaskRegisters = 9,
+    GetTaskDumpRegion = 10,
+    ReadTaskDumpRegion = 11,
+    ReadImageHeader = 12,
 }
 
 impl core::convert::TryFrom<u16> for Kipcnum {
@@ -496,6 +629,12 @@ impl core::convert::TryFrom<u16> for Kipcnum {
             4 => Ok(Self::ReadImageId),
             5 => Ok(Self::Reset),
             6 => Ok(Self::ExitCurrentTask),
+            7 => Ok(Self::ReadTaskStats),
+            8 => Ok(Self::ReadStackUsage),
+            9 => Ok(Self::ReadTaskRegisters),
+            10 => Ok(Self::GetTaskDumpRegion),
+            11 => Ok(Self::ReadTaskDumpRegion),
+            12 => Ok(Self::ReadImageHeader),
             _ => Err(()),
         }
     }
@@ -510,6 +649,17 @@ pub struct SAUEntry {
 
 pub const HEADER_MAGIC: u32 = 0x1535_6637;
 
+/// The layout of the image header described by `ImageHeader`. Version 1 ended
+/// at `epoch`; version 2 added the build metadata and the task manifest.
+pub const HEADER_VERSION: u32 = 2;
+
+/// Set in `ImageHeader::flags` if the image was built from a git tree with
+/// uncommitted changes.
+pub const HEADER_FLAG_DIRTY: u32 = 1 << 0;
+
+/// Length of the NUL-padded names in the image header and task manifest.
+pub const HEADER_NAME_LEN: usize = 32;
+
 #[repr(C)]
 #[derive(Default, AsBytes, FromBytes)]
 pub struct ImageHeader {
@@ -518,6 +668,204 @@ pub struct ImageHeader {
     pub sau_entries: [SAUEntry; 8],
     pub version: u32,
     pub epoch: u32,
+
+    // Everything below was added in version 2. Fields must only ever be added
+    // at the end, since stage0 reads the header of whichever image it boots.
+    pub header_version: u32,
+    /// `HEADER_FLAG_*` bits.
+    pub flags: u32,
+    /// The git commit the image was built from, or all zeroes if it was built
+    /// outside a git repository.
+    pub git_commit: [u8; 20],
+    /// The name of the app, NUL-padded.
+    pub app_name: [u8; HEADER_NAME_LEN],
+    /// The board the app was built for, NUL-padded.
+    pub board: [u8; HEADER_NAME_LEN],
+    /// The number of `TaskManifestEntry`s that immediately follow the header,
+    /// one per task, in task index order.
+    pub task_count: u32,
+}
+
+impl ImageHeader {
+    /// Returns the size of the header along with the task manifest that
+    /// follows it.
+    pub fn len_with_manifest(&self) -> usize {
+        core::mem::size_of::<Self>()
+            + self.task_count as usize
+                * core::mem::size_of::<TaskManifestEntry>()
+    }
+}
+
+/// Describes one task in the image, for working out exactly what's running.
+#[repr(C)]
+#[derive(Default, Copy, Clone, AsBytes, FromBytes)]
+pub struct TaskManifestEntry {
+    /// The task's name in the app, NUL-padded.
+    pub name: [u8; HEADER_NAME_LEN],
+    /// The version of the task's crate, NUL-padded.
+    pub crate_version: [u8; 16],
+    /// The SHA3-256 of the task's flash contents.
+    pub hash: [u8; 32],
+}
+
+pub const SIGNATURE_MAGIC: u32 = 0x4e47_4953;
+
+/// `ImageSignature::algorithm` for an ECDSA P-256 signature of the SHA-256
+/// digest of the image, as `r || s`.
+pub const SIGNATURE_ECDSA_P256_SHA256: u32 = 1;
+
+/// `ImageSignature::algorithm` for an Ed25519 signature of the image.
+pub const SIGNATURE_ED25519: u32 = 2;
+
+/// A detached signature over an image: the `image_len` bytes starting at the
+/// image's vector table, which take in the image header and every loadable
+/// segment, with any gaps between them filled with `0xff`. It's placed
+/// immediately after those bytes, at the next 4-byte boundary, so it doesn't
+/// depend on anything chip-specific.
+#[repr(C)]
+#[derive(Copy, Clone, AsBytes, FromBytes)]
+pub struct ImageSignature {
+    pub magic: u32,
+    pub algorithm: u32,
+    /// Number of bytes signed, which matches the header's `total_image_len`.
+    pub image_len: u32,
+    /// SHA3-256 of the signer's raw public key (an uncompressed SEC1 point
+    /// for P-256, or the 32-byte key for Ed25519), so a verifier holding
+    /// several keys knows which to check against.
+    pub key_id: [u8; 32],
+    pub signature: [u8; 64],
+}
+
+pub const BOOT_STATE_MAGIC: u32 = 0x5453_4f42;
+
+/// Number of times stage0 will boot an unconfirmed image before giving up
+/// on it and falling back to the other slot.
+pub const MAX_BOOT_ATTEMPTS: u32 = 3;
+
+/// `BootState::preferred` values.
+pub const BOOT_SLOT_A: u32 = 0;
+pub const BOOT_SLOT_B: u32 = 1;
+
+/// Persistent state stage0 uses to choose between the A and B images, kept
+/// in its own flash page.
+///
+/// An update makes the slot it wrote `preferred` but unconfirmed. stage0
+/// counts each boot of an unconfirmed image and, after `MAX_BOOT_ATTEMPTS`,
+/// prefers the other slot instead. Once the running image has confirmed
+/// itself, booting it raises `min_epoch` to its epoch, so nothing older can
+/// be booted again.
+#[repr(C)]
+#[derive(Copy, Clone, Debug, Eq, PartialEq, AsBytes, FromBytes)]
+pub struct BootState {
+    pub magic: u32,
+    /// Slot to boot if it holds a valid image.
+    pub preferred: u32,
+    /// Images with an epoch lower than this are never booted.
+    pub min_epoch: u32,
+    /// Boots of `preferred` since it was last made preferred.
+    pub boot_attempts: u32,
+    /// Non-zero once the image in `preferred` has confirmed it's good.
+    pub confirmed: u32,
+}
+
+impl BootState {
+    /// State for `slot` that has been written but not yet booted.
+    pub fn prefer(self, slot: u32) -> Self {
+        Self {
+            magic: BOOT_STATE_MAGIC,
+            preferred: slot,
+            boot_attempts: 0,
+            confirmed: 0,
+            ..self
+        }
+    }
+
+    /// State once the image in `slot` has confirmed it's good.
+    pub fn confirm(self, slot: u32) -> Self {
+        Self {
+            confirmed: 1,
+            ..self.prefer(slot)
+        }
+    }
+
+    /// Picks the slot to boot from the images that validated, returning it
+    /// along with the state to record for this boot. Returns `None` if
+    /// neither image may be booted.
+    pub fn select(
+        self,
+        a: Option<BootImage>,
+        b: Option<BootImage>,
+    ) -> Option<(u32, Self)> {
+        // Never go back to an epoch older than an image that has confirmed
+        // itself.
+        let allowed =
+            |img: Option<BootImage>| img.filter(|i| i.epoch >= self.min_epoch);
+        let (a, b) = (allowed(a), allowed(b));
+
+        let (preferred, other, other_slot) = match self.preferred {
+            BOOT_SLOT_A => (a, b, BOOT_SLOT_B),
+            BOOT_SLOT_B => (b, a, BOOT_SLOT_A),
+            // Nothing has been recorded yet.
+            _ => return newest(a, b).map(|slot| (slot, self)),
+        };
+        let preferred_slot = self.preferred;
+
+        let mut next = self;
+        let slot = match (preferred, other) {
+            (Some(p), _) if self.confirmed != 0 => {
+                next.min_epoch = next.min_epoch.max(p.epoch);
+                preferred_slot
+            }
+            // With nothing to fall back to, there's no point counting.
+            (Some(_), None) => preferred_slot,
+            (Some(_), Some(_)) if self.boot_attempts < MAX_BOOT_ATTEMPTS => {
+                next.boot_attempts += 1;
+                preferred_slot
+            }
+            // The preferred image has had its chances without confirming
+            // itself, so give the other one a go.
+            (Some(_), Some(_)) => {
+                next = self.prefer(other_slot);
+                next.boot_attempts = 1;
+                other_slot
+            }
+            (None, Some(_)) => other_slot,
+            (None, None) => return None,
+        };
+
+        Some((slot, next))
+    }
+}
+
+impl Default for BootState {
+    /// The state assumed before anything has been written: no preference,
+    /// which stage0 treats as "boot the newest image".
+    fn default() -> Self {
+        Self {
+            magic: BOOT_STATE_MAGIC,
+            preferred: !0,
+            min_epoch: 0,
+            boot_attempts: 0,
+            confirmed: 0,
+        }
+    }
+}
+
+/// What `BootState::select` needs to know about an image that validated.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+pub struct BootImage {
+    pub version: u32,
+    pub epoch: u32,
+}
+
+fn newest(a: Option<BootImage>, b: Option<BootImage>) -> Option<u32> {
+    match (a, b) {
+        (None, None) => None,
+        (Some(_), None) => Some(BOOT_SLOT_A),
+        (None, Some(_)) => Some(BOOT_SLOT_B),
+        (Some(a), Some(b)) if a.version > b.version => Some(BOOT_SLOT_A),
+        (Some(_), Some(_)) => Some(BOOT_SLOT_B),
+    }
 }
 
 // Corresponds to the ARM vector table, limited to what we need
@@ -528,3 +876,103 @@ pub struct ImageVectors {
     pub sp: u32,
     pub entry: u32,
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    const OLD: BootImage = BootImage {
+        version: 1,
+        epoch: 0,
+    };
+    const NEW: BootImage = BootImage {
+        version: 2,
+        epoch: 1,
+    };
+
+    #[test]
+    fn boots_newest_with_no_preference() {
+        let state = BootState::default();
+        assert_eq!(
+            state.select(Some(NEW), Some(OLD)),
+            Some((BOOT_SLOT_A, state))
+        );
+        assert_eq!(
+            state.select(Some(OLD), Some(NEW)),
+            Some((BOOT_SLOT_B, state))
+        );
+        assert_eq!(state.select(None, Some(OLD)), Some((BOOT_SLOT_B, state)));
+        assert_eq!(state.select(None, None), None);
+    }
+
+    #[test]
+    fn counts_unconfirmed_boots() {
+        let mut state = BootState::default().prefer(BOOT_SLOT_B);
+        for attempt in 1..=MAX_BOOT_ATTEMPTS {
+            let (slot, next) = state.select(Some(OLD), Some(NEW)).unwrap();
+            assert_eq!(slot, BOOT_SLOT_B);
+            assert_eq!(next.boot_attempts, attempt);
+            state = next;
+        }
+    }
+
+    #[test]
+    fn falls_back_once_trial_expires() {
+        let state = BootState {
+            boot_attempts: MAX_BOOT_ATTEMPTS,
+            ..BootState::default().prefer(BOOT_SLOT_B)
+        };
+        let (slot, next) = state.select(Some(OLD), Some(NEW)).unwrap();
+        assert_eq!(slot, BOOT_SLOT_A);
+        assert_eq!(next.preferred, BOOT_SLOT_A);
+        assert_eq!(next.boot_attempts, 1);
+        assert_eq!(next.confirmed, 0);
+    }
+
+    #[test]
+    fn keeps_trying_with_nothing_to_fall_back_to() {
+        let state = BootState {
+            boot_attempts: MAX_BOOT_ATTEMPTS,
+            ..BootState::default().prefer(BOOT_SLOT_B)
+        };
+        assert_eq!(state.select(None, Some(NEW)), Some((BOOT_SLOT_B, state)));
+    }
+
+    #[test]
+    fn falls_back_when_preferred_is_invalid() {
+        let state = BootState::default().prefer(BOOT_SLOT_B);
+        assert_eq!(state.select(Some(OLD), None), Some((BOOT_SLOT_A, state)));
+    }
+
+    #[test]
+    fn confirmed_boot_raises_epoch_floor() {
+        let state = BootState::default().confirm(BOOT_SLOT_B);
+        let (slot, next) = state.select(Some(OLD), Some(NEW)).unwrap();
+        assert_eq!(slot, BOOT_SLOT_B);
+        assert_eq!(next.min_epoch, NEW.epoch);
+        assert_eq!(next.boot_attempts, 0);
+
+        // Booting it again changes nothing, so there's nothing to write.
+        assert_eq!(next.select(Some(OLD), Some(NEW)), Some((slot, next)));
+    }
+
+    #[test]
+    fn refuses_rollback_below_epoch_floor() {
+        let state = BootState {
+            min_epoch: NEW.epoch,
+            ..BootState::default().confirm(BOOT_SLOT_B)
+        };
+        // Even with B gone, A's older epoch is never booted...
+        assert_eq!(state.select(Some(OLD), None), None);
+
+        // ...nor when an update has made it preferred.
+        let state = BootState {
+            min_epoch: NEW.epoch,
+            ..BootState::default().prefer(BOOT_SLOT_A)
+        };
+        assert_eq!(
+            state.select(Some(OLD), Some(NEW)),
+            Some((BOOT_SLOT_B, state))
+        );
+    }
+}
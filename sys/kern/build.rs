@@ -2,7 +2,7 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use std::collections::HashMap;
+use std::collections::{BTreeMap, HashMap};
 use std::fs::File;
 use std::io::Write;
 
@@ -31,6 +31,7 @@ struct Generated {
     k_regions: Vec<TokenStream>,
     irq_code: TokenStream,
     timer_code: TokenStream,
+    page_table_code: TokenStream,
 }
 
 #[derive(Clone, Debug, Eq, PartialEq, Ord, PartialOrd, Hash)]
@@ -129,10 +130,26 @@ fn make_task_table(
 
         let index = u16::try_from(i).expect("over 2**16 tasks??");
         let priority = task.priority;
-        let flags = if task.start_at_boot {
-            quote::quote! { TaskFlags::START_AT_BOOT }
-        } else {
+        let mut flags = vec![];
+        if task.start_at_boot {
+            flags.push(quote::quote! { TaskFlags::START_AT_BOOT });
+        }
+        if task.dump_access {
+            flags.push(quote::quote! { TaskFlags::DUMP_ACCESS });
+        }
+        let flags = if flags.is_empty() {
             quote::quote! { TaskFlags::empty() }
+        } else {
+            quote::quote! { #(#flags)|* }
+        };
+        let ipc_allowlist = match &task.ipc_allowlist {
+            Some(peers) => {
+                let peers = peers
+                    .iter()
+                    .map(|&p| u16::try_from(p).expect("over 2**16 tasks??"));
+                quote::quote! { Some(&[#(#peers),*]) }
+            }
+            None => quote::quote! { None },
         };
         task_descs.push(quote::quote! {
             TaskDesc {
@@ -142,6 +159,7 @@ fn make_task_table(
                 priority: #priority,
                 index: #index,
                 flags: #flags,
+                ipc_allowlist: #ipc_allowlist,
             }
         });
     }
@@ -180,7 +198,10 @@ fn generate_irq_maps(kconfig: &KernelConfig) -> Result<TokenStream> {
 
     let target = build_util::target();
     Ok(
-        if target.starts_with("thumbv6m") || target.starts_with("riscv") {
+        if target.starts_with("thumbv6m")
+            || target.starts_with("riscv")
+            || is_sim()
+        {
             // On ARMv6-M we have no hardware division, which the perfect hash table
             // relies on (to get efficient integer remainder). Fall back to a good
             // old sorted list with binary search instead.
@@ -287,10 +308,27 @@ fn generate_irq_maps(kconfig: &KernelConfig) -> Result<TokenStream> {
     )
 }
 
+/// Checks whether we're building for the simulator (`arch::sim`), which is
+/// used for any hosted target.
+fn is_sim() -> bool {
+    build_util::target_os() != "none"
+}
+
 fn process_config() -> Result<Generated> {
-    let kconfig: KernelConfig =
+    let kconfig: KernelConfig = if is_sim() {
+        // Simulated task tables are built at runtime by the tests, so there's
+        // no application to configure.
+        KernelConfig {
+            tasks: vec![],
+            shared_regions: BTreeMap::new(),
+            kernel_regions: BTreeMap::new(),
+            irqs: BTreeMap::new(),
+            timer: (0, 0),
+        }
+    } else {
         ron::de::from_str(&build_util::env_var("HUBRIS_KCONFIG")?)
-            .context("parsing kconfig from HUBRIS_KCONFIG")?;
+            .context("parsing kconfig from HUBRIS_KCONFIG")?
+    };
 
     // The kconfig data structure keeps things somewhat abstract to give us, the
     // kernel, more freedom about our internal implementation choices. However,
@@ -321,7 +359,15 @@ fn process_config() -> Result<Generated> {
 
     let irq_code = generate_irq_maps(&kconfig)?;
 
-    let timer_code = if build_util::target().starts_with("riscv") {
+    let page_table_code = if build_util::has_feature("s-mode") {
+        generate_page_tables(&kconfig)?
+    } else {
+        quote::quote! {}
+    };
+
+    let timer_code = if build_util::target().starts_with("riscv")
+        && !build_util::has_feature("s-mode")
+    {
         // TODO: This will eventually need to be changed so that the timer info
         //       doesn't have to be shoved into `chip.toml`.
         let mtime: usize = kconfig.timer.0.try_into().unwrap();
@@ -340,6 +386,70 @@ fn process_config() -> Result<Generated> {
         k_regions: k_region_descs,
         irq_code,
         timer_code,
+        page_table_code,
+    })
+}
+
+/// In Supervisor mode, RISC-V tasks are isolated by page tables rather than
+/// the PMP (see `arch::riscv::sv32`). This generates, for each task, a
+/// second-level table for every 4 MiB span that any task's regions touch.
+///
+/// These are built by const evaluation from the same region descriptors the
+/// task table uses, so they can't disagree with it.
+fn generate_page_tables(kconfig: &KernelConfig) -> Result<TokenStream> {
+    const PAGE_SIZE: u32 = 4096;
+    const SPAN_SHIFT: u32 = 22;
+
+    let task_regions = kconfig
+        .tasks
+        .iter()
+        .map(|task| {
+            let shared = task
+                .shared_regions
+                .iter()
+                .map(|name| &kconfig.shared_regions[name]);
+            task.owned_regions
+                .values()
+                .chain(shared)
+                .collect::<Vec<_>>()
+        })
+        .collect::<Vec<_>>();
+
+    let mut spans = std::collections::BTreeSet::new();
+    for region in task_regions.iter().flatten() {
+        if region.base % PAGE_SIZE != 0 || region.size % PAGE_SIZE != 0 {
+            bail!(
+                "region at {:#x} ({:#x} bytes) is not page aligned, \
+                 which Supervisor mode requires",
+                region.base,
+                region.size
+            );
+        }
+        let first = region.base >> SPAN_SHIFT;
+        let last = (region.base + (region.size - 1)) >> SPAN_SHIFT;
+        spans.extend((first..=last).map(|s| s as usize));
+    }
+
+    let span_count = spans.len();
+    let tables = task_regions.iter().map(|regions| {
+        let regions = regions.iter().map(|r| fmt_region(r)).collect::<Vec<_>>();
+        let leaves = spans.iter().map(|span| {
+            quote::quote! {
+                crate::arch::PageTable::leaf(#span, &[#(#regions),*])
+            }
+        });
+        quote::quote! { [#(#leaves),*] }
+    });
+
+    Ok(quote::quote! {
+        pub(crate) const HUBRIS_PAGE_TABLE_SPANS: [usize; #span_count] = [
+            #(#spans),*
+        ];
+
+        pub(crate) static HUBRIS_TASK_PAGE_TABLES:
+            [[crate::arch::PageTable; #span_count]; HUBRIS_TASK_COUNT] = [
+            #(#tables,)*
+        ];
     })
 }
 
@@ -443,9 +553,13 @@ fn generate_consts() -> Result<()> {
 }
 
 fn generate_statics(gen: &Generated) -> Result<()> {
-    let image_id: u64 = build_util::env_var("HUBRIS_IMAGE_ID")?
-        .parse()
-        .context("parsing HUBRIS_IMAGE_ID")?;
+    let image_id: u64 = if is_sim() {
+        0
+    } else {
+        build_util::env_var("HUBRIS_IMAGE_ID")?
+            .parse()
+            .context("parsing HUBRIS_IMAGE_ID")?
+    };
 
     let out = build_util::out_dir();
     let kconfig_path = out.join("kconfig.rs");
@@ -462,7 +576,7 @@ fn generate_statics(gen: &Generated) -> Result<()> {
         file,
         "{}",
         quote::quote! {
-            const HUBRIS_TASK_COUNT: usize = #task_count;
+            pub(crate) const HUBRIS_TASK_COUNT: usize = #task_count;
             #[no_mangle]
             pub static HUBRIS_IMAGE_ID: u64 = #image_id;
 
@@ -490,17 +604,21 @@ fn generate_statics(gen: &Generated) -> Result<()> {
     /////////////////////////////////////////////////////////
     // Task Region descriptors
 
-    let regions = &gen.task_regions;
-    let region_count = regions.len();
-    writeln!(
-        file,
-        "{}",
-        quote::quote! {
-            static HUBRIS_REGION_DESCS: [RegionDesc; #region_count] = [
-                #(#regions,)*
-            ];
-        },
-    )?;
+    // Only the task descriptors refer to these, and the simulator has none:
+    // its tests build their own task tables.
+    if !is_sim() {
+        let regions = &gen.task_regions;
+        let region_count = regions.len();
+        writeln!(
+            file,
+            "{}",
+            quote::quote! {
+                static HUBRIS_REGION_DESCS: [RegionDesc; #region_count] = [
+                    #(#regions,)*
+                ];
+            },
+        )?;
+    }
 
     /////////////////////////////////////////////////////////
     // Kernel Region descriptors
@@ -525,6 +643,8 @@ fn generate_statics(gen: &Generated) -> Result<()> {
 
     writeln!(file, "{}", gen.timer_code)?;
 
+    writeln!(file, "{}", gen.page_table_code)?;
+
     drop(file);
     call_rustfmt::rustfmt(kconfig_path)?;
 
@@ -19,16 +19,29 @@ cfg_if::cfg_if! {
     // Note: cfg_if! is slightly touchy about ordering and expression
     // complexity; this chain seems to be the best compromise.
 
-    if #[cfg(not(target_pointer_width = "32"))] {
-        compile_error!("non-32-bit targets not supported (even for simulation)");
+    if #[cfg(not(target_os = "none"))] {
+        // Hosted builds get the simulator, for testing. This is the one
+        // configuration allowed to have 64-bit pointers; see the `sim` module
+        // for how it keeps task addresses in 32 bits.
+        #[macro_use]
+        pub mod sim;
+        pub use sim::*;
+    } else if #[cfg(target_arch = "riscv64")] {
+        // RV64 has 64-bit registers, but the kernel ABI is still 32 bits
+        // wide; see the `riscv` module for what that requires of the image.
+        #[macro_use]
+        pub mod riscv;
+        pub use riscv::*;
+    } else if #[cfg(not(target_pointer_width = "32"))] {
+        compile_error!("non-32-bit targets not supported");
     } else if #[cfg(target_arch = "arm")] {
         #[macro_use]
         pub mod arm_m;
         pub use arm_m::*;
     } else if #[cfg(target_arch = "riscv32")] {
-    #[macro_use]
-    pub mod riscv32;
-    pub use riscv32::*;
+        #[macro_use]
+        pub mod riscv;
+        pub use riscv::*;
     } else {
         compile_error!("support for this architecture not implemented");
     }
@@ -73,7 +73,7 @@
 use core::arch;
 use core::sync::atomic::{AtomicBool, AtomicPtr, AtomicU32, Ordering};
 
-use zerocopy::FromBytes;
+use zerocopy::{AsBytes, FromBytes};
 
 use crate::atomic::AtomicExt;
 use crate::descs::RegionAttributes;
@@ -107,7 +107,7 @@ static CLOCK_FREQ_KHZ: AtomicU32 = AtomicU32::new(0);
 
 /// ARMvx-M volatile registers that must be saved across context switches.
 #[repr(C)]
-#[derive(Debug, Default)]
+#[derive(Debug, Default, AsBytes)]
 pub struct SavedState {
     // NOTE: the following fields must be kept contiguous!
     r4: u32,
@@ -186,6 +186,14 @@ impl task::ArchState for SavedState {
     fn arg6(&self) -> u32 {
         self.r10
     }
+    /// r11 holds the syscall number, so SEND_WITH_TIMEOUT passes its extra
+    /// argument in r0, which the hardware stacked on the way in.
+    fn arg7(&self) -> u32 {
+        // Safety: the hardware pushed this frame at `psp` on entry to the
+        // syscall, through the task's own MPU regions (or the task would have
+        // faulted instead), and the task hasn't run since.
+        unsafe { (*(self.psp as *const BaseExceptionFrame)).r0 }
+    }
 
     fn syscall_descriptor(&self) -> u32 {
         self.r11
@@ -299,7 +307,7 @@ pub fn reinitialize(task: &mut task::Task) {
 
         let zap = task.try_write(&mut uslice).unwrap_lite();
         for word in zap.iter_mut() {
-            *word = 0xbaddcafe;
+            *word = task::STACK_PAINT;
         }
     }
 
@@ -912,6 +920,41 @@ pub fn now() -> Timestamp {
     ])
 }
 
+/// Reads the free-running counter used for per-task run time accounting.
+///
+/// On ARM-M this is derived from SysTick: the number of whole kernel ticks
+/// times the reload value, plus the progress through the current tick. The
+/// result is in SysTick input clock cycles, which is finer-grained than any
+/// context switch we'd care to measure, and works on ARMv6-M parts that lack
+/// the DWT cycle counter.
+pub fn run_time_counter() -> u64 {
+    // Safety: we're only reading SysTick and SCB registers here, which has no
+    // side effects.
+    let (syst, scb) = unsafe {
+        (
+            &*cortex_m::peripheral::SYST::PTR,
+            &*cortex_m::peripheral::SCB::PTR,
+        )
+    };
+    const PENDSTSET: u32 = 1 << 26;
+
+    let reload = syst.rvr.read();
+    // The SysTick interrupt can't preempt the kernel, so the counter may
+    // have wrapped without `TICKS` being advanced yet. Detect that via the
+    // pending bit, and make sure it didn't change while we were reading the
+    // counter.
+    let (pending, current) = loop {
+        let pending = scb.icsr.read() & PENDSTSET != 0;
+        let current = syst.cvr.read();
+        if pending == (scb.icsr.read() & PENDSTSET != 0) {
+            break (pending, current);
+        }
+    };
+    let ticks = u64::from(now()) + u64::from(pending);
+    // The counter counts down from `reload` to zero.
+    ticks * (u64::from(reload) + 1) + u64::from(reload - current)
+}
+
 /// Kernel global for tracking the current timestamp, measured in ticks.
 ///
 /// This is a pair of `AtomicU32` because (1) we want the interior mutability of
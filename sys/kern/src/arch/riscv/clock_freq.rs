@@ -0,0 +1,76 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::time::Timestamp;
+
+/// To allow our clock frequency to be easily determined from a debugger, we
+/// store it in memory.
+#[no_mangle]
+pub static mut CLOCK_FREQ_KHZ: u32 = 0;
+
+// Because debuggers need to know the clock frequency to set the SWO clock
+// scaler that enables ITM, and because ITM is particularly useful when
+// debugging boot failures, this should be set as early in boot as it can
+// be.
+pub fn set_clock_freq(tick_divisor: u32) {
+    // TODO switch me to an atomic. Note that this may break Humility.
+    // SAFETY:
+    // In a single-threaded, single-process context (which the kernel is in),
+    // access to global mutables are safe as data races are impossible.
+    unsafe {
+        CLOCK_FREQ_KHZ = tick_divisor;
+    }
+}
+
+#[used]
+pub static mut TICKS: u64 = 0;
+
+/// Reads the tick counter.
+pub fn now() -> Timestamp {
+    Timestamp::from(unsafe { TICKS })
+}
+
+/// Reads the free-running counter used for per-task run time accounting.
+///
+/// On RISC-V this is `mtime` itself, which ticks at a fixed (platform-defined)
+/// rate regardless of the kernel tick.
+#[cfg(target_arch = "riscv64")]
+pub fn run_time_counter() -> u64 {
+    // Safety: MTIME is the address of the memory-mapped `mtime` register,
+    // which is always safe to read.
+    unsafe { core::ptr::read_volatile(crate::startup::MTIME as *const u64) }
+}
+
+/// Reads the free-running counter used for per-task run time accounting.
+///
+/// As above, but RV32 needs two loads to read `mtime`.
+#[cfg(all(target_arch = "riscv32", not(feature = "s-mode")))]
+pub fn run_time_counter() -> u64 {
+    let mtime = crate::startup::MTIME as *const u32;
+    // On RV32 we can't read the 64-bit `mtime` atomically. Read the high half
+    // on either side of the low half, and retry if the low half carried into
+    // it in between.
+    loop {
+        // Safety: MTIME is the address of the memory-mapped `mtime` register,
+        // which is always safe to read.
+        let (hi, lo, hi2) = unsafe {
+            (
+                core::ptr::read_volatile(mtime.add(1)),
+                core::ptr::read_volatile(mtime),
+                core::ptr::read_volatile(mtime.add(1)),
+            )
+        };
+        if hi == hi2 {
+            return u64::from(hi) << 32 | u64::from(lo);
+        }
+    }
+}
+
+/// Reads the free-running counter used for per-task run time accounting.
+///
+/// In Supervisor mode `mtime` is out of reach, but the `time` CSR shadows it.
+#[cfg(feature = "s-mode")]
+pub fn run_time_counter() -> u64 {
+    riscv::register::time::read64()
+}
@@ -0,0 +1,131 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Architecture support for RISC-V.
+//!
+//! The kernel should support any riscv32imc, riscv32imac or riscv64imac
+//! target. By default the kernel runs in Machine mode with tasks running in
+//! User mode, and isolates tasks using the PMP.
+//!
+//! With the `s-mode` feature, the kernel instead runs in Supervisor mode
+//! underneath SBI firmware (such as OpenSBI) that owns Machine mode. Traps
+//! arrive through `stvec`, the timer is programmed through the SBI TIME
+//! extension, and reset goes through SBI SRST. Tasks are isolated with Sv32
+//! page tables, which the kernel's build script generates from the task region
+//! table; every mapping is an identity mapping, so task addresses are the same
+//! as in Machine mode. This is only supported on RV32.
+//!
+//! On RV64, registers are saved and restored at their full width, but the
+//! syscall ABI is unchanged: arguments are truncated to 32 bits, and addresses
+//! in region and lease descriptors are 32 bits. This means the kernel and all
+//! tasks must be linked below 4 GiB, which the build system checks when it
+//! lays out memory. Results are sign-extended into 64-bit registers, as the
+//! calling convention requires of 32-bit values.
+//!
+//! Interrupts are supported through the PLIC, but due to the nature of their
+//! implementation here it's not possible for the kernel to support core
+//! interrupts on the lines reserved for custom extensions. To fix this,
+//! the external interrupt controller will need to be treated like an external
+//! device, and have a driver task.
+extern crate riscv_rt;
+
+#[cfg(all(feature = "s-mode", not(target_arch = "riscv32")))]
+compile_error!("the s-mode feature requires Sv32, and so RV32");
+
+#[allow(unused)]
+macro_rules! uassert {
+    ($cond : expr) => {
+        if !$cond {
+            panic!("Assertion failed!");
+        }
+    };
+}
+
+// Register-width load and store instructions, so that the assembly saving
+// and restoring task state can be shared between RV32 and RV64. Each expands
+// to a single instruction moving `$reg` to or from slot `$slot` of the array
+// of registers at `$base`.
+cfg_if::cfg_if! {
+    if #[cfg(target_arch = "riscv64")] {
+        macro_rules! load_reg {
+            ($reg:literal, $slot:literal, $base:literal) => {
+                concat!("ld ", $reg, ", ", $slot, "*8(", $base, ")")
+            };
+        }
+        macro_rules! store_reg {
+            ($reg:literal, $slot:literal, $base:literal) => {
+                concat!("sd ", $reg, ", ", $slot, "*8(", $base, ")")
+            };
+        }
+    } else {
+        macro_rules! load_reg {
+            ($reg:literal, $slot:literal, $base:literal) => {
+                concat!("lw ", $reg, ", ", $slot, "*4(", $base, ")")
+            };
+        }
+        macro_rules! store_reg {
+            ($reg:literal, $slot:literal, $base:literal) => {
+                concat!("sw ", $reg, ", ", $slot, "*4(", $base, ")")
+            };
+        }
+    }
+}
+
+// Names the CSR or trap-return instruction for the privilege mode the kernel
+// runs in: `priv_name!("epc")` is `sepc` in Supervisor mode and `mepc` in
+// Machine mode. Like the macros above, this is for use in assembly.
+cfg_if::cfg_if! {
+    if #[cfg(feature = "s-mode")] {
+        macro_rules! priv_name {
+            ($name:literal) => {
+                concat!("s", $name)
+            };
+        }
+    } else {
+        macro_rules! priv_name {
+            ($name:literal) => {
+                concat!("m", $name)
+            };
+        }
+    }
+}
+
+cfg_if::cfg_if! {
+    if #[cfg(feature = "s-mode")] {
+        mod sbi;
+
+        mod sv32;
+        pub use sv32::*;
+
+        mod stimer;
+        pub use stimer::*;
+    } else {
+        mod pmp;
+        pub use pmp::*;
+
+        mod mtimer;
+        pub use mtimer::*;
+    }
+}
+
+mod trap;
+pub use trap::*;
+
+mod saved_state;
+pub use saved_state::*;
+
+mod task;
+pub use task::*;
+
+mod clock_freq;
+pub use clock_freq::*;
+
+mod atomics;
+pub use atomics::*;
+
+mod power;
+pub use power::*;
+
+// Constants that may change depending on configuration
+include!(concat!(env!("OUT_DIR"), "/consts.rs"));
@@ -3,6 +3,7 @@
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
 use crate::arch::CLOCK_FREQ_KHZ;
+use riscv::register;
 
 // Timer handling.
 //
@@ -14,6 +15,22 @@ use crate::arch::CLOCK_FREQ_KHZ;
 // RISC-V Privileged Architecture Manual
 // 3.2.1 Machine Timer Registers (mtime and mtimecmp)
 //
+
+/// Starts the kernel tick, counting from the current value of `mtime`.
+pub fn start_timer() {
+    unsafe {
+        // make MTIMECMP start with MTIME
+        let mtime = core::ptr::read_volatile(crate::startup::MTIME as *mut u64);
+        core::ptr::write_volatile(crate::startup::MTIMECMP as *mut u64, mtime);
+
+        // increment mtimecmp for appropriate timer interrupts
+        reset_timer();
+
+        // Machine timer interrupt enable
+        register::mie::set_mtimer();
+    }
+}
+
 pub fn reset_timer() {
     //
     // Increase mtimecmp for the next interrupt
@@ -31,6 +31,10 @@ pub fn apply_memory_protection(task: &task::Task) {
                 PmpCfg::new(Mode::TOR, pmp_perm, false)
             };
 
+        // Each region takes a pair of TOR entries. `set_cfg_entry` takes
+        // care of the difference in pmpcfg layout between RV32 (four entries
+        // per register) and RV64 (eight per even-numbered register), and
+        // since regions are below 4 GiB the addresses fit either way.
         unsafe {
             // Configure the base address entry
             register::set_cfg_entry(i * 2, null_cfg);
@@ -0,0 +1,14 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+#[cfg(feature = "s-mode")]
+pub fn reset() -> ! {
+    crate::arch::sbi::system_reset();
+    panic!("SBI firmware refused to reset");
+}
+
+#[cfg(not(feature = "s-mode"))]
+pub fn reset() -> ! {
+    unimplemented!();
+}
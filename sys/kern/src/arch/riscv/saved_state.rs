@@ -0,0 +1,133 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::task;
+use zerocopy::{AsBytes, FromBytes};
+
+/// RISC-V volatile registers that must be saved across context switches.
+///
+/// Each field is one register wide, so that the trap handler can save them
+/// all at the native width.
+#[repr(C)]
+#[derive(Clone, Debug, Default, AsBytes, FromBytes)]
+pub struct SavedState {
+    // NOTE: the following fields must be kept contiguous!
+    ra: usize,
+    sp: usize,
+    gp: usize,
+    tp: usize,
+    t0: usize,
+    t1: usize,
+    t2: usize,
+    s0: usize,
+    s1: usize,
+    a0: usize,
+    a1: usize,
+    a2: usize,
+    a3: usize,
+    a4: usize,
+    a5: usize,
+    a6: usize,
+    a7: usize,
+    s2: usize,
+    s3: usize,
+    s4: usize,
+    s5: usize,
+    s6: usize,
+    s7: usize,
+    s8: usize,
+    s9: usize,
+    s10: usize,
+    s11: usize,
+    t3: usize,
+    t4: usize,
+    t5: usize,
+    t6: usize,
+    // Additional save value for task program counter
+    pc: usize,
+    // NOTE: the above fields must be kept contiguous!
+}
+
+impl SavedState {
+    pub fn sp(&self) -> u32 {
+        self.sp as u32
+    }
+    pub fn pc(&self) -> u32 {
+        self.pc as u32
+    }
+    pub fn set_sp(&mut self, val: u32) {
+        self.sp = val as usize;
+    }
+    pub fn set_pc(&mut self, val: u32) {
+        self.pc = val as usize;
+    }
+}
+
+/// Widens a syscall result to fill a register. The RISC-V calling convention
+/// keeps 32-bit values sign-extended in 64-bit registers, signed or not, and
+/// the userlib stubs pass results straight through to Rust code that relies
+/// on this. On RV32 this is a no-op.
+fn widen(x: u32) -> usize {
+    x as i32 as isize as usize
+}
+
+/// Map the volatile registers to (architecture-independent) syscall argument
+/// and return slots.
+impl task::ArchState for SavedState {
+    fn stack_pointer(&self) -> u32 {
+        self.sp as u32
+    }
+
+    /// Reads syscall argument register 0.
+    fn arg0(&self) -> u32 {
+        self.a0 as u32
+    }
+    fn arg1(&self) -> u32 {
+        self.a1 as u32
+    }
+    fn arg2(&self) -> u32 {
+        self.a2 as u32
+    }
+    fn arg3(&self) -> u32 {
+        self.a3 as u32
+    }
+    fn arg4(&self) -> u32 {
+        self.a4 as u32
+    }
+    fn arg5(&self) -> u32 {
+        self.a5 as u32
+    }
+    fn arg6(&self) -> u32 {
+        self.a6 as u32
+    }
+    /// a7 holds the syscall number, so SEND_WITH_TIMEOUT passes its extra
+    /// argument in t0.
+    fn arg7(&self) -> u32 {
+        self.t0 as u32
+    }
+
+    fn syscall_descriptor(&self) -> u32 {
+        self.a7 as u32
+    }
+
+    /// Writes syscall return argument 0.
+    fn ret0(&mut self, x: u32) {
+        self.a0 = widen(x)
+    }
+    fn ret1(&mut self, x: u32) {
+        self.a1 = widen(x)
+    }
+    fn ret2(&mut self, x: u32) {
+        self.a2 = widen(x)
+    }
+    fn ret3(&mut self, x: u32) {
+        self.a3 = widen(x)
+    }
+    fn ret4(&mut self, x: u32) {
+        self.a4 = widen(x)
+    }
+    fn ret5(&mut self, x: u32) {
+        self.a5 = widen(x)
+    }
+}
@@ -0,0 +1,62 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Calls into the SBI firmware that runs in Machine mode underneath us.
+//!
+//! RISC-V Supervisor Binary Interface Specification, v1.0
+//! 3. Binary Encoding
+
+use core::arch::asm;
+
+/// Timer extension, "TIME".
+const TIME_EID: usize = 0x5449_4D45;
+const TIME_SET_TIMER: usize = 0;
+
+/// System reset extension, "SRST".
+const SRST_EID: usize = 0x5352_5354;
+const SRST_SYSTEM_RESET: usize = 0;
+const SRST_TYPE_COLD_REBOOT: usize = 1;
+const SRST_REASON_NONE: usize = 0;
+
+/// Makes an SBI call with up to two arguments, returning the SBI error code
+/// (zero for success).
+fn sbi_call(eid: usize, fid: usize, arg0: usize, arg1: usize) -> isize {
+    let error;
+    // Safety: an SBI call only affects state owned by the firmware, and the
+    // extensions used here don't touch our memory.
+    unsafe {
+        asm!(
+            "ecall",
+            inlateout("a0") arg0 => error,
+            inlateout("a1") arg1 => _,
+            in("a6") fid,
+            in("a7") eid,
+        );
+    }
+    error
+}
+
+/// Programs the next timer event for `stime_value`, in units of the `time`
+/// CSR. This also clears the pending Supervisor timer interrupt.
+pub fn set_timer(stime_value: u64) {
+    // On RV32 the 64-bit deadline is split across a0 (low) and a1 (high).
+    let err = sbi_call(
+        TIME_EID,
+        TIME_SET_TIMER,
+        stime_value as usize,
+        (stime_value >> 32) as usize,
+    );
+    uassert!(err == 0);
+}
+
+/// Asks the firmware to reboot the system. This only returns if the firmware
+/// refused.
+pub fn system_reset() {
+    sbi_call(
+        SRST_EID,
+        SRST_SYSTEM_RESET,
+        SRST_TYPE_COLD_REBOOT,
+        SRST_REASON_NONE,
+    );
+}
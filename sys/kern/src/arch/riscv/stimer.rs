@@ -0,0 +1,44 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+use crate::arch::sbi;
+use crate::arch::CLOCK_FREQ_KHZ;
+use riscv::register;
+
+// Timer handling in Supervisor mode.
+//
+// We can't reach mtimecmp from here, so the deadline is kept in memory and
+// handed to the firmware through the SBI TIME extension, which also takes
+// care of the pending interrupt.
+//
+// RISC-V Supervisor Binary Interface Specification, v1.0
+// 6. Timer Extension
+//
+static mut DEADLINE: u64 = 0;
+
+/// Starts the kernel tick, counting from the current value of `time`.
+pub fn start_timer() {
+    // Safety: this runs once, before the first task starts and so before any
+    // timer interrupt can arrive.
+    unsafe {
+        DEADLINE = register::time::read64();
+        reset_timer();
+
+        // Supervisor timer interrupt enable
+        register::sie::set_stimer();
+    }
+}
+
+pub fn reset_timer() {
+    //
+    // Advance the deadline for the next interrupt. Setting it also clears the
+    // pending timer interrupt.
+    //
+    // Safety: DEADLINE is only touched from the timer interrupt and from
+    // `start_timer`, which are serialized.
+    unsafe {
+        DEADLINE += CLOCK_FREQ_KHZ as u64;
+        sbi::set_timer(DEADLINE);
+    }
+}
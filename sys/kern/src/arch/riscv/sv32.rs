@@ -0,0 +1,140 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Task isolation with Sv32 page tables, for use in Supervisor mode where the
+//! PMP belongs to the firmware.
+//!
+//! Everything is identity mapped. The root table maps all of memory with 4 MiB
+//! megapages that only the kernel can use. Each 4 MiB span that holds any task
+//! region is instead pointed at a second-level table belonging to the running
+//! task, in which the task's own pages are User pages with the permissions of
+//! their region, and every other page is left to the kernel.
+//!
+//! The second-level tables are generated at build time from the task region
+//! table (see `build.rs`), so a context switch only has to rewrite the
+//! root entries for those spans.
+//!
+//! RISC-V Privileged Architecture Manual
+//! 4.3 Sv32: Page-Based 32-bit Virtual-Memory Systems
+
+use crate::descs::{RegionAttributes, RegionDesc};
+use crate::task;
+use core::arch::asm;
+
+const PTE_V: u32 = 1 << 0;
+const PTE_R: u32 = 1 << 1;
+const PTE_W: u32 = 1 << 2;
+const PTE_X: u32 = 1 << 3;
+const PTE_U: u32 = 1 << 4;
+const PTE_A: u32 = 1 << 6;
+const PTE_D: u32 = 1 << 7;
+
+/// Flags for memory only the kernel may use. Accessed and Dirty are set up
+/// front, both here and for task pages, so that the hardware never needs to
+/// update tables that live in flash.
+const KERNEL_FLAGS: u32 = PTE_V | PTE_R | PTE_W | PTE_X | PTE_A | PTE_D;
+
+const PAGE_SHIFT: usize = 12;
+
+/// log2 of the memory covered by one second-level table (4 MiB).
+const SPAN_SHIFT: usize = 22;
+
+const ENTRIES: usize = 1024;
+
+const SATP_MODE_SV32: usize = 1 << 31;
+
+/// A page table, at either level.
+#[repr(C, align(4096))]
+pub struct PageTable([u32; ENTRIES]);
+
+impl PageTable {
+    /// Builds the root table, which identity maps all of memory for the
+    /// kernel only.
+    const fn root() -> Self {
+        let mut entries = [0; ENTRIES];
+        let mut i = 0;
+        while i < ENTRIES {
+            // The physical page number of megapage `i` is `i << 10`.
+            entries[i] = (i as u32) << 20 | KERNEL_FLAGS;
+            i += 1;
+        }
+        Self(entries)
+    }
+
+    /// Builds the second-level table for span `span` of a task with the
+    /// given regions. Regions must be page aligned, which the build script
+    /// checks.
+    pub const fn leaf(span: usize, regions: &[RegionDesc]) -> Self {
+        let mut entries = [0; ENTRIES];
+        let mut i = 0;
+        while i < ENTRIES {
+            let addr = (span << SPAN_SHIFT | i << PAGE_SHIFT) as u32;
+            let mut flags = KERNEL_FLAGS;
+            let mut r = 0;
+            while r < regions.len() {
+                let region = &regions[r];
+                if addr >= region.base && addr - region.base < region.size {
+                    flags = user_flags(region.attributes);
+                }
+                r += 1;
+            }
+            entries[i] = (addr >> PAGE_SHIFT) << 10 | flags;
+            i += 1;
+        }
+        Self(entries)
+    }
+}
+
+/// Translates region attributes into the flags for a task page.
+const fn user_flags(attributes: RegionAttributes) -> u32 {
+    let bits = attributes.bits();
+    if bits & 0b111 == 0 {
+        // The task can't use this region at all, so leave it to the kernel.
+        return KERNEL_FLAGS;
+    }
+    let mut flags = PTE_V | PTE_U | PTE_A | PTE_D;
+    if bits & RegionAttributes::READ.bits() != 0 {
+        flags |= PTE_R;
+    }
+    if bits & RegionAttributes::WRITE.bits() != 0 {
+        // Write-only pages are reserved encodings in Sv32, as in the PMP.
+        if flags & PTE_R == 0 {
+            panic!("write-only regions can't be mapped");
+        }
+        flags |= PTE_W;
+    }
+    if bits & RegionAttributes::EXECUTE.bits() != 0 {
+        flags |= PTE_X;
+    }
+    flags
+}
+
+/// The root table. Entries for the spans in `HUBRIS_PAGE_TABLE_SPANS` are
+/// rewritten on each context switch; the rest are never changed.
+static mut ROOT_PAGE_TABLE: PageTable = PageTable::root();
+
+pub fn apply_memory_protection(task: &task::Task) {
+    let index = usize::from(task.descriptor().index);
+    let tables = &crate::startup::HUBRIS_TASK_PAGE_TABLES[index];
+
+    // Safety: the root table is only touched here, and the kernel runs with
+    // interrupts masked, so this can't be reentered. Identity mapping means
+    // each table's address is also its physical address.
+    unsafe {
+        for (&span, table) in
+            crate::startup::HUBRIS_PAGE_TABLE_SPANS.iter().zip(tables)
+        {
+            let ppn = table as *const PageTable as u32 >> PAGE_SHIFT;
+            ROOT_PAGE_TABLE.0[span] = ppn << 10 | PTE_V;
+        }
+
+        let root = core::ptr::addr_of!(ROOT_PAGE_TABLE) as usize;
+        asm!(
+            "csrw satp, {satp}",
+            // Flush any translations cached from the previous task's tables.
+            "sfence.vma",
+            satp = in(reg) SATP_MODE_SV32 | root >> PAGE_SHIFT,
+        );
+    }
+}
@@ -2,7 +2,7 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use crate::arch::reset_timer;
+use crate::arch::start_timer;
 use crate::arch::SavedState;
 use crate::arch::CLOCK_FREQ_KHZ;
 
@@ -13,10 +13,19 @@ use unwrap_lite::UnwrapLite;
 use core::arch::asm;
 use core::ptr::NonNull;
 use riscv::register;
-use riscv::register::mstatus::MPP;
+
+cfg_if::cfg_if! {
+    if #[cfg(feature = "s-mode")] {
+        use riscv::register::sepc as epc;
+        use riscv::register::sstatus::SPP;
+    } else {
+        use riscv::register::mepc as epc;
+        use riscv::register::mstatus::MPP;
+    }
+}
 
 /// On RISC-V we use a global to record the current task pointer.  It may be
-/// possible to use the mscratch register instead.
+/// possible to use the mscratch (or sscratch) register instead.
 #[no_mangle]
 pub static mut CURRENT_TASK_PTR: Option<NonNull<task::Task>> = None;
 
@@ -41,33 +50,34 @@ pub fn start_first_task(tick_divisor: u32, task: &mut task::Task) -> ! {
         // Configure the timer
         //
         CLOCK_FREQ_KHZ = tick_divisor;
-
-        // make MTIMECMP start with MTIME
-        let mtime = core::ptr::read_volatile(crate::startup::MTIME as *mut u64);
-        core::ptr::write_volatile(crate::startup::MTIMECMP as *mut u64, mtime);
-
-        // increment mtimecmp for appropriate timer interrupts
-        reset_timer();
-
-        // Machine timer interrupt enable
-        register::mie::set_mtimer();
-
-        // Configure MPP to switch us to User mode on exit from Machine
-        // mode (when we call "mret" below).
-        register::mstatus::set_mpp(MPP::User);
+        start_timer();
+
+        cfg_if::cfg_if! {
+            if #[cfg(feature = "s-mode")] {
+                // Configure SPP to switch us to User mode on exit from
+                // Supervisor mode (when we call "sret" below), and permit the
+                // kernel to access the tasks' User pages.
+                register::sstatus::set_spp(SPP::User);
+                register::sstatus::set_sum();
+            } else {
+                // Configure MPP to switch us to User mode on exit from Machine
+                // mode (when we call "mret" below).
+                register::mstatus::set_mpp(MPP::User);
+            }
+        }
     }
 
     // Write the initial task program counter.
-    register::mepc::write(task.save().pc() as *const usize as usize);
+    epc::write(task.save().pc() as usize);
 
     // Load first task pointer, set its initial stack pointer, and exit out
-    // of machine mode, launching the task.
+    // of the kernel's mode, launching the task.
     unsafe {
         crate::task::activate_next_task(task);
-        asm!("
-            lw sp, ({sp})
-            mret",
-            sp = in(reg) &task.save().sp(),
+        asm!(
+            "mv sp, {sp}",
+            priv_name!("ret"),
+            sp = in(reg) task.save().sp() as usize,
             options(noreturn)
         );
     }
@@ -95,7 +105,7 @@ pub fn reinitialize(task: &mut task::Task) {
 
         let zap = task.try_write(&mut uslice).unwrap_lite();
         for word in zap.iter_mut() {
-            *word = 0xbaddcafe;
+            *word = task::STACK_PAINT;
         }
     }
     // Set the initial program counter
@@ -12,14 +12,25 @@ use core::arch::asm;
 #[cfg(feature = "custom-interrupts")]
 use core::convert::TryInto;
 
-use riscv::register;
-use riscv::register::mcause::{Exception, Interrupt, Trap};
+cfg_if::cfg_if! {
+    if #[cfg(feature = "s-mode")] {
+        use riscv::register::scause::{self as cause, Exception, Interrupt, Trap};
+        use riscv::register::{sepc as epc, sie as ie, stval as tval};
+    } else {
+        use riscv::register::mcause::{self as cause, Exception, Interrupt, Trap};
+        use riscv::register::{mepc as epc, mie as ie, mtval as tval};
+    }
+}
 
 cfg_if::cfg_if! {
     if #[cfg(feature = "vectored-interrupts")] {
-        use riscv::register::mtvec::{self, TrapMode};
+        #[cfg(feature = "s-mode")]
+        use riscv::register::stvec::{self as xtvec, TrapMode};
+        #[cfg(not(feature = "s-mode"))]
+        use riscv::register::mtvec::{self as xtvec, TrapMode};
 
-        // Setup interrupt vector `mtvec` with vectored mode to the trap table.
+        // Setup interrupt vector `mtvec` (or `stvec`) with vectored mode to
+        // the trap table.
         #[export_name = "_setup_interrupts"]
         extern "C" fn _setup_interrupts() {
             // SAFETY:
@@ -27,7 +38,7 @@ cfg_if::cfg_if! {
             // address could become corrupt and traps will not jump to the
             // expected address. As long as the linker works correctly, this
             // write is safe.
-            unsafe { mtvec::write(_trap_table as usize, TrapMode::Vectored); };
+            unsafe { xtvec::write(_trap_table as usize, TrapMode::Vectored); };
         }
 
         // Create a trap table to vector interrupts to the correct handler.
@@ -82,103 +93,100 @@ cfg_if::cfg_if! {
 unsafe extern "C" fn _start_trap() {
     unsafe {
         asm!(
-            "
-        #
-        # Store full task status on entry, setting up a0 to point at our
-        # current task so that it's passed into our exception handler.
-        #
-        csrw mscratch, a0
-        la a0, CURRENT_TASK_PTR
-        lw a0, (a0)
-        sw ra,   0*4(a0)
-        sw sp,   1*4(a0)
-        sw gp,   2*4(a0)
-        sw tp,   3*4(a0)
-        sw t0,   4*4(a0)
-        sw t1,   5*4(a0)
-        sw t2,   6*4(a0)
-        sw s0,   7*4(a0)
-        sw s1,   8*4(a0)
-        #sw a0,  9*4(a0)
-        sw a1,  10*4(a0)
-        sw a2,  11*4(a0)
-        sw a3,  12*4(a0)
-        sw a4,  13*4(a0)
-        sw a5,  14*4(a0)
-        sw a6,  15*4(a0)
-        sw a7,  16*4(a0)
-        sw s2,  17*4(a0)
-        sw s3,  18*4(a0)
-        sw s4,  19*4(a0)
-        sw s5,  20*4(a0)
-        sw s6,  21*4(a0)
-        sw s7,  22*4(a0)
-        sw s8,  23*4(a0)
-        sw s9,  24*4(a0)
-        sw s10, 25*4(a0)
-        sw s11, 26*4(a0)
-        sw t3,  27*4(a0)
-        sw t4,  28*4(a0)
-        sw t5,  29*4(a0)
-        sw t6,  30*4(a0)
-        csrr a1, mepc
-        sw a1,  31*4(a0)    # store mepc for resume
-        csrr a1, mscratch
-        sw a1, 9*4(a0)      # store a0 itself
-
-        # now that everything is saved, we can clobber sp with the kernel
-        # stack pointer
-        la sp, _stack_start
-
-        #
-        # Jump to our main rust handler
-        #
-        jal trap_handler
-
-        #
-        # On the way out we may have switched to a different task, load
-        # everything in and resume (using t6 as it's restored last).
-        #
-        la t6, CURRENT_TASK_PTR
-        lw t6, (t6)
-
-        lw t5,  31*4(t6)     # restore mepc
-        csrw mepc, t5
-
-        lw ra,   0*4(t6)
-        lw sp,   1*4(t6)
-        lw gp,   2*4(t6)
-        lw tp,   3*4(t6)
-        lw t0,   4*4(t6)
-        lw t1,   5*4(t6)
-        lw t2,   6*4(t6)
-        lw s0,   7*4(t6)
-        lw s1,   8*4(t6)
-        lw a0,   9*4(t6)
-        lw a1,  10*4(t6)
-        lw a2,  11*4(t6)
-        lw a3,  12*4(t6)
-        lw a4,  13*4(t6)
-        lw a5,  14*4(t6)
-        lw a6,  15*4(t6)
-        lw a7,  16*4(t6)
-        lw s2,  17*4(t6)
-        lw s3,  18*4(t6)
-        lw s4,  19*4(t6)
-        lw s5,  20*4(t6)
-        lw s6,  21*4(t6)
-        lw s7,  22*4(t6)
-        lw s8,  23*4(t6)
-        lw s9,  24*4(t6)
-        lw s10, 25*4(t6)
-        lw s11, 26*4(t6)
-        lw t3,  27*4(t6)
-        lw t4,  28*4(t6)
-        lw t5,  29*4(t6)
-        lw t6,  30*4(t6)
-
-        mret
-        ",
+            //
+            // Store full task status on entry, setting up a0 to point at our
+            // current task so that it's passed into our exception handler.
+            //
+            concat!("csrw ", priv_name!("scratch"), ", a0"),
+            "la a0, CURRENT_TASK_PTR",
+            load_reg!("a0", 0, "a0"),
+            store_reg!("ra", 0, "a0"),
+            store_reg!("sp", 1, "a0"),
+            store_reg!("gp", 2, "a0"),
+            store_reg!("tp", 3, "a0"),
+            store_reg!("t0", 4, "a0"),
+            store_reg!("t1", 5, "a0"),
+            store_reg!("t2", 6, "a0"),
+            store_reg!("s0", 7, "a0"),
+            store_reg!("s1", 8, "a0"),
+            // a0 is saved below, once we have it back from mscratch.
+            store_reg!("a1", 10, "a0"),
+            store_reg!("a2", 11, "a0"),
+            store_reg!("a3", 12, "a0"),
+            store_reg!("a4", 13, "a0"),
+            store_reg!("a5", 14, "a0"),
+            store_reg!("a6", 15, "a0"),
+            store_reg!("a7", 16, "a0"),
+            store_reg!("s2", 17, "a0"),
+            store_reg!("s3", 18, "a0"),
+            store_reg!("s4", 19, "a0"),
+            store_reg!("s5", 20, "a0"),
+            store_reg!("s6", 21, "a0"),
+            store_reg!("s7", 22, "a0"),
+            store_reg!("s8", 23, "a0"),
+            store_reg!("s9", 24, "a0"),
+            store_reg!("s10", 25, "a0"),
+            store_reg!("s11", 26, "a0"),
+            store_reg!("t3", 27, "a0"),
+            store_reg!("t4", 28, "a0"),
+            store_reg!("t5", 29, "a0"),
+            store_reg!("t6", 30, "a0"),
+            concat!("csrr a1, ", priv_name!("epc")),
+            // Store mepc (or sepc) for resume.
+            store_reg!("a1", 31, "a0"),
+            concat!("csrr a1, ", priv_name!("scratch")),
+            // Store a0 itself.
+            store_reg!("a1", 9, "a0"),
+            //
+            // Now that everything is saved, we can clobber sp with the kernel
+            // stack pointer.
+            //
+            "la sp, _stack_start",
+            //
+            // Jump to our main rust handler
+            //
+            "jal trap_handler",
+            //
+            // On the way out we may have switched to a different task, load
+            // everything in and resume (using t6 as it's restored last).
+            //
+            "la t6, CURRENT_TASK_PTR",
+            load_reg!("t6", 0, "t6"),
+            // Restore mepc (or sepc).
+            load_reg!("t5", 31, "t6"),
+            concat!("csrw ", priv_name!("epc"), ", t5"),
+            load_reg!("ra", 0, "t6"),
+            load_reg!("sp", 1, "t6"),
+            load_reg!("gp", 2, "t6"),
+            load_reg!("tp", 3, "t6"),
+            load_reg!("t0", 4, "t6"),
+            load_reg!("t1", 5, "t6"),
+            load_reg!("t2", 6, "t6"),
+            load_reg!("s0", 7, "t6"),
+            load_reg!("s1", 8, "t6"),
+            load_reg!("a0", 9, "t6"),
+            load_reg!("a1", 10, "t6"),
+            load_reg!("a2", 11, "t6"),
+            load_reg!("a3", 12, "t6"),
+            load_reg!("a4", 13, "t6"),
+            load_reg!("a5", 14, "t6"),
+            load_reg!("a6", 15, "t6"),
+            load_reg!("a7", 16, "t6"),
+            load_reg!("s2", 17, "t6"),
+            load_reg!("s3", 18, "t6"),
+            load_reg!("s4", 19, "t6"),
+            load_reg!("s5", 20, "t6"),
+            load_reg!("s6", 21, "t6"),
+            load_reg!("s7", 22, "t6"),
+            load_reg!("s8", 23, "t6"),
+            load_reg!("s9", 24, "t6"),
+            load_reg!("s10", 25, "t6"),
+            load_reg!("s11", 26, "t6"),
+            load_reg!("t3", 27, "t6"),
+            load_reg!("t4", 28, "t6"),
+            load_reg!("t5", 29, "t6"),
+            load_reg!("t6", 30, "t6"),
+            priv_name!("ret"),
             options(noreturn),
         );
     }
@@ -225,7 +233,7 @@ fn timer_handler() {
             }
 
             //
-            // Increase mtimecmp for the next interrupt
+            // Move the timer deadline on for the next interrupt
             // This will also clear the pending timer interrupt.
             //
             reset_timer();
@@ -306,36 +314,46 @@ unsafe fn handle_fault(task: *mut task::Task, fault: FaultInfo) {
 //
 #[no_mangle]
 fn trap_handler(task: &mut task::Task) {
-    let mcause = register::mcause::read();
+    let mcause = cause::read();
     match mcause.cause() {
         //
-        // Interrupts.  Only our periodic MachineTimer interrupt via mtime is
-        // supported at present.
+        // Interrupts.  Only our periodic timer interrupt (via mtime, or the
+        // SBI in Supervisor mode) is supported at present.
         //
+        #[cfg(not(feature = "s-mode"))]
         Trap::Interrupt(Interrupt::MachineTimer) => {
             timer_handler();
         }
+        #[cfg(feature = "s-mode")]
+        Trap::Interrupt(Interrupt::SupervisorTimer) => {
+            timer_handler();
+        }
 
         //
         // External Interrupts
         //
+        #[cfg(not(feature = "s-mode"))]
         Trap::Interrupt(Interrupt::MachineExternal) => {
             platform_interrupt_handler(11);
         }
+        #[cfg(feature = "s-mode")]
+        Trap::Interrupt(Interrupt::SupervisorExternal) => {
+            platform_interrupt_handler(9);
+        }
         //
         // System Calls.
         //
         Trap::Exception(Exception::UserEnvCall) => {
             unsafe {
                 // Advance program counter past ecall instruction.
-                task.save_mut().set_pc(register::mepc::read() as u32 + 4);
+                task.save_mut().set_pc(epc::read() as u32 + 4);
                 asm!(
-                    "
-                    la a1, CURRENT_TASK_PTR
-                    mv a0, a7               # arg0 = syscall number
-                    lw a1, (a1)             # arg1 = task ptr
-                    jal syscall_entry
-                    ",
+                    "la a1, CURRENT_TASK_PTR",
+                    // arg0 = syscall number
+                    "mv a0, a7",
+                    // arg1 = task ptr
+                    load_reg!("a1", 0, "a1"),
+                    "jal syscall_entry",
                 );
             }
         }
@@ -350,7 +368,7 @@ fn trap_handler(task: &mut task::Task) {
             handle_fault(
                 task,
                 FaultInfo::MemoryAccess {
-                    address: Some(register::mtval::read() as u32),
+                    address: Some(tval::read() as u32),
                     source: FaultSource::User,
                 },
             );
@@ -358,6 +376,25 @@ fn trap_handler(task: &mut task::Task) {
         Trap::Exception(Exception::InstructionFault) => unsafe {
             handle_fault(task, FaultInfo::IllegalText);
         },
+        //
+        // Page faults, which only occur in Supervisor mode, are how the page
+        // tables report the same violations as the PMP does above.
+        //
+        #[cfg(feature = "s-mode")]
+        Trap::Exception(Exception::LoadPageFault)
+        | Trap::Exception(Exception::StorePageFault) => unsafe {
+            handle_fault(
+                task,
+                FaultInfo::MemoryAccess {
+                    address: Some(tval::read() as u32),
+                    source: FaultSource::User,
+                },
+            );
+        },
+        #[cfg(feature = "s-mode")]
+        Trap::Exception(Exception::InstructionPageFault) => unsafe {
+            handle_fault(task, FaultInfo::IllegalText);
+        },
 
         _ => {
             cfg_if::cfg_if! {
@@ -381,22 +418,22 @@ fn trap_handler(task: &mut task::Task) {
 }
 
 pub fn disable_irq(n: u32) {
-    let cur_mie = register::mie::read();
+    let cur_mie = ie::read();
     let new_mie = cur_mie.bits() & !(0x1 << n);
     unsafe {
-        asm!("
-            csrw mie, {x}",
+        asm!(
+            concat!("csrw ", priv_name!("ie"), ", {x}"),
             x = in(reg) new_mie,
         );
     }
 }
 
 pub fn enable_irq(n: u32) {
-    let cur_mie = register::mie::read();
+    let cur_mie = ie::read();
     let new_mie = cur_mie.bits() | (0x1 << n);
     unsafe {
-        asm!("
-            csrw mie, {x}",
+        asm!(
+            concat!("csrw ", priv_name!("ie"), ", {x}"),
             x = in(reg) new_mie,
         );
     }
@@ -0,0 +1,423 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Simulated architecture support, for running the portable parts of the
+//! kernel on a development machine.
+//!
+//! This is not an emulator: no task code ever runs. Instead, tests build a
+//! task table out of `make_task` and `region`, load syscall arguments into a
+//! task's `SavedState` as user code would before trapping, and hand the table
+//! to the same syscall implementations the real kernel uses. The results can
+//! then be read back out of the `SavedState` and the tasks' memory.
+//!
+//! Task memory is real host memory, mapped below 4 GiB so that its addresses
+//! survive the trip through the kernel's 32-bit ABI. The kernel accesses it
+//! through the usual `try_read`/`try_write` checks, so lease and slice
+//! validation is exercised exactly as it would be on hardware.
+//!
+//! The simulated application has no interrupts, and its clock only moves when
+//! a test calls `set_now`. Each test thread gets its own clock.
+
+use core::cell::Cell;
+use core::sync::atomic::{AtomicPtr, AtomicU32, AtomicUsize, Ordering};
+
+use abi::Sysnum;
+
+use crate::descs::{
+    RegionAttributes, RegionDesc, TaskDesc, TaskFlags, REGIONS_PER_TASK,
+};
+use crate::task;
+use crate::time::Timestamp;
+use crate::umem::USlice;
+use unwrap_lite::UnwrapLite;
+use zerocopy::AsBytes;
+
+macro_rules! uassert {
+    ($cond : expr) => {
+        if !$cond {
+            panic!("Assertion failed!");
+        }
+    };
+}
+
+/// Records the current task pointer, as on the real architectures. Nothing in
+/// the simulator reads it back.
+static CURRENT_TASK_PTR: AtomicPtr<task::Task> =
+    AtomicPtr::new(core::ptr::null_mut());
+
+static CLOCK_FREQ_KHZ: AtomicU32 = AtomicU32::new(0);
+
+std::thread_local! {
+    /// The simulated tick counter for this thread.
+    static TICKS: Cell<u64> = Cell::new(0);
+}
+
+/// Simulated registers.
+///
+/// Like RISC-V, the syscall arguments and return values share registers:
+/// argument `n` and return value `n` are both `regs[n]`. The syscall number
+/// is in `regs[7]`, so SEND_WITH_TIMEOUT's extra argument has a register of
+/// its own, `t0`.
+#[repr(C)]
+#[derive(Clone, Debug, Default, AsBytes)]
+pub struct SavedState {
+    regs: [u32; 8],
+    t0: u32,
+    sp: u32,
+    pc: u32,
+}
+
+impl SavedState {
+    pub fn sp(&self) -> u32 {
+        self.sp
+    }
+    pub fn pc(&self) -> u32 {
+        self.pc
+    }
+
+    /// Loads the registers for a syscall, as the userlib stubs would before
+    /// trapping into the kernel. Unused argument registers are zeroed.
+    pub fn set_syscall(&mut self, nr: Sysnum, args: &[u32]) {
+        uassert!(args.len() <= 8);
+        self.regs = [0; 8];
+        self.t0 = 0;
+        for (i, &arg) in args.iter().enumerate() {
+            match i {
+                7 => self.t0 = arg,
+                _ => self.regs[i] = arg,
+            }
+        }
+        self.regs[7] = nr as u32;
+    }
+
+    /// Reads syscall return value `n`, as the userlib stubs would on return
+    /// from the kernel.
+    pub fn ret(&self, n: usize) -> u32 {
+        self.regs[n]
+    }
+}
+
+impl task::ArchState for SavedState {
+    fn stack_pointer(&self) -> u32 {
+        self.sp
+    }
+
+    /// Reads syscall argument register 0.
+    fn arg0(&self) -> u32 {
+        self.regs[0]
+    }
+    fn arg1(&self) -> u32 {
+        self.regs[1]
+    }
+    fn arg2(&self) -> u32 {
+        self.regs[2]
+    }
+    fn arg3(&self) -> u32 {
+        self.regs[3]
+    }
+    fn arg4(&self) -> u32 {
+        self.regs[4]
+    }
+    fn arg5(&self) -> u32 {
+        self.regs[5]
+    }
+    fn arg6(&self) -> u32 {
+        self.regs[6]
+    }
+    fn arg7(&self) -> u32 {
+        self.t0
+    }
+
+    fn syscall_descriptor(&self) -> u32 {
+        self.regs[7]
+    }
+
+    /// Writes syscall return argument 0.
+    fn ret0(&mut self, x: u32) {
+        self.regs[0] = x
+    }
+    fn ret1(&mut self, x: u32) {
+        self.regs[1] = x
+    }
+    fn ret2(&mut self, x: u32) {
+        self.regs[2] = x
+    }
+    fn ret3(&mut self, x: u32) {
+        self.regs[3] = x
+    }
+    fn ret4(&mut self, x: u32) {
+        self.regs[4] = x
+    }
+    fn ret5(&mut self, x: u32) {
+        self.regs[5] = x
+    }
+}
+
+impl crate::atomic::AtomicExt for core::sync::atomic::AtomicBool {
+    type Primitive = bool;
+
+    #[inline(always)]
+    fn swap_polyfill(
+        &self,
+        value: Self::Primitive,
+        ordering: Ordering,
+    ) -> Self::Primitive {
+        self.swap(value, ordering)
+    }
+}
+
+/// Records the clock frequency, as on ARM.
+///
+/// # Safety
+///
+/// This is not actually unsafe in the simulator; it matches the signature
+/// `start_kernel` expects.
+pub unsafe fn set_clock_freq(tick_divisor: u32) {
+    CLOCK_FREQ_KHZ.store(tick_divisor, Ordering::Relaxed);
+}
+
+pub fn reinitialize(task: &mut task::Task) {
+    *task.save_mut() = SavedState::default();
+    let initial_stack = task.descriptor().initial_stack;
+    task.save_mut().sp = initial_stack;
+
+    // Paint the stack the way the real architectures do, so that stack usage
+    // measurement has something to find.
+    if let Some(region) = task
+        .region_table()
+        .iter()
+        .find(|region| region.contains(initial_stack as usize))
+    {
+        let mut uslice: USlice<u32> = USlice::from_raw(
+            region.base as usize,
+            (initial_stack - region.base) as usize >> 2,
+        )
+        .unwrap_lite();
+
+        let zap = task.try_write(&mut uslice).unwrap_lite();
+        for word in zap.iter_mut() {
+            *word = task::STACK_PAINT;
+        }
+    }
+    task.save_mut().pc = task.descriptor().entry_point;
+}
+
+pub fn apply_memory_protection(_task: &task::Task) {
+    // Simulated tasks don't run, so there's nothing to protect.
+}
+
+/// Records the address of `task` as the current user task.
+///
+/// # Safety
+///
+/// This records a pointer that aliases `task`. The simulator never reads it,
+/// but keeps the same contract as the real architectures.
+pub unsafe fn set_current_task(task: &mut task::Task) {
+    CURRENT_TASK_PTR.store(task, Ordering::Relaxed);
+}
+
+pub fn start_first_task(_tick_divisor: u32, _task: &mut task::Task) -> ! {
+    panic!("the simulator can't run task code");
+}
+
+/// Reads the tick counter.
+pub fn now() -> Timestamp {
+    Timestamp::from(TICKS.with(Cell::get))
+}
+
+/// Sets this thread's simulated tick counter.
+pub fn set_now(ticks: u64) {
+    TICKS.with(|t| t.set(ticks));
+}
+
+/// Reads the free-running counter used for per-task run time accounting.
+///
+/// In the simulator this is the tick counter.
+pub fn run_time_counter() -> u64 {
+    TICKS.with(Cell::get)
+}
+
+pub fn disable_irq(_n: u32) {
+    // The simulated application has no interrupts, so `irq_control` never
+    // finds one to get here.
+}
+
+pub fn enable_irq(_n: u32) {
+    // As above.
+}
+
+pub fn reset() -> ! {
+    panic!("kernel reset requested");
+}
+
+/// Host mappings are placed from here up. This is arbitrary, but low enough
+/// to leave plenty of 32-bit address space and high enough to stay out of the
+/// way of anything the host loader put at the bottom.
+const SIM_MEMORY_BASE: usize = 0x1000_0000;
+
+/// Mappings are rounded up to this, which covers the page size of any host
+/// we're likely to run on.
+const SIM_PAGE_SIZE: usize = 0x1_0000;
+
+/// Next address to try when placing a simulated region. Shared between test
+/// threads so that each region gets its own address range.
+static NEXT_REGION: AtomicUsize = AtomicUsize::new(SIM_MEMORY_BASE);
+
+/// The no-access region used to fill unused slots in each task's region
+/// table, as the build system does with region 0.
+static NULL_REGION: RegionDesc = RegionDesc {
+    base: 0,
+    size: 32,
+    attributes: RegionAttributes::empty(),
+};
+
+/// Size of the RAM region given to each task by `task_table`.
+pub const SIM_RAM_SIZE: u32 = 4096;
+
+/// Allocates a region of `size` bytes of zeroed host memory below 4 GiB.
+///
+/// The memory is never freed; regions live as long as the test process.
+///
+/// # Panics
+///
+/// If the simulated address space is exhausted, or the host won't map memory.
+pub fn region(size: u32, attributes: RegionAttributes) -> &'static RegionDesc {
+    let len = (size as usize + SIM_PAGE_SIZE - 1) & !(SIM_PAGE_SIZE - 1);
+    loop {
+        let hint = NEXT_REGION.fetch_add(len, Ordering::Relaxed);
+        assert!(
+            hint + len <= u32::MAX as usize,
+            "out of simulated address space"
+        );
+        // Safety: we're asking for a fresh anonymous mapping and not using
+        // MAP_FIXED, so this can't disturb any existing mapping.
+        let p = unsafe {
+            libc::mmap(
+                hint as *mut libc::c_void,
+                len,
+                libc::PROT_READ | libc::PROT_WRITE,
+                libc::MAP_PRIVATE | libc::MAP_ANONYMOUS,
+                -1,
+                0,
+            )
+        };
+        if p == libc::MAP_FAILED {
+            panic!("can't map simulated memory");
+        }
+        if p as usize != hint {
+            // The host put it somewhere else, probably because something
+            // already lives at `hint`. Give it back and try further up.
+            //
+            // Safety: we just mapped this, and nothing else has seen it.
+            unsafe {
+                libc::munmap(p, len);
+            }
+            continue;
+        }
+        return Box::leak(Box::new(RegionDesc {
+            base: hint as u32,
+            size,
+            attributes,
+        }));
+    }
+}
+
+/// Builds task `index` of a simulated task table.
+///
+/// The task's entry point is the start of its first region. As in a real
+/// image, its stack sits at the bottom of its RAM: here, the lower half of its
+/// first writable region. Unused slots in its region table are filled with a
+/// no-access region. The task is marked to start at boot, and is initialized
+/// as it would be by `start_kernel`.
+pub fn make_task(
+    index: usize,
+    priority: u8,
+    regions: &[&'static RegionDesc],
+) -> task::Task {
+    uassert!(regions.len() < REGIONS_PER_TASK);
+    let mut table = [&NULL_REGION; REGIONS_PER_TASK];
+    table[1..=regions.len()].copy_from_slice(regions);
+
+    let initial_stack = regions
+        .iter()
+        .find(|r| r.attributes.contains(RegionAttributes::WRITE))
+        .map_or(0, |r| r.base + r.size / 2);
+    let descriptor = Box::leak(Box::new(TaskDesc {
+        regions: table,
+        entry_point: regions.first().map_or(0, |r| r.base),
+        initial_stack,
+        priority,
+        flags: TaskFlags::START_AT_BOOT,
+        index: u16::try_from(index).unwrap_lite(),
+        ipc_allowlist: None,
+    }));
+
+    let mut task = task::Task::from_descriptor(descriptor);
+    reinitialize(&mut task);
+    task
+}
+
+/// Restricts `task` to sending and posting to the tasks in `peers`, as an
+/// application with an IPC allowlist would. This rebuilds the task, so it
+/// should be done before the test starts using it.
+pub fn restrict_ipc(task: &mut task::Task, peers: &'static [u16]) {
+    let descriptor = Box::leak(Box::new(TaskDesc {
+        ipc_allowlist: Some(peers),
+        ..task.descriptor().clone()
+    }));
+    *task = task::Task::from_descriptor(descriptor);
+    reinitialize(task);
+}
+
+/// Gives `task` the `DUMP_ACCESS` flag, as a dump agent would have. Like
+/// `restrict_ipc`, this rebuilds the task.
+pub fn grant_dump_access(task: &mut task::Task) {
+    let descriptor = Box::leak(Box::new(TaskDesc {
+        flags: task.descriptor().flags | TaskFlags::DUMP_ACCESS,
+        ..task.descriptor().clone()
+    }));
+    *task = task::Task::from_descriptor(descriptor);
+    reinitialize(task);
+}
+
+/// Builds a task table with one task per entry in `priorities`, each with a
+/// private read-write RAM region of `SIM_RAM_SIZE` bytes. The upper half of
+/// each region is free for tests to use as buffers.
+pub fn task_table(priorities: &[u8]) -> Vec<task::Task> {
+    priorities
+        .iter()
+        .enumerate()
+        .map(|(i, &prio)| {
+            let ram = region(
+                SIM_RAM_SIZE,
+                RegionAttributes::READ | RegionAttributes::WRITE,
+            );
+            make_task(i, prio, &[ram])
+        })
+        .collect()
+}
+
+/// Returns the base address of `task`'s first region, which for tasks from
+/// `task_table` is its RAM.
+pub fn ram_base(task: &task::Task) -> u32 {
+    task.region_table()[1].base
+}
+
+/// Copies `data` into `task`'s memory at `addr`, which must be writable by
+/// the task.
+pub fn write_mem(task: &mut task::Task, addr: u32, data: &[u8]) {
+    let mut slice = USlice::from_raw(addr as usize, data.len()).unwrap_lite();
+    task.try_write(&mut slice)
+        .expect("simulated write outside task memory")
+        .copy_from_slice(data);
+}
+
+/// Reads `len` bytes of `task`'s memory at `addr`, which must be readable by
+/// the task.
+pub fn read_mem(task: &task::Task, addr: u32, len: usize) -> Vec<u8> {
+    let slice = USlice::from_raw(addr as usize, len).unwrap_lite();
+    task.try_read(&slice)
+        .expect("simulated read outside task memory")
+        .to_vec()
+}
@@ -6,7 +6,7 @@
 
 use crate::umem::USlice;
 
-pub(crate) const REGIONS_PER_TASK: usize = 8;
+pub(crate) use abi::REGIONS_PER_TASK;
 
 /// Indicates priority of a task.
 ///
@@ -66,13 +66,19 @@ pub struct TaskDesc {
     /// The index is a u16 to save space in the `TaskDesc` struct; in practice
     /// other factors limit us to fewer than `2**16` tasks.
     pub index: u16,
+    /// Indices of the tasks this task may send to or post to, in ascending
+    /// order, if the application restricts IPC. `None` allows any task.
+    pub ipc_allowlist: Option<&'static [u16]>,
 }
 
 bitflags::bitflags! {
     #[repr(transparent)]
     pub struct TaskFlags: u8 {
         const START_AT_BOOT = 1 << 0;
-        const RESERVED = !1;
+        /// Task may read the registers and memory of faulted tasks, as the
+        /// supervisor can.
+        const DUMP_ACCESS = 1 << 1;
+        const RESERVED = !((1 << 2) - 1);
     }
 }
 
@@ -2,13 +2,68 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use abi::ImageHeader;
+use abi::{ImageHeader, TaskManifestEntry};
 use core::mem::MaybeUninit;
 
+/// The image header along with the task manifest, which the build system
+/// expects to find right after it.
+#[repr(C)]
+struct Header {
+    header: ImageHeader,
+    tasks: [TaskManifestEntry; MANIFEST_LEN],
+}
+
+/// Number of entries in the task manifest, one per task.
+#[cfg(target_os = "none")]
+const MANIFEST_LEN: usize = crate::startup::HUBRIS_TASK_COUNT;
+
+/// The simulator's task tables are built by the tests at runtime, so there's
+/// no task count to size this by; this gives the tests a manifest long enough
+/// to need reading in pieces.
+#[cfg(not(target_os = "none"))]
+pub(crate) const MANIFEST_LEN: usize = 3;
+
 // This is updated by build scripts (which is why this is marked as no_mangle)
-// Although we don't access any fields of the header from hubris right now, it
-// is safer to treat this as MaybeUninit in case we need to do so in the future.
+// As far as the compiler knows this is never initialized, so it's only ever
+// read through `read_image_header`.
 #[used]
 #[no_mangle]
 #[link_section = ".image_header"]
-static HEADER: MaybeUninit<ImageHeader> = MaybeUninit::uninit();
+static HEADER: MaybeUninit<Header> = MaybeUninit::uninit();
+
+/// Copies as much of the image header and task manifest, starting `offset`
+/// bytes in, as fits into `buf`, returning the number of bytes copied. This is
+/// zero once `offset` reaches the end of the manifest.
+pub fn read_image_header(offset: usize, buf: &mut [u8]) -> usize {
+    let src = HEADER.as_ptr() as *const u8;
+    let len = core::mem::size_of::<Header>();
+    let offset = offset.min(len);
+    let n = buf.len().min(len - offset);
+    for (i, b) in (offset..).zip(buf[..n].iter_mut()) {
+        // Safety: `i` is within `HEADER`, which was filled in by the build
+        // system after linking. The read has to be volatile for the same
+        // reason `HUBRIS_IMAGE_ID` is read that way: otherwise the compiler
+        // is free to assume it's still uninitialized.
+        *b = unsafe { core::ptr::read_volatile(src.add(i)) };
+    }
+    n
+}
+
+// On RISC-V the image starts with a jump over the header to riscv-rt's
+// `_start`, so it can be entered at its first byte like an image without a
+// header. This must be a full-size instruction, since the linker script puts
+// the header right after it (see `lds/rv*/kernel-link.x`).
+#[cfg(all(
+    target_os = "none",
+    any(target_arch = "riscv32", target_arch = "riscv64")
+))]
+core::arch::global_asm!(
+    "
+    .pushsection .image_entry, \"ax\"
+    .option push
+    .option norvc
+    j _start
+    .option pop
+    .popsection
+    "
+);
@@ -4,12 +4,17 @@
 
 //! Implementation of IPC operations on the virtual kernel task.
 
-use abi::{FaultInfo, Kipcnum, SchedState, TaskState, UsageError};
+use abi::{
+    FaultInfo, FaultSource, Kipcnum, SchedState, TaskDumpRegion, TaskState,
+    UsageError,
+};
+use zerocopy::AsBytes;
 
 use crate::arch;
+use crate::descs::{RegionAttributes, RegionDesc, TaskFlags};
 use crate::err::UserError;
 use crate::task::{current_id, ArchState, NextTask, Task};
-use crate::umem::USlice;
+use crate::umem::{safe_copy, USlice};
 use core::convert::TryFrom;
 
 /// Message dispatcher.
@@ -33,6 +38,24 @@ pub fn handle_kernel_message(
             read_image_id(tasks, caller, args.response?)
         }
         Ok(Kipcnum::Reset) => reset(tasks, caller, args.message?),
+        Ok(Kipcnum::ReadTaskStats) => {
+            read_task_stats(tasks, caller, args.message?, args.response?)
+        }
+        Ok(Kipcnum::ReadStackUsage) => {
+            read_stack_usage(tasks, caller, args.message?, args.response?)
+        }
+        Ok(Kipcnum::ReadTaskRegisters) => {
+            read_task_registers(tasks, caller, args.message?, args.response?)
+        }
+        Ok(Kipcnum::GetTaskDumpRegion) => {
+            get_task_dump_region(tasks, caller, args.message?, args.response?)
+        }
+        Ok(Kipcnum::ReadTaskDumpRegion) => {
+            read_task_dump_region(tasks, caller, args.message?, args.response?)
+        }
+        Ok(Kipcnum::ReadImageHeader) => {
+            read_image_header(tasks, caller, args.message?, args.response?)
+        }
         _ => {
             // Task has sent an unknown message to the kernel. That's bad.
             Err(UserError::Unrecoverable(FaultInfo::SyscallUsage(
@@ -100,6 +123,199 @@ fn read_task_status(
     Ok(NextTask::Same)
 }
 
+fn read_task_stats(
+    tasks: &mut [Task],
+    caller: usize,
+    message: USlice<u8>,
+    response: USlice<u8>,
+) -> Result<NextTask, UserError> {
+    let index: u32 = deserialize_message(&tasks[caller], message)?;
+    if index as usize >= tasks.len() {
+        return Err(UserError::Unrecoverable(FaultInfo::SyscallUsage(
+            UsageError::TaskOutOfRange,
+        )));
+    }
+    let stats = crate::stats::read(index as usize);
+
+    let response_len =
+        serialize_response(&mut tasks[caller], response, &stats)?;
+    tasks[caller]
+        .save_mut()
+        .set_send_response_and_length(0, response_len);
+    Ok(NextTask::Same)
+}
+
+fn read_stack_usage(
+    tasks: &mut [Task],
+    caller: usize,
+    message: USlice<u8>,
+    response: USlice<u8>,
+) -> Result<NextTask, UserError> {
+    let index: u32 = deserialize_message(&tasks[caller], message)?;
+    if index as usize >= tasks.len() {
+        return Err(UserError::Unrecoverable(FaultInfo::SyscallUsage(
+            UsageError::TaskOutOfRange,
+        )));
+    }
+    let usage = tasks[index as usize].stack_usage();
+
+    let response_len =
+        serialize_response(&mut tasks[caller], response, &usage)?;
+    tasks[caller]
+        .save_mut()
+        .set_send_response_and_length(0, response_len);
+    Ok(NextTask::Same)
+}
+
+/// Checks that `caller` may dump task `index`: the caller must be the
+/// supervisor or have the `DUMP_ACCESS` flag, and the task must be a faulted
+/// task other than the caller. Dumping a running task would give a torn view
+/// of its state, and the supervisor is expected to hold faulted tasks until
+/// they've been dumped.
+fn check_dump_access(
+    tasks: &[Task],
+    caller: usize,
+    index: usize,
+) -> Result<(), UserError> {
+    if caller != 0
+        && !tasks[caller]
+            .descriptor()
+            .flags
+            .contains(TaskFlags::DUMP_ACCESS)
+    {
+        return Err(UserError::Unrecoverable(FaultInfo::SyscallUsage(
+            UsageError::NotPrivileged,
+        )));
+    }
+    if index >= tasks.len() {
+        return Err(UserError::Unrecoverable(FaultInfo::SyscallUsage(
+            UsageError::TaskOutOfRange,
+        )));
+    }
+    if index == caller
+        || !matches!(tasks[index].state(), TaskState::Faulted { .. })
+    {
+        return Err(UserError::Unrecoverable(FaultInfo::SyscallUsage(
+            UsageError::IllegalTask,
+        )));
+    }
+    Ok(())
+}
+
+/// Copies out the registers a faulted task had saved when it faulted, as the
+/// raw bytes of the architecture's `SavedState`. Like `serialize_response`,
+/// this tolerates a short response buffer, filling it and reporting the size
+/// that would have been needed.
+fn read_task_registers(
+    tasks: &mut [Task],
+    caller: usize,
+    message: USlice<u8>,
+    mut response: USlice<u8>,
+) -> Result<NextTask, UserError> {
+    let index: u32 = deserialize_message(&tasks[caller], message)?;
+    let index = index as usize;
+    check_dump_access(tasks, caller, index)?;
+
+    let (target, caller_task) = if index < caller {
+        let (prefix, suffix) = tasks.split_at_mut(caller);
+        (&prefix[index], &mut suffix[0])
+    } else {
+        let (prefix, suffix) = tasks.split_at_mut(index);
+        (&suffix[0], &mut prefix[caller])
+    };
+    let regs = target.save().as_bytes();
+    let buf = caller_task.try_write(&mut response)?;
+    let n = regs.len().min(buf.len());
+    buf[..n].copy_from_slice(&regs[..n]);
+
+    caller_task
+        .save_mut()
+        .set_send_response_and_length(0, regs.len());
+    Ok(NextTask::Same)
+}
+
+/// Returns the `n`th region of a faulted task's memory that is worth
+/// dumping, or `None` if it has fewer. These are the task's writable regions
+/// of normal memory, which is where all of its mutable state lives.
+fn get_task_dump_region(
+    tasks: &mut [Task],
+    caller: usize,
+    message: USlice<u8>,
+    response: USlice<u8>,
+) -> Result<NextTask, UserError> {
+    let (index, n): (u32, u32) = deserialize_message(&tasks[caller], message)?;
+    let index = index as usize;
+    check_dump_access(tasks, caller, index)?;
+
+    let region = tasks[index]
+        .region_table()
+        .iter()
+        .filter(|region| is_dump_region(region))
+        .nth(n as usize)
+        .map(|region| TaskDumpRegion {
+            base: region.base,
+            size: region.size,
+        });
+
+    let response_len =
+        serialize_response(&mut tasks[caller], response, &region)?;
+    tasks[caller]
+        .save_mut()
+        .set_send_response_and_length(0, response_len);
+    Ok(NextTask::Same)
+}
+
+/// Checks whether `region` is one that `get_task_dump_region` describes:
+/// writable normal memory, which holds all of a task's mutable state.
+fn is_dump_region(region: &RegionDesc) -> bool {
+    region.attributes.contains(RegionAttributes::WRITE)
+        && !region
+            .attributes
+            .intersects(RegionAttributes::DEVICE | RegionAttributes::DMA)
+}
+
+/// Copies part of a faulted task's memory into the response buffer. The
+/// memory must lie within one of the faulted task's dump regions; the copy is
+/// cut short if the response buffer is smaller, and the length copied is
+/// returned.
+fn read_task_dump_region(
+    tasks: &mut [Task],
+    caller: usize,
+    message: USlice<u8>,
+    response: USlice<u8>,
+) -> Result<NextTask, UserError> {
+    let (index, region): (u32, TaskDumpRegion) =
+        deserialize_message(&tasks[caller], message)?;
+    let index = index as usize;
+    check_dump_access(tasks, caller, index)?;
+
+    let source = USlice::from_raw(region.base as usize, region.size as usize)?;
+    // Only memory that `get_task_dump_region` would describe may be read.
+    // That keeps dump agents out of flash, and out of peripherals, where a
+    // read can have side effects.
+    let in_dump_region = tasks[index]
+        .region_table()
+        .iter()
+        .any(|r| is_dump_region(r) && r.covers(&source));
+    if !source.is_empty() && !in_dump_region {
+        return Err(UserError::Unrecoverable(FaultInfo::MemoryAccess {
+            address: Some(region.base),
+            source: FaultSource::Kernel,
+        }));
+    }
+    // Any fault here is the caller's, for asking for the wrong memory or
+    // providing a bad buffer; the faulted task's record stays as it was.
+    let n =
+        safe_copy(tasks, index, source, caller, response).map_err(|fault| {
+            match fault.dst.or(fault.src) {
+                Some(fault) => UserError::Unrecoverable(fault),
+                None => UsageError::BadKernelMessage.into(),
+            }
+        })?;
+    tasks[caller].save_mut().set_send_response_and_length(0, n);
+    Ok(NextTask::Same)
+}
+
 fn exit_current_task(
     tasks: &mut [Task],
     caller: usize,
@@ -139,6 +355,10 @@ fn restart_task(
             continue;
         }
 
+        // The restarted task has forgotten any messages it was holding, so
+        // it won't reply to sends that were cancelled while it had them.
+        task.discard_reply_from(index);
+
         // We'll skip processing faulted tasks, because we don't want to lose
         // information in their fault records by changing their states.
         if let TaskState::Healthy(sched) = task.state() {
@@ -222,3 +442,290 @@ fn read_image_id(
         .set_send_response_and_length(0, response_len);
     Ok(NextTask::Same)
 }
+
+/// Copies the image header and task manifest, starting at the offset in the
+/// message, into the response buffer, cut short if the buffer is smaller,
+/// returning the length copied.
+fn read_image_header(
+    tasks: &mut [Task],
+    caller: usize,
+    message: USlice<u8>,
+    mut response: USlice<u8>,
+) -> Result<NextTask, UserError> {
+    let offset: u32 = deserialize_message(&tasks[caller], message)?;
+    let n = crate::header::read_image_header(
+        offset as usize,
+        tasks[caller].try_write(&mut response)?,
+    );
+    tasks[caller].save_mut().set_send_response_and_length(0, n);
+    Ok(NextTask::Same)
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arch::{
+        grant_dump_access, make_task, ram_base, read_mem, region, task_table,
+        write_mem,
+    };
+    use crate::task::force_fault;
+    use abi::{Sysnum, TaskId};
+
+    const MSG: u32 = 0x800;
+    const RESPONSE: u32 = 0x900;
+    const DATA: u32 = 0xC00;
+
+    const BUF_LEN: u32 = 256;
+
+    const SUPERVISOR: usize = 0;
+    const VICTIM: usize = 1;
+    const AGENT: usize = 2;
+
+    /// A supervisor, a task that has faulted, and a dump agent.
+    fn tasks() -> Vec<Task> {
+        let mut tasks = task_table(&[0, 1, 1]);
+        grant_dump_access(&mut tasks[AGENT]);
+        let _ = force_fault(&mut tasks, VICTIM, FaultInfo::Panic);
+        tasks
+    }
+
+    /// Has `caller` send kipc `op` with `msg`, returning the response code
+    /// and response, or the fault the caller would take.
+    fn kipc(
+        tasks: &mut [Task],
+        caller: usize,
+        op: Kipcnum,
+        msg: &[u8],
+    ) -> Result<(u32, Vec<u8>), FaultInfo> {
+        let base = ram_base(&tasks[caller]);
+        write_mem(&mut tasks[caller], base + MSG, msg);
+        let args = [
+            u32::from(TaskId::KERNEL.0) << 16 | op as u32,
+            base + MSG,
+            msg.len() as u32,
+            base + RESPONSE,
+            BUF_LEN,
+            0,
+            0,
+        ];
+        tasks[caller].save_mut().set_syscall(Sysnum::Send, &args);
+        match handle_kernel_message(tasks, caller) {
+            Ok(_) => {
+                let save = tasks[caller].save();
+                let len = (save.ret(1) as usize).min(BUF_LEN as usize);
+                let response = read_mem(&tasks[caller], base + RESPONSE, len);
+                Ok((save.ret(0), response))
+            }
+            Err(UserError::Unrecoverable(fault)) => Err(fault),
+            Err(UserError::Recoverable(code, _)) => Ok((code, vec![])),
+        }
+    }
+
+    fn fault(tasks: &[Task], index: usize) -> Option<FaultInfo> {
+        match tasks[index].state() {
+            TaskState::Faulted { fault, .. } => Some(*fault),
+            TaskState::Healthy(_) => None,
+        }
+    }
+
+    #[test]
+    fn dump_agent_reads_registers() {
+        let mut tasks = tasks();
+        tasks[VICTIM]
+            .save_mut()
+            .set_syscall(Sysnum::Panic, &[0x1234, 0x5678]);
+        let expected = tasks[VICTIM].save().as_bytes().to_vec();
+
+        let msg = (VICTIM as u32).to_le_bytes();
+        let response =
+            kipc(&mut tasks, AGENT, Kipcnum::ReadTaskRegisters, &msg);
+        assert_eq!(response, Ok((0, expected)));
+    }
+
+    #[test]
+    fn dump_agent_reads_memory() {
+        let mut tasks = tasks();
+        let ram = tasks[VICTIM].region_table()[1];
+        let data = ram_base(&tasks[VICTIM]) + DATA;
+        write_mem(&mut tasks[VICTIM], data, b"last words");
+
+        let mut msg = [0; 8];
+        ssmarshal::serialize(&mut msg, &(VICTIM as u32, 0u32)).unwrap();
+        let (rc, response) =
+            kipc(&mut tasks, AGENT, Kipcnum::GetTaskDumpRegion, &msg).unwrap();
+        assert_eq!(rc, 0);
+        let (region, _): (Option<TaskDumpRegion>, _) =
+            ssmarshal::deserialize(&response).unwrap();
+        assert_eq!(
+            region,
+            Some(TaskDumpRegion {
+                base: ram.base,
+                size: ram.size,
+            })
+        );
+
+        // The task has no other writable memory.
+        ssmarshal::serialize(&mut msg, &(VICTIM as u32, 1u32)).unwrap();
+        let (_, response) =
+            kipc(&mut tasks, AGENT, Kipcnum::GetTaskDumpRegion, &msg).unwrap();
+        let (region, _): (Option<TaskDumpRegion>, _) =
+            ssmarshal::deserialize(&response).unwrap();
+        assert_eq!(region, None);
+
+        let mut msg = [0; 12];
+        let region = TaskDumpRegion {
+            base: data,
+            size: 10,
+        };
+        ssmarshal::serialize(&mut msg, &(VICTIM as u32, region)).unwrap();
+        let response =
+            kipc(&mut tasks, AGENT, Kipcnum::ReadTaskDumpRegion, &msg);
+        assert_eq!(response, Ok((0, b"last words".to_vec())));
+
+        // Dumping leaves the fault record alone.
+        assert_eq!(fault(&tasks, VICTIM), Some(FaultInfo::Panic));
+    }
+
+    #[test]
+    fn dump_requires_privilege() {
+        let mut tasks = task_table(&[0, 1, 1]);
+        let _ = force_fault(&mut tasks, VICTIM, FaultInfo::Panic);
+
+        let msg = (VICTIM as u32).to_le_bytes();
+        let response =
+            kipc(&mut tasks, AGENT, Kipcnum::ReadTaskRegisters, &msg);
+        assert_eq!(
+            response,
+            Err(FaultInfo::SyscallUsage(UsageError::NotPrivileged))
+        );
+
+        // The supervisor needs no flag.
+        let response =
+            kipc(&mut tasks, SUPERVISOR, Kipcnum::ReadTaskRegisters, &msg);
+        assert!(response.is_ok());
+    }
+
+    #[test]
+    fn dump_refuses_healthy_task() {
+        let mut tasks = tasks();
+        let msg = (SUPERVISOR as u32).to_le_bytes();
+        let response =
+            kipc(&mut tasks, AGENT, Kipcnum::ReadTaskRegisters, &msg);
+        assert_eq!(
+            response,
+            Err(FaultInfo::SyscallUsage(UsageError::IllegalTask))
+        );
+    }
+
+    #[test]
+    fn dump_refuses_memory_task_cannot_read() {
+        let mut tasks = tasks();
+        let mut msg = [0; 12];
+        let region = TaskDumpRegion {
+            base: ram_base(&tasks[AGENT]) + DATA,
+            size: 4,
+        };
+        ssmarshal::serialize(&mut msg, &(VICTIM as u32, region)).unwrap();
+        let response =
+            kipc(&mut tasks, AGENT, Kipcnum::ReadTaskDumpRegion, &msg);
+        assert!(matches!(response, Err(FaultInfo::MemoryAccess { .. })));
+        assert_eq!(fault(&tasks, VICTIM), Some(FaultInfo::Panic));
+    }
+
+    #[test]
+    fn dump_refuses_memory_outside_dump_regions() {
+        let mut tasks = tasks();
+        let rw = RegionAttributes::READ | RegionAttributes::WRITE;
+        let ram = region(0x1000, rw);
+        let flash = region(0x1000, RegionAttributes::READ);
+        let device = region(0x1000, rw | RegionAttributes::DEVICE);
+        tasks[VICTIM] = make_task(VICTIM, 1, &[ram, flash, device]);
+        let _ = force_fault(&mut tasks, VICTIM, FaultInfo::Panic);
+
+        // Only RAM is offered for dumping...
+        let mut msg = [0; 8];
+        ssmarshal::serialize(&mut msg, &(VICTIM as u32, 1u32)).unwrap();
+        let (_, response) =
+            kipc(&mut tasks, AGENT, Kipcnum::GetTaskDumpRegion, &msg).unwrap();
+        let (region, _): (Option<TaskDumpRegion>, _) =
+            ssmarshal::deserialize(&response).unwrap();
+        assert_eq!(region, None);
+
+        // ...and the rest can't be read, even though the task could read it.
+        for r in [flash, device] {
+            let mut msg = [0; 12];
+            let region = TaskDumpRegion {
+                base: r.base,
+                size: 4,
+            };
+            ssmarshal::serialize(&mut msg, &(VICTIM as u32, region)).unwrap();
+            let response =
+                kipc(&mut tasks, AGENT, Kipcnum::ReadTaskDumpRegion, &msg);
+            assert_eq!(
+                response,
+                Err(FaultInfo::MemoryAccess {
+                    address: Some(r.base),
+                    source: abi::FaultSource::Kernel,
+                })
+            );
+        }
+
+        // Nor can a read start in RAM and run off the end.
+        let mut msg = [0; 12];
+        let region = TaskDumpRegion {
+            base: ram.base + ram.size - 4,
+            size: 8,
+        };
+        ssmarshal::serialize(&mut msg, &(VICTIM as u32, region)).unwrap();
+        let response =
+            kipc(&mut tasks, AGENT, Kipcnum::ReadTaskDumpRegion, &msg);
+        assert!(matches!(response, Err(FaultInfo::MemoryAccess { .. })));
+        assert_eq!(fault(&tasks, VICTIM), Some(FaultInfo::Panic));
+    }
+
+    #[test]
+    fn any_task_reads_image_header() {
+        let mut tasks = task_table(&[0, 1, 1]);
+        let (rc, response) =
+            kipc(&mut tasks, AGENT, Kipcnum::ReadImageHeader, &[0; 4]).unwrap();
+        assert_eq!(rc, 0);
+        assert_eq!(response.len(), image_header_len().min(BUF_LEN as usize));
+    }
+
+    #[test]
+    fn image_header_reads_in_chunks() {
+        let mut tasks = task_table(&[0, 1, 1]);
+        let len = image_header_len();
+        assert!(len > BUF_LEN as usize, "manifest fits in one read");
+
+        let mut header = vec![];
+        loop {
+            let mut msg = [0; 4];
+            ssmarshal::serialize(&mut msg, &(header.len() as u32)).unwrap();
+            let (rc, response) =
+                kipc(&mut tasks, AGENT, Kipcnum::ReadImageHeader, &msg)
+                    .unwrap();
+            assert_eq!(rc, 0);
+            if response.is_empty() {
+                break;
+            }
+            header.extend(response);
+        }
+        assert_eq!(header.len(), len);
+
+        // Reading from well past the end is empty rather than a fault.
+        let mut msg = [0; 4];
+        ssmarshal::serialize(&mut msg, &u32::MAX).unwrap();
+        let (rc, response) =
+            kipc(&mut tasks, AGENT, Kipcnum::ReadImageHeader, &msg).unwrap();
+        assert_eq!(rc, 0);
+        assert!(response.is_empty());
+    }
+
+    /// Length of the image header and the simulator's task manifest.
+    fn image_header_len() -> usize {
+        core::mem::size_of::<abi::ImageHeader>()
+            + crate::header::MANIFEST_LEN
+                * core::mem::size_of::<abi::TaskManifestEntry>()
+    }
+}
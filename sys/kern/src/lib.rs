@@ -12,6 +12,10 @@
 //! on ARMv7-M, so it's entirely possible that some ARM-isms have
 //! unintentionally leaked into the portable parts.
 //!
+//! When built for a hosted target, the kernel uses a simulated architecture
+//! (`arch::sim`) instead, so that the portable parts can be unit tested on a
+//! development machine with `cargo test -p kern`.
+//!
 //! # Design principles
 //!
 //! While this isn't a *deeply* principled kernel, there are some basic ideas
@@ -44,6 +48,7 @@ pub mod header;
 pub mod kipc;
 pub mod profiling;
 pub mod startup;
+mod stats;
 pub mod syscalls;
 pub mod task;
 pub mod time;
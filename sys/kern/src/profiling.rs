@@ -43,6 +43,16 @@
 //! 2. Determine the base address of the task array (`HUBRIS_TASK_TABLE_SPACE`).
 //! 3. Compute the code corresponding to each task index as
 //!    `(HUBRIS_TASK_TABLE_SPACE + index * size) >> 4 & PINS_EXPOSED`.
+//!
+//! # Trace buffer backend
+//!
+//! If you'd rather not dedicate GPIOs and a logic analyzer, the `trace`
+//! module (behind the `trace-buffer` feature) provides a ready-made
+//! `EventsTable` that records events into a ring buffer in kernel RAM. It
+//! does the task index translation described above for you.
+
+#[cfg(feature = "trace-buffer")]
+pub mod trace;
 
 use core::sync::atomic::Ordering;
 cfg_if::cfg_if! {
@@ -167,12 +177,15 @@ pub fn event_isr_exit() {
     }
 }
 
+// The simulator has no timer interrupt; its tests move time on by hand.
+#[cfg(target_os = "none")]
 pub(crate) fn event_timer_isr_enter() {
     if let Some(t) = table() {
         (t.timer_isr_enter)()
     }
 }
 
+#[cfg(target_os = "none")]
 pub(crate) fn event_timer_isr_exit() {
     if let Some(t) = table() {
         (t.timer_isr_exit)()
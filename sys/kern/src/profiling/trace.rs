@@ -0,0 +1,141 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Built-in profiling backend that records events into a RAM trace buffer.
+//!
+//! This is an alternative to wiring the profiling hooks to GPIOs for a logic
+//! analyzer. Each event is written, along with a timestamp from the
+//! architecture's run-time counter, into `KERNEL_TRACE`, a ring buffer in
+//! kernel RAM at a well-known symbol. A debugger can dump that buffer at any
+//! point, and `cargo xtask trace` will turn the dump into a Chrome/Perfetto
+//! trace.
+//!
+//! To use it, enable the kernel's `trace-buffer` feature and, in the board's
+//! startup routine, call
+//!
+//! ```ignore
+//! kern::profiling::trace::install(CYCLES_PER_MS);
+//! ```
+//!
+//! before `start_kernel`, in place of `configure_events_table`.
+//!
+//! Unlike the GPIO implementations, context switch events record the index
+//! of the incoming task rather than bits of its TCB address.
+
+use crate::profiling::{configure_events_table, EventsTable};
+
+/// Number of records in the ring buffer. At 16 bytes per record this costs
+/// 4 KiB of kernel RAM.
+pub const TRACE_CAPACITY: usize = 256;
+
+/// Value of `TraceBuffer::magic` once the buffer has been initialized, so
+/// that tools can tell it apart from uninitialized RAM.
+pub const TRACE_MAGIC: u32 = 0x5452_4143; // "TRAC"
+
+/// Kinds of event that can appear in a `TraceRecord`. The `arg` field's
+/// meaning depends on the kind, as noted.
+///
+/// These values are part of the dump format read by `xtask trace`; don't
+/// renumber them.
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+#[repr(u32)]
+pub enum TraceKind {
+    /// `arg` is the syscall number.
+    SyscallEnter = 1,
+    SyscallExit = 2,
+    SecondarySyscallEnter = 3,
+    SecondarySyscallExit = 4,
+    IsrEnter = 5,
+    IsrExit = 6,
+    TimerIsrEnter = 7,
+    TimerIsrExit = 8,
+    /// `arg` is the index of the task being switched to.
+    ContextSwitch = 9,
+}
+
+/// A single event in the trace buffer.
+#[derive(Copy, Clone, Debug)]
+#[repr(C)]
+pub struct TraceRecord {
+    /// Value of the architecture's run-time counter when the event occurred.
+    pub timestamp: u64,
+    /// A `TraceKind`, or 0 for a record that has never been written.
+    pub kind: u32,
+    pub arg: u32,
+}
+
+/// The trace buffer, laid out for consumption by a debugger.
+#[repr(C)]
+pub struct TraceBuffer {
+    /// `TRACE_MAGIC` once initialized.
+    pub magic: u32,
+    /// Run-time counter ticks per millisecond, for converting timestamps.
+    pub counts_per_ms: u32,
+    /// Number of entries in `records`.
+    pub capacity: u32,
+    /// Total number of records ever written. The next record goes at index
+    /// `written % capacity`; if `written > capacity`, the oldest record is
+    /// also at that index.
+    pub written: u32,
+    pub records: [TraceRecord; TRACE_CAPACITY],
+}
+
+#[no_mangle]
+static mut KERNEL_TRACE: TraceBuffer = TraceBuffer {
+    magic: 0,
+    counts_per_ms: 0,
+    capacity: TRACE_CAPACITY as u32,
+    written: 0,
+    records: [TraceRecord {
+        timestamp: 0,
+        kind: 0,
+        arg: 0,
+    }; TRACE_CAPACITY],
+};
+
+static TRACE_EVENTS: EventsTable = EventsTable {
+    syscall_enter: |nr| record(TraceKind::SyscallEnter, nr),
+    syscall_exit: || record(TraceKind::SyscallExit, 0),
+    secondary_syscall_enter: || record(TraceKind::SecondarySyscallEnter, 0),
+    secondary_syscall_exit: || record(TraceKind::SecondarySyscallExit, 0),
+    isr_enter: || record(TraceKind::IsrEnter, 0),
+    isr_exit: || record(TraceKind::IsrExit, 0),
+    timer_isr_enter: || record(TraceKind::TimerIsrEnter, 0),
+    timer_isr_exit: || record(TraceKind::TimerIsrExit, 0),
+    context_switch: |tcb| {
+        let index = crate::startup::task_index_from_addr(tcb)
+            .map(|i| i as u32)
+            .unwrap_or(u32::MAX);
+        record(TraceKind::ContextSwitch, index)
+    },
+};
+
+/// Initializes the trace buffer and installs it as the kernel's profiling
+/// events table.
+///
+/// `counts_per_ms` should be the same `tick_divisor` passed to
+/// `start_kernel`, which is also the rate of the run-time counter.
+pub fn install(counts_per_ms: u32) {
+    // Safety: this is called from the board's startup routine before the
+    // kernel starts, so nothing else can be using the buffer yet.
+    let buf = unsafe { &mut *core::ptr::addr_of_mut!(KERNEL_TRACE) };
+    buf.counts_per_ms = counts_per_ms;
+    buf.written = 0;
+    buf.magic = TRACE_MAGIC;
+    configure_events_table(&TRACE_EVENTS);
+}
+
+fn record(kind: TraceKind, arg: u32) {
+    let timestamp = crate::arch::run_time_counter();
+    // Safety: profiling hooks are only called from kernel context, which is
+    // single-threaded and doesn't nest, so this is the only reference.
+    let buf = unsafe { &mut *core::ptr::addr_of_mut!(KERNEL_TRACE) };
+    let slot = buf.written as usize % TRACE_CAPACITY;
+    buf.records[slot] = TraceRecord {
+        timestamp,
+        kind: kind as u32,
+        arg,
+    };
+    buf.written = buf.written.wrapping_add(1);
+}
@@ -126,5 +126,20 @@ pub(crate) fn with_task_table<R>(body: impl FnOnce(&mut [Task]) -> R) -> R {
     r
 }
 
+/// Converts the address of an entry in the task table, as given to the
+/// profiling `context_switch` hook, back into a task index.
+#[cfg(feature = "trace-buffer")]
+pub(crate) fn task_index_from_addr(addr: usize) -> Option<usize> {
+    // Safety: we're only taking the address of the task table, not reading
+    // or writing through it.
+    let base = unsafe { core::ptr::addr_of!(HUBRIS_TASK_TABLE_SPACE) } as usize;
+    let offset = addr.checked_sub(base)?;
+    let size = core::mem::size_of::<Task>();
+    if offset % size != 0 || offset / size >= HUBRIS_TASK_COUNT {
+        return None;
+    }
+    Some(offset / size)
+}
+
 use crate::descs::*;
 include!(concat!(env!("OUT_DIR"), "/kconfig.rs"));
@@ -0,0 +1,193 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Per-task CPU time accounting.
+//!
+//! Each time the kernel switches tasks (in `task::activate_next_task`), it
+//! charges the time elapsed since the previous switch to the outgoing task, as
+//! measured by the architecture's `run_time_counter`. Tasks can read the
+//! results through the `ReadTaskStats` kipc.
+//!
+//! Time spent in the kernel itself -- syscalls, interrupts -- is charged to
+//! whichever task was running when the kernel was entered. This is the same
+//! attribution a logic analyzer hooked to the `profiling` events would show.
+//!
+//! The counters live here, rather than in `Task`, because
+//! `activate_next_task` is only given the incoming task, and we'd rather not
+//! manufacture a second reference into the task table to reach the outgoing
+//! one.
+
+use abi::TaskStats;
+
+#[cfg(target_os = "none")]
+use crate::startup::HUBRIS_TASK_COUNT;
+
+struct Accounting {
+    /// Counters for each task, indexed by task index.
+    stats: Counters,
+    /// Index of the task most recently switched in, if any.
+    current: Option<usize>,
+    /// Value of the run-time counter when `current` was switched in.
+    switched_in_at: u64,
+}
+
+#[cfg(target_os = "none")]
+type Counters = [TaskStats; HUBRIS_TASK_COUNT];
+
+#[cfg(target_os = "none")]
+static mut ACCOUNTING: Accounting = Accounting {
+    stats: [TaskStats {
+        run_time: 0,
+        switches: 0,
+    }; HUBRIS_TASK_COUNT],
+    current: None,
+    switched_in_at: 0,
+};
+
+#[cfg(target_os = "none")]
+fn with_accounting<R>(body: impl FnOnce(&mut Accounting) -> R) -> R {
+    // Safety: the kernel is single-threaded and not preemptible, and the
+    // functions in this module neither call each other nor call out to code
+    // that could, so this reference is unique for its lifetime.
+    body(unsafe { &mut *core::ptr::addr_of_mut!(ACCOUNTING) })
+}
+
+// The simulator's task tables are built by the tests at runtime, so there's
+// no task count to size the counters by; they grow to fit instead. Tests run
+// in parallel, so like the simulated clock, each test thread gets its own.
+// The harness runs tests one after another on a single thread when it has only
+// one CPU, so tests that look at the counters start by resetting them.
+#[cfg(not(target_os = "none"))]
+type Counters = std::vec::Vec<TaskStats>;
+
+#[cfg(not(target_os = "none"))]
+std::thread_local! {
+    static ACCOUNTING: core::cell::RefCell<Accounting> =
+        core::cell::RefCell::new(Accounting {
+            stats: Counters::new(),
+            current: None,
+            switched_in_at: 0,
+        });
+}
+
+#[cfg(not(target_os = "none"))]
+fn with_accounting<R>(body: impl FnOnce(&mut Accounting) -> R) -> R {
+    ACCOUNTING.with(|acct| body(&mut acct.borrow_mut()))
+}
+
+impl Accounting {
+    #[cfg(target_os = "none")]
+    fn task(&mut self, index: usize) -> &mut TaskStats {
+        &mut self.stats[index]
+    }
+
+    #[cfg(not(target_os = "none"))]
+    fn task(&mut self, index: usize) -> &mut TaskStats {
+        if index >= self.stats.len() {
+            self.stats.resize(index + 1, TaskStats::default());
+        }
+        &mut self.stats[index]
+    }
+}
+
+/// Charges elapsed time to the outgoing task and records `next` as running.
+pub(crate) fn record_switch(next: usize) {
+    let now = crate::arch::run_time_counter();
+    with_accounting(|acct| {
+        if let Some(prev) = acct.current {
+            let elapsed = now.wrapping_sub(acct.switched_in_at);
+            let stats = acct.task(prev);
+            stats.run_time = stats.run_time.wrapping_add(elapsed);
+            if prev == next {
+                // Rescheduling the same task isn't a context switch; keep
+                // accumulating without bumping the counter.
+                acct.switched_in_at = now;
+                return;
+            }
+        }
+        let stats = acct.task(next);
+        stats.switches = stats.switches.wrapping_add(1);
+        acct.current = Some(next);
+        acct.switched_in_at = now;
+    })
+}
+
+/// Returns the counters for task `index`, including any time it has run
+/// since it was last switched in.
+///
+/// # Panics
+///
+/// If `index` is out of range for the task table.
+pub(crate) fn read(index: usize) -> TaskStats {
+    let now = crate::arch::run_time_counter();
+    with_accounting(|acct| {
+        let mut stats = *acct.task(index);
+        if acct.current == Some(index) {
+            stats.run_time = stats
+                .run_time
+                .wrapping_add(now.wrapping_sub(acct.switched_in_at));
+        }
+        stats
+    })
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arch::set_now;
+
+    /// Forgets whatever earlier tests on this thread recorded.
+    fn reset() {
+        with_accounting(|acct| {
+            acct.stats.clear();
+            acct.current = None;
+            acct.switched_in_at = 0;
+        })
+    }
+
+    #[test]
+    fn time_is_charged_to_outgoing_task() {
+        reset();
+        set_now(0);
+        record_switch(0);
+        set_now(10);
+        record_switch(2);
+        set_now(15);
+
+        assert_eq!(
+            read(0),
+            TaskStats {
+                run_time: 10,
+                switches: 1,
+            }
+        );
+        // The running task includes time since it was switched in.
+        assert_eq!(
+            read(2),
+            TaskStats {
+                run_time: 5,
+                switches: 1,
+            }
+        );
+        assert_eq!(read(1), TaskStats::default());
+    }
+
+    #[test]
+    fn rescheduling_same_task_is_not_a_switch() {
+        reset();
+        set_now(0);
+        record_switch(1);
+        set_now(4);
+        record_switch(1);
+        set_now(6);
+
+        assert_eq!(
+            read(1),
+            TaskStats {
+                run_time: 6,
+                switches: 1,
+            }
+        );
+    }
+}
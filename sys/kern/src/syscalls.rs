@@ -99,7 +99,7 @@ pub unsafe extern "C" fn syscall_entry(nr: u32, task: *const Task) {
 /// unsafe.
 fn safe_syscall_entry(nr: u32, current: usize, tasks: &mut [Task]) -> NextTask {
     let res = match Sysnum::try_from(nr) {
-        Ok(Sysnum::Send) => send(tasks, current),
+        Ok(Sysnum::Send) => send(tasks, current, None),
         Ok(Sysnum::Recv) => recv(tasks, current).map_err(UserError::from),
         Ok(Sysnum::Reply) => reply(tasks, current).map_err(UserError::from),
         Ok(Sysnum::SetTimer) => Ok(set_timer(&mut tasks[current], arch::now())),
@@ -114,6 +114,10 @@ fn safe_syscall_entry(nr: u32, current: usize, tasks: &mut [Task]) -> NextTask {
         Ok(Sysnum::ReplyFault) => {
             reply_fault(tasks, current).map_err(UserError::from)
         }
+        Ok(Sysnum::SendWithTimeout) => {
+            let timeout = tasks[current].save().as_send_timeout();
+            send(tasks, current, timeout)
+        }
         Err(_) => {
             // Bogus syscall number! That's a fault.
             Err(FaultInfo::SyscallUsage(UsageError::BadSyscallNumber).into())
@@ -131,29 +135,43 @@ fn safe_syscall_entry(nr: u32, current: usize, tasks: &mut [Task]) -> NextTask {
     }
 }
 
-/// Implementation of the SEND IPC primitive.
+/// Implementation of the SEND IPC primitive, and of SEND_WITH_TIMEOUT, which
+/// also gives the `timeout` in ticks after which the kernel cancels it.
 ///
 /// `caller` is a valid task index (i.e. not directly from user code).
 ///
 /// # Panics
 ///
 /// If `caller` is out of range for `tasks`.
-fn send(tasks: &mut [Task], caller: usize) -> Result<NextTask, UserError> {
+fn send(
+    tasks: &mut [Task],
+    caller: usize,
+    timeout: Option<u32>,
+) -> Result<NextTask, UserError> {
     // Extract callee.
     let callee_id = tasks[caller].save().as_send_args().callee;
 
-    // Check IPC filter - TODO
-    // Open question: should out-of-range task IDs be handled by faulting below,
-    // or by failing the IPC filter? Either condition will fault...
-
-    // Route kernel messages.
+    // Route kernel messages. The kernel isn't a task, so it's never subject
+    // to the IPC allowlist.
     if callee_id == TaskId::KERNEL {
         return crate::kipc::handle_kernel_message(tasks, caller);
     }
 
+    // Check the allowlist before the callee's generation, so that a task
+    // can't learn anything about tasks it isn't allowed to talk to.
+    check_ipc_allowed(tasks, caller, callee_id)?;
+
     // Verify the given callee ID, converting it into a table index on success.
     let callee = task::check_task_id_against_table(tasks, callee_id)?;
 
+    // If an earlier send to this callee timed out after it received the
+    // message, it may still reply to that one. Don't give it the chance to
+    // mix the two up.
+    if tasks[caller].awaits_abandoned_reply(callee) {
+        return Err(UserError::Recoverable(abi::TIMED_OUT, NextTask::Same));
+    }
+    tasks[caller].start_send(arch::now(), timeout);
+
     // Check for ready peer.
     let mut next_task = NextTask::Same;
     let caller_id = current_id(tasks, caller);
@@ -188,6 +206,25 @@ fn send(tasks: &mut [Task], caller: usize) -> Result<NextTask, UserError> {
     Ok(NextTask::Other.combine(next_task))
 }
 
+/// Checks that `caller` may send to or post to `peer`, if its application
+/// restricts IPC. Out-of-range task IDs can't be in the allowlist, so they
+/// fail here rather than with `TaskOutOfRange`.
+fn check_ipc_allowed(
+    tasks: &[Task],
+    caller: usize,
+    peer: TaskId,
+) -> Result<(), UserError> {
+    if let Some(peers) = tasks[caller].descriptor().ipc_allowlist {
+        let index = u16::try_from(peer.index()).unwrap_lite();
+        if peers.binary_search(&index).is_err() {
+            return Err(
+                FaultInfo::SyscallUsage(UsageError::IpcNotAllowed).into()
+            );
+        }
+    }
+    Ok(())
+}
+
 /// Implementation of the RECV IPC primitive.
 ///
 /// `caller` is a valid task index (i.e. not directly from user code).
@@ -324,7 +361,8 @@ fn reply(tasks: &mut [Task], caller: usize) -> Result<NextTask, FaultInfo> {
     {
         // Huh. The target task is off doing something else. This can happen if
         // application-specific supervisory logic unblocks it before we've had a
-        // chance to reply (e.g. to implement timeouts).
+        // chance to reply, or if its send timed out.
+        tasks[callee].discard_reply_from(caller);
         return Ok(NextTask::Same);
     }
 
@@ -426,7 +464,9 @@ fn borrow_read(
 
     let lender = task::check_task_id_against_table(tasks, args.lender)?;
 
-    let lease =
+    // If the lease was forwarded, the memory belongs to the original lender,
+    // so that's who we copy from (and blame) below.
+    let (lease, lender) =
         borrow_lease(tasks, caller, lender, args.lease_number, args.offset)?;
 
     // Does the lease grant us the ability to read from the memory?
@@ -469,9 +509,11 @@ fn borrow_write(
 
     let lender = task::check_task_id_against_table(tasks, args.lender)?;
 
-    let lease =
+    let (lease, lender) =
         borrow_lease(tasks, caller, lender, args.lease_number, args.offset)?;
 
+    // As in `borrow_read`, `lender` is now the task that owns the memory.
+
     // Does the lease grant us the ability to write to the memory?
     if !lease.attributes.contains(LeaseAttributes::WRITE) {
         // Lease is not readable. Defecting lender.
@@ -511,7 +553,7 @@ fn borrow_info(
 
     let lender = task::check_task_id_against_table(tasks, args.lender)?;
 
-    let lease = borrow_lease(tasks, caller, lender, args.lease_number, 0)?;
+    let (lease, _) = borrow_lease(tasks, caller, lender, args.lease_number, 0)?;
 
     tasks[caller]
         .save_mut()
@@ -525,12 +567,53 @@ fn borrow_lease(
     lender: usize,
     lease_number: usize,
     offset: usize,
-) -> Result<ULease, UserError> {
+) -> Result<(ULease, usize), UserError> {
     let caller_id = current_id(tasks, caller);
 
+    let lease = match lender_lease(tasks, caller_id, lender, lease_number)? {
+        Some(lease) => lease,
+        None => {
+            // Borrower provided an invalid lease number. Borrower was told the
+            // number of leases on successful RECV and should respect that.
+            // (Note: if the lender's lease table changed shape, this will
+            // fault the borrower, which might be bad.)
+            return Err(
+                FaultInfo::SyscallUsage(UsageError::LeaseOutOfRange).into()
+            );
+        }
+    };
+    let (mut lease, holder) = resolve_forwarded_lease(tasks, lender, lease)?;
+
+    // Attempt to offset the lease. Handle cases where the offset is bogus.
+    // First, we must convert to u32, which _should be_ a no-op but we'll do
+    // it the careful way:
+    let offset = u32::try_from(offset).unwrap_lite();
+    // Now, proceed only if both neither the length nor address computation
+    // wrap.
+    if let (Some(off_len), Some(off_addr)) = (
+        lease.length.checked_sub(offset),
+        lease.base_address.checked_add(offset),
+    ) {
+        lease.base_address = off_addr;
+        lease.length = off_len;
+        Ok((lease, holder))
+    } else {
+        Err(FaultInfo::SyscallUsage(UsageError::OffsetOutOfRange).into())
+    }
+}
+
+/// Reads lease `lease_number` out of the lease table of `lender`, which must
+/// be blocked waiting for a reply from `borrower`. Returns `None` if the lease
+/// number is out of range, leaving it to the caller to decide who's at fault.
+fn lender_lease(
+    tasks: &mut [Task],
+    borrower: TaskId,
+    lender: usize,
+    lease_number: usize,
+) -> Result<Option<ULease>, UserError> {
     // Check state of lender and range of lease table.
     if tasks[lender].state()
-        != &TaskState::Healthy(SchedState::InReply(caller_id))
+        != &TaskState::Healthy(SchedState::InReply(borrower))
     {
         // The alleged lender isn't lending anything at all.
         // Let's assume this is a defecting lender.
@@ -562,32 +645,84 @@ fn borrow_lease(
     // Try reading the lease. This is unsafe in the general case, but since
     // we've just convinced ourselves that the lease table is in task memory,
     // we can do this safely.
-    let lease = leases.get(lease_number).cloned();
-    // Is the lease number provided by the borrower legitimate?
-    if let Some(mut lease) = lease {
-        // Attempt to offset the lease. Handle cases where the offset is bogus.
-        // First, we must convert to u32, which _should be_ a no-op but we'll do
-        // it the careful way:
-        let offset = u32::try_from(offset).unwrap_lite();
-        // Now, proceed only if both neither the length nor address computation
-        // wrap.
-        if let (Some(off_len), Some(off_addr)) = (
-            lease.length.checked_sub(offset),
-            lease.base_address.checked_add(offset),
-        ) {
-            lease.base_address = off_addr;
-            lease.length = off_len;
-            Ok(lease)
-        } else {
-            Err(FaultInfo::SyscallUsage(UsageError::OffsetOutOfRange).into())
+    Ok(leases.get(lease_number).cloned())
+}
+
+/// Follows `lease`, found in the lease table of `holder`, back through any
+/// forwarding to the task whose memory it actually describes.
+///
+/// Returns the lease with its attributes reduced to access rights and its
+/// base address pointing into the memory of the returned task.
+///
+/// Every task along the chain must still be blocked waiting for a reply from
+/// the task it lent to; if one isn't, the borrow fails with `DEFECT` just as
+/// it would if the immediate lender had gone away. A malformed forward is the
+/// fault of the task that sent it.
+fn resolve_forwarded_lease(
+    tasks: &mut [Task],
+    mut holder: usize,
+    mut lease: ULease,
+) -> Result<(ULease, usize), UserError> {
+    // Each hop needs a distinct task blocked in reply, so no valid chain is
+    // longer than the task table.
+    for _ in 0..tasks.len() {
+        let (original_id, original_number) = match lease.forwarded_from() {
+            Some(f) => f,
+            None => {
+                lease.attributes &= LeaseAttributes::ACCESS;
+                return Ok((lease, holder));
+            }
+        };
+        let forwarder_id = current_id(tasks, holder);
+
+        let original = original_id.index();
+        if original >= tasks.len() {
+            return Err(bad_forward(tasks, holder));
         }
-    } else {
-        // Borrower provided an invalid lease number. Borrower was told the
-        // number of leases on successful RECV and should respect that. (Note:
-        // if the lender's lease table changed shape, this will fault the
-        // borrower, which might be bad.)
-        Err(FaultInfo::SyscallUsage(UsageError::LeaseOutOfRange).into())
+        if tasks[original].generation() != original_id.generation() {
+            // The original lender has been restarted, taking its leases
+            // with it.
+            return Err(UserError::Recoverable(abi::DEFECT, NextTask::Same));
+        }
+        let inner =
+            match lender_lease(tasks, forwarder_id, original, original_number)?
+            {
+                Some(inner) => inner,
+                None => return Err(bad_forward(tasks, holder)),
+            };
+
+        // The forward may only narrow the original lease.
+        let fits = lease
+            .base_address
+            .checked_add(lease.length)
+            .map_or(false, |end| end <= inner.length);
+        let rights = lease.attributes & LeaseAttributes::ACCESS;
+        if !fits || !inner.attributes.contains(rights) {
+            return Err(bad_forward(tasks, holder));
+        }
+
+        // `lease.base_address` is an offset into `inner`; if `inner` is
+        // itself forwarded, its base is an offset too, and we go around
+        // again.
+        lease = ULease {
+            attributes: (inner.attributes & !LeaseAttributes::ACCESS) | rights,
+            base_address: inner.base_address.wrapping_add(lease.base_address),
+            length: lease.length,
+        };
+        holder = original;
     }
+    Err(UserError::Recoverable(abi::DEFECT, NextTask::Same))
+}
+
+/// Faults `forwarder` for sending a malformed forwarded lease and reports the
+/// defection to the borrower.
+fn bad_forward(tasks: &mut [Task], forwarder: usize) -> UserError {
+    let wake_hint = task::force_fault(
+        tasks,
+        forwarder,
+        FaultInfo::SyscallUsage(UsageError::BadForwardedLease),
+    );
+    UserError::Recoverable(abi::DEFECT, wake_hint)
 }
 
 /// Transfers a message from caller's context into callee's. This may be called
@@ -724,6 +859,7 @@ fn post(tasks: &mut [Task], caller: usize) -> Result<NextTask, UserError> {
     let args = tasks[caller].save().as_post_args();
     let peer_id = args.task_id;
 
+    check_ipc_allowed(tasks, caller, peer_id)?;
     let peer_idx = task::check_task_id_against_table(tasks, peer_id)?;
 
     let woke = tasks[peer_idx].post(args.notification_bits);
@@ -778,7 +914,8 @@ fn reply_fault(
     {
         // Huh. The target task is off doing something else. This can happen if
         // application-specific supervisory logic unblocks it before we've had a
-        // chance to reply (e.g. to implement timeouts).
+        // chance to reply, or if its send timed out.
+        tasks[callee].discard_reply_from(caller);
         return Ok(NextTask::Same);
     }
 
@@ -795,3 +932,827 @@ fn reply_fault(
     // the task using it faults.
     Ok(NextTask::Same)
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arch::{
+        ram_base, read_mem, restrict_ipc, set_now, task_table, write_mem,
+    };
+    use abi::Generation;
+
+    // Where the helpers below put things, as offsets into each task's RAM.
+    // These are all in the half of RAM that isn't stack.
+    const MSG: u32 = 0x800;
+    const RESPONSE: u32 = 0x900;
+    const LEASES: u32 = 0xA00;
+    const RECV_BUF: u32 = 0xB00;
+    const DATA: u32 = 0xC00;
+
+    const BUF_LEN: u32 = 64;
+
+    const SUPERVISOR: usize = 0;
+    const SERVER: usize = 1;
+    const CLIENT: usize = 2;
+    const DRIVER: usize = 3;
+
+    /// A supervisor, a server, a client, and a driver that the server can
+    /// pass work on to.
+    fn tasks() -> Vec<Task> {
+        task_table(&[0, 1, 2, 0])
+    }
+
+    fn id(tasks: &[Task], index: usize) -> TaskId {
+        current_id(tasks, index)
+    }
+
+    fn ret(tasks: &[Task], index: usize, n: usize) -> u32 {
+        tasks[index].save().ret(n)
+    }
+
+    fn fault(tasks: &[Task], index: usize) -> Option<FaultInfo> {
+        match tasks[index].state() {
+            TaskState::Faulted { fault, .. } => Some(*fault),
+            TaskState::Healthy(_) => None,
+        }
+    }
+
+    fn addr(tasks: &[Task], index: usize, offset: u32) -> u32 {
+        ram_base(&tasks[index]) + offset
+    }
+
+    /// Has task `caller` make syscall `nr`, returning the scheduling hint.
+    fn syscall(
+        tasks: &mut [Task],
+        caller: usize,
+        nr: Sysnum,
+        args: &[u32],
+    ) -> NextTask {
+        tasks[caller].save_mut().set_syscall(nr, args);
+        let nr = tasks[caller].save().syscall_descriptor();
+        safe_syscall_entry(nr, caller, tasks)
+    }
+
+    /// Sends `msg` from `caller` to `callee` as operation 1, lending `leases`.
+    fn send(
+        tasks: &mut [Task],
+        caller: usize,
+        callee: TaskId,
+        msg: &[u8],
+        leases: &[ULease],
+    ) -> NextTask {
+        let args = send_args(tasks, caller, callee, msg, leases);
+        syscall(tasks, caller, Sysnum::Send, &args)
+    }
+
+    /// Like `send`, but cancelled after `ticks` (if not zero).
+    fn timed_send(
+        tasks: &mut [Task],
+        caller: usize,
+        callee: TaskId,
+        msg: &[u8],
+        leases: &[ULease],
+        ticks: u32,
+    ) -> NextTask {
+        let mut args = send_args(tasks, caller, callee, msg, leases).to_vec();
+        args.push(ticks);
+        syscall(tasks, caller, Sysnum::SendWithTimeout, &args)
+    }
+
+    /// Writes out `msg` and `leases` in `caller`'s memory and returns the
+    /// SEND arguments that refer to them.
+    fn send_args(
+        tasks: &mut [Task],
+        caller: usize,
+        callee: TaskId,
+        msg: &[u8],
+        leases: &[ULease],
+    ) -> [u32; 7] {
+        let table: Vec<u8> = leases
+            .iter()
+            .flat_map(|l| [l.attributes.bits(), l.base_address, l.length])
+            .flat_map(u32::to_le_bytes)
+            .collect();
+        let (msg_addr, leases_addr) =
+            (addr(tasks, caller, MSG), addr(tasks, caller, LEASES));
+        write_mem(&mut tasks[caller], msg_addr, msg);
+        write_mem(&mut tasks[caller], leases_addr, &table);
+        [
+            u32::from(callee.0) << 16 | 1,
+            msg_addr,
+            msg.len() as u32,
+            addr(tasks, caller, RESPONSE),
+            BUF_LEN,
+            leases_addr,
+            leases.len() as u32,
+        ]
+    }
+
+    /// Does an open receive in `caller`, accepting notifications in `mask`.
+    fn recv(tasks: &mut [Task], caller: usize, mask: u32) -> NextTask {
+        let args = [addr(tasks, caller, RECV_BUF), BUF_LEN, mask, 0];
+        syscall(tasks, caller, Sysnum::Recv, &args)
+    }
+
+    fn reply(
+        tasks: &mut [Task],
+        caller: usize,
+        callee: TaskId,
+        code: u32,
+        msg: &[u8],
+    ) -> NextTask {
+        let msg_addr = addr(tasks, caller, MSG);
+        write_mem(&mut tasks[caller], msg_addr, msg);
+        let args = [u32::from(callee.0), code, msg_addr, msg.len() as u32];
+        syscall(tasks, caller, Sysnum::Reply, &args)
+    }
+
+    /// Has `caller` read `len` bytes of lease `n` from `lender`, starting at
+    /// `offset`, into its receive buffer.
+    fn borrow_read(
+        tasks: &mut [Task],
+        caller: usize,
+        lender: TaskId,
+        n: u32,
+        offset: u32,
+        len: u32,
+    ) -> NextTask {
+        let args = [
+            u32::from(lender.0),
+            n,
+            offset,
+            addr(tasks, caller, RECV_BUF),
+            len,
+        ];
+        syscall(tasks, caller, Sysnum::BorrowRead, &args)
+    }
+
+    /// Has `caller` write `data` to lease `n` from `lender`, at `offset`.
+    fn borrow_write(
+        tasks: &mut [Task],
+        caller: usize,
+        lender: TaskId,
+        n: u32,
+        offset: u32,
+        data: &[u8],
+    ) -> NextTask {
+        let msg_addr = addr(tasks, caller, MSG);
+        write_mem(&mut tasks[caller], msg_addr, data);
+        let args =
+            [u32::from(lender.0), n, offset, msg_addr, data.len() as u32];
+        syscall(tasks, caller, Sysnum::BorrowWrite, &args)
+    }
+
+    /// Lends `len` bytes of `index`'s data area, which is filled with a
+    /// recognizable pattern.
+    fn data_lease(
+        tasks: &mut [Task],
+        index: usize,
+        attributes: LeaseAttributes,
+        len: u32,
+    ) -> ULease {
+        let base = addr(tasks, index, DATA);
+        write_mem(&mut tasks[index], base, b"0123456789abcdef");
+        ULease {
+            attributes,
+            base_address: base,
+            length: len,
+        }
+    }
+
+    /// Gets a message, with `leases`, from the client to the server.
+    fn connect(tasks: &mut [Task], leases: &[ULease]) {
+        let server = id(tasks, SERVER);
+        let _ = send(tasks, CLIENT, server, b"hello", leases);
+        assert_eq!(recv(tasks, SERVER, 0), NextTask::Same);
+    }
+
+    /// Has the server, already holding a lease from the client, pass
+    /// `make_lease(client)` on to the driver.
+    fn forward(tasks: &mut [Task], make_lease: impl FnOnce(TaskId) -> ULease) {
+        let lease = make_lease(id(tasks, CLIENT));
+        let driver = id(tasks, DRIVER);
+        assert_eq!(recv(tasks, DRIVER, 0), NextTask::Other);
+        assert_eq!(
+            send(tasks, SERVER, driver, b"fwd", &[lease]),
+            NextTask::Specific(DRIVER)
+        );
+    }
+
+    #[test]
+    fn send_blocks_until_recv() {
+        let mut tasks = tasks();
+        let server = id(&tasks, SERVER);
+        let client = id(&tasks, CLIENT);
+
+        assert_eq!(
+            send(&mut tasks, CLIENT, server, b"hello", &[]),
+            NextTask::Other
+        );
+        assert_eq!(
+            tasks[CLIENT].state(),
+            &TaskState::Healthy(SchedState::InSend(server))
+        );
+
+        assert_eq!(recv(&mut tasks, SERVER, 0), NextTask::Same);
+        assert_eq!(ret(&tasks, SERVER, 1), u32::from(client.0));
+        assert_eq!(ret(&tasks, SERVER, 2), 1);
+        assert_eq!(ret(&tasks, SERVER, 3), 5);
+        assert_eq!(ret(&tasks, SERVER, 4), BUF_LEN);
+        assert_eq!(ret(&tasks, SERVER, 5), 0);
+        let buf = addr(&tasks, SERVER, RECV_BUF);
+        assert_eq!(read_mem(&tasks[SERVER], buf, 5), b"hello");
+        assert_eq!(
+            tasks[CLIENT].state(),
+            &TaskState::Healthy(SchedState::InReply(server))
+        );
+    }
+
+    #[test]
+    fn send_to_waiting_server_switches_to_it() {
+        let mut tasks = tasks();
+        let server = id(&tasks, SERVER);
+
+        assert_eq!(recv(&mut tasks, SERVER, 0), NextTask::Other);
+        assert_eq!(
+            tasks[SERVER].state(),
+            &TaskState::Healthy(SchedState::InRecv(None))
+        );
+        assert_eq!(
+            send(&mut tasks, CLIENT, server, b"hello", &[]),
+            NextTask::Specific(SERVER)
+        );
+        assert!(tasks[SERVER].is_runnable());
+        assert_eq!(ret(&tasks, SERVER, 3), 5);
+    }
+
+    #[test]
+    fn reply_resumes_client() {
+        let mut tasks = tasks();
+        connect(&mut tasks, &[]);
+        let client = id(&tasks, CLIENT);
+
+        assert_eq!(
+            reply(&mut tasks, SERVER, client, 7, b"world"),
+            NextTask::Same
+        );
+        assert!(tasks[CLIENT].is_runnable());
+        assert_eq!(ret(&tasks, CLIENT, 0), 7);
+        assert_eq!(ret(&tasks, CLIENT, 1), 5);
+        let buf = addr(&tasks, CLIENT, RESPONSE);
+        assert_eq!(read_mem(&tasks[CLIENT], buf, 5), b"world");
+    }
+
+    #[test]
+    fn send_to_nonexistent_task_faults_sender() {
+        let mut tasks = tasks();
+        let bogus =
+            TaskId::for_index_and_gen(tasks.len(), Generation::default());
+
+        let _ = send(&mut tasks, CLIENT, bogus, b"hello", &[]);
+        assert_eq!(
+            fault(&tasks, CLIENT),
+            Some(FaultInfo::SyscallUsage(UsageError::TaskOutOfRange))
+        );
+    }
+
+    #[test]
+    fn allowlist_permits_listed_peers() {
+        let mut tasks = tasks();
+        restrict_ipc(&mut tasks[CLIENT], &[SERVER as u16]);
+        let server = id(&tasks, SERVER);
+
+        assert_eq!(
+            send(&mut tasks, CLIENT, server, b"hello", &[]),
+            NextTask::Other
+        );
+        assert_eq!(fault(&tasks, CLIENT), None);
+    }
+
+    #[test]
+    fn allowlist_faults_send_to_unlisted_peer() {
+        let mut tasks = tasks();
+        restrict_ipc(&mut tasks[CLIENT], &[SERVER as u16]);
+        let driver = id(&tasks, DRIVER);
+
+        assert_eq!(recv(&mut tasks, DRIVER, 0), NextTask::Other);
+        let _ = send(&mut tasks, CLIENT, driver, b"hello", &[]);
+        assert_eq!(
+            fault(&tasks, CLIENT),
+            Some(FaultInfo::SyscallUsage(UsageError::IpcNotAllowed))
+        );
+        // The message never reached the driver.
+        assert_eq!(
+            tasks[DRIVER].state(),
+            &TaskState::Healthy(SchedState::InRecv(None))
+        );
+    }
+
+    #[test]
+    fn allowlist_faults_post_to_unlisted_peer() {
+        let mut tasks = tasks();
+        restrict_ipc(&mut tasks[CLIENT], &[SERVER as u16]);
+        let driver = id(&tasks, DRIVER);
+
+        let _ = syscall(
+            &mut tasks,
+            CLIENT,
+            Sysnum::Post,
+            &[u32::from(driver.0), 1],
+        );
+        assert_eq!(
+            fault(&tasks, CLIENT),
+            Some(FaultInfo::SyscallUsage(UsageError::IpcNotAllowed))
+        );
+    }
+
+    /// Sends a message from the client that times out after 10 ticks, and
+    /// has `server` receive it.
+    fn timed_connect(tasks: &mut [Task], server: usize) {
+        let server_id = id(tasks, server);
+        let _ = timed_send(tasks, CLIENT, server_id, b"hello", &[], 10);
+        assert_eq!(recv(tasks, server, 0), NextTask::Same);
+    }
+
+    #[test]
+    fn send_times_out_before_recv() {
+        let mut tasks = tasks();
+        let server = id(&tasks, SERVER);
+        set_now(100);
+
+        assert_eq!(
+            timed_send(&mut tasks, CLIENT, server, b"hello", &[], 10),
+            NextTask::Other
+        );
+        assert_eq!(
+            task::process_timers(&mut tasks, Timestamp::from(109)),
+            NextTask::Same
+        );
+        assert_eq!(
+            task::process_timers(&mut tasks, Timestamp::from(110)),
+            NextTask::Specific(CLIENT)
+        );
+        assert!(tasks[CLIENT].is_runnable());
+        assert_eq!(ret(&tasks, CLIENT, 0), abi::TIMED_OUT);
+        assert_eq!(ret(&tasks, CLIENT, 1), 0);
+
+        // The message was withdrawn, so the server doesn't get it.
+        assert_eq!(recv(&mut tasks, SERVER, 0), NextTask::Other);
+    }
+
+    #[test]
+    fn send_times_out_awaiting_reply() {
+        let mut tasks = tasks();
+        set_now(100);
+        timed_connect(&mut tasks, SERVER);
+        let server = id(&tasks, SERVER);
+        let client = id(&tasks, CLIENT);
+
+        let _ = task::process_timers(&mut tasks, Timestamp::from(110));
+        assert!(tasks[CLIENT].is_runnable());
+        assert_eq!(ret(&tasks, CLIENT, 0), abi::TIMED_OUT);
+
+        // Until the server replies to the first message, sending to it again
+        // fails straight away.
+        assert_eq!(
+            send(&mut tasks, CLIENT, server, b"again", &[]),
+            NextTask::Same
+        );
+        assert_eq!(ret(&tasks, CLIENT, 0), abi::TIMED_OUT);
+
+        // The late reply is dropped, rather than being taken for a reply to
+        // anything else.
+        let _ = reply(&mut tasks, SERVER, client, 7, b"late");
+        assert!(tasks[CLIENT].is_runnable());
+        assert_eq!(ret(&tasks, CLIENT, 0), abi::TIMED_OUT);
+
+        assert_eq!(
+            send(&mut tasks, CLIENT, server, b"again", &[]),
+            NextTask::Other
+        );
+        assert_eq!(
+            tasks[CLIENT].state(),
+            &TaskState::Healthy(SchedState::InSend(server))
+        );
+    }
+
+    #[test]
+    fn send_finished_in_time_is_not_cancelled() {
+        let mut tasks = tasks();
+        set_now(100);
+        timed_connect(&mut tasks, SERVER);
+        let client = id(&tasks, CLIENT);
+        let _ = reply(&mut tasks, SERVER, client, 7, b"world");
+
+        let _ = task::process_timers(&mut tasks, Timestamp::from(110));
+        assert_eq!(ret(&tasks, CLIENT, 0), 7);
+        assert!(!tasks[CLIENT].awaits_abandoned_reply(SERVER));
+    }
+
+    #[test]
+    fn timeout_applies_to_its_send_only() {
+        let mut tasks = tasks();
+        let server = id(&tasks, SERVER);
+        let client = id(&tasks, CLIENT);
+        set_now(100);
+        timed_connect(&mut tasks, SERVER);
+        let _ = reply(&mut tasks, SERVER, client, 7, b"world");
+
+        // A later send without a timeout may block for as long as it takes.
+        let _ = send(&mut tasks, CLIENT, server, b"again", &[]);
+        let _ = task::process_timers(&mut tasks, Timestamp::from(1000));
+        assert_eq!(
+            tasks[CLIENT].state(),
+            &TaskState::Healthy(SchedState::InSend(server))
+        );
+    }
+
+    #[test]
+    fn timeout_leaves_lease_count_alone() {
+        let mut tasks = tasks();
+        let server = id(&tasks, SERVER);
+        let lease = data_lease(&mut tasks, CLIENT, LeaseAttributes::READ, 10);
+        assert_eq!(recv(&mut tasks, SERVER, 0), NextTask::Other);
+
+        let _ = timed_send(&mut tasks, CLIENT, server, b"hello", &[lease], 10);
+        assert_eq!(ret(&tasks, SERVER, 5), 1);
+    }
+
+    #[test]
+    fn timeout_may_exceed_16_bits() {
+        let mut tasks = tasks();
+        let server = id(&tasks, SERVER);
+        set_now(100);
+
+        let _ = timed_send(&mut tasks, CLIENT, server, b"hello", &[], 100_000);
+        let _ = task::process_timers(&mut tasks, Timestamp::from(65_636));
+        assert_eq!(
+            tasks[CLIENT].state(),
+            &TaskState::Healthy(SchedState::InSend(server))
+        );
+        let _ = task::process_timers(&mut tasks, Timestamp::from(100_100));
+        assert_eq!(ret(&tasks, CLIENT, 0), abi::TIMED_OUT);
+    }
+
+    #[test]
+    fn send_timeouts_are_tracked_per_server() {
+        let mut tasks = tasks();
+        let server = id(&tasks, SERVER);
+        let driver = id(&tasks, DRIVER);
+        let client = id(&tasks, CLIENT);
+
+        // Both servers receive a message and then sit on it.
+        set_now(100);
+        timed_connect(&mut tasks, SERVER);
+        let _ = task::process_timers(&mut tasks, Timestamp::from(110));
+        assert_eq!(ret(&tasks, CLIENT, 0), abi::TIMED_OUT);
+
+        set_now(200);
+        timed_connect(&mut tasks, DRIVER);
+        let _ = task::process_timers(&mut tasks, Timestamp::from(210));
+        assert_eq!(ret(&tasks, CLIENT, 0), abi::TIMED_OUT);
+
+        // The second timeout doesn't make the client forget the first.
+        assert_eq!(
+            send(&mut tasks, CLIENT, server, b"again", &[]),
+            NextTask::Same
+        );
+        assert_eq!(ret(&tasks, CLIENT, 0), abi::TIMED_OUT);
+
+        // Once the first server replies, the client can send to it again,
+        // but not to the second.
+        let _ = reply(&mut tasks, SERVER, client, 7, b"late");
+        assert_eq!(
+            send(&mut tasks, CLIENT, driver, b"again", &[]),
+            NextTask::Same
+        );
+        assert_eq!(ret(&tasks, CLIENT, 0), abi::TIMED_OUT);
+        assert_eq!(
+            send(&mut tasks, CLIENT, server, b"again", &[]),
+            NextTask::Other
+        );
+        assert_eq!(
+            tasks[CLIENT].state(),
+            &TaskState::Healthy(SchedState::InSend(server))
+        );
+    }
+
+    #[test]
+    fn notification_interrupts_recv() {
+        let mut tasks = tasks();
+        let server = id(&tasks, SERVER);
+
+        assert_eq!(recv(&mut tasks, SERVER, 0b100), NextTask::Other);
+        // The server is more important than the client, so posting to it
+        // should switch to it.
+        assert_eq!(
+            syscall(
+                &mut tasks,
+                CLIENT,
+                Sysnum::Post,
+                &[u32::from(server.0), 0b110]
+            ),
+            NextTask::Specific(SERVER)
+        );
+        assert_eq!(ret(&tasks, CLIENT, 0), 0);
+        assert!(tasks[SERVER].is_runnable());
+        assert_eq!(ret(&tasks, SERVER, 1), u32::from(TaskId::KERNEL.0));
+        assert_eq!(ret(&tasks, SERVER, 2), 0b100);
+
+        // The other bit stays pending until asked for.
+        assert_eq!(recv(&mut tasks, SERVER, 0b010), NextTask::Same);
+        assert_eq!(ret(&tasks, SERVER, 2), 0b010);
+    }
+
+    #[test]
+    fn masked_notification_does_not_wake() {
+        let mut tasks = tasks();
+        let server = id(&tasks, SERVER);
+
+        assert_eq!(recv(&mut tasks, SERVER, 0b1), NextTask::Other);
+        assert_eq!(
+            syscall(
+                &mut tasks,
+                CLIENT,
+                Sysnum::Post,
+                &[u32::from(server.0), 0b10]
+            ),
+            NextTask::Same
+        );
+        assert_eq!(
+            tasks[SERVER].state(),
+            &TaskState::Healthy(SchedState::InRecv(None))
+        );
+    }
+
+    #[test]
+    fn expired_timer_posts_immediately() {
+        let mut tasks = tasks();
+        set_now(100);
+
+        let _ = syscall(&mut tasks, SERVER, Sysnum::GetTimer, &[]);
+        assert_eq!(ret(&tasks, SERVER, 0), 100);
+
+        // Deadline 50 has already passed, so setting it posts right away.
+        let _ = syscall(&mut tasks, SERVER, Sysnum::SetTimer, &[1, 50, 0, 1]);
+        assert_eq!(recv(&mut tasks, SERVER, 1), NextTask::Same);
+        assert_eq!(ret(&tasks, SERVER, 1), u32::from(TaskId::KERNEL.0));
+        assert_eq!(ret(&tasks, SERVER, 2), 1);
+    }
+
+    #[test]
+    fn send_to_restarted_task_reports_death() {
+        let mut tasks = tasks();
+        let stale = id(&tasks, SERVER);
+        tasks[SERVER].reinitialize();
+
+        assert_eq!(
+            send(&mut tasks, CLIENT, stale, b"hello", &[]),
+            NextTask::Same
+        );
+        assert_eq!(
+            ret(&tasks, CLIENT, 0),
+            abi::dead_response_code(tasks[SERVER].generation())
+        );
+        assert!(tasks[CLIENT].is_runnable());
+    }
+
+    #[test]
+    fn reply_to_restarted_client_is_dropped() {
+        let mut tasks = tasks();
+        connect(&mut tasks, &[]);
+        let stale = id(&tasks, CLIENT);
+        tasks[CLIENT].reinitialize();
+
+        assert_eq!(
+            reply(&mut tasks, SERVER, stale, 0, b"late"),
+            NextTask::Same
+        );
+        assert_eq!(fault(&tasks, SERVER), None);
+        assert_eq!(tasks[CLIENT].state(), &TaskState::default());
+    }
+
+    #[test]
+    fn fault_wakes_supervisor() {
+        let mut tasks = tasks();
+        assert_eq!(recv(&mut tasks, SUPERVISOR, 1), NextTask::Other);
+
+        // Syscall 0xff doesn't exist.
+        tasks[CLIENT].save_mut().set_syscall(Sysnum::Send, &[]);
+        assert_eq!(
+            safe_syscall_entry(0xff, CLIENT, &mut tasks),
+            NextTask::Specific(SUPERVISOR)
+        );
+        assert_eq!(
+            fault(&tasks, CLIENT),
+            Some(FaultInfo::SyscallUsage(UsageError::BadSyscallNumber))
+        );
+        assert_eq!(ret(&tasks, SUPERVISOR, 2), 1);
+    }
+
+    #[test]
+    fn borrow_read_copies_from_client() {
+        let mut tasks = tasks();
+        let lease = data_lease(&mut tasks, CLIENT, LeaseAttributes::READ, 10);
+        connect(&mut tasks, &[lease]);
+        assert_eq!(ret(&tasks, SERVER, 5), 1);
+        let client = id(&tasks, CLIENT);
+
+        assert_eq!(
+            borrow_read(&mut tasks, SERVER, client, 0, 2, 4),
+            NextTask::Same
+        );
+        assert_eq!(ret(&tasks, SERVER, 0), 0);
+        assert_eq!(ret(&tasks, SERVER, 1), 4);
+        let buf = addr(&tasks, SERVER, RECV_BUF);
+        assert_eq!(read_mem(&tasks[SERVER], buf, 4), b"2345");
+    }
+
+    #[test]
+    fn borrow_write_copies_to_client() {
+        let mut tasks = tasks();
+        let lease = data_lease(&mut tasks, CLIENT, LeaseAttributes::WRITE, 10);
+        connect(&mut tasks, &[lease]);
+        let client = id(&tasks, CLIENT);
+
+        let _ = borrow_write(&mut tasks, SERVER, client, 0, 1, b"xyz");
+        assert_eq!(ret(&tasks, SERVER, 0), 0);
+        assert_eq!(ret(&tasks, SERVER, 1), 3);
+        let data = addr(&tasks, CLIENT, DATA);
+        assert_eq!(read_mem(&tasks[CLIENT], data, 5), b"0xyz4");
+    }
+
+    #[test]
+    fn borrow_info_reports_lease() {
+        let mut tasks = tasks();
+        let lease = data_lease(
+            &mut tasks,
+            CLIENT,
+            LeaseAttributes::READ | LeaseAttributes::WRITE,
+            10,
+        );
+        connect(&mut tasks, &[lease]);
+        let client = id(&tasks, CLIENT);
+
+        let _ = syscall(
+            &mut tasks,
+            SERVER,
+            Sysnum::BorrowInfo,
+            &[u32::from(client.0), 0],
+        );
+        assert_eq!(ret(&tasks, SERVER, 0), 0);
+        assert_eq!(ret(&tasks, SERVER, 1), 0b11);
+        assert_eq!(ret(&tasks, SERVER, 2), 10);
+    }
+
+    #[test]
+    fn borrow_without_permission_is_defect() {
+        let mut tasks = tasks();
+        let lease = data_lease(&mut tasks, CLIENT, LeaseAttributes::READ, 10);
+        connect(&mut tasks, &[lease]);
+        let client = id(&tasks, CLIENT);
+
+        let _ = borrow_write(&mut tasks, SERVER, client, 0, 0, b"x");
+        assert_eq!(ret(&tasks, SERVER, 0), abi::DEFECT);
+        assert_eq!(fault(&tasks, SERVER), None);
+        let data = addr(&tasks, CLIENT, DATA);
+        assert_eq!(read_mem(&tasks[CLIENT], data, 1), b"0");
+    }
+
+    #[test]
+    fn borrow_past_end_faults_borrower() {
+        let mut tasks = tasks();
+        let lease = data_lease(&mut tasks, CLIENT, LeaseAttributes::READ, 10);
+        connect(&mut tasks, &[lease]);
+        let client = id(&tasks, CLIENT);
+
+        let _ = borrow_read(&mut tasks, SERVER, client, 0, 11, 1);
+        assert_eq!(
+            fault(&tasks, SERVER),
+            Some(FaultInfo::SyscallUsage(UsageError::OffsetOutOfRange))
+        );
+    }
+
+    #[test]
+    fn bad_lease_number_faults_borrower() {
+        let mut tasks = tasks();
+        connect(&mut tasks, &[]);
+        let client = id(&tasks, CLIENT);
+
+        let _ = borrow_read(&mut tasks, SERVER, client, 0, 0, 1);
+        assert_eq!(
+            fault(&tasks, SERVER),
+            Some(FaultInfo::SyscallUsage(UsageError::LeaseOutOfRange))
+        );
+    }
+
+    #[test]
+    fn lease_of_foreign_memory_faults_lender() {
+        let mut tasks = tasks();
+        // The client lends out memory that belongs to the server.
+        let lease = data_lease(&mut tasks, SERVER, LeaseAttributes::READ, 10);
+        connect(&mut tasks, &[lease]);
+        let client = id(&tasks, CLIENT);
+
+        let _ = borrow_read(&mut tasks, SERVER, client, 0, 0, 4);
+        assert_eq!(ret(&tasks, SERVER, 0), abi::DEFECT);
+        assert_eq!(fault(&tasks, SERVER), None);
+        assert!(matches!(
+            fault(&tasks, CLIENT),
+            Some(FaultInfo::MemoryAccess { .. })
+        ));
+    }
+
+    #[test]
+    fn borrow_after_reply_is_defect() {
+        let mut tasks = tasks();
+        let lease = data_lease(&mut tasks, CLIENT, LeaseAttributes::READ, 10);
+        connect(&mut tasks, &[lease]);
+        let client = id(&tasks, CLIENT);
+        let _ = reply(&mut tasks, SERVER, client, 0, &[]);
+
+        let _ = borrow_read(&mut tasks, SERVER, client, 0, 0, 4);
+        assert_eq!(ret(&tasks, SERVER, 0), abi::DEFECT);
+        assert_eq!(fault(&tasks, SERVER), None);
+    }
+
+    #[test]
+    fn forwarded_lease_reaches_client_memory() {
+        let mut tasks = tasks();
+        let lease = data_lease(
+            &mut tasks,
+            CLIENT,
+            LeaseAttributes::READ | LeaseAttributes::WRITE,
+            10,
+        );
+        connect(&mut tasks, &[lease]);
+        forward(&mut tasks, |client| {
+            ULease::forwarded(client, 0, LeaseAttributes::READ, 2, 4)
+        });
+        let server = id(&tasks, SERVER);
+
+        let _ = syscall(
+            &mut tasks,
+            DRIVER,
+            Sysnum::BorrowInfo,
+            &[u32::from(server.0), 0],
+        );
+        assert_eq!(ret(&tasks, DRIVER, 1), LeaseAttributes::READ.bits());
+        assert_eq!(ret(&tasks, DRIVER, 2), 4);
+
+        let _ = borrow_read(&mut tasks, DRIVER, server, 0, 1, 8);
+        assert_eq!(ret(&tasks, DRIVER, 0), 0);
+        assert_eq!(ret(&tasks, DRIVER, 1), 3);
+        let buf = addr(&tasks, DRIVER, RECV_BUF);
+        assert_eq!(read_mem(&tasks[DRIVER], buf, 3), b"345");
+
+        // The server only passed on read access.
+        let _ = borrow_write(&mut tasks, DRIVER, server, 0, 0, b"x");
+        assert_eq!(ret(&tasks, DRIVER, 0), abi::DEFECT);
+    }
+
+    #[test]
+    fn forwarded_lease_dies_with_client() {
+        let mut tasks = tasks();
+        let lease = data_lease(&mut tasks, CLIENT, LeaseAttributes::READ, 10);
+        connect(&mut tasks, &[lease]);
+        forward(&mut tasks, |client| {
+            ULease::forwarded(client, 0, LeaseAttributes::READ, 0, 10)
+        });
+        let server = id(&tasks, SERVER);
+        tasks[CLIENT].reinitialize();
+
+        let _ = borrow_read(&mut tasks, DRIVER, server, 0, 0, 4);
+        assert_eq!(ret(&tasks, DRIVER, 0), abi::DEFECT);
+        assert_eq!(fault(&tasks, DRIVER), None);
+        assert_eq!(fault(&tasks, SERVER), None);
+    }
+
+    #[test]
+    fn forwarding_more_than_was_lent_faults_forwarder() {
+        let cases: [fn(TaskId) -> ULease; 3] = [
+            // More access than the client granted.
+            |c| ULease::forwarded(c, 0, LeaseAttributes::WRITE, 0, 4),
+            // Runs off the end of the client's lease.
+            |c| ULease::forwarded(c, 0, LeaseAttributes::READ, 8, 4),
+            // Names a lease the server never got.
+            |c| ULease::forwarded(c, 1, LeaseAttributes::READ, 0, 4),
+        ];
+        for case in cases {
+            let mut tasks = tasks();
+            let lease =
+                data_lease(&mut tasks, CLIENT, LeaseAttributes::READ, 10);
+            connect(&mut tasks, &[lease]);
+            forward(&mut tasks, case);
+            let server = id(&tasks, SERVER);
+
+            let _ = borrow_read(&mut tasks, DRIVER, server, 0, 0, 4);
+            assert_eq!(ret(&tasks, DRIVER, 0), abi::DEFECT);
+            assert_eq!(fault(&tasks, DRIVER), None);
+            assert_eq!(
+                fault(&tasks, SERVER),
+                Some(FaultInfo::SyscallUsage(UsageError::BadForwardedLease))
+            );
+        }
+    }
+}
@@ -7,8 +7,8 @@
 use core::convert::TryFrom;
 
 use abi::{
-    FaultInfo, FaultSource, Generation, ReplyFaultReason, SchedState, TaskId,
-    TaskState, ULease, UsageError,
+    FaultInfo, FaultSource, Generation, ReplyFaultReason, SchedState,
+    StackUsage, TaskId, TaskState, ULease, UsageError,
 };
 use zerocopy::FromBytes;
 
@@ -23,6 +23,10 @@ use crate::umem::USlice;
 
 const HUBRIS_TASK_STATE_CHANGE_NOTIFICATION: u32 = 1;
 
+/// Pattern written over each task's stack when it is (re)initialized, so that
+/// `Task::stack_usage` can find the high-water mark later.
+pub const STACK_PAINT: u32 = 0xbaddcafe;
+
 /// Internal representation of a task.
 ///
 /// The fields of this struct are private to this module so that we can maintain
@@ -48,6 +52,13 @@ pub struct Task {
     /// Notification status.
     notifications: u32,
 
+    /// Moment at which this task's most recent SEND gets cancelled, if it
+    /// was given a timeout and hasn't finished by then.
+    send_deadline: Option<Timestamp>,
+    /// Servers that still hold a message from this task whose SEND was
+    /// cancelled, and so may yet reply to it.
+    abandoned_replies: PeerSet,
+
     /// Pointer to the ROM descriptor used to create this task, so it can be
     /// restarted.
     descriptor: &'static TaskDesc,
@@ -69,6 +80,8 @@ impl Task {
 
             generation: 0,
             notifications: 0,
+            send_deadline: None,
+            abandoned_replies: PeerSet::default(),
             save: crate::arch::SavedState::default(),
             timer: crate::task::TimerState::default(),
         }
@@ -265,6 +278,45 @@ impl Task {
         (self.timer.deadline, self.timer.to_post)
     }
 
+    /// Starts the clock on a SEND made at `now`, if it was given a timeout of
+    /// `timeout` ticks.
+    pub fn start_send(&mut self, now: Timestamp, timeout: Option<u32>) {
+        self.send_deadline = timeout
+            .map(|ticks| Timestamp::from(u64::from(now) + u64::from(ticks)));
+    }
+
+    /// Checks whether the task at index `server` may still reply to a
+    /// cancelled SEND from this task.
+    pub fn awaits_abandoned_reply(&self, server: usize) -> bool {
+        self.abandoned_replies.contains(server)
+    }
+
+    /// Notes that the task at index `server` is done with any message from
+    /// this task whose SEND was cancelled, either because it has replied to
+    /// this task when it wasn't waiting, or because it has been restarted.
+    /// This task may then send to it again.
+    pub fn discard_reply_from(&mut self, server: usize) {
+        self.abandoned_replies.remove(server);
+    }
+
+    /// Cancels this task's SEND if it's still waiting on its peer, returning
+    /// `true` if this woke the task.
+    fn cancel_send(&mut self) -> bool {
+        match self.state {
+            TaskState::Healthy(SchedState::InSend(_)) => (),
+            TaskState::Healthy(SchedState::InReply(peer)) => {
+                // The peer has our message and may still reply to it. Keep
+                // track of that, so that its reply can't be taken for the
+                // reply to a later message.
+                self.abandoned_replies.insert(peer.index());
+            }
+            _ => return false,
+        }
+        self.save.set_send_response_and_length(abi::TIMED_OUT, 0);
+        self.state = TaskState::Healthy(SchedState::Runnable);
+        true
+    }
+
     /// Rewrites this task's state back to its initial form, to effect a task
     /// reboot.
     ///
@@ -279,11 +331,51 @@ impl Task {
         self.generation = self.generation.wrapping_add(1);
         self.timer = TimerState::default();
         self.notifications = 0;
+        self.send_deadline = None;
+        self.abandoned_replies = PeerSet::default();
         self.state = TaskState::default();
 
         crate::arch::reinitialize(self);
     }
 
+    /// Measures this task's peak stack usage since it was last
+    /// reinitialized, by scanning up from the bottom of its stack for the
+    /// first word that no longer holds `STACK_PAINT`.
+    ///
+    /// The stack is assumed to occupy the region containing the task's
+    /// initial stack pointer, from the region base up to that pointer. If no
+    /// such region exists, returns a zeroed `StackUsage`.
+    pub fn stack_usage(&self) -> StackUsage {
+        let initial_stack = self.descriptor.initial_stack as usize;
+        let region = match self
+            .region_table()
+            .iter()
+            .find(|region| region.contains(initial_stack))
+        {
+            Some(region) => region,
+            None => return StackUsage::default(),
+        };
+        let base = region.base as usize;
+        let size = initial_stack - base;
+        let uslice = match USlice::<u32>::from_raw(base, size >> 2) {
+            Ok(uslice) => uslice,
+            Err(_) => return StackUsage::default(),
+        };
+        let words = match self.try_read(&uslice) {
+            Ok(words) => words,
+            Err(_) => return StackUsage::default(),
+        };
+        let untouched = words
+            .iter()
+            .take_while(|&&word| word == STACK_PAINT)
+            .count();
+
+        StackUsage {
+            peak: (size - untouched * 4) as u32,
+            size: size as u32,
+        }
+    }
+
     /// Returns a reference to the `TaskDesc` that was used to initially create
     /// this task.
     pub fn descriptor(&self) -> &'static TaskDesc {
@@ -366,6 +458,8 @@ pub trait ArchState: Default {
     fn arg5(&self) -> u32;
     /// Reads syscall argument register 6.
     fn arg6(&self) -> u32;
+    /// Reads syscall argument register 7, which only SEND_WITH_TIMEOUT uses.
+    fn arg7(&self) -> u32;
 
     /// Reads the syscall descriptor (number).
     fn syscall_descriptor(&self) -> u32;
@@ -408,6 +502,15 @@ pub trait ArchState: Default {
         }
     }
 
+    /// Interprets the extra argument of the SEND_WITH_TIMEOUT syscall,
+    /// returning its timeout in ticks, if any.
+    fn as_send_timeout(&self) -> Option<u32> {
+        match self.arg7() {
+            0 => None,
+            ticks => Some(ticks),
+        }
+    }
+
     /// Interprets arguments as for the RECV syscall and returns the results.
     ///
     /// This is inlined because it's called from several places, and most of
@@ -659,6 +762,39 @@ pub struct PostArgs {
     pub notification_bits: NotificationSet,
 }
 
+/// Number of words in a `PeerSet`, enough for one bit per task.
+#[cfg(target_os = "none")]
+const PEER_SET_WORDS: usize = (crate::startup::HUBRIS_TASK_COUNT + 31) / 32;
+
+/// The simulator's task tables are built by the tests at runtime, so there's
+/// no task count to size this by; the tests use only a handful of tasks.
+#[cfg(not(target_os = "none"))]
+const PEER_SET_WORDS: usize = 2;
+
+/// Set of tasks, by index in the task table.
+#[derive(Copy, Clone, Debug)]
+struct PeerSet([u32; PEER_SET_WORDS]);
+
+impl Default for PeerSet {
+    fn default() -> Self {
+        PeerSet([0; PEER_SET_WORDS])
+    }
+}
+
+impl PeerSet {
+    fn insert(&mut self, index: usize) {
+        self.0[index / 32] |= 1 << (index % 32);
+    }
+
+    fn remove(&mut self, index: usize) {
+        self.0[index / 32] &= !(1 << (index % 32));
+    }
+
+    fn contains(&self, index: usize) -> bool {
+        self.0[index / 32] & (1 << (index % 32)) != 0
+    }
+}
+
 /// State for a task timer.
 ///
 /// Task timers are used to multiplex the hardware timer.
@@ -715,6 +851,8 @@ impl NextTask {
 
 /// Processes all enabled timers in the task table, posting notifications for
 /// any that have expired by `current_time` (and disabling them atomically).
+///
+/// This also cancels any SENDs that have run past their task's send timeout.
 pub fn process_timers(tasks: &mut [Task], current_time: Timestamp) -> NextTask {
     let mut sched_hint = NextTask::Same;
     for (index, task) in tasks.iter_mut().enumerate() {
@@ -729,6 +867,14 @@ pub fn process_timers(tasks: &mut [Task], current_time: Timestamp) -> NextTask {
                 sched_hint = sched_hint.combine(task_hint)
             }
         }
+        if let Some(deadline) = task.send_deadline {
+            if deadline <= current_time {
+                task.send_deadline = None;
+                if task.cancel_send() {
+                    sched_hint = sched_hint.combine(NextTask::Specific(index));
+                }
+            }
+        }
     }
     sched_hint
 }
@@ -900,5 +1046,159 @@ pub unsafe fn activate_next_task(task: &mut Task) {
     unsafe {
         crate::arch::set_current_task(task);
     }
+    crate::stats::record_switch(usize::from(task.descriptor().index));
     profiling::event_context_switch(task as *mut _ as usize);
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arch::{ram_base, task_table, write_mem, SIM_RAM_SIZE};
+    use abi::Sysnum;
+
+    /// Puts `task` in an open receive, accepting notifications in `mask`.
+    fn block_in_recv(task: &mut Task, mask: u32) {
+        task.save_mut().set_syscall(Sysnum::Recv, &[0, 0, mask, 0]);
+        task.set_healthy_state(SchedState::InRecv(None));
+    }
+
+    #[test]
+    fn select_prefers_more_important_task() {
+        let tasks = task_table(&[2, 0, 1]);
+        assert_eq!(select(0, &tasks), 1);
+        assert_eq!(select(1, &tasks), 1);
+    }
+
+    #[test]
+    fn select_round_robins_within_priority() {
+        let tasks = task_table(&[1, 1, 1]);
+        assert_eq!(select(0, &tasks), 1);
+        assert_eq!(select(1, &tasks), 2);
+        assert_eq!(select(2, &tasks), 0);
+    }
+
+    #[test]
+    fn select_skips_blocked_tasks() {
+        let mut tasks = task_table(&[0, 1, 1]);
+        block_in_recv(&mut tasks[0], 0);
+        assert_eq!(select(2, &tasks), 1);
+    }
+
+    #[test]
+    fn priority_scan_can_find_nothing() {
+        let tasks = task_table(&[0, 1]);
+        assert_eq!(priority_scan(0, &tasks, |_| false), None);
+    }
+
+    #[test]
+    fn timer_fires_at_deadline() {
+        let mut tasks = task_table(&[0, 1]);
+        block_in_recv(&mut tasks[1], 0b1);
+        tasks[1].set_timer(Some(Timestamp::from(10)), NotificationSet(0b1));
+
+        assert_eq!(
+            process_timers(&mut tasks, Timestamp::from(9)),
+            NextTask::Same
+        );
+        assert!(!tasks[1].is_runnable());
+
+        assert_eq!(
+            process_timers(&mut tasks, Timestamp::from(10)),
+            NextTask::Specific(1)
+        );
+        assert!(tasks[1].is_runnable());
+        assert_eq!(tasks[1].timer().0, None);
+    }
+
+    #[test]
+    fn timer_for_busy_task_stays_pending() {
+        let mut tasks = task_table(&[0, 1]);
+        tasks[1].set_timer(Some(Timestamp::from(10)), NotificationSet(0b1));
+
+        // The task isn't receiving, so firing doesn't wake it...
+        assert_eq!(
+            process_timers(&mut tasks, Timestamp::from(10)),
+            NextTask::Same
+        );
+        // ...but the notification is waiting for its next receive.
+        block_in_recv(&mut tasks[1], 0b1);
+        assert_eq!(tasks[1].take_notifications(), Some(0b1));
+    }
+
+    #[test]
+    fn fault_notifies_supervisor() {
+        let mut tasks = task_table(&[0, 1]);
+        block_in_recv(&mut tasks[0], HUBRIS_TASK_STATE_CHANGE_NOTIFICATION);
+
+        assert_eq!(
+            force_fault(&mut tasks, 1, FaultInfo::DivideByZero),
+            NextTask::Specific(0)
+        );
+        assert_eq!(
+            tasks[1].state(),
+            &TaskState::Faulted {
+                original_state: SchedState::Runnable,
+                fault: FaultInfo::DivideByZero,
+            }
+        );
+        assert!(tasks[0].is_runnable());
+    }
+
+    #[test]
+    fn double_fault_keeps_original_state() {
+        let mut tasks = task_table(&[0, 1]);
+        block_in_recv(&mut tasks[1], 0);
+
+        let _ = force_fault(&mut tasks, 1, FaultInfo::DivideByZero);
+        let _ = force_fault(&mut tasks, 1, FaultInfo::IllegalInstruction);
+        assert_eq!(
+            tasks[1].state(),
+            &TaskState::Faulted {
+                original_state: SchedState::InRecv(None),
+                fault: FaultInfo::IllegalInstruction,
+            }
+        );
+    }
+
+    #[test]
+    fn reinitialize_starts_new_generation() {
+        let mut tasks = task_table(&[0, 1]);
+        let old = current_id(&tasks, 1);
+        tasks[1].set_timer(Some(Timestamp::from(10)), NotificationSet(1));
+        let _ = force_fault(&mut tasks, 1, FaultInfo::DivideByZero);
+
+        tasks[1].reinitialize();
+        assert_eq!(tasks[1].state(), &TaskState::default());
+        assert_eq!(tasks[1].timer().0, None);
+        assert_ne!(current_id(&tasks, 1), old);
+        assert!(matches!(
+            check_task_id_against_table(&tasks, old),
+            Err(UserError::Recoverable(_, NextTask::Same))
+        ));
+        assert_eq!(
+            check_task_id_against_table(&tasks, current_id(&tasks, 1)).ok(),
+            Some(1)
+        );
+    }
+
+    #[test]
+    fn stack_usage_tracks_deepest_write() {
+        let mut tasks = task_table(&[0]);
+        let stack_size = SIM_RAM_SIZE / 2;
+        assert_eq!(
+            tasks[0].stack_usage(),
+            StackUsage {
+                peak: 0,
+                size: stack_size,
+            }
+        );
+
+        let top = ram_base(&tasks[0]) + stack_size;
+        write_mem(&mut tasks[0], top - 16, &[0; 4]);
+        assert_eq!(tasks[0].stack_usage().peak, 16);
+
+        // Restarting repaints the stack.
+        tasks[0].reinitialize();
+        assert_eq!(tasks[0].stack_usage().peak, 0);
+    }
+}
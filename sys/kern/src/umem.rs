@@ -334,3 +334,93 @@ fn index2_distinct<T>(
         panic!()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::arch::{
+        make_task, ram_base, region, task_table, write_mem, SIM_RAM_SIZE,
+    };
+    use crate::descs::RegionAttributes;
+
+    fn slice(base: u32, len: usize) -> USlice<u8> {
+        USlice::from_raw(base as usize, len).unwrap()
+    }
+
+    #[test]
+    fn from_raw_rejects_bad_slices() {
+        assert_eq!(
+            USlice::<u32>::from_raw(0x1001, 1).err(),
+            Some(UsageError::InvalidSlice)
+        );
+        assert_eq!(
+            USlice::<u8>::from_raw(usize::MAX, 2).err(),
+            Some(UsageError::InvalidSlice)
+        );
+        assert_eq!(
+            USlice::<u32>::from_raw(0x1000, usize::MAX / 2).err(),
+            Some(UsageError::InvalidSlice)
+        );
+    }
+
+    #[test]
+    fn aliasing() {
+        let a = slice(0x100, 16);
+        assert!(a.aliases(&slice(0x10f, 1)));
+        assert!(a.aliases(&slice(0xf0, 17)));
+        assert!(!a.aliases(&slice(0x110, 4)));
+        assert!(!a.aliases(&slice(0x108, 0)));
+    }
+
+    #[test]
+    fn kernel_access_follows_regions() {
+        let rw = RegionAttributes::READ | RegionAttributes::WRITE;
+        let ram = region(256, rw);
+        let rom = region(256, RegionAttributes::READ);
+        let dev = region(256, rw | RegionAttributes::DEVICE);
+        let dma = region(256, rw | RegionAttributes::DMA);
+        let mut task = make_task(0, 0, &[ram, rom, dev, dma]);
+
+        assert!(task.try_read(&slice(ram.base, 256)).is_ok());
+        assert!(task.try_write(&mut slice(ram.base, 256)).is_ok());
+        assert!(task.try_read(&slice(rom.base, 256)).is_ok());
+        assert!(task.try_write(&mut slice(rom.base, 1)).is_err());
+        // The kernel won't touch device or DMA memory on a task's behalf.
+        assert!(task.try_read(&slice(dev.base, 1)).is_err());
+        assert!(task.try_read(&slice(dma.base, 1)).is_err());
+        // Nor will it let a slice hang off the end of a region.
+        assert!(task.try_read(&slice(ram.base + 255, 2)).is_err());
+        // Empty slices confer no authority, so are fine anywhere. (Not at
+        // null, though, which can't make a valid slice even when empty.)
+        assert!(task.try_read(&slice(4, 0)).is_ok());
+    }
+
+    #[test]
+    fn safe_copy_copies_shorter_length() {
+        let mut tasks = task_table(&[0, 1]);
+        // Stay clear of the (painted) stacks at the bottom of RAM.
+        let src = ram_base(&tasks[0]) + SIM_RAM_SIZE / 2;
+        let dst = ram_base(&tasks[1]) + SIM_RAM_SIZE / 2;
+        write_mem(&mut tasks[0], src, b"abcdef");
+
+        let n = safe_copy(&mut tasks, 0, slice(src, 6), 1, slice(dst, 4));
+        assert_eq!(n.ok(), Some(4));
+        assert_eq!(tasks[1].try_read(&slice(dst, 5)).unwrap(), b"abcd\0");
+    }
+
+    #[test]
+    fn safe_copy_blames_the_right_side() {
+        let mut tasks = task_table(&[0, 1]);
+        let (src, dst) = (ram_base(&tasks[0]), ram_base(&tasks[1]));
+
+        // Source names memory the source task doesn't have.
+        let e = safe_copy(&mut tasks, 0, slice(dst, 4), 1, slice(dst + 8, 4))
+            .unwrap_err();
+        assert!(e.src.is_some() && e.dst.is_none());
+
+        // Destination names memory the destination task doesn't have.
+        let e = safe_copy(&mut tasks, 0, slice(src, 4), 1, slice(src + 8, 4))
+            .unwrap_err();
+        assert!(e.src.is_none() && e.dst.is_some());
+    }
+}
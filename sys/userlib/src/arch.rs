@@ -19,7 +19,13 @@ cfg_if::cfg_if! {
     // Note: cfg_if! is slightly touchy about ordering and expression
     // complexity; this chain seems to be the best compromise.
 
-    if #[cfg(not(target_pointer_width = "32"))] {
+    if #[cfg(target_arch = "riscv64")] {
+        // The one 64-bit target, which passes 32-bit values to the kernel in
+        // 64-bit registers.
+        #[macro_use]
+        pub mod riscv;
+        pub use riscv::*;
+    } else if #[cfg(not(target_pointer_width = "32"))] {
         compile_error!("non-32-bit targets not supported (even for simulation)");
     } else if #[cfg(any(armv6m, armv7m, armv8m))] {
         #[macro_use]
@@ -27,8 +33,8 @@ cfg_if::cfg_if! {
         pub use arm_m::*;
     } else if #[cfg(target_arch = "riscv32")] {
         #[macro_use]
-        pub mod riscv32;
-        pub use riscv32::*;
+        pub mod riscv;
+        pub use riscv::*;
     } else {
         compile_error!("support for this architecture not implemented");
     }
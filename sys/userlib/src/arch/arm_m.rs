@@ -270,6 +270,86 @@ pub(crate) unsafe extern "C" fn sys_send_stub(
     }
 }
 
+/// Core implementation of the SEND_WITH_TIMEOUT syscall, which passes `ticks`
+/// in r0 on top of the SEND arguments.
+#[naked]
+pub(crate) unsafe extern "C" fn sys_send_with_timeout_stub(
+    _args: &mut SendArgs<'_>,
+    _ticks: u32,
+) -> RcLen {
+    cfg_if::cfg_if! {
+        if #[cfg(armv6m)] {
+            core::arch::asm!("
+                @ Spill the registers we're about to use to pass stuff.
+                push {{r4-r7, lr}}
+                mov r4, r8
+                mov r5, r9
+                mov r6, r10
+                mov r7, r11
+                push {{r4-r7}}
+                @ Load the constant syscall number.
+                eors r4, r4
+                adds r4, #{sysnum}
+                mov r11, r4
+                @ Keep the timeout out of the way of the loads below.
+                mov r3, r1
+                @ Load in args from the struct.
+                ldm r0!, {{r4-r7}}
+                ldm r0, {{r0-r2}}
+                mov r8, r0
+                mov r9, r1
+                mov r10, r2
+                @ The timeout goes in r0.
+                mov r0, r3
+
+                @ To the kernel!
+                svc #0
+
+                @ Move the two results back into their return positions.
+                mov r0, r4
+                mov r1, r5
+                @ Restore the registers we used.
+                pop {{r4-r7}}
+                mov r8, r4
+                mov r9, r5
+                mov r10, r6
+                mov r11, r7
+                pop {{r4-r7, pc}}
+                ",
+                sysnum = const Sysnum::SendWithTimeout as u32,
+                options(noreturn),
+            )
+        } else if #[cfg(any(armv7m, armv8m))] {
+            core::arch::asm!("
+                @ Spill the registers we're about to use to pass stuff.
+                push {{r4-r11}}
+                @ Load in args from the struct.
+                ldm r0, {{r4-r10}}
+                @ The timeout goes in r0.
+                mov r0, r1
+                @ Load the constant syscall number.
+                mov r11, {sysnum}
+
+                @ To the kernel!
+                svc #0
+
+                @ Move the two results back into their return positions.
+                mov r0, r4
+                mov r1, r5
+                @ Restore the registers we used.
+                pop {{r4-r11}}
+                @ Fin.
+                bx lr
+                ",
+                sysnum = const Sysnum::SendWithTimeout as u32,
+                options(noreturn),
+            )
+        } else {
+            compile_error!("missing sys_send_with_timeout_stub for ARM profile");
+        }
+    }
+}
+
 /// Core implementation of the RECV syscall.
 #[naked]
 #[must_use]
@@ -2,15 +2,94 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-//! User application architecture support stubs for RISC-V 32bit
+//! User application architecture support stubs for RISC-V, 32 and 64 bit
 //!
 //! See the note on syscall stubs at the top of the userlib module for
 //! rationale.
+//!
+//! The kernel ABI is the same on RV64 as on RV32: every argument and result
+//! is 32 bits. The kernel sign-extends results into registers, as the calling
+//! convention expects of `u32` values, so on RV64 results that Rust sees as
+//! `usize` must be zero-extended here before they're returned or stored. The
+//! macros below cover the differences in register width.
 
 use crate::*;
 
 use core::arch::asm;
 
+cfg_if::cfg_if! {
+    if #[cfg(target_arch = "riscv64")] {
+        macro_rules! load_reg {
+            ($reg:literal, $slot:literal, $base:literal) => {
+                concat!("ld ", $reg, ", ", $slot, "*8(", $base, ")")
+            };
+        }
+        macro_rules! store_reg {
+            ($reg:literal, $slot:literal, $base:literal) => {
+                concat!("sd ", $reg, ", ", $slot, "*8(", $base, ")")
+            };
+        }
+        macro_rules! reg_bytes {
+            () => {
+                "8"
+            };
+        }
+
+        // Stores a result into the `usize` field `$field` of an output struct
+        // that starts with two `u32`s, clearing the field's upper half.
+        macro_rules! store_usize {
+            ($reg:literal, $field:literal, $base:literal) => {
+                concat!(
+                    "sw ", $reg, ", 8+", $field, "*8(", $base, ")\n",
+                    "sw zero, 8+", $field, "*8+4(", $base, ")",
+                )
+            };
+        }
+
+        // Packs the response code in a0 and the length in a1 into the 64-bit
+        // `RcLen` returned in a0.
+        macro_rules! pack_rc_len {
+            () => {
+                "
+                slli a1, a1, 32
+                slli a0, a0, 32
+                srli a0, a0, 32
+                or a0, a0, a1
+                "
+            };
+        }
+    } else {
+        macro_rules! load_reg {
+            ($reg:literal, $slot:literal, $base:literal) => {
+                concat!("lw ", $reg, ", ", $slot, "*4(", $base, ")")
+            };
+        }
+        macro_rules! store_reg {
+            ($reg:literal, $slot:literal, $base:literal) => {
+                concat!("sw ", $reg, ", ", $slot, "*4(", $base, ")")
+            };
+        }
+        macro_rules! reg_bytes {
+            () => {
+                "4"
+            };
+        }
+        macro_rules! store_usize {
+            ($reg:literal, $field:literal, $base:literal) => {
+                concat!("sw ", $reg, ", 8+", $field, "*4(", $base, ")")
+            };
+        }
+
+        // A 64-bit `RcLen` is returned in a0 and a1, which is where the kernel
+        // has already placed the response code and length.
+        macro_rules! pack_rc_len {
+            () => {
+                ""
+            };
+        }
+    }
+}
+
 /// This is the entry point for the task, invoked by the kernel. Its job is to
 /// set up our memory before jumping to user-defined `main`.
 #[doc(hidden)]
@@ -23,28 +102,29 @@ pub unsafe extern "C" fn _start() -> ! {
         fn main() -> !;
     }
 
-    asm!("
+    asm!(
+        "
         # Copy data initialization image into data section.
         la t0, __edata       # upper bound in t0
         la t1, __sidata      # source in t1
         la t2, __sdata       # dest in t2
         j 1f
-
-    2:  lw s3, (t1)
-        add t1, t1, 4
-        sw s3, (t2)
-        add t2, t2, 4
-
+        ",
+        concat!("2: ", load_reg!("s3", 0, "t1")),
+        concat!("add t1, t1, ", reg_bytes!()),
+        store_reg!("s3", 0, "t2"),
+        concat!("add t2, t2, ", reg_bytes!()),
+        "
     1:  bne t2, t0, 2b
 
         # Zero BSS
         la t0, __ebss        # upper bound in t0
         la t1, __sbss        # base in t1
         j 1f
-
-    2:  sw zero, (t1)
-        add t1, t1, 4
-
+        ",
+        concat!("2: ", store_reg!("zero", 0, "t1")),
+        concat!("add t1, t1, ", reg_bytes!()),
+        "
     1:  bne t1, t0, 2b
         j {main}
         ",
@@ -77,31 +157,66 @@ pub(crate) unsafe extern "C" fn sys_refresh_task_id_stub(_tid: u32) -> u32 {
 pub(crate) unsafe extern "C" fn sys_send_stub(
     _args: &mut SendArgs<'_>,
 ) -> RcLen {
-    asm!("
+    asm!(
+        "
         # Load in args from the struct.
-        lw a6, 6*4(a0)
-        lw a5, 5*4(a0)
-        lw a4, 4*4(a0)
-        lw a3, 3*4(a0)
-        lw a2, 2*4(a0)
-        lw a1, 1*4(a0)
-        lw a0, 0*4(a0)
-
+        ",
+        load_reg!("a6", 6, "a0"),
+        load_reg!("a5", 5, "a0"),
+        load_reg!("a4", 4, "a0"),
+        load_reg!("a3", 3, "a0"),
+        load_reg!("a2", 2, "a0"),
+        load_reg!("a1", 1, "a0"),
+        "lw a0, 0(a0)",
+        "
         # Load the constant syscall number.
         li a7, {sysnum}
 
         # To the kernel!
         ecall
-
-        # Results are placed into the correct registers by the kernel, we can
-        # just return now.
-        ret
         ",
+        pack_rc_len!(),
+        "ret",
         sysnum = const Sysnum::Send as u32,
         options(noreturn),
     )
 }
 
+/// Core implementation of the SEND_WITH_TIMEOUT syscall, which passes `ticks`
+/// in t0 on top of the SEND arguments.
+#[naked]
+pub(crate) unsafe extern "C" fn sys_send_with_timeout_stub(
+    _args: &mut SendArgs<'_>,
+    _ticks: u32,
+) -> RcLen {
+    asm!(
+        "
+        # The timeout goes in t0.
+        mv t0, a1
+
+        # Load in args from the struct.
+        ",
+        load_reg!("a6", 6, "a0"),
+        load_reg!("a5", 5, "a0"),
+        load_reg!("a4", 4, "a0"),
+        load_reg!("a3", 3, "a0"),
+        load_reg!("a2", 2, "a0"),
+        load_reg!("a1", 1, "a0"),
+        "lw a0, 0(a0)",
+        "
+        # Load the constant syscall number.
+        li a7, {sysnum}
+
+        # To the kernel!
+        ecall
+        ",
+        pack_rc_len!(),
+        "ret",
+        sysnum = const Sysnum::SendWithTimeout as u32,
+        options(noreturn),
+    )
+}
+
 /// Core implementation of the RECV syscall.
 #[naked]
 #[must_use]
@@ -112,11 +227,14 @@ pub(crate) unsafe extern "C" fn sys_recv_stub(
     _specific_sender: u32,
     _out: *mut RawRecvMessage,
 ) -> u32 {
-    asm!("
+    asm!(
+        "
         # Preserve output buffer pointer in callee-save register, ensuring
         # it is saved on the stack, which is kept 16-byte aligned.
-        addi sp, sp, -4*4
-        sw s2, 0*4(sp)
+        addi sp, sp, -16
+        ",
+        store_reg!("s2", 0, "sp"),
+        "
         mv s2, a4
 
         # Load the constant syscall number.
@@ -128,13 +246,16 @@ pub(crate) unsafe extern "C" fn sys_recv_stub(
         # Write all the results out into the raw output buffer.
         sw a1, 0*4(s2)
         sw a2, 1*4(s2)
-        sw a3, 2*4(s2)
-        sw a4, 3*4(s2)
-        sw a5, 4*4(s2)
-
+        ",
+        store_usize!("a3", 0, "s2"),
+        store_usize!("a4", 1, "s2"),
+        store_usize!("a5", 2, "s2"),
+        "
         # Restore callee-save register and stack pointer and return.
-        lw s2, 0*4(sp)
-        addi sp, sp, 4*4
+        ",
+        load_reg!("s2", 0, "sp"),
+        "
+        addi sp, sp, 16
         ret
         ",
         sysnum = const Sysnum::Recv as u32,
@@ -193,25 +314,25 @@ pub(crate) unsafe extern "C" fn sys_set_timer_stub(
 pub(crate) unsafe extern "C" fn sys_borrow_read_stub(
     _args: *mut BorrowReadArgs,
 ) -> RcLen {
-    asm!("
+    asm!(
+        "
         # Move register arguments into place, in reverse order so that a0 is
         # loaded last when we're finished with it.
-        lw a4, 4*4(a0)
-        lw a3, 3*4(a0)
-        lw a2, 2*4(a0)
-        lw a1, 1*4(a0)
-        lw a0, 0*4(a0)
-
+        ",
+        load_reg!("a4", 4, "a0"),
+        load_reg!("a3", 3, "a0"),
+        load_reg!("a2", 2, "a0"),
+        load_reg!("a1", 1, "a0"),
+        "lw a0, 0(a0)",
+        "
         # Load the constant syscall number.
         li a7, {sysnum}
 
         # To the kernel!
         ecall
-
-        # Results will be placed into return registers by the kernel, we can
-        # just return now.
-        ret
         ",
+        pack_rc_len!(),
+        "ret",
         sysnum = const Sysnum::BorrowRead as u32,
         options(noreturn),
     )
@@ -222,25 +343,25 @@ pub(crate) unsafe extern "C" fn sys_borrow_read_stub(
 pub(crate) unsafe extern "C" fn sys_borrow_write_stub(
     _args: *mut BorrowWriteArgs,
 ) -> RcLen {
-    asm!("
+    asm!(
+        "
         # Move register arguments into place, in reverse order so that a0 is
         # loaded last when we're finished with it.
-        lw a4, 4*4(a0)
-        lw a3, 3*4(a0)
-        lw a2, 2*4(a0)
-        lw a1, 1*4(a0)
-        lw a0, 0*4(a0)
-
+        ",
+        load_reg!("a4", 4, "a0"),
+        load_reg!("a3", 3, "a0"),
+        load_reg!("a2", 2, "a0"),
+        load_reg!("a1", 1, "a0"),
+        "lw a0, 0(a0)",
+        "
         # Load the constant syscall number.
         li a7, {sysnum}
 
         # To the kernel!
         ecall
-
-        # Results are placed into the correct registers by the kernel, we can
-        # just return now.
-        ret
         ",
+        pack_rc_len!(),
+        "ret",
         sysnum = const Sysnum::BorrowWrite as u32,
         options(noreturn),
     )
@@ -253,11 +374,14 @@ pub(crate) unsafe extern "C" fn sys_borrow_info_stub(
     _index: usize,
     _out: *mut RawBorrowInfo,
 ) {
-    asm!("
+    asm!(
+        "
         # Preserve output buffer pointer in callee-save register, ensuring
         # it is saved on the stack, which is kept 16-byte aligned.
-        addi sp, sp, -4*4
-        sw s2, 0*4(sp)
+        addi sp, sp, -16
+        ",
+        store_reg!("s2", 0, "sp"),
+        "
         mv s2, a2
 
         # Load the constant syscall number.
@@ -269,11 +393,14 @@ pub(crate) unsafe extern "C" fn sys_borrow_info_stub(
         # Write all the results out into the raw output buffer.
         sw a0, 0*4(s2)
         sw a1, 1*4(s2)
-        sw a2, 2*4(s2)
-
+        ",
+        store_usize!("a2", 0, "s2"),
+        "
         # Restore callee-save register and stack pointer and return.
-        lw s2, 0*4(sp)
-        addi sp, sp, 4*4
+        ",
+        load_reg!("s2", 0, "sp"),
+        "
+        addi sp, sp, 16
         ret
         ",
         sysnum = const Sysnum::BorrowInfo as u32,
@@ -323,11 +450,14 @@ pub(crate) unsafe extern "C" fn sys_panic_stub(
 /// Core implementation of the GET_TIMER syscall.
 #[naked]
 pub(crate) unsafe extern "C" fn sys_get_timer_stub(_out: *mut RawTimerState) {
-    asm!("
+    asm!(
+        "
         # Preserve output buffer pointer in callee-save register, ensuring
         # it is saved on the stack, which is kept 16-byte aligned.
-        addi sp, sp, -4*4
-        sw s2, 0*4(sp)
+        addi sp, sp, -16
+        ",
+        store_reg!("s2", 0, "sp"),
+        "
         mv s2, a0
 
         # Load the constant syscall number.
@@ -345,8 +475,10 @@ pub(crate) unsafe extern "C" fn sys_get_timer_stub(_out: *mut RawTimerState) {
         sw a5, 5*4(s2)
 
         # Restore callee-save register and stack pointer and return.
-        lw s2, 0*4(sp)
-        addi sp, sp, 4*4
+        ",
+        load_reg!("s2", 0, "sp"),
+        "
+        addi sp, sp, 16
         ret
         ",
         sysnum = const Sysnum::GetTimer as u32,
@@ -7,14 +7,14 @@
 //! This is intended to provide a more ergonomic interface than the raw
 //! syscalls.
 
-use abi::TaskId;
+use abi::{LeaseAttributes, TaskId};
 use core::marker::PhantomData;
 use zerocopy::{AsBytes, FromBytes, LayoutVerified};
 
 use crate::{
     sys_borrow_info, sys_borrow_read, sys_borrow_write, sys_get_timer,
     sys_recv, sys_recv_closed, sys_recv_open, sys_reply, sys_set_timer,
-    BorrowInfo, ClosedRecvError, FromPrimitive,
+    BorrowInfo, ClosedRecvError, FromPrimitive, Lease,
 };
 
 const INTERNAL_TIMER_NOTIFICATION: u32 = 1 << 31;
@@ -327,7 +327,36 @@ pub struct Borrow<'caller> {
     _phantom: PhantomData<&'caller ()>,
 }
 
-impl Borrow<'_> {
+impl<'caller> Borrow<'caller> {
+    /// Produces a lease covering `range` of this borrow, with (at most) the
+    /// access in `attributes`, that can be passed on to another server in a
+    /// `sys_send`. The other server then reads and writes our caller's memory
+    /// directly, for as long as our caller is still waiting on us.
+    ///
+    /// Returns `None` if the borrow is gone, `range` runs past its end, or
+    /// `attributes` asks for more than our caller granted.
+    pub fn forward(
+        &self,
+        range: core::ops::Range<usize>,
+        attributes: LeaseAttributes,
+    ) -> Option<Lease<'caller>> {
+        let info = self.info()?;
+        if range.start > range.end
+            || range.end > info.len
+            || !info.attributes.contains(attributes)
+            || self.index > usize::from(u8::MAX)
+        {
+            return None;
+        }
+        Some(Lease::forwarded(
+            self.id,
+            self.index,
+            attributes,
+            range.start,
+            range.end - range.start,
+        ))
+    }
+
     /// Gets information on this borrow from the kernel.
     ///
     /// This is a wrapper for the `sys_borrow_info` syscall.
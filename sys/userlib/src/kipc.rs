@@ -24,6 +24,104 @@ pub fn read_task_status(task: usize) -> abi::TaskState {
     ssmarshal::deserialize(&response[..len]).unwrap_lite().0
 }
 
+pub fn read_task_stats(task: usize) -> abi::TaskStats {
+    // Coerce `task` to a known size (Rust doesn't assume that usize == u32)
+    let task = task as u32;
+    let mut response = [0; core::mem::size_of::<abi::TaskStats>()];
+    let (rc, len) = sys_send(
+        TaskId::KERNEL,
+        Kipcnum::ReadTaskStats as u16,
+        task.as_bytes(),
+        &mut response,
+        &[],
+    );
+    assert_eq!(rc, 0);
+    ssmarshal::deserialize(&response[..len]).unwrap_lite().0
+}
+
+pub fn read_stack_usage(task: usize) -> abi::StackUsage {
+    // Coerce `task` to a known size (Rust doesn't assume that usize == u32)
+    let task = task as u32;
+    let mut response = [0; core::mem::size_of::<abi::StackUsage>()];
+    let (rc, len) = sys_send(
+        TaskId::KERNEL,
+        Kipcnum::ReadStackUsage as u16,
+        task.as_bytes(),
+        &mut response,
+        &[],
+    );
+    assert_eq!(rc, 0);
+    ssmarshal::deserialize(&response[..len]).unwrap_lite().0
+}
+
+/// Copies the saved registers of a faulted task into `buf`, returning the
+/// size of the architecture's saved register block. If that's larger than
+/// `buf`, the registers were truncated.
+///
+/// This is only available to the supervisor and tasks with `dump-access`.
+pub fn read_task_registers(task: usize, buf: &mut [u8]) -> usize {
+    // Coerce `task` to a known size (Rust doesn't assume that usize == u32)
+    let task = task as u32;
+    let (rc, len) = sys_send(
+        TaskId::KERNEL,
+        Kipcnum::ReadTaskRegisters as u16,
+        task.as_bytes(),
+        buf,
+        &[],
+    );
+    assert_eq!(rc, 0);
+    len
+}
+
+/// Returns the `region`th memory region of a faulted task worth dumping, or
+/// `None` if it has fewer regions than that.
+///
+/// This is only available to the supervisor and tasks with `dump-access`.
+pub fn get_task_dump_region(
+    task: usize,
+    region: usize,
+) -> Option<abi::TaskDumpRegion> {
+    // Coerce `task` and `region` to a known size (Rust doesn't assume that
+    // usize == u32)
+    let msg = (task as u32, region as u32);
+    let mut buf = [0; core::mem::size_of::<(u32, u32)>()];
+    ssmarshal::serialize(&mut buf, &msg).unwrap_lite();
+    let mut response = [0; core::mem::size_of::<Option<abi::TaskDumpRegion>>()];
+    let (rc, len) = sys_send(
+        TaskId::KERNEL,
+        Kipcnum::GetTaskDumpRegion as u16,
+        &buf,
+        &mut response,
+        &[],
+    );
+    assert_eq!(rc, 0);
+    ssmarshal::deserialize(&response[..len]).unwrap_lite().0
+}
+
+/// Copies the start of `region` of a faulted task's memory into `buf`,
+/// returning the number of bytes copied.
+///
+/// This is only available to the supervisor and tasks with `dump-access`.
+pub fn read_task_dump_region(
+    task: usize,
+    region: abi::TaskDumpRegion,
+    buf: &mut [u8],
+) -> usize {
+    // Coerce `task` to a known size (Rust doesn't assume that usize == u32)
+    let msg = (task as u32, region);
+    let mut msg_buf = [0; core::mem::size_of::<(u32, abi::TaskDumpRegion)>()];
+    ssmarshal::serialize(&mut msg_buf, &msg).unwrap_lite();
+    let (rc, len) = sys_send(
+        TaskId::KERNEL,
+        Kipcnum::ReadTaskDumpRegion as u16,
+        &msg_buf,
+        buf,
+        &[],
+    );
+    assert_eq!(rc, 0);
+    len
+}
+
 pub fn exit_current_task() {
     sys_send(
         TaskId::KERNEL,
@@ -80,3 +178,30 @@ pub fn read_image_id() -> u64 {
     assert_eq!(len, 8); // we *really* expect this to be a u64
     ssmarshal::deserialize(&response[..len]).unwrap_lite().0
 }
+
+/// Reads the image header, without the task manifest that follows it.
+pub fn read_image_header() -> abi::ImageHeader {
+    let mut header = abi::ImageHeader::default();
+    let len = read_image_header_raw(0, header.as_bytes_mut());
+    assert_eq!(len, core::mem::size_of::<abi::ImageHeader>());
+    header
+}
+
+/// Copies the image header, followed by its task manifest, into `buf`,
+/// starting `offset` bytes in, and returns the number of bytes copied. The
+/// whole thing is `ImageHeader::len_with_manifest` bytes long, so a manifest
+/// too big for one buffer can be read in pieces; past the end, nothing is
+/// copied.
+pub fn read_image_header_raw(offset: u32, buf: &mut [u8]) -> usize {
+    let mut msg = [0; core::mem::size_of::<u32>()];
+    ssmarshal::serialize(&mut msg, &offset).unwrap_lite();
+    let (rc, len) = sys_send(
+        TaskId::KERNEL,
+        Kipcnum::ReadImageHeader as u16,
+        &msg,
+        buf,
+        &[],
+    );
+    assert_eq!(rc, 0);
+    len
+}
@@ -47,6 +47,7 @@ use core::marker::PhantomData;
 
 pub mod hl;
 pub mod kipc;
+pub mod shared_memory;
 pub mod task_slot;
 pub mod units;
 pub mod util;
@@ -91,6 +92,31 @@ impl<'a> Lease<'a> {
             _marker: PhantomData,
         }
     }
+
+    /// Passes on part of a lease that we have received, so that the task we
+    /// send to can access the original lender's memory directly.
+    ///
+    /// This is normally reached through `hl::Borrow::forward`, which checks
+    /// the arguments first; here, they're only checked by the kernel when the
+    /// recipient uses the lease, and faulting that check faults *us*.
+    pub fn forwarded(
+        lender: TaskId,
+        lease_number: usize,
+        attributes: LeaseAttributes,
+        offset: usize,
+        length: usize,
+    ) -> Self {
+        Self {
+            _kern_rep: abi::ULease::forwarded(
+                lender,
+                lease_number as u8,
+                attributes,
+                offset as u32,
+                length as u32,
+            ),
+            _marker: PhantomData,
+        }
+    }
 }
 
 impl<'a> From<&'a [u8]> for Lease<'a> {
@@ -129,19 +155,30 @@ pub fn sys_send(
     incoming: &mut [u8],
     leases: &[Lease<'_>],
 ) -> (u32, usize) {
-    let mut args = SendArgs {
-        packed_target_operation: u32::from(target.0) << 16
-            | u32::from(operation),
-        outgoing_ptr: outgoing.as_ptr(),
-        outgoing_len: outgoing.len(),
-        incoming_ptr: incoming.as_mut_ptr(),
-        incoming_len: incoming.len(),
-        lease_ptr: leases.as_ptr(),
-        lease_len: leases.len(),
-    };
+    let mut args = SendArgs::new(target, operation, outgoing, incoming, leases);
     unsafe { crate::arch::sys_send_stub(&mut args).into() }
 }
 
+/// Like `sys_send`, except that if the recipient hasn't both received and
+/// replied to the message within `ticks` (unless that's zero), the kernel
+/// cancels the send, and it returns `TIMED_OUT` with an empty response.
+///
+/// If the recipient had already received the message, it may still get around
+/// to replying; until it does, further sends to it return `TIMED_OUT` straight
+/// away. Messages to the kernel are never cancelled.
+#[inline(always)]
+pub fn sys_send_with_timeout(
+    target: TaskId,
+    operation: u16,
+    outgoing: &[u8],
+    incoming: &mut [u8],
+    leases: &[Lease<'_>],
+    ticks: u32,
+) -> (u32, usize) {
+    let mut args = SendArgs::new(target, operation, outgoing, incoming, leases);
+    unsafe { crate::arch::sys_send_with_timeout_stub(&mut args, ticks).into() }
+}
+
 #[allow(dead_code)] // this gets used from asm
 #[repr(C)] // field order matters
 struct SendArgs<'a> {
@@ -154,6 +191,28 @@ struct SendArgs<'a> {
     lease_len: usize,
 }
 
+impl<'a> SendArgs<'a> {
+    #[inline(always)]
+    fn new(
+        target: TaskId,
+        operation: u16,
+        outgoing: &[u8],
+        incoming: &mut [u8],
+        leases: &[Lease<'a>],
+    ) -> Self {
+        Self {
+            packed_target_operation: u32::from(target.0) << 16
+                | u32::from(operation),
+            outgoing_ptr: outgoing.as_ptr(),
+            outgoing_len: outgoing.len(),
+            incoming_ptr: incoming.as_mut_ptr(),
+            incoming_len: incoming.len(),
+            lease_ptr: leases.as_ptr(),
+            lease_len: leases.len(),
+        }
+    }
+}
+
 /// Performs an "open" RECV that will accept messages from any task or
 /// notifications from the kernel.
 ///
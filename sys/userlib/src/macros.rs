@@ -32,7 +32,10 @@ cfg_if::cfg_if! {
                 { let _ = cortex_m_semihosting::hprintln!($s, $($tt)*); }
             };
         }
-    } else if #[cfg(all(feature = "log-semihosting", target_arch = "riscv32"))] {
+    } else if #[cfg(all(
+        feature = "log-semihosting",
+        any(target_arch = "riscv32", target_arch = "riscv64"),
+    ))] {
         #[macro_export]
         macro_rules! sys_log {
         ($s:expr) => {
@@ -106,3 +109,28 @@ macro_rules! task_slot {
         }
     };
 }
+
+/// Declares a static `SharedMemory<$t>` handle named `$var` for the shared
+/// memory region `$region` from `app.toml`. Linking fails if the task hasn't
+/// been given access to the region.
+#[macro_export]
+macro_rules! shared_memory {
+    ($var:ident, $region:ident, $t:ty) => {
+        $crate::macros::paste::paste! {
+            static $var: $crate::shared_memory::SharedMemory<$t> = {
+                // These are defined by the task's linker script.
+                #[allow(non_upper_case_globals)]
+                extern "C" {
+                    static [< __SHARED_MEMORY_ $region _BASE >]: u8;
+                    static [< __SHARED_MEMORY_ $region _END >]: u8;
+                }
+                unsafe {
+                    $crate::shared_memory::SharedMemory::from_linker_symbols(
+                        &[< __SHARED_MEMORY_ $region _BASE >],
+                        &[< __SHARED_MEMORY_ $region _END >],
+                    )
+                }
+            };
+        }
+    };
+}
@@ -0,0 +1,166 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Access to shared memory regions declared in `app.toml`.
+//!
+//! A shared memory region is a block of RAM that the build system maps into
+//! each task listed in its `access` table, read-only or read-write. Tasks name
+//! a region with the `shared_memory!` macro, which gives them a
+//! `SharedMemory<T>` handle viewing the region as a `T`:
+//!
+//! ```ignore
+//! shared_memory!(PACKETS, packets, [u8; 2048]);
+//! ```
+//!
+//! The kernel knows nothing of how tasks use a region; they must agree on that
+//! among themselves, typically by using IPC to pass ownership of the contents
+//! back and forth. The contents are not initialized at boot.
+
+use crate::util::{StaticCell, StaticRef};
+use core::marker::PhantomData;
+use core::ops::{Deref, DerefMut};
+use zerocopy::{AsBytes, FromBytes};
+
+/// Handle to a shared memory region, viewed as a `T`.
+///
+/// Because other tasks may write to the region at any time, `T` must be valid
+/// for any bit pattern, which `FromBytes` guarantees.
+pub struct SharedMemory<T> {
+    base: *const u8,
+    end: *const u8,
+    bytes_borrowed: StaticCell<()>,
+    _marker: PhantomData<T>,
+}
+
+// SAFETY
+//
+// SharedMemory only holds the bounds of a region, which don't change, and the
+// flag for `borrow_bytes`. Access to the contents goes through methods that
+// are either volatile, unsafe, or check that flag.
+unsafe impl<T> Sync for SharedMemory<T> {}
+
+impl<T> SharedMemory<T> {
+    /// Creates a handle from the linker symbols bounding a region. This is
+    /// intended for use by `shared_memory!`.
+    ///
+    /// # Safety
+    ///
+    /// `base..end` must be a shared memory region accessible to this task.
+    #[doc(hidden)]
+    pub const unsafe fn from_linker_symbols(
+        base: *const u8,
+        end: *const u8,
+    ) -> Self {
+        Self {
+            base,
+            end,
+            bytes_borrowed: StaticCell::new(()),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns the size of the region in bytes, which may be larger than the
+    /// size requested in `app.toml`.
+    pub fn size(&self) -> usize {
+        self.end as usize - self.base as usize
+    }
+
+    /// Returns a pointer to the region's contents.
+    ///
+    /// # Panics
+    ///
+    /// If the region is too small for a `T`, or isn't aligned for one.
+    pub fn as_ptr(&self) -> *mut T {
+        assert!(core::mem::size_of::<T>() <= self.size());
+        assert!(self.base as usize % core::mem::align_of::<T>() == 0);
+        self.base as *mut T
+    }
+
+    /// Borrows the whole region as bytes, in place.
+    ///
+    /// Writing through the slice will fault if the task only has read access
+    /// to the region. The contents can still change underneath the slice if
+    /// another task writes to the region, so tasks sharing it need to agree on
+    /// who owns it when, as with `get`.
+    ///
+    /// # Panics
+    ///
+    /// If the region is already borrowed this way.
+    pub fn borrow_bytes(&self) -> SharedBytes<'_> {
+        let guard = self.bytes_borrowed.borrow_mut();
+        // Safety: the guard ensures that this is the only slice of the region
+        // that we hand out, and any bytes are valid `u8`s.
+        let bytes = unsafe {
+            core::slice::from_raw_parts_mut(self.base as *mut u8, self.size())
+        };
+        SharedBytes {
+            bytes,
+            _guard: guard,
+        }
+    }
+}
+
+/// A shared memory region borrowed as bytes by `SharedMemory::borrow_bytes`,
+/// which can be borrowed again once this is dropped.
+pub struct SharedBytes<'a> {
+    bytes: &'a mut [u8],
+    _guard: StaticRef<'a, ()>,
+}
+
+impl Deref for SharedBytes<'_> {
+    type Target = [u8];
+
+    fn deref(&self) -> &[u8] {
+        self.bytes
+    }
+}
+
+impl DerefMut for SharedBytes<'_> {
+    fn deref_mut(&mut self) -> &mut [u8] {
+        self.bytes
+    }
+}
+
+impl<T: FromBytes> SharedMemory<T> {
+    /// Copies the contents of the region out.
+    pub fn read(&self) -> T {
+        // Safety: `as_ptr` checks that the region holds an aligned `T`, and
+        // `FromBytes` means whatever is there is a valid one.
+        unsafe { core::ptr::read_volatile(self.as_ptr()) }
+    }
+
+    /// Borrows the contents of the region in place.
+    ///
+    /// # Safety
+    ///
+    /// No other task may write to the region while the borrow lasts, and nor
+    /// may this one through `borrow_bytes`.
+    pub unsafe fn get(&self) -> &T {
+        &*self.as_ptr()
+    }
+}
+
+impl<T: FromBytes + AsBytes> SharedMemory<T> {
+    /// Overwrites the contents of the region.
+    ///
+    /// This will fault if the task only has read access to the region.
+    pub fn write(&self, value: T) {
+        // Safety: as in `read`.
+        unsafe { core::ptr::write_volatile(self.as_ptr(), value) }
+    }
+
+    /// Borrows the contents of the region mutably, in place.
+    ///
+    /// Writing through the reference will fault if the task only has read
+    /// access to the region.
+    ///
+    /// # Safety
+    ///
+    /// No other task may access the region while the borrow lasts, and the
+    /// task must not borrow it again, including with `borrow_bytes`.
+    #[allow(clippy::mut_from_ref)]
+    pub unsafe fn get_mut(&self) -> &mut T {
+        &mut *self.as_ptr()
+    }
+}
@@ -0,0 +1,155 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! Post-mortem dump agent.
+//!
+//! When a task faults, Jefe holds it instead of restarting it, and posts a
+//! notification to this task. We then copy the faulted task's saved
+//! registers and writable memory out through the kernel's dump kipcs, into a
+//! shared memory region named `dump`, and tell Jefe to go ahead and restart
+//! the task. `cargo xtask core` turns the contents of the region into an ELF
+//! core file that GDB can load alongside the task's binary.
+//!
+//! The dump area holds one dump, which is overwritten by the next fault. It
+//! isn't cleared at boot, so a dump survives a reset that doesn't power-cycle
+//! RAM. The `dump-format` crate describes its layout.
+//!
+//! The app needs to give this task `dump-access`, a `jefe` task slot, and the
+//! `dump` region, and tell Jefe about it:
+//!
+//! ```toml
+//! [tasks.dump_agent]
+//! name = "task-dump-agent"
+//! priority = 1
+//! start = true
+//! dump-access = true
+//! task-slots = ["jefe"]
+//!
+//! [shared-memory.dump]
+//! size = 8192
+//! access = {dump_agent = "rw"}
+//!
+//! [tasks.jefe.config.dump-agent]
+//! task = "dump_agent"
+//! bit-number = 0
+//! ```
+
+#![no_std]
+#![no_main]
+
+use dump_format::{DUMP_MAGIC, DUMP_VERSION, FLAG_TRUNCATED, HEADER_SIZE};
+use task_jefe_api::Jefe;
+use userlib::*;
+
+task_slot!(JEFE, jefe);
+shared_memory!(DUMP_AREA, dump, u8);
+
+/// Notification Jefe posts when a faulted task is waiting to be dumped. This
+/// must match the `bit-number` in Jefe's `dump-agent` configuration.
+const DUMP_REQUEST_MASK: u32 = 1 << 0;
+
+#[export_name = "main"]
+fn main() -> ! {
+    let jefe = Jefe::from(JEFE.get_task_id());
+
+    let mut area = DUMP_AREA.borrow_bytes();
+    assert!(area.len() >= HEADER_SIZE);
+
+    loop {
+        // Check for work before waiting, in case a task faulted before we
+        // started (or while we were being restarted).
+        while let Some(task) = jefe.get_dump_request() {
+            dump_task(&mut area, task as usize);
+            jefe.dump_taken(task);
+        }
+        let _ = sys_recv_closed(&mut [], DUMP_REQUEST_MASK, TaskId::KERNEL);
+    }
+}
+
+/// Writes a dump of faulted task `task` into `area`.
+fn dump_task(area: &mut [u8], task: usize) {
+    // Jefe only asks us about faulted tasks, but the task could have been
+    // restarted by someone else since, and the kernel won't let us dump a
+    // running task.
+    if !matches!(kipc::read_task_status(task), abi::TaskState::Faulted { .. }) {
+        return;
+    }
+
+    let mut out = Writer {
+        buf: area,
+        pos: HEADER_SIZE,
+        truncated: false,
+    };
+    // Invalidate the previous dump before we start overwriting it.
+    out.put_u32(0, 0);
+
+    let regs_len = kipc::read_task_registers(task, out.remaining(usize::MAX));
+    let regs_len = out.advance(regs_len);
+
+    let mut region_count = 0;
+    for index in 0.. {
+        let region = match kipc::get_task_dump_region(task, index) {
+            Some(region) => region,
+            None => break,
+        };
+        let at = out.pos;
+        if out.advance(8) < 8 {
+            break;
+        }
+        let buf = out.remaining(region.size as usize);
+        let n = kipc::read_task_dump_region(task, region, buf);
+        let n = out.advance(n);
+        out.put_u32(at, region.base);
+        out.put_u32(at + 4, n as u32);
+        region_count += 1;
+        if n < region.size as usize {
+            out.truncated = true;
+            break;
+        }
+    }
+
+    let image_id = kipc::read_image_id();
+    let flags = if out.truncated { FLAG_TRUNCATED } else { 0 };
+    let length = out.pos as u32;
+    out.put_u32(4, DUMP_VERSION);
+    out.put_u32(8, length);
+    out.put_u32(12, task as u32);
+    out.put_u32(16, flags);
+    out.put_u32(20, image_id as u32);
+    out.put_u32(24, (image_id >> 32) as u32);
+    out.put_u32(28, regs_len as u32);
+    out.put_u32(32, region_count);
+    out.put_u32(0, DUMP_MAGIC);
+}
+
+/// Appends to the dump area, keeping track of whether anything was cut off.
+struct Writer<'a> {
+    buf: &'a mut [u8],
+    pos: usize,
+    truncated: bool,
+}
+
+impl Writer<'_> {
+    /// Returns the unwritten part of the area, up to `max` bytes of it.
+    fn remaining(&mut self, max: usize) -> &mut [u8] {
+        let end = self.buf.len().min(self.pos.saturating_add(max));
+        &mut self.buf[self.pos..end]
+    }
+
+    /// Moves past `n` bytes that were just written through `remaining`, and
+    /// any padding after them. Returns how many of those bytes actually fit.
+    fn advance(&mut self, n: usize) -> usize {
+        let room = self.buf.len() - self.pos;
+        if n > room {
+            self.truncated = true;
+        }
+        let n = n.min(room);
+        self.pos = ((self.pos + n + 3) & !3).min(self.buf.len());
+        n
+    }
+
+    fn put_u32(&mut self, at: usize, value: u32) {
+        self.buf[at..at + 4].copy_from_slice(&value.to_le_bytes());
+    }
+}
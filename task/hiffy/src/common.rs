@@ -871,30 +871,29 @@ pub(crate) fn write_block(
         return Err(Failure::Fault(Fault::AccessOutOfBounds));
     }
 
+    // The update server splits the data into blocks itself.
     let update = drv_update_api::Update::from(UPDATE.get_task_id());
-
-    let block_size = func_err(update.block_size())?;
-
-    for (i, c) in data[..len].chunks(block_size).enumerate() {
-        func_err(update.write_one_block(start_block + i, c))?;
-    }
+    func_err(update.write_blocks(start_block, &data[..len]))?;
 
     Ok(0)
 }
 
+/// Starts (or resumes) an update. The stack holds the target and the image
+/// length; the image's SHA-256, if known, is passed as data.
 #[cfg(feature = "update")]
 pub(crate) fn start_update(
     stack: &[Option<u32>],
-    _data: &[u8],
+    data: &[u8],
     _rval: &mut [u8],
 ) -> Result<usize, Failure> {
+    use drv_update_api::SHA256_SZ;
     use userlib::FromPrimitive;
 
-    if stack.is_empty() {
+    if stack.len() < 2 {
         return Err(Failure::Fault(Fault::MissingParameters));
     }
 
-    let fp = stack.len() - 1;
+    let fp = stack.len() - 2;
 
     let target = match stack[fp + 0] {
         Some(target) => target as usize,
@@ -908,10 +907,23 @@ pub(crate) fn start_update(
         None => return Err(Failure::Fault(Fault::BadParameter(0))),
     };
 
-    func_err(
-        drv_update_api::Update::from(UPDATE.get_task_id())
-            .prep_image_update(img),
-    )?;
+    let image_len = match stack[fp + 1] {
+        Some(len) => len,
+        None => {
+            return Err(Failure::Fault(Fault::EmptyParameter(1)));
+        }
+    };
+
+    let update = drv_update_api::Update::from(UPDATE.get_task_id());
+    match data.len() {
+        0 => func_err(update.prep_unverified_image_update(img, image_len))?,
+        SHA256_SZ => {
+            let mut sha256 = [0u8; SHA256_SZ];
+            sha256.copy_from_slice(data);
+            func_err(update.prep_image_update(img, image_len, sha256))?
+        }
+        _ => return Err(Failure::Fault(Fault::BadParameter(2))),
+    }
     Ok(0)
 }
 
@@ -76,7 +76,7 @@ pub enum Functions {
     #[cfg(feature = "spctrl")]
     SpCtrlInit((), drv_sp_ctrl_api::SpCtrlError),
     #[cfg(feature = "update")]
-    StartUpdate((), drv_update_api::UpdateError),
+    StartUpdate((usize, usize), drv_update_api::UpdateError),
     #[cfg(feature = "update")]
     WriteBlock((usize, usize), drv_update_api::UpdateError),
     #[cfg(feature = "update")]
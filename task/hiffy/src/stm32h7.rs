@@ -133,7 +133,7 @@ pub enum Functions {
     #[cfg(feature = "rng")]
     Rng(usize, drv_rng_api::RngError),
     #[cfg(feature = "update")]
-    StartUpdate((), drv_update_api::UpdateError),
+    StartUpdate((usize, usize), drv_update_api::UpdateError),
     #[cfg(feature = "update")]
     WriteBlock((usize, usize), drv_update_api::UpdateError),
     #[cfg(feature = "update")]
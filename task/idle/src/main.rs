@@ -9,7 +9,7 @@
 // - we need it for our _start routine.
 extern crate userlib;
 
-#[cfg(target_arch = "riscv32")]
+#[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
 use userlib::*;
 
 #[export_name = "main"]
@@ -33,7 +33,7 @@ fn main() -> ! {
         }
         // RISC-V has wfi, but unfortunately it is an illegal instruction if
         // called from User mode, so instead we spin on a timer call.
-        #[cfg(target_arch = "riscv32")]
+        #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
         while sys_get_timer().now > 0 {}
     }
 }
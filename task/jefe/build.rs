@@ -2,16 +2,30 @@
 // License, v. 2.0. If a copy of the MPL was not distributed with this
 // file, You can obtain one at https://mozilla.org/MPL/2.0/.
 
-use anyhow::{Context, Result};
+use anyhow::{bail, Context, Result};
 use serde::Deserialize;
-use std::collections::BTreeMap;
+use std::collections::{BTreeMap, BTreeSet};
 use std::io::Write;
 
 fn main() -> Result<()> {
     let cfg = build_util::task_maybe_config::<Config>()?.unwrap_or_default();
 
-    let allowed_callers = build_util::task_ids()
-        .remap_allowed_caller_names_to_ids(&cfg.allowed_callers)?;
+    let task_ids = build_util::task_ids();
+
+    // Unless the app says otherwise, only the dump agent may collect and
+    // release the tasks Jefe holds for it.
+    let mut allowed_callers = cfg.allowed_callers.clone();
+    if let Some(agent) = &cfg.dump_agent {
+        task_ids.names_to_ids(std::slice::from_ref(&agent.task))?;
+        for op in &["get_dump_request", "dump_taken"] {
+            allowed_callers
+                .entry(op.to_string())
+                .or_insert_with(|| vec![agent.task.clone()]);
+        }
+    }
+    let allowed_callers =
+        task_ids.remap_allowed_caller_names_to_ids(&allowed_callers)?;
+    check_start_after(&task_ids, &cfg.start_after)?;
 
     idol::server::build_restricted_server_support(
         "../../idl/jefe.idol",
@@ -41,6 +55,94 @@ fn main() -> Result<()> {
     }
     writeln!(out, "];")?;
 
+    // The idle task's run time counts as unloaded time in Jefe's load report.
+    match task_ids.get("idle") {
+        Some(idle) => writeln!(
+            out,
+            "pub(crate) const IDLE_TASK: Option<usize> = Some({});",
+            idle
+        )?,
+        None => {
+            writeln!(out, "pub(crate) const IDLE_TASK: Option<usize> = None;")?
+        }
+    }
+
+    match &cfg.dump_agent {
+        Some(agent) => writeln!(
+            out,
+            "pub(crate) const DUMP_AGENT: Option<({}, u32)> = \
+             Some(({}::{}, 1 << {}));",
+            task, task, agent.task, agent.bit_number
+        )?,
+        None => writeln!(
+            out,
+            "pub(crate) const DUMP_AGENT: Option<({}, u32)> = None;",
+            task
+        )?,
+    }
+
+    writeln!(
+        out,
+        "pub(crate) const START_AFTER: [(usize, &[usize]); {}] = [",
+        cfg.start_after.len()
+    )?;
+    for (name, deps) in &cfg.start_after {
+        write!(out, "    ({}::{} as usize, &[", task, name)?;
+        for dep in deps {
+            write!(out, "{}::{} as usize, ", task, dep)?;
+        }
+        writeln!(out, "]),")?;
+    }
+    writeln!(out, "];")?;
+
+    Ok(())
+}
+
+/// Checks that every task named in the `start-after` table exists, and that
+/// the dependencies form a DAG. Jefe walks this graph at runtime without any
+/// cycle detection of its own, so a cycle would either deadlock startup or
+/// recurse forever on restart.
+fn check_start_after(
+    task_ids: &build_util::TaskIds,
+    start_after: &BTreeMap<String, Vec<String>>,
+) -> Result<()> {
+    for (name, deps) in start_after {
+        task_ids.names_to_ids(std::slice::from_ref(name))?;
+        task_ids.names_to_ids(deps)?;
+        if deps.contains(name) {
+            bail!("task `{}` cannot start after itself", name);
+        }
+    }
+
+    // Depth-first search for back edges, keeping the current path so we can
+    // print the offending cycle.
+    fn visit<'a>(
+        name: &'a str,
+        start_after: &'a BTreeMap<String, Vec<String>>,
+        path: &mut Vec<&'a str>,
+        done: &mut BTreeSet<&'a str>,
+    ) -> Result<()> {
+        if done.contains(name) {
+            return Ok(());
+        }
+        if let Some(pos) = path.iter().position(|n| *n == name) {
+            let mut cycle = path[pos..].to_vec();
+            cycle.push(name);
+            bail!("start-after dependency cycle: {}", cycle.join(" -> "));
+        }
+        path.push(name);
+        for dep in start_after.get(name).into_iter().flatten() {
+            visit(dep, start_after, path, done)?;
+        }
+        path.pop();
+        done.insert(name);
+        Ok(())
+    }
+
+    let mut done = BTreeSet::new();
+    for name in start_after.keys() {
+        visit(name, start_after, &mut vec![], &mut done)?;
+    }
     Ok(())
 }
 
@@ -52,9 +154,30 @@ struct Config {
     /// `StateChange` record.
     #[serde(default)]
     on_state_change: BTreeMap<String, StateChange>,
+    /// Tasks that Jefe should start only once other tasks have called
+    /// `mark_ready`, as a map from task name to the names of the tasks it
+    /// depends on. Tasks listed here should have `start = false`; Jefe stops
+    /// any that the kernel starts anyway.
+    #[serde(default)]
+    start_after: BTreeMap<String, Vec<String>>,
     /// Map of operation names to tasks allowed to call them.
     #[serde(default)]
     allowed_callers: BTreeMap<String, Vec<String>>,
+    /// Task to be notified when another task faults, so that it can dump the
+    /// faulted task before Jefe restarts it.
+    #[serde(default)]
+    dump_agent: Option<DumpAgent>,
+}
+
+/// Description of the task that takes post-mortem dumps.
+#[derive(Deserialize)]
+#[serde(rename_all = "kebab-case", deny_unknown_fields)]
+struct DumpAgent {
+    /// Name of the task.
+    task: String,
+    /// Number of notification bit to signal when a task is waiting to be
+    /// dumped (_not_ mask).
+    bit_number: u8,
 }
 
 /// Description of something a task wants done on state change.
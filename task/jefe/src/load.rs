@@ -0,0 +1,103 @@
+// This Source Code Form is subject to the terms of the Mozilla Public
+// License, v. 2.0. If a copy of the MPL was not distributed with this
+// file, You can obtain one at https://mozilla.org/MPL/2.0/.
+
+//! CPU load reporting.
+//!
+//! Every `LOAD_PERIOD` timer intervals, Jefe samples each task's cumulative
+//! run time from the kernel (via the `ReadTaskStats` kipc) and computes the
+//! share of the CPU each task used over that window, in parts per thousand.
+//! Time spent in the idle task is treated as unloaded time, so the system
+//! load is simply everything else.
+//!
+//! The most recent window is available over IPC, and each system load figure
+//! is also recorded in a ringbuf so that Humility can show recent history.
+
+use abi::TaskStats;
+use hubris_num_tasks::NUM_TASKS;
+use ringbuf::*;
+use userlib::*;
+
+/// Number of Jefe timer intervals in a load sampling window (~1 second).
+const LOAD_PERIOD: u32 = 10;
+
+#[derive(Copy, Clone, Debug, Eq, PartialEq)]
+enum Trace {
+    None,
+    /// System load over the last window, in parts per thousand.
+    Load(u16),
+}
+
+ringbuf!(Trace, 8, Trace::None);
+
+pub struct LoadMonitor {
+    /// Counters as of the start of the current window.
+    last: [TaskStats; NUM_TASKS],
+    /// Share of CPU time each task used in the last complete window, in parts
+    /// per thousand.
+    per_task: [u16; NUM_TASKS],
+    /// Share of CPU time not spent in the idle task in the last complete
+    /// window, in parts per thousand.
+    busy: u16,
+    /// Timer intervals elapsed in the current window.
+    intervals: u32,
+}
+
+impl LoadMonitor {
+    pub fn new() -> Self {
+        let mut last = [TaskStats::default(); NUM_TASKS];
+        for (i, stats) in last.iter_mut().enumerate() {
+            *stats = kipc::read_task_stats(i);
+        }
+        Self {
+            last,
+            per_task: [0; NUM_TASKS],
+            busy: 0,
+            intervals: 0,
+        }
+    }
+
+    /// Called from Jefe's periodic timer; closes out the current window every
+    /// `LOAD_PERIOD` calls.
+    pub fn timer_tick(&mut self) {
+        self.intervals += 1;
+        if self.intervals >= LOAD_PERIOD {
+            self.intervals = 0;
+            self.sample();
+        }
+    }
+
+    fn sample(&mut self) {
+        let mut delta = [0u64; NUM_TASKS];
+        for (i, d) in delta.iter_mut().enumerate() {
+            let now = kipc::read_task_stats(i);
+            *d = now.run_time.wrapping_sub(self.last[i].run_time);
+            self.last[i] = now;
+        }
+
+        let total: u64 = delta.iter().sum();
+        if total == 0 {
+            return;
+        }
+        for (share, d) in self.per_task.iter_mut().zip(delta.iter()) {
+            *share = (d * 1000 / total) as u16;
+        }
+        self.busy = match crate::generated::IDLE_TASK {
+            Some(idle) => 1000 - self.per_task[idle],
+            None => 1000,
+        };
+        ringbuf_entry!(Trace::Load(self.busy));
+    }
+
+    /// Returns the system load over the last complete window, in parts per
+    /// thousand.
+    pub fn busy(&self) -> u16 {
+        self.busy
+    }
+
+    /// Returns the share of the CPU used by `task` over the last complete
+    /// window, in parts per thousand, or `None` if `task` is out of range.
+    pub fn task(&self, task: usize) -> Option<u16> {
+        self.per_task.get(task).copied()
+    }
+}
@@ -8,6 +8,12 @@
 //!
 //! - Maintaining the system console output (currently via semihosting).
 //! - Monitoring tasks for failures and restarting them.
+//! - Reporting per-task and overall CPU load.
+//! - Starting tasks configured with `start-after` once the tasks they depend
+//!   on have called `mark_ready`, and stopping them again whenever one of
+//!   those dependencies is restarted.
+//! - Holding faulted tasks for a configured dump agent, so that their state
+//!   can be captured before they're restarted.
 //!
 //! It will probably become responsible for:
 //!
@@ -27,6 +33,7 @@
 #![no_main]
 
 mod external;
+mod load;
 
 use core::convert::Infallible;
 
@@ -115,6 +122,12 @@ const TIMER_INTERVAL: u64 = 100;
 const TIMER_MASK: u32 = 1 << 1;
 // We'll have notification 0 wired up to receive information about task state changes.
 const TASK_STATE_CHANGE_MASK: u32 = 1 << 0;
+// If the dump agent hasn't released a faulted task after this many timer
+// intervals (~1 s), we assume it's wedged and restart the task anyway.
+const DUMP_HOLD_TICKS: u32 = 10;
+// The system must run for this many timer intervals (~10 s) without any
+// task faulting before we report it as healthy.
+const HEALTHY_TICKS: u32 = 100;
 
 #[export_name = "main"]
 fn main() -> ! {
@@ -124,6 +137,10 @@ fn main() -> ! {
         [Disposition::Restart; hubris_num_tasks::NUM_TASKS];
     let mut logged: [bool; hubris_num_tasks::NUM_TASKS] =
         [false; hubris_num_tasks::NUM_TASKS];
+    let mut ready: [bool; hubris_num_tasks::NUM_TASKS] =
+        [false; hubris_num_tasks::NUM_TASKS];
+    let mut dump_hold: [Option<u32>; hubris_num_tasks::NUM_TASKS] =
+        [None; hubris_num_tasks::NUM_TASKS];
     let deadline = sys_get_timer().now + TIMER_INTERVAL;
 
     sys_set_timer(Some(deadline), TIMER_MASK);
@@ -135,8 +152,14 @@ fn main() -> ! {
         deadline,
         disposition: &mut disposition,
         logged: &mut logged,
+        ready: &mut ready,
+        dump_hold: &mut dump_hold,
+        load: load::LoadMonitor::new(),
         reset_reason: ResetReason::Unknown,
+        uptime_ticks: 0,
+        any_faulted: false,
     };
+    server.stop_waiting_tasks();
     let mut buf = [0u8; idl::INCOMING_SIZE];
 
     loop {
@@ -148,8 +171,151 @@ struct ServerImpl<'s> {
     state: u32,
     disposition: &'s mut [Disposition; NUM_TASKS],
     logged: &'s mut [bool; NUM_TASKS],
+    ready: &'s mut [bool; NUM_TASKS],
+    /// Timer intervals left before each task being held for the dump agent
+    /// is restarted regardless; `None` if the task isn't being held.
+    dump_hold: &'s mut [Option<u32>; NUM_TASKS],
+    load: load::LoadMonitor,
     deadline: u64,
     reset_reason: ResetReason,
+    /// Timer intervals since boot, saturating at `HEALTHY_TICKS`.
+    uptime_ticks: u32,
+    /// Whether any task has faulted since boot.
+    any_faulted: bool,
+}
+
+impl ServerImpl<'_> {
+    /// Checks whether every task that `task` has been configured to start
+    /// after has called `mark_ready` since it was last restarted. Tasks with
+    /// no `start-after` entry trivially pass.
+    fn dependencies_ready(&self, task: usize) -> bool {
+        generated::START_AFTER
+            .iter()
+            .filter(|(t, _)| *t == task)
+            .flat_map(|(_, deps)| deps.iter())
+            .all(|dep| self.ready[*dep])
+    }
+
+    /// Restarts a task on behalf of the supervisor. If the task has
+    /// dependencies that aren't ready, it's left stopped for
+    /// `start_waiting_tasks` to pick up later.
+    fn restart_task(&mut self, task: usize) {
+        let start = self.dependencies_ready(task);
+        kipc::restart_task(task, start);
+        self.task_reinitialized(task);
+    }
+
+    /// Called after `task` has been reinitialized. Its readiness is
+    /// forgotten, and anything that was started after it is put back into
+    /// the stopped state until it becomes ready again.
+    fn task_reinitialized(&mut self, task: usize) {
+        let mut stopped = [false; NUM_TASKS];
+        self.stop_dependents(task, &mut stopped);
+    }
+
+    /// Forgets `task`'s readiness and stops everything that depends on it,
+    /// directly or transitively. `stopped` records the dependents we've
+    /// already stopped, so one reachable along several paths is only
+    /// restarted once.
+    ///
+    /// This recurses through the dependency graph; `build.rs` ensures that
+    /// graph is acyclic.
+    fn stop_dependents(
+        &mut self,
+        task: usize,
+        stopped: &mut [bool; NUM_TASKS],
+    ) {
+        self.ready[task] = false;
+        for (dependent, deps) in generated::START_AFTER {
+            if deps.contains(&task) && !stopped[dependent] {
+                stopped[dependent] = true;
+                kipc::restart_task(dependent, false);
+                self.logged[dependent] = false;
+                self.stop_dependents(dependent, stopped);
+            }
+        }
+    }
+
+    /// Decides whether a faulted task should be held for the dump agent
+    /// instead of being restarted. The first time we see the task faulted,
+    /// we start holding it and notify the agent; it's then held until the
+    /// agent calls `dump_taken` or `DUMP_HOLD_TICKS` pass.
+    fn hold_for_dump(&mut self, task: usize) -> bool {
+        let (agent, mask) = match generated::DUMP_AGENT {
+            // The agent can't dump itself.
+            Some((agent, mask)) if agent as usize != task => (agent, mask),
+            _ => return false,
+        };
+        if self.dump_hold[task].is_none() {
+            self.dump_hold[task] = Some(DUMP_HOLD_TICKS);
+            let agent =
+                TaskId::for_index_and_gen(agent as usize, Generation::ZERO);
+            sys_post(sys_refresh_task_id(agent), mask);
+        }
+        true
+    }
+
+    /// Stops holding `task` for the dump agent, restarting it if it's still
+    /// faulted and we'd otherwise have restarted it.
+    fn release_dump_hold(&mut self, task: usize) {
+        self.dump_hold[task] = None;
+        if self.disposition[task] != Disposition::Restart {
+            return;
+        }
+        if let abi::TaskState::Faulted { .. } = kipc::read_task_status(task) {
+            self.restart_task(task);
+            self.logged[task] = false;
+        }
+    }
+
+    /// Counts down the time left on each dump hold, restarting any task whose
+    /// time has run out.
+    fn expire_dump_holds(&mut self) {
+        for task in 0..NUM_TASKS {
+            match self.dump_hold[task] {
+                Some(0) => {
+                    sys_log!("Task #{} was not dumped in time", task);
+                    self.release_dump_hold(task);
+                }
+                Some(ticks) => self.dump_hold[task] = Some(ticks - 1),
+                None => (),
+            }
+        }
+    }
+
+    /// Stops any `start-after` task that the kernel started at boot because
+    /// it was configured with `start = true`; it's started again once its
+    /// dependencies are ready, like any other.
+    fn stop_waiting_tasks(&mut self) {
+        for (task, _) in generated::START_AFTER {
+            if self.dependencies_ready(task) {
+                continue;
+            }
+            if let abi::TaskState::Healthy(abi::SchedState::Stopped) =
+                kipc::read_task_status(task)
+            {
+                continue;
+            }
+            kipc::restart_task(task, false);
+        }
+    }
+
+    /// Starts any `start-after` task that is still stopped and whose
+    /// dependencies have all become ready.
+    fn start_waiting_tasks(&mut self) {
+        for (task, _) in generated::START_AFTER {
+            if self.disposition[task] == Disposition::Hold
+                || !self.dependencies_ready(task)
+            {
+                continue;
+            }
+            if let abi::TaskState::Healthy(abi::SchedState::Stopped) =
+                kipc::read_task_status(task)
+            {
+                kipc::restart_task(task, true);
+            }
+        }
+    }
 }
 
 impl idl::InOrderJefeImpl for ServerImpl<'_> {
@@ -162,6 +328,37 @@ impl idl::InOrderJefeImpl for ServerImpl<'_> {
         kipc::system_restart();
     }
 
+    fn mark_ready(
+        &mut self,
+        msg: &userlib::RecvMessage,
+    ) -> Result<(), idol_runtime::RequestError<Infallible>> {
+        let caller = usize::from(msg.sender.index());
+        if !self.ready[caller] {
+            self.ready[caller] = true;
+            self.start_waiting_tasks();
+        }
+        Ok(())
+    }
+
+    fn get_load(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+    ) -> Result<u32, idol_runtime::RequestError<Infallible>> {
+        Ok(u32::from(self.load.busy()))
+    }
+
+    fn get_task_load(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+        task: u32,
+    ) -> Result<u32, idol_runtime::RequestError<Infallible>> {
+        self.load.task(task as usize).map(u32::from).ok_or(
+            idol_runtime::RequestError::Fail(
+                idol_runtime::ClientError::BadMessageContents,
+            ),
+        )
+    }
+
     fn get_reset_reason(
         &mut self,
         _msg: &userlib::RecvMessage,
@@ -178,6 +375,41 @@ impl idl::InOrderJefeImpl for ServerImpl<'_> {
         Ok(())
     }
 
+    fn tasks_healthy(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+    ) -> Result<bool, idol_runtime::RequestError<Infallible>> {
+        Ok(!self.any_faulted && self.uptime_ticks >= HEALTHY_TICKS)
+    }
+
+    fn get_dump_request(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+    ) -> Result<Option<u32>, idol_runtime::RequestError<Infallible>> {
+        Ok(self
+            .dump_hold
+            .iter()
+            .position(Option::is_some)
+            .map(|task| task as u32))
+    }
+
+    fn dump_taken(
+        &mut self,
+        _msg: &userlib::RecvMessage,
+        task: u32,
+    ) -> Result<(), idol_runtime::RequestError<Infallible>> {
+        let task = task as usize;
+        if task >= NUM_TASKS {
+            return Err(idol_runtime::RequestError::Fail(
+                idol_runtime::ClientError::BadMessageContents,
+            ));
+        }
+        if self.dump_hold[task].is_some() {
+            self.release_dump_hold(task);
+        }
+        Ok(())
+    }
+
     fn get_state(
         &mut self,
         _msg: &userlib::RecvMessage,
@@ -217,6 +449,9 @@ impl idol_runtime::NotificationHandler for ServerImpl<'_> {
         if bits & TIMER_MASK != 0 {
             self.deadline += TIMER_INTERVAL;
             sys_set_timer(Some(self.deadline), TIMER_MASK);
+            self.load.timer_tick();
+            self.expire_dump_holds();
+            self.uptime_ticks = (self.uptime_ticks + 1).min(HEALTHY_TICKS);
         }
 
         // If our disposition has changed or if we have been notified of
@@ -229,18 +464,27 @@ impl idol_runtime::NotificationHandler for ServerImpl<'_> {
                             log_fault(i, &fault);
                             self.logged[i] = true;
                         }
+                        self.any_faulted = true;
 
-                        if self.disposition[i] == Disposition::Restart {
+                        if self.disposition[i] == Disposition::Restart
+                            && !self.hold_for_dump(i)
+                        {
                             // Stand it back up
-                            kipc::restart_task(i, true);
+                            self.restart_task(i);
                             self.logged[i] = false;
                         }
                     }
 
                     abi::TaskState::Healthy(abi::SchedState::Stopped)
                     | abi::TaskState::Healthy(abi::SchedState::Exited) => {
-                        if self.disposition[i] == Disposition::Start {
-                            kipc::restart_task(i, true);
+                        // A task that's waiting on its dependencies is
+                        // supposed to be stopped; `start_waiting_tasks` starts
+                        // it once they're ready. Restarting it here would
+                        // also reset its dependents, every time we look.
+                        if self.disposition[i] == Disposition::Start
+                            && self.dependencies_ready(i)
+                        {
+                            self.restart_task(i);
                         }
                     }
 
@@ -11,9 +11,7 @@ use gateway_messages::{
     UpdateId, UpdatePrepare, UpdateStatus,
 };
 use ringbuf::ringbuf_entry_root;
-
-// TODO How are we versioning SP images? This is a placeholder.
-const VERSION: u32 = 1;
+use userlib::kipc;
 
 /// Provider of MGS handler logic common to all targets (gimlet, sidecar, psc).
 pub(crate) struct MgsCommon {
@@ -67,9 +65,12 @@ impl MgsCommon {
             *to = from;
         }
 
+        // `SpState` only has room for the image version; the rest of what's
+        // running (git commit, per-task hashes) is in the image header, which
+        // can be read over udprpc.
         Ok(SpState {
             serial_number,
-            version: VERSION,
+            version: kipc::read_image_header().version,
         })
     }
 
@@ -89,8 +90,15 @@ impl MgsCommon {
 
         self.update_buf.ensure_no_update_in_progress()?;
 
+        // MGS doesn't send us the image's digest, so the update server can
+        // only check that every block arrived. Verifying the image needs the
+        // digest added to the MGS protocol's update request, which is out of
+        // scope for the update server's protocol; see `drv-update-api`.
         self.update_task
-            .prep_image_update(UpdateTarget::Alternate)
+            .prep_unverified_image_update(
+                UpdateTarget::Alternate,
+                update.total_size,
+            )
             .map_err(|err| ResponseError::UpdateFailed(err as u32))?;
 
         self.update_buf.start(update.id, update.total_size);
@@ -11,6 +11,12 @@ task_slot!(PEER, peer);
 #[cfg(feature = "uart")]
 task_slot!(UART, usart_driver);
 
+// With `shared-memory`, we leave our message in a region that pong can read
+// rather than sending it. Pong only looks at it while we're waiting for its
+// reply, so we can write it whenever we aren't.
+#[cfg(feature = "shared-memory")]
+shared_memory!(MESSAGE, pingpong, [u8; 16]);
+
 #[inline(never)]
 fn nullread() {
     unsafe {
@@ -34,7 +40,7 @@ fn divzero() {
 }
 
 #[inline(never)]
-#[cfg(target_arch = "riscv32")]
+#[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
 fn illinst() {
     unsafe {
         core::arch::asm!("unimp");
@@ -53,16 +59,25 @@ fn main() -> ! {
     #[cfg(any(armv7m, armv8m))]
     let faultme = [nullread, divzero];
 
-    #[cfg(target_arch = "riscv32")]
+    #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
     let faultme = [nullread, illinst];
 
     let mut response = [0; 16];
     loop {
         uart_send(b"Ping!\r\n");
 
+        #[cfg(not(feature = "shared-memory"))]
         let (code, _len) =
             sys_send(peer, PING_OP, b"hello", &mut response, &[]);
 
+        #[cfg(feature = "shared-memory")]
+        let (code, _len) = {
+            let mut message = [0; 16];
+            message[..5].copy_from_slice(b"hello");
+            MESSAGE.write(message);
+            sys_send(peer, PING_OP, &[], &mut response, &[])
+        };
+
         if code % FAULT_EVERY != 0 {
             continue;
         }
@@ -10,6 +10,11 @@ use userlib::*;
 #[cfg(feature = "leds")]
 task_slot!(USER_LEDS, user_leds);
 
+// With `shared-memory`, ping leaves its message here rather than sending it;
+// see the ping task.
+#[cfg(feature = "shared-memory")]
+shared_memory!(MESSAGE, pingpong, [u8; 16]);
+
 #[export_name = "main"]
 pub fn main() -> ! {
     const TIMER_NOTIFICATION: u32 = 1;
@@ -31,6 +36,16 @@ pub fn main() -> ! {
         let msginfo = sys_recv_open(&mut msg, TIMER_NOTIFICATION);
 
         if msginfo.sender != TaskId::KERNEL {
+            #[cfg(feature = "shared-memory")]
+            {
+                // Ping is blocked waiting for our reply, so it won't be
+                // writing to the message while we read it.
+                msg = MESSAGE.read();
+                if !msg.starts_with(b"hello") {
+                    sys_panic(b"ping left the wrong message");
+                }
+            }
+
             // We'll just assume this is a ping message and reply.
             sys_reply(msginfo.sender, response, &[]);
             response += 1;
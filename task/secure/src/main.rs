@@ -15,6 +15,10 @@ use userlib::*;
 static TZ_TABLE: SecureTable = SecureTable {
     magic: TABLE_MAGIC,
     write_to_flash: Some(write_to_flash),
+    confirm_image: Some(confirm_image),
+    prefer_image: Some(prefer_image),
+    write_certs_page: Some(write_certs_page),
+    read_from_flash: Some(read_from_flash),
 };
 
 #[export_name = "main"]
@@ -50,3 +54,76 @@ pub unsafe extern "C" fn write_to_flash(
         options(noreturn)
     );
 }
+
+// These follow the same pattern as `write_to_flash`.
+
+#[naked]
+#[no_mangle]
+#[link_section = ".nsc"]
+pub unsafe extern "C" fn confirm_image() -> HypoStatus {
+    core::arch::asm!(
+        "
+        sg
+        push {{lr}}
+        bl __confirm_image
+        pop {{lr}}
+        bxns lr
+        ",
+        options(noreturn)
+    );
+}
+
+#[naked]
+#[no_mangle]
+#[link_section = ".nsc"]
+pub unsafe extern "C" fn prefer_image(image_num: UpdateTarget) -> HypoStatus {
+    core::arch::asm!(
+        "
+        sg
+        push {{lr}}
+        bl __prefer_image
+        pop {{lr}}
+        bxns lr
+        ",
+        options(noreturn)
+    );
+}
+
+#[naked]
+#[no_mangle]
+#[link_section = ".nsc"]
+pub unsafe extern "C" fn write_certs_page(
+    page_num: u32,
+    buffer: *mut u8,
+) -> HypoStatus {
+    core::arch::asm!(
+        "
+        sg
+        push {{lr}}
+        bl __write_certs_page
+        pop {{lr}}
+        bxns lr
+        ",
+        options(noreturn)
+    );
+}
+
+#[naked]
+#[no_mangle]
+#[link_section = ".nsc"]
+pub unsafe extern "C" fn read_from_flash(
+    image_num: UpdateTarget,
+    page_num: u32,
+    buffer: *mut u8,
+) -> HypoStatus {
+    core::arch::asm!(
+        "
+        sg
+        push {{lr}}
+        bl __read_block
+        pop {{lr}}
+        bxns lr
+        ",
+        options(noreturn)
+    );
+}
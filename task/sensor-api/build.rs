@@ -4,5 +4,10 @@
 
 fn main() -> Result<(), Box<dyn std::error::Error>> {
     idol::client::build_client_stub("../../idl/sensor.idol", "client_stub.rs")?;
+    build_idol_ext::build_client_ext(
+        "../../idl/sensor.idol",
+        "client_ext.rs",
+        build_idol_ext::Timeouts::Supported,
+    )?;
     Ok(())
 }
@@ -52,7 +52,8 @@ impl From<ResponseCode> for NoData {
             ResponseCode::NoRegister => NoData::DeviceUnavailable,
             ResponseCode::BusLocked
             | ResponseCode::BusLockedMux
-            | ResponseCode::ControllerLocked => NoData::DeviceTimeout,
+            | ResponseCode::ControllerLocked
+            | ResponseCode::TimedOut => NoData::DeviceTimeout,
             _ => NoData::DeviceError,
         }
     }
@@ -67,6 +68,11 @@ pub enum SensorError {
     DeviceUnavailable = 5,
     DeviceTimeout = 6,
     DeviceOff = 7,
+
+    /// The sensor task didn't answer in time; only returned by clients made
+    /// with `Sensor::with_timeout`.
+    #[idol(timed_out)]
+    TimedOut = 8,
 }
 
 impl From<NoData> for SensorError {
@@ -82,3 +88,4 @@ impl From<NoData> for SensorError {
 }
 
 include!(concat!(env!("OUT_DIR"), "/client_stub.rs"));
+include!(concat!(env!("OUT_DIR"), "/client_ext.rs"));
@@ -5,7 +5,7 @@
 //! BSP for the Gimlet rev B hardware
 
 use crate::control::{
-    Device, FanControl, InputChannel, PidConfig, TemperatureSensor,
+    Device, FanControl, I2cDevices, InputChannel, PidConfig, TemperatureSensor,
     ThermalProperties,
 };
 use core::convert::TryInto;
@@ -103,9 +103,12 @@ impl Bsp {
         }
         let fans = fans.map(Option::unwrap);
 
-        // Initializes and build a handle to the fan controller IC
-        let fctrl = Max31790::new(&devices::max31790(i2c_task)[0]);
-        fctrl.initialize().unwrap();
+        let i2c = I2cDevices::new(i2c_task);
+        let dimms = i2c.all(devices::tse2004av);
+
+        // Build a handle to the fan controller IC, which the control loop
+        // initializes
+        let fctrl = Max31790::new(&i2c.all(devices::max31790)[0]);
 
         // Handle for the sequencer task, which we check for power state
         let seq = Sequencer::from(SEQ.get_task_id());
@@ -159,7 +162,7 @@ impl Bsp {
             inputs: [
                 InputChannel::new(
                     TemperatureSensor::new(
-                        Device::CPU(Sbtsi::new(&devices::sbtsi(i2c_task)[0])),
+                        Device::CPU(Sbtsi::new(&i2c.all(devices::sbtsi)[0])),
                         sensors::SBTSI_TEMPERATURE_SENSOR,
                     ),
                     CPU_THERMALS,
@@ -169,7 +172,7 @@ impl Bsp {
                 InputChannel::new(
                     TemperatureSensor::new(
                         Device::Tmp451(Tmp451::new(
-                            &devices::tmp451(i2c_task)[0],
+                            &i2c.all(devices::tmp451)[0],
                             Target::Remote,
                         )),
                         sensors::TMP451_TEMPERATURE_SENSOR,
@@ -180,9 +183,7 @@ impl Bsp {
                 ),
                 InputChannel::new(
                     TemperatureSensor::new(
-                        Device::Dimm(Tse2004Av::new(
-                            &devices::tse2004av(i2c_task)[0],
-                        )),
+                        Device::Dimm(Tse2004Av::new(&dimms[0])),
                         sensors::TSE2004AV_TEMPERATURE_SENSORS[0],
                     ),
                     DIMM_THERMALS,
@@ -191,9 +192,7 @@ impl Bsp {
                 ),
                 InputChannel::new(
                     TemperatureSensor::new(
-                        Device::Dimm(Tse2004Av::new(
-                            &devices::tse2004av(i2c_task)[1],
-                        )),
+                        Device::Dimm(Tse2004Av::new(&dimms[1])),
                         sensors::TSE2004AV_TEMPERATURE_SENSORS[1],
                     ),
                     DIMM_THERMALS,
@@ -202,9 +201,7 @@ impl Bsp {
                 ),
                 InputChannel::new(
                     TemperatureSensor::new(
-                        Device::Dimm(Tse2004Av::new(
-                            &devices::tse2004av(i2c_task)[2],
-                        )),
+                        Device::Dimm(Tse2004Av::new(&dimms[2])),
                         sensors::TSE2004AV_TEMPERATURE_SENSORS[2],
                     ),
                     DIMM_THERMALS,
@@ -213,9 +210,7 @@ impl Bsp {
                 ),
                 InputChannel::new(
                     TemperatureSensor::new(
-                        Device::Dimm(Tse2004Av::new(
-                            &devices::tse2004av(i2c_task)[3],
-                        )),
+                        Device::Dimm(Tse2004Av::new(&dimms[3])),
                         sensors::TSE2004AV_TEMPERATURE_SENSORS[3],
                     ),
                     DIMM_THERMALS,
@@ -224,9 +219,7 @@ impl Bsp {
                 ),
                 InputChannel::new(
                     TemperatureSensor::new(
-                        Device::Dimm(Tse2004Av::new(
-                            &devices::tse2004av(i2c_task)[4],
-                        )),
+                        Device::Dimm(Tse2004Av::new(&dimms[4])),
                         sensors::TSE2004AV_TEMPERATURE_SENSORS[4],
                     ),
                     DIMM_THERMALS,
@@ -235,9 +228,7 @@ impl Bsp {
                 ),
                 InputChannel::new(
                     TemperatureSensor::new(
-                        Device::Dimm(Tse2004Av::new(
-                            &devices::tse2004av(i2c_task)[5],
-                        )),
+                        Device::Dimm(Tse2004Av::new(&dimms[5])),
                         sensors::TSE2004AV_TEMPERATURE_SENSORS[5],
                     ),
                     DIMM_THERMALS,
@@ -246,9 +237,7 @@ impl Bsp {
                 ),
                 InputChannel::new(
                     TemperatureSensor::new(
-                        Device::Dimm(Tse2004Av::new(
-                            &devices::tse2004av(i2c_task)[6],
-                        )),
+                        Device::Dimm(Tse2004Av::new(&dimms[6])),
                         sensors::TSE2004AV_TEMPERATURE_SENSORS[6],
                     ),
                     DIMM_THERMALS,
@@ -257,9 +246,7 @@ impl Bsp {
                 ),
                 InputChannel::new(
                     TemperatureSensor::new(
-                        Device::Dimm(Tse2004Av::new(
-                            &devices::tse2004av(i2c_task)[7],
-                        )),
+                        Device::Dimm(Tse2004Av::new(&dimms[7])),
                         sensors::TSE2004AV_TEMPERATURE_SENSORS[7],
                     ),
                     DIMM_THERMALS,
@@ -268,9 +255,7 @@ impl Bsp {
                 ),
                 InputChannel::new(
                     TemperatureSensor::new(
-                        Device::Dimm(Tse2004Av::new(
-                            &devices::tse2004av(i2c_task)[8],
-                        )),
+                        Device::Dimm(Tse2004Av::new(&dimms[8])),
                         sensors::TSE2004AV_TEMPERATURE_SENSORS[8],
                     ),
                     DIMM_THERMALS,
@@ -279,9 +264,7 @@ impl Bsp {
                 ),
                 InputChannel::new(
                     TemperatureSensor::new(
-                        Device::Dimm(Tse2004Av::new(
-                            &devices::tse2004av(i2c_task)[9],
-                        )),
+                        Device::Dimm(Tse2004Av::new(&dimms[9])),
                         sensors::TSE2004AV_TEMPERATURE_SENSORS[9],
                     ),
                     DIMM_THERMALS,
@@ -290,9 +273,7 @@ impl Bsp {
                 ),
                 InputChannel::new(
                     TemperatureSensor::new(
-                        Device::Dimm(Tse2004Av::new(
-                            &devices::tse2004av(i2c_task)[10],
-                        )),
+                        Device::Dimm(Tse2004Av::new(&dimms[10])),
                         sensors::TSE2004AV_TEMPERATURE_SENSORS[10],
                     ),
                     DIMM_THERMALS,
@@ -301,9 +282,7 @@ impl Bsp {
                 ),
                 InputChannel::new(
                     TemperatureSensor::new(
-                        Device::Dimm(Tse2004Av::new(
-                            &devices::tse2004av(i2c_task)[11],
-                        )),
+                        Device::Dimm(Tse2004Av::new(&dimms[11])),
                         sensors::TSE2004AV_TEMPERATURE_SENSORS[11],
                     ),
                     DIMM_THERMALS,
@@ -312,9 +291,7 @@ impl Bsp {
                 ),
                 InputChannel::new(
                     TemperatureSensor::new(
-                        Device::Dimm(Tse2004Av::new(
-                            &devices::tse2004av(i2c_task)[12],
-                        )),
+                        Device::Dimm(Tse2004Av::new(&dimms[12])),
                         sensors::TSE2004AV_TEMPERATURE_SENSORS[12],
                     ),
                     DIMM_THERMALS,
@@ -323,9 +300,7 @@ impl Bsp {
                 ),
                 InputChannel::new(
                     TemperatureSensor::new(
-                        Device::Dimm(Tse2004Av::new(
-                            &devices::tse2004av(i2c_task)[13],
-                        )),
+                        Device::Dimm(Tse2004Av::new(&dimms[13])),
                         sensors::TSE2004AV_TEMPERATURE_SENSORS[13],
                     ),
                     DIMM_THERMALS,
@@ -334,9 +309,7 @@ impl Bsp {
                 ),
                 InputChannel::new(
                     TemperatureSensor::new(
-                        Device::Dimm(Tse2004Av::new(
-                            &devices::tse2004av(i2c_task)[14],
-                        )),
+                        Device::Dimm(Tse2004Av::new(&dimms[14])),
                         sensors::TSE2004AV_TEMPERATURE_SENSORS[14],
                     ),
                     DIMM_THERMALS,
@@ -345,9 +318,7 @@ impl Bsp {
                 ),
                 InputChannel::new(
                     TemperatureSensor::new(
-                        Device::Dimm(Tse2004Av::new(
-                            &devices::tse2004av(i2c_task)[15],
-                        )),
+                        Device::Dimm(Tse2004Av::new(&dimms[15])),
                         sensors::TSE2004AV_TEMPERATURE_SENSORS[15],
                     ),
                     DIMM_THERMALS,
@@ -362,39 +333,39 @@ impl Bsp {
             // another on rev A; see Gimlet issue #1302 for details.
             misc_sensors: [
                 TemperatureSensor::new(
-                    Device::Tmp117(Tmp117::new(&devices::tmp117_northeast(
-                        i2c_task,
-                    ))),
+                    Device::Tmp117(Tmp117::new(
+                        &i2c.one(devices::tmp117_northeast),
+                    )),
                     sensors::TMP117_NORTHEAST_TEMPERATURE_SENSOR,
                 ),
                 TemperatureSensor::new(
-                    Device::Tmp117(Tmp117::new(&devices::tmp117_north(
-                        i2c_task,
-                    ))),
+                    Device::Tmp117(Tmp117::new(
+                        &i2c.one(devices::tmp117_north),
+                    )),
                     sensors::TMP117_NORTH_TEMPERATURE_SENSOR,
                 ),
                 TemperatureSensor::new(
-                    Device::Tmp117(Tmp117::new(&devices::tmp117_northwest(
-                        i2c_task,
-                    ))),
+                    Device::Tmp117(Tmp117::new(
+                        &i2c.one(devices::tmp117_northwest),
+                    )),
                     sensors::TMP117_NORTHWEST_TEMPERATURE_SENSOR,
                 ),
                 TemperatureSensor::new(
-                    Device::Tmp117(Tmp117::new(&devices::tmp117_southeast(
-                        i2c_task,
-                    ))),
+                    Device::Tmp117(Tmp117::new(
+                        &i2c.one(devices::tmp117_southeast),
+                    )),
                     sensors::TMP117_SOUTHEAST_TEMPERATURE_SENSOR,
                 ),
                 TemperatureSensor::new(
-                    Device::Tmp117(Tmp117::new(&devices::tmp117_south(
-                        i2c_task,
-                    ))),
+                    Device::Tmp117(Tmp117::new(
+                        &i2c.one(devices::tmp117_south),
+                    )),
                     sensors::TMP117_SOUTH_TEMPERATURE_SENSOR,
                 ),
                 TemperatureSensor::new(
-                    Device::Tmp117(Tmp117::new(&devices::tmp117_southwest(
-                        i2c_task,
-                    ))),
+                    Device::Tmp117(Tmp117::new(
+                        &i2c.one(devices::tmp117_southwest),
+                    )),
                     sensors::TMP117_SOUTHWEST_TEMPERATURE_SENSOR,
                 ),
             ],
@@ -5,7 +5,7 @@
 //! BSP for Sidecar
 
 use crate::control::{
-    Device, FanControl, InputChannel, PidConfig, TemperatureSensor,
+    Device, FanControl, I2cDevices, InputChannel, PidConfig, TemperatureSensor,
     ThermalProperties,
 };
 use core::convert::TryInto;
@@ -116,10 +116,11 @@ impl Bsp {
         }
         let fans = fans.map(Option::unwrap);
 
-        let fctrl_east = Max31790::new(&devices::max31790_east(i2c_task));
-        let fctrl_west = Max31790::new(&devices::max31790_west(i2c_task));
-        fctrl_east.initialize().unwrap();
-        fctrl_west.initialize().unwrap();
+        let i2c = I2cDevices::new(i2c_task);
+
+        // The control loop initializes the fan controller ICs
+        let fctrl_east = Max31790::new(&i2c.one(devices::max31790_east));
+        let fctrl_west = Max31790::new(&i2c.one(devices::max31790_west));
 
         // Handle for the sequencer task, which we check for power state
         let seq = Sequencer::from(SEQUENCER.get_task_id());
@@ -162,7 +163,7 @@ impl Bsp {
                 InputChannel::new(
                     TemperatureSensor::new(
                         Device::Tmp451(Tmp451::new(
-                            &devices::tmp451_tf2(i2c_task),
+                            &i2c.one(devices::tmp451_tf2),
                             Target::Remote,
                         )),
                         sensors::TMP451_TF2_TEMPERATURE_SENSOR,
@@ -174,7 +175,7 @@ impl Bsp {
                 InputChannel::new(
                     TemperatureSensor::new(
                         Device::Tmp451(Tmp451::new(
-                            &devices::tmp451_vsc7448(i2c_task),
+                            &i2c.one(devices::tmp451_vsc7448),
                             Target::Remote,
                         )),
                         sensors::TMP451_VSC7448_TEMPERATURE_SENSOR,
@@ -188,41 +189,41 @@ impl Bsp {
             // We monitor and log all of the air temperatures
             misc_sensors: [
                 TemperatureSensor::new(
-                    Device::Tmp117(Tmp117::new(&devices::tmp117_northeast(
-                        i2c_task,
-                    ))),
+                    Device::Tmp117(Tmp117::new(
+                        &i2c.one(devices::tmp117_northeast),
+                    )),
                     sensors::TMP117_NORTHEAST_TEMPERATURE_SENSOR,
                 ),
                 TemperatureSensor::new(
-                    Device::Tmp117(Tmp117::new(&devices::tmp117_nne(i2c_task))),
+                    Device::Tmp117(Tmp117::new(&i2c.one(devices::tmp117_nne))),
                     sensors::TMP117_NNE_TEMPERATURE_SENSOR,
                 ),
                 TemperatureSensor::new(
-                    Device::Tmp117(Tmp117::new(&devices::tmp117_nnw(i2c_task))),
+                    Device::Tmp117(Tmp117::new(&i2c.one(devices::tmp117_nnw))),
                     sensors::TMP117_NNW_TEMPERATURE_SENSOR,
                 ),
                 TemperatureSensor::new(
-                    Device::Tmp117(Tmp117::new(&devices::tmp117_northwest(
-                        i2c_task,
-                    ))),
+                    Device::Tmp117(Tmp117::new(
+                        &i2c.one(devices::tmp117_northwest),
+                    )),
                     sensors::TMP117_NORTHWEST_TEMPERATURE_SENSOR,
                 ),
                 TemperatureSensor::new(
-                    Device::Tmp117(Tmp117::new(&devices::tmp117_southeast(
-                        i2c_task,
-                    ))),
+                    Device::Tmp117(Tmp117::new(
+                        &i2c.one(devices::tmp117_southeast),
+                    )),
                     sensors::TMP117_SOUTHEAST_TEMPERATURE_SENSOR,
                 ),
                 TemperatureSensor::new(
-                    Device::Tmp117(Tmp117::new(&devices::tmp117_south(
-                        i2c_task,
-                    ))),
+                    Device::Tmp117(Tmp117::new(
+                        &i2c.one(devices::tmp117_south),
+                    )),
                     sensors::TMP117_SOUTH_TEMPERATURE_SENSOR,
                 ),
                 TemperatureSensor::new(
-                    Device::Tmp117(Tmp117::new(&devices::tmp117_southwest(
-                        i2c_task,
-                    ))),
+                    Device::Tmp117(Tmp117::new(
+                        &i2c.one(devices::tmp117_southwest),
+                    )),
                     sensors::TMP117_SOUTHWEST_TEMPERATURE_SENSOR,
                 ),
             ],
@@ -6,16 +6,17 @@ use crate::{
     bsp::{self, Bsp},
     Fan, ThermalError, Trace,
 };
-use drv_i2c_api::ResponseCode;
+use drv_i2c_api::{I2cDevice, ResponseCode};
 use drv_i2c_devices::max31790::{I2cWatchdog, Max31790};
 use drv_i2c_devices::TempSensor;
 use drv_i2c_devices::{
     sbtsi::Sbtsi, tmp117::Tmp117, tmp451::Tmp451, tse2004av::Tse2004Av,
 };
 use ringbuf::ringbuf_entry_root as ringbuf_entry;
-use task_sensor_api::{Sensor as SensorApi, SensorId};
+use task_sensor_api::{Sensor as SensorApi, SensorId, TimedSensor};
 use task_thermal_api::ThermalAutoState;
 use userlib::units::{Celsius, PWMDuty, Rpm};
+use userlib::TaskId;
 
 ////////////////////////////////////////////////////////////////////////////////
 
@@ -79,6 +80,12 @@ impl<'a> FanControl<'a> {
             Self::Max31790(m, _fan) => m.set_watchdog(wd),
         }
     }
+
+    fn initialize(&self) -> Result<(), ResponseCode> {
+        match self {
+            Self::Max31790(m, _fan) => m.initialize(),
+        }
+    }
 }
 
 ////////////////////////////////////////////////////////////////////////////////
@@ -137,6 +144,42 @@ impl InputChannel {
 
 ////////////////////////////////////////////////////////////////////////////////
 
+/// How long to wait on the I2C server for any one operation, in kernel ticks
+/// (milliseconds, like `now_ms`), before giving up on it until the next pass.
+const I2C_TIMEOUT_TICKS: u32 = 100;
+
+/// Likewise for posting readings to the sensor task; a failed post is logged
+/// and skipped like a failed read.
+const SENSOR_TIMEOUT_TICKS: u32 = 100;
+
+/// Builds the I2C devices that a BSP hands to the thermal loop from its
+/// `i2c_config::devices` functions, giving each one `I2C_TIMEOUT_TICKS` so
+/// that a wedged bus can't wedge the thermal loop too.
+pub(crate) struct I2cDevices(TaskId);
+
+impl I2cDevices {
+    pub fn new(i2c_task: TaskId) -> Self {
+        Self(i2c_task)
+    }
+
+    /// Builds the single device that `f` describes.
+    pub fn one(&self, f: impl FnOnce(TaskId) -> I2cDevice) -> I2cDevice {
+        Self::timed(f(self.0))
+    }
+
+    /// Builds every device that `f` describes.
+    pub fn all<const N: usize>(
+        &self,
+        f: impl FnOnce(TaskId) -> [I2cDevice; N],
+    ) -> [I2cDevice; N] {
+        f(self.0).map(Self::timed)
+    }
+
+    fn timed(dev: I2cDevice) -> I2cDevice {
+        dev.with_timeout(I2C_TIMEOUT_TICKS)
+    }
+}
+
 /// The thermal control loop.
 ///
 /// This object uses slices of sensors and fans, which must be owned
@@ -147,7 +190,7 @@ pub(crate) struct ThermalControl<'a> {
     bsp: &'a Bsp,
 
     /// Task to which we should post sensor data updates
-    sensor_api: SensorApi,
+    sensor_api: TimedSensor,
 
     /// Target temperature margin. This must be >= 0; as it increases, parts
     /// are kept cooler than their target temperature value.
@@ -168,6 +211,10 @@ pub(crate) struct ThermalControl<'a> {
 
     /// PID parameters, pulled from the BSP by default but user-modifiable
     pid_config: PidConfig,
+
+    /// Whether every fan controller has been initialized. Until then, we
+    /// retry on each pass, as one may not have answered in time.
+    fctrl_initialized: bool,
 }
 
 /// Represents a temperature reading at the time at which it was taken
@@ -330,7 +377,7 @@ impl<'a> ThermalControl<'a> {
     pub fn new(bsp: &'a Bsp, sensor_api: SensorApi) -> Self {
         Self {
             bsp,
-            sensor_api,
+            sensor_api: sensor_api.with_timeout(SENSOR_TIMEOUT_TICKS),
             target_margin: Celsius(0.0f32),
             state: ThermalControlState::Boot {
                 values: [None; bsp::NUM_TEMPERATURE_INPUTS],
@@ -341,6 +388,29 @@ impl<'a> ThermalControl<'a> {
             overheat_timeout_ms: 60_000,
 
             power_mode: 0, // no sensors active
+
+            fctrl_initialized: false,
+        }
+    }
+
+    /// Initializes the fan controllers, unless that has already succeeded.
+    ///
+    /// Records failures in the local ringbuf; the next call tries again.
+    pub fn initialize_fctrl(&mut self) {
+        if self.fctrl_initialized {
+            return;
+        }
+
+        let mut result = Ok(());
+        self.bsp.for_each_fctrl(|fctrl| {
+            if let Err(e) = fctrl.initialize() {
+                result = Err(e);
+            }
+        });
+
+        match result {
+            Ok(()) => self.fctrl_initialized = true,
+            Err(e) => ringbuf_entry!(Trace::FanInitFailed(e)),
         }
     }
 
@@ -413,6 +483,9 @@ impl<'a> ThermalControl<'a> {
     /// Records failed sensor reads and failed posts to the sensors task in
     /// the local ringbuf.
     pub fn read_sensors(&mut self, now_ms: u64) {
+        // Give any fan controller that didn't answer before another go
+        self.initialize_fctrl();
+
         // Read fan data and log it to the sensors task
         for (index, sensor_id) in self.bsp.fans.iter().enumerate() {
             let post_result =
@@ -49,11 +49,13 @@ enum Trace {
     Start,
     ThermalMode(ThermalMode),
     AutoState(ThermalAutoState),
+    FanInitFailed(ResponseCode),
     FanReadFailed(usize, ResponseCode),
     MiscReadFailed(usize, ResponseCode),
     SensorReadFailed(usize, ResponseCode),
     PostFailed(SensorId, SensorError),
     ControlPwm(u8),
+    SetPwmFailed(ThermalError),
     PowerModeChanged(u32),
     PowerDownFailed(SeqError),
 }
@@ -261,7 +263,8 @@ fn main() -> ! {
     ringbuf_entry!(Trace::Start);
 
     let bsp = Bsp::new(i2c_task);
-    let control = ThermalControl::new(&bsp, sensor_api);
+    let mut control = ThermalControl::new(&bsp, sensor_api);
+    control.initialize_fctrl();
 
     // This will put our timer in the past, and should immediately kick us.
     let deadline = sys_get_timer().now;
@@ -274,8 +277,10 @@ fn main() -> ! {
     };
     if bsp::USE_CONTROLLER {
         server.set_mode_auto().unwrap();
-    } else {
-        server.set_mode_manual(PWMDuty(0)).unwrap();
+    } else if let Err(e) = server.set_mode_manual(PWMDuty(0)) {
+        // A fan controller that didn't answer will get a PWM of zero anyway
+        // once it's initialized.
+        ringbuf_entry!(Trace::SetPwmFailed(e));
     }
 
     let mut buffer = [0; idl::INCOMING_SIZE];
@@ -42,6 +42,18 @@ struct RpcHeader {
     nbytes: U16<LittleEndian>,
 }
 
+impl RpcHeader {
+    /// Checks whether this asks the kernel for the image header. That's
+    /// allowed whatever the image id, since it's how a client finds out what
+    /// it's talking to in the first place. A manifest longer than one reply
+    /// is read in pieces, with the offset of each in the message, and the
+    /// last piece's `nreply` must match what's left.
+    fn reads_image_header(&self) -> bool {
+        self.task.get() == TaskId::KERNEL.0
+            && self.op.get() == Kipcnum::ReadImageHeader as u16
+    }
+}
+
 #[export_name = "main"]
 fn main() -> ! {
     let net = NET.get_task_id();
@@ -51,7 +63,8 @@ fn main() -> ! {
 
     // We use the image id to make sure that we're compatible, since we're
     // sending raw bytes using `sys_send`.  This isn't robust against malicious
-    // behavior, but prevents basic user error.
+    // behavior, but prevents basic user error. The one exception is reading
+    // the image header (see `RpcHeader::reads_image_header`).
     let image_id = kipc::read_image_id();
 
     // The output format is dependent on status code.  The first byte is always
@@ -88,7 +101,9 @@ fn main() -> ! {
                     let nbytes = header.nbytes.get() as usize;
                     let nreply = header.nreply.get() as usize;
 
-                    let r = if image_id != header.image_id.get() {
+                    let r = if image_id != header.image_id.get()
+                        && !header.reads_image_header()
+                    {
                         tx_data_buf[1..9].copy_from_slice(image_id.as_bytes());
                         RpcReply::BadImageId
                     } else if meta.size as usize != HEADER_SIZE + nbytes {
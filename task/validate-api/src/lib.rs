@@ -29,7 +29,8 @@ impl From<ResponseCode> for ValidateError {
             ResponseCode::NoRegister => ValidateError::Unavailable,
             ResponseCode::BusLocked
             | ResponseCode::BusLockedMux
-            | ResponseCode::ControllerLocked => ValidateError::DeviceTimeout,
+            | ResponseCode::ControllerLocked
+            | ResponseCode::TimedOut => ValidateError::DeviceTimeout,
             _ => ValidateError::DeviceError,
         }
     }
@@ -31,7 +31,8 @@ impl From<ResponseCode> for VpdError {
             ResponseCode::NoRegister => VpdError::Unavailable,
             ResponseCode::BusLocked
             | ResponseCode::BusLockedMux
-            | ResponseCode::ControllerLocked => VpdError::DeviceTimeout,
+            | ResponseCode::ControllerLocked
+            | ResponseCode::TimedOut => VpdError::DeviceTimeout,
             _ => VpdError::DeviceError,
         }
     }
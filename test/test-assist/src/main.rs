@@ -111,7 +111,7 @@ fn illinst(_arg: u32) {
         // an illegal instruction
         #[cfg(target_arch = "arm")]
         asm!("udf 0xde");
-        #[cfg(target_arch = "riscv32")]
+        #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
         asm!("unimp");
     }
 }
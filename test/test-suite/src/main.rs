@@ -360,7 +360,7 @@ fn test_fault_buserror() {
         #[cfg(armv6m)]
         FaultInfo::InvalidOperation(_) => {}
 
-        #[cfg(target_arch = "riscv32")]
+        #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
         FaultInfo::MemoryAccess { .. } => {}
         _ => {
             panic!("expected BusFault or MemoryAccess; found {:?}", fault);
@@ -385,7 +385,7 @@ fn test_fault_illaccess() {
         #[cfg(armv6m)]
         FaultInfo::InvalidOperation(_) => {}
 
-        #[cfg(target_arch = "riscv32")]
+        #[cfg(any(target_arch = "riscv32", target_arch = "riscv64"))]
         FaultInfo::MemoryAccess { .. } => {}
         _ => {
             panic!("expected BusFault or MemoryAccess; found {:?}", fault);
@@ -1275,13 +1275,13 @@ fn test_floating_point(highregs: bool) {
 
 #[cfg(any(armv7m, armv8m))]
 fn test_floating_point_lowregs() {
-    #[cfg(not(target_arch = "riscv32"))]
+    #[cfg(not(any(target_arch = "riscv32", target_arch = "riscv64")))]
     test_floating_point(false);
 }
 
 #[cfg(any(armv7m, armv8m))]
 fn test_floating_point_highregs() {
-    #[cfg(not(target_arch = "riscv32"))]
+    #[cfg(not(any(target_arch = "riscv32", target_arch = "riscv64")))]
     test_floating_point(true);
 }
 